@@ -3,6 +3,7 @@ use std::collections::HashMap;
 #[derive(Debug, Default)]
 pub(crate) struct ArgsBuilder {
     positions: Vec<(usize, String)>,
+    rest: Option<(usize, String)>,
     flags: Vec<String>,
     single_args: Vec<String>,
 }
@@ -17,6 +18,13 @@ impl ArgsBuilder {
         self
     }
 
+    // NOTE:
+    // Collects every positional argument from the position on.
+    pub(crate) fn positions(mut self, from: usize, name: &str) -> Self {
+        self.rest = Some((from, name.into()));
+        self
+    }
+
     pub(crate) fn flag(mut self, name: &str) -> Self {
         self.flags.push(name.into());
         self
@@ -29,9 +37,10 @@ impl ArgsBuilder {
 
     pub(crate) fn build(self, args: &[String]) -> Args {
         let mut map: HashMap<String, ArgValue> = HashMap::new();
-        let mut args = args.to_vec();
+        let mut args = self.expand_clusters(args);
         let Self {
             mut positions,
+            rest,
             flags,
             single_args,
         } = self;
@@ -43,29 +52,90 @@ impl ArgsBuilder {
             }
         }
 
+        // NOTE:
+        // Long options also take the value in the same argument like "--format=%H".
         for single_arg in single_args {
+            let inline = format!("{single_arg}=");
             if let Some(pos) = args.iter().position(|v| v.as_str() == single_arg.as_str()) {
                 args.remove(pos);
-                let value = args.remove(pos);
+                if pos < args.len() {
+                    let value = args.remove(pos);
+                    map.insert(single_arg, ArgValue::String(value));
+                }
+            } else if let Some(pos) = args
+                .iter()
+                .position(|v| single_arg.starts_with("--") && v.starts_with(inline.as_str()))
+            {
+                let value = args.remove(pos)[inline.len()..].to_string();
                 map.insert(single_arg, ArgValue::String(value));
             }
         }
 
-        positions.sort_by(|a, b| a.0.cmp(&b.0));
+        positions.sort_by_key(|a| a.0);
         for (pos, name) in positions {
             if let Some(value) = args.get(pos) {
                 map.insert(name, ArgValue::String(value.into()));
             }
         }
 
+        if let Some((from, name)) = rest {
+            let values = args.get(from..).map(|v| v.to_vec()).unwrap_or_default();
+            map.insert(name, ArgValue::List(values));
+        }
+
         Args(map)
     }
+
+    // NOTE:
+    // Short options can be clustered like "-am msg", where every letter is a
+    // flag but the last one, which can also take a value. Values, arguments
+    // with any other letter and everything after "--" are kept as they are.
+    fn expand_clusters(&self, args: &[String]) -> Vec<String> {
+        let is_short = |names: &[String], c: char| names.contains(&format!("-{c}"));
+        let mut expanded: Vec<String> = vec![];
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                expanded.push(arg.clone());
+                expanded.extend(args.by_ref().cloned());
+                break;
+            }
+            if self.single_args.contains(arg) {
+                expanded.push(arg.clone());
+                expanded.extend(args.next().cloned());
+                continue;
+            }
+
+            let letters: Vec<char> = match arg.strip_prefix('-') {
+                Some(rest) if !rest.starts_with('-') => rest.chars().collect(),
+                _ => vec![],
+            };
+            let clustered = letters.len() > 1
+                && letters.iter().enumerate().all(|(i, c)| {
+                    is_short(&self.flags, *c)
+                        || (i == letters.len() - 1 && is_short(&self.single_args, *c))
+                });
+            if !clustered {
+                expanded.push(arg.clone());
+                continue;
+            }
+            expanded.extend(letters.iter().map(|c| format!("-{c}")));
+            if letters
+                .last()
+                .is_some_and(|c| is_short(&self.single_args, *c))
+            {
+                expanded.extend(args.next().cloned());
+            }
+        }
+        expanded
+    }
 }
 
 #[derive(Debug)]
 enum ArgValue {
     Bool(bool),
     String(String),
+    List(Vec<String>),
 }
 
 #[derive(Debug)]
@@ -89,6 +159,13 @@ impl Args {
             _ => None,
         }
     }
+
+    pub(crate) fn values(&self, key: &str) -> Vec<String> {
+        match self.0.get(key) {
+            Some(ArgValue::List(v)) => v.to_vec(),
+            _ => vec![],
+        }
+    }
 }
 
 #[cfg(test)]
@@ -103,6 +180,14 @@ mod tests {
         assert_eq!(args.value("no_key"), None);
     }
 
+    #[test]
+    fn it_parses_long_arg_with_inline_value() {
+        let values = vec!["--format=%H %s".to_string(), "-p=x".to_string()];
+        let args = Args::builder().arg("--format").arg("-p").build(&values);
+        assert_eq!(args.value("--format"), Some("%H %s".into()));
+        assert_eq!(args.value("-p"), None);
+    }
+
     #[test]
     fn it_parses_flag_arg() {
         let values = vec!["--foo".to_string()];
@@ -150,4 +235,53 @@ mod tests {
         assert_eq!(args.value("url"), Some("foobar".into()));
         assert_eq!(args.value("dir"), Some("foobarbaz".into()));
     }
+
+    #[test]
+    fn it_parses_rest_of_positional_args() {
+        let values = vec![
+            "--cached".to_string(),
+            "foo".to_string(),
+            "bar".to_string(),
+            "baz".to_string(),
+        ];
+        let args = Args::builder()
+            .flag("--cached")
+            .position(0, "first")
+            .positions(1, "paths")
+            .build(&values);
+        assert!(args.flag("--cached"));
+        assert_eq!(args.value("first"), Some("foo".into()));
+        assert_eq!(
+            args.values("paths"),
+            vec!["bar".to_string(), "baz".to_string()]
+        );
+        assert!(args.values("notfound").is_empty());
+    }
+
+    #[test]
+    fn it_expands_clustered_short_options() {
+        let values: Vec<String> = ["-fam", "-b msg", "-xv", "--", "-af"]
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        let args = Args::builder()
+            .flag("-a")
+            .flag("-f")
+            .arg("-m")
+            .positions(0, "rest")
+            .build(&values);
+        assert!(args.flag("-a"));
+        assert!(args.flag("-f"));
+        assert_eq!(args.value("-m"), Some("-b msg".into()));
+        assert_eq!(args.values("rest"), vec!["-xv", "--", "-af"]);
+
+        let values = vec!["-m".to_string(), "-af".to_string()];
+        let args = Args::builder()
+            .flag("-a")
+            .flag("-f")
+            .arg("-m")
+            .build(&values);
+        assert!(!args.flag("-a"));
+        assert_eq!(args.value("-m"), Some("-af".into()));
+    }
 }
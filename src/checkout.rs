@@ -0,0 +1,714 @@
+use super::{
+    git_object::tree::Mode,
+    index::{Index, IndexEntry},
+    worktree, Error, GitObject, Result, Sha1Hash,
+};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+pub type Entry = (Mode, Sha1Hash);
+
+// NOTE:
+// The command updating the working tree, which the errors are worded after.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Checkout,
+    Merge,
+}
+
+impl Operation {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Checkout => "checkout",
+            Self::Merge => "merge",
+        }
+    }
+
+    fn action(self) -> &'static str {
+        match self {
+            Self::Checkout => "switch branches",
+            Self::Merge => "merge",
+        }
+    }
+}
+
+// NOTE:
+// Moves the index and the working tree from the `old` tree to the `new` one,
+// like git's two-way merge. Paths that are the same in both trees are left
+// alone with their local changes. The others are updated unless the index or
+// the file has changes that would be lost, or an untracked file is in the way,
+// in which case nothing is touched. With `force`, everything is reset to the
+// new tree.
+pub fn switch<P: AsRef<Path>>(
+    root: P,
+    index: &mut Index,
+    old: Option<&str>,
+    new: &str,
+    force: bool,
+) -> Result<()> {
+    two_way_merge(root.as_ref(), index, old, new, force, Operation::Checkout)
+}
+
+// NOTE:
+// Like `switch`, for a merge that only moves the branch forward.
+pub fn fast_forward<P: AsRef<Path>>(
+    root: P,
+    index: &mut Index,
+    old: Option<&str>,
+    new: &str,
+) -> Result<()> {
+    two_way_merge(root.as_ref(), index, old, new, false, Operation::Merge)
+}
+
+fn two_way_merge(
+    root: &Path,
+    index: &mut Index,
+    old: Option<&str>,
+    new: &str,
+    force: bool,
+    operation: Operation,
+) -> Result<()> {
+    let old_files = tree_files(root, old)?;
+    let new_files = tree_files(root, Some(new))?;
+    if index.is_unmerged() && !force {
+        return Err(Error::from("you need to resolve your current index first"));
+    }
+
+    let staged: BTreeMap<String, Entry> = index
+        .entries()
+        .iter()
+        .filter(|e| e.stage() == 0)
+        .map(|e| (e.path().to_string(), (e.mode(), e.hash())))
+        .collect();
+    let mut paths: Vec<&String> = old_files
+        .keys()
+        .chain(new_files.keys())
+        .chain(staged.keys())
+        .collect();
+    paths.sort();
+    paths.dedup();
+
+    let mut updates: Vec<(&str, Option<Entry>)> = vec![];
+    let (mut changed, mut untracked, mut dirs): (Vec<&str>, Vec<&str>, Vec<&str>) =
+        (vec![], vec![], vec![]);
+    for path in paths {
+        let old = old_files.get(path).copied();
+        let new = new_files.get(path).copied();
+        let current = staged.get(path).copied();
+        if force {
+            if current != new || is_modified(root, index, path)? {
+                updates.push((path, new));
+            }
+            continue;
+        }
+        if old == new || current == new {
+            continue;
+        }
+        if current != old || (current.is_some() && is_modified(root, index, path)?) {
+            changed.push(path);
+        } else if current.is_none() && is_in_the_way(root, path, &staged)? {
+            if root.join(path).is_dir() {
+                dirs.push(path);
+            } else {
+                untracked.push(path);
+            }
+        } else {
+            updates.push((path, new));
+        }
+    }
+
+    refuse(operation, &changed, &untracked, &dirs)?;
+
+    // NOTE:
+    // Files are removed first, so that a directory can replace a file of the
+    // same name and the other way around.
+    for (path, _) in updates.iter().filter(|(_, new)| new.is_none()) {
+        worktree::remove_file(root, path)?;
+        index.remove(path);
+    }
+    if force {
+        let unmerged: Vec<String> = index
+            .entries()
+            .iter()
+            .filter(|e| e.stage() != 0)
+            .map(|e| e.path().to_string())
+            .collect();
+        for path in unmerged {
+            index.remove(&path);
+        }
+    }
+    for (path, new) in updates {
+        if let Some((mode, hash)) = new {
+            index.add(write_file(root, path, mode, hash)?);
+        }
+    }
+    Ok(())
+}
+
+// NOTE:
+// Fails with git's errors for the files with local changes, the untracked
+// files and the directories with untracked files that the operation would
+// overwrite, if there are any.
+pub fn refuse(
+    operation: Operation,
+    changed: &[&str],
+    untracked: &[&str],
+    dirs: &[&str],
+) -> Result<()> {
+    let (name, action) = (operation.name(), operation.action());
+    let mut errors: Vec<String> = vec![];
+    if !changed.is_empty() {
+        errors.push(format!(
+            "Your local changes to the following files would be overwritten by {name}:\n\t{}\n\
+             Please commit your changes or stash them before you {action}.",
+            changed.join("\n\t")
+        ));
+    }
+    if !untracked.is_empty() {
+        errors.push(format!(
+            "The following untracked working tree files would be overwritten by {name}:\n\t{}\n\
+             Please move or remove them before you {action}.",
+            untracked.join("\n\t")
+        ));
+    }
+    if !dirs.is_empty() {
+        errors.push(format!(
+            "Updating the following directories would lose untracked files in them:\n\t{}\n",
+            dirs.join("\n\t")
+        ));
+    }
+    if errors.is_empty() {
+        return Ok(());
+    }
+    errors.push("Aborting".to_string());
+    Err(Error::from(errors.join("\n").as_str()))
+}
+
+// NOTE:
+// Overwrites the files under the pathspecs with their version in the tree,
+// or in the index without one, and returns how many had to be written.
+// Files that are not in the tree are kept, as in git's default overlay mode.
+pub fn checkout_paths<P: AsRef<Path>>(
+    root: P,
+    index: &mut Index,
+    tree: Option<&str>,
+    pathspecs: &[String],
+) -> Result<usize> {
+    let root = root.as_ref();
+    let pathspecs: Vec<String> = pathspecs.iter().map(|p| worktree::normalize(p)).collect();
+    let files: BTreeMap<String, Entry> = match tree {
+        Some(_) => tree_files(root, tree)?,
+        None => {
+            let unmerged = index
+                .entries()
+                .iter()
+                .find(|e| e.stage() != 0 && matches_any(&pathspecs, e.path()));
+            if let Some(entry) = unmerged {
+                let msg = format!("path '{}' is unmerged", entry.path());
+                return Err(Error::from(msg.as_str()));
+            }
+            index
+                .entries()
+                .iter()
+                .map(|e| (e.path().to_string(), (e.mode(), e.hash())))
+                .collect()
+        }
+    };
+
+    for spec in pathspecs.iter() {
+        if !files.keys().any(|path| worktree::matches(spec, path)) {
+            let msg = format!("pathspec '{spec}' did not match any file(s) known to git");
+            return Err(Error::from(msg.as_str()));
+        }
+    }
+
+    let mut count = 0;
+    for (path, entry) in files.iter().filter(|(p, _)| matches_any(&pathspecs, p)) {
+        if is_checked_out(root, index, path, *entry)? {
+            let meta = fs::symlink_metadata(root.join(path))?;
+            index.add(IndexEntry::new(path.clone(), entry.0, entry.1, &meta));
+            continue;
+        }
+        index.add(write_file(root, path, entry.0, entry.1)?);
+        count += 1;
+    }
+    Ok(count)
+}
+
+// NOTE:
+// Puts the files under the pathspecs, or all of them without any, in the
+// index as they are in the tree, leaving the working tree alone. Entries that
+// don't change keep their stat data, so that their files aren't hashed again.
+pub fn reset_index<P: AsRef<Path>>(
+    root: P,
+    index: &mut Index,
+    tree: Option<&str>,
+    pathspecs: &[String],
+) -> Result<()> {
+    let root = root.as_ref();
+    let pathspecs: Vec<String> = pathspecs.iter().map(|p| worktree::normalize(p)).collect();
+    let selected = |path: &str| pathspecs.is_empty() || matches_any(&pathspecs, path);
+    let files = tree_files(root, tree)?;
+
+    let stale: Vec<String> = index
+        .entries()
+        .iter()
+        .filter(|e| selected(e.path()))
+        .filter(|e| e.stage() != 0 || files.get(e.path()) != Some(&(e.mode(), e.hash())))
+        .map(|e| e.path().to_string())
+        .collect();
+    for path in stale {
+        index.remove(&path);
+    }
+    for (path, entry) in files.iter().filter(|(p, _)| selected(p)) {
+        if index.entries().iter().any(|e| e.path() == path) {
+            continue;
+        }
+        let entry = match fs::symlink_metadata(root.join(path)) {
+            Ok(meta) if is_checked_out(root, index, path, *entry)? => {
+                IndexEntry::new(path.clone(), entry.0, entry.1, &meta)
+            }
+            _ => IndexEntry::with_stage(path.clone(), entry.0, entry.1, 0),
+        };
+        index.add(entry);
+    }
+    Ok(())
+}
+
+// NOTE:
+// Restores the files under the pathspecs as they are in the tree, or in the
+// index without one, in the index with `update_index` and in the working tree
+// with `update_worktree`. Unlike `checkout_paths`, the files that are not in
+// the source are removed, like `git restore`.
+pub fn restore_paths<P: AsRef<Path>>(
+    root: P,
+    index: &mut Index,
+    tree: Option<&str>,
+    pathspecs: &[String],
+    update_index: bool,
+    update_worktree: bool,
+) -> Result<()> {
+    let root = root.as_ref();
+    let pathspecs: Vec<String> = pathspecs.iter().map(|p| worktree::normalize(p)).collect();
+    let mut files: BTreeMap<String, Entry> = BTreeMap::new();
+    match tree {
+        Some(tree) => {
+            for spec in pathspecs.iter() {
+                files.extend(tree_files_under(root, tree, spec)?);
+            }
+        }
+        None => {
+            for entry in index.entries() {
+                if !matches_any(&pathspecs, entry.path()) {
+                    continue;
+                }
+                if entry.stage() != 0 {
+                    let msg = format!("path '{}' is unmerged", entry.path());
+                    return Err(Error::from(msg.as_str()));
+                }
+                files.insert(entry.path().to_string(), (entry.mode(), entry.hash()));
+            }
+        }
+    }
+
+    for spec in pathspecs.iter() {
+        let known = files.keys().any(|path| worktree::matches(spec, path))
+            || index
+                .entries()
+                .iter()
+                .any(|e| worktree::matches(spec, e.path()));
+        if !known {
+            let msg = format!("pathspec '{spec}' did not match any file(s) known to git");
+            return Err(Error::from(msg.as_str()));
+        }
+    }
+    if !update_worktree {
+        return reset_index(root, index, tree, &pathspecs);
+    }
+
+    let mut stale: Vec<String> = index
+        .entries()
+        .iter()
+        .filter(|e| matches_any(&pathspecs, e.path()) && !files.contains_key(e.path()))
+        .map(|e| e.path().to_string())
+        .collect();
+    stale.dedup();
+    for path in stale {
+        worktree::remove_file(root, &path)?;
+        if update_index {
+            index.remove(&path);
+        }
+    }
+    for (path, entry) in files {
+        let written = if is_checked_out(root, index, &path, entry)? {
+            let meta = fs::symlink_metadata(root.join(&path))?;
+            IndexEntry::new(path, entry.0, entry.1, &meta)
+        } else {
+            write_file(root, &path, entry.0, entry.1)?
+        };
+        // NOTE:
+        // Files restored from the index only get their stat data updated.
+        if update_index || tree.is_none() {
+            index.add(written);
+        }
+    }
+    Ok(())
+}
+
+fn tree_files_under(root: &Path, tree: &str, pathspec: &str) -> Result<BTreeMap<String, Entry>> {
+    let (prefix, tree) = match pathspec {
+        "" => (String::new(), tree.to_string()),
+        _ => match GitObject::find_in_tree(root, tree, pathspec)? {
+            Some(node) if node.mode() == Mode::Directory => {
+                (format!("{pathspec}/"), node.hash().hex())
+            }
+            Some(node) => {
+                return Ok(BTreeMap::from([(
+                    pathspec.to_string(),
+                    (node.mode(), node.hash()),
+                )]))
+            }
+            None => return Ok(BTreeMap::new()),
+        },
+    };
+    Ok(GitObject::flatten_tree(root, &tree)?
+        .into_iter()
+        .map(|node| {
+            (
+                format!("{prefix}{}", node.name()),
+                (node.mode(), node.hash()),
+            )
+        })
+        .collect())
+}
+
+fn matches_any(pathspecs: &[String], path: &str) -> bool {
+    pathspecs.iter().any(|spec| worktree::matches(spec, path))
+}
+
+pub fn tree_files(root: &Path, tree: Option<&str>) -> Result<BTreeMap<String, Entry>> {
+    let Some(tree) = tree else {
+        return Ok(BTreeMap::new());
+    };
+    Ok(GitObject::flatten_tree(root, tree)?
+        .into_iter()
+        .map(|node| (node.name().to_string(), (node.mode(), node.hash())))
+        .collect())
+}
+
+// NOTE:
+// A file deleted from the working tree has nothing to lose.
+pub fn is_modified(root: &Path, index: &Index, path: &str) -> Result<bool> {
+    let Some(entry) = index
+        .entries()
+        .iter()
+        .find(|e| e.path() == path && e.stage() == 0)
+    else {
+        return Ok(false);
+    };
+    if fs::symlink_metadata(root.join(path)).is_err() {
+        return Ok(false);
+    }
+    Ok(!is_checked_out(
+        root,
+        index,
+        path,
+        (entry.mode(), entry.hash()),
+    )?)
+}
+
+// NOTE:
+// Whether the file on disk has the mode and content. The hash is only
+// computed when the index can't tell from the file's stat data.
+fn is_checked_out(root: &Path, index: &Index, path: &str, (mode, hash): Entry) -> Result<bool> {
+    let full_path = root.join(path);
+    let Ok(meta) = fs::symlink_metadata(&full_path) else {
+        return Ok(false);
+    };
+    if mode == Mode::Gitlink {
+        return Ok(meta.is_dir());
+    }
+    if Mode::from_path(&full_path)? != mode {
+        return Ok(false);
+    }
+    let indexed = index
+        .entries()
+        .iter()
+        .find(|e| e.path() == path && e.stage() == 0);
+    if let Some(entry) = indexed {
+        if entry.mode() == mode && entry.hash() == hash && index.is_up_to_date(entry, &meta) {
+            return Ok(true);
+        }
+    }
+    Ok(GitObject::new_blob_from_path(&full_path, mode)?.hash() == hash)
+}
+
+// NOTE:
+// An untracked file at the path, under it or at one of its parents would be
+// overwritten by a new file.
+pub fn is_in_the_way(root: &Path, path: &str, staged: &BTreeMap<String, Entry>) -> Result<bool> {
+    let parents = path.match_indices('/').map(|(i, _)| &path[..i]);
+    for parent in parents {
+        let full_path = root.join(parent);
+        if fs::symlink_metadata(&full_path).is_ok()
+            && Mode::from_path(&full_path)? != Mode::Directory
+        {
+            return Ok(!staged.contains_key(parent));
+        }
+    }
+    Ok(worktree::files(root, path)?
+        .iter()
+        .any(|file| !staged.contains_key(file)))
+}
+
+// NOTE:
+// Writes the blob as a file, an executable or a symlink, replacing whatever
+// is at the path, and returns the index entry for it. A gitlink only gets an
+// empty directory, as the submodule is not cloned.
+pub fn write_file(root: &Path, path: &str, mode: Mode, hash: Sha1Hash) -> Result<IndexEntry> {
+    let full_path = root.join(path);
+    if mode == Mode::Gitlink {
+        if fs::symlink_metadata(&full_path).is_ok_and(|meta| !meta.is_dir()) {
+            fs::remove_file(&full_path)?;
+        }
+        fs::create_dir_all(&full_path)?;
+        let meta = fs::symlink_metadata(&full_path)?;
+        return Ok(IndexEntry::new(path.to_string(), mode, hash, &meta));
+    }
+    let content = match GitObject::open_from_hash(root, &hash.hex())? {
+        GitObject::Blob(blob) => blob.as_ref().to_vec(),
+        obj => {
+            let msg = format!("{} is a {}, not a blob", hash.hex(), obj.type_name());
+            return Err(Error::from(msg.as_str()));
+        }
+    };
+
+    for (i, _) in path.match_indices('/') {
+        let parent = root.join(&path[..i]);
+        if fs::symlink_metadata(&parent).is_ok_and(|meta| !meta.is_dir()) {
+            fs::remove_file(&parent)?;
+        }
+    }
+    if let Ok(meta) = fs::symlink_metadata(&full_path) {
+        if meta.is_dir() {
+            fs::remove_dir_all(&full_path)?;
+        } else {
+            fs::remove_file(&full_path)?;
+        }
+    }
+    if let Some(dir) = full_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    match mode {
+        #[cfg(unix)]
+        Mode::Symlink => {
+            let target = String::from_utf8_lossy(&content).to_string();
+            std::os::unix::fs::symlink(target, &full_path)?;
+        }
+        #[cfg(unix)]
+        Mode::Executable => {
+            use std::os::unix::fs::PermissionsExt;
+            fs::write(&full_path, &content)?;
+            fs::set_permissions(&full_path, fs::Permissions::from_mode(0o755))?;
+        }
+        _ => fs::write(&full_path, &content)?,
+    }
+
+    let meta = fs::symlink_metadata(&full_path)?;
+    Ok(IndexEntry::new(path.to_string(), mode, hash, &meta))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::path::PathBuf;
+
+    #[test]
+    fn it_switches_between_trees() {
+        let root = testing::repo("checkout-switch");
+        let one = testing::write_tree(&root, &[("a", "1"), ("dir/b", "1"), ("c", "1")]);
+        let two = testing::write_tree(&root, &[("a", "2"), ("dir", "file"), ("c", "1")]);
+
+        let mut index = Index::default();
+        switch(&root, &mut index, None, &one, false).unwrap();
+        assert_eq!(fs::read_to_string(root.join("dir/b")).unwrap(), "1");
+
+        fs::write(root.join("c"), "local").unwrap();
+        switch(&root, &mut index, Some(&one), &two, false).unwrap();
+        assert_eq!(fs::read_to_string(root.join("a")).unwrap(), "2");
+        assert_eq!(fs::read_to_string(root.join("dir")).unwrap(), "file");
+        assert_eq!(fs::read_to_string(root.join("c")).unwrap(), "local");
+        let paths: Vec<&str> = index.entries().iter().map(IndexEntry::path).collect();
+        assert_eq!(paths, vec!["a", "c", "dir"]);
+    }
+
+    #[test]
+    fn it_refuses_to_overwrite_local_changes() {
+        let root = testing::repo("checkout-refuse");
+        let one = testing::write_tree(&root, &[("a", "1")]);
+        let two = testing::write_tree(&root, &[("a", "2"), ("b", "2")]);
+
+        let mut index = Index::default();
+        switch(&root, &mut index, None, &one, false).unwrap();
+        fs::write(root.join("a"), "local").unwrap();
+        fs::write(root.join("b"), "untracked").unwrap();
+        let err = switch(&root, &mut index, Some(&one), &two, false).unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("would be overwritten by checkout:\n\ta\n"));
+        assert!(msg.contains("untracked working tree files would be overwritten"));
+        assert_eq!(fs::read_to_string(root.join("a")).unwrap(), "local");
+
+        switch(&root, &mut index, Some(&one), &two, true).unwrap();
+        assert_eq!(fs::read_to_string(root.join("a")).unwrap(), "2");
+        assert_eq!(fs::read_to_string(root.join("b")).unwrap(), "2");
+    }
+
+    #[test]
+    fn it_checks_out_gitlinks_as_empty_directories() {
+        let root = testing::repo("checkout-gitlink");
+        fs::create_dir(root.join("sub")).unwrap();
+        let meta = fs::symlink_metadata(root.join("sub")).unwrap();
+        let mut index = Index::default();
+        index.add(IndexEntry::new(
+            "sub".into(),
+            Mode::Gitlink,
+            [7; 20].into(),
+            &meta,
+        ));
+        let tree = index.write_tree(&root).unwrap().hash().hex();
+        let empty = Index::default().write_tree(&root).unwrap().hash().hex();
+        fs::remove_dir(root.join("sub")).unwrap();
+
+        let mut index = Index::default();
+        switch(&root, &mut index, None, &tree, false).unwrap();
+        index.write(&root).unwrap();
+        let mut index = Index::open(&root).unwrap();
+        assert!(root.join("sub").is_dir());
+        assert_eq!(index.entries()[0].mode(), Mode::Gitlink);
+        assert_eq!(index.entries()[0].hash(), [7; 20].into());
+
+        fs::write(root.join("sub/file"), "of the submodule").unwrap();
+        assert!(!is_modified(&root, &index, "sub").unwrap());
+        assert!(worktree::untracked(&root, &["sub"]).unwrap().is_empty());
+
+        fs::remove_file(root.join("sub/file")).unwrap();
+        switch(&root, &mut index, Some(&tree), &empty, false).unwrap();
+        assert!(!root.join("sub").exists());
+        assert!(index.entries().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_writes_modes_and_paths() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = testing::repo("checkout-modes");
+        fs::write(root.join("run"), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(root.join("run"), fs::Permissions::from_mode(0o755)).unwrap();
+        std::os::unix::fs::symlink("run", root.join("link")).unwrap();
+        let mut index = Index::default();
+        index.add(worktree::stage(&root, "run".into()).unwrap());
+        index.add(worktree::stage(&root, "link".into()).unwrap());
+        let tree = index.write_tree(&root).unwrap().hash().hex();
+        fs::remove_file(root.join("run")).unwrap();
+        fs::remove_file(root.join("link")).unwrap();
+
+        let mut index = Index::default();
+        switch(&root, &mut index, None, &tree, false).unwrap();
+        let mode = fs::metadata(root.join("run")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o111, 0o111);
+        assert_eq!(
+            fs::read_link(root.join("link")).unwrap(),
+            PathBuf::from("run")
+        );
+
+        fs::write(root.join("run"), "changed").unwrap();
+        let count =
+            checkout_paths(&root, &mut index, None, &["run".into(), "link".into()]).unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(fs::read_to_string(root.join("run")).unwrap(), "#!/bin/sh\n");
+        assert!(checkout_paths(&root, &mut index, None, &["missing".into()]).is_err());
+    }
+
+    #[test]
+    fn it_resets_the_index_to_a_tree() {
+        let root = testing::repo("checkout-reset-index");
+        let one = testing::write_tree(&root, &[("a", "1"), ("dir/b", "1")]);
+        let two = testing::write_tree(&root, &[("a", "2"), ("c", "2")]);
+
+        let mut index = Index::default();
+        switch(&root, &mut index, None, &two, false).unwrap();
+        reset_index(&root, &mut index, Some(&one), &["dir".into()]).unwrap();
+        let paths: Vec<&str> = index.entries().iter().map(IndexEntry::path).collect();
+        assert_eq!(paths, vec!["a", "c", "dir/b"]);
+        assert!(!root.join("dir/b").exists());
+
+        reset_index(&root, &mut index, Some(&one), &[]).unwrap();
+        let paths: Vec<&str> = index.entries().iter().map(IndexEntry::path).collect();
+        assert_eq!(paths, vec!["a", "dir/b"]);
+        assert_eq!(fs::read_to_string(root.join("a")).unwrap(), "2");
+        assert_eq!(fs::read_to_string(root.join("c")).unwrap(), "2");
+        assert_eq!(index.write_tree(&root).unwrap().hash().hex(), one);
+    }
+
+    #[test]
+    fn it_restores_paths_from_a_tree() {
+        let root = testing::repo("checkout-restore");
+        let one = testing::write_tree(&root, &[("a", "1"), ("dir/b", "1"), ("dir/c", "1")]);
+        let two = testing::write_tree(&root, &[("a", "2"), ("dir/b", "2"), ("dir/d", "2")]);
+
+        let mut index = Index::default();
+        switch(&root, &mut index, None, &two, false).unwrap();
+        restore_paths(&root, &mut index, Some(&one), &["dir".into()], false, true).unwrap();
+        assert_eq!(fs::read_to_string(root.join("dir/b")).unwrap(), "1");
+        assert_eq!(fs::read_to_string(root.join("dir/c")).unwrap(), "1");
+        assert!(!root.join("dir/d").exists());
+        let paths: Vec<&str> = index.entries().iter().map(IndexEntry::path).collect();
+        assert_eq!(paths, vec!["a", "dir/b", "dir/d"]);
+
+        restore_paths(&root, &mut index, None, &["dir/d".into()], false, true).unwrap();
+        assert_eq!(fs::read_to_string(root.join("dir/d")).unwrap(), "2");
+        restore_paths(&root, &mut index, Some(&one), &[".".into()], true, true).unwrap();
+        assert_eq!(index.write_tree(&root).unwrap().hash().hex(), one);
+        assert!(!root.join("dir/d").exists());
+        assert!(restore_paths(&root, &mut index, None, &["e".into()], false, true).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_restores_modes_from_a_tree() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = testing::repo("checkout-restore-modes");
+        fs::write(root.join("run"), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(root.join("run"), fs::Permissions::from_mode(0o755)).unwrap();
+        std::os::unix::fs::symlink("run", root.join("link")).unwrap();
+        let mut index = Index::default();
+        index.add(worktree::stage(&root, "run".into()).unwrap());
+        index.add(worktree::stage(&root, "link".into()).unwrap());
+        let source = index.write_tree(&root).unwrap().hash().hex();
+        fs::remove_file(root.join("run")).unwrap();
+        fs::remove_file(root.join("link")).unwrap();
+        let plain = testing::write_tree(&root, &[("run", "plain"), ("link", "plain")]);
+
+        let mut index = Index::default();
+        switch(&root, &mut index, None, &plain, false).unwrap();
+        let paths = ["run".into(), "link".into()];
+        restore_paths(&root, &mut index, Some(&source), &paths, false, true).unwrap();
+        let mode = fs::metadata(root.join("run")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o111, 0o111);
+        assert_eq!(fs::read_to_string(root.join("run")).unwrap(), "#!/bin/sh\n");
+        assert_eq!(
+            fs::read_link(root.join("link")).unwrap(),
+            PathBuf::from("run")
+        );
+        assert_eq!(index.write_tree(&root).unwrap().hash().hex(), plain);
+
+        restore_paths(&root, &mut index, Some(&source), &paths, true, false).unwrap();
+        let modes: Vec<Mode> = index.entries().iter().map(IndexEntry::mode).collect();
+        assert_eq!(modes, vec![Mode::Symlink, Mode::Executable]);
+        assert_eq!(index.write_tree(&root).unwrap().hash().hex(), source);
+    }
+}
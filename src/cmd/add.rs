@@ -0,0 +1,116 @@
+use super::{ignore::Excludes, index::Index, worktree, Error, Result};
+use std::fs;
+
+pub(crate) fn run(paths: Vec<String>, force: bool) -> Result<()> {
+    let mut index = Index::lock(".")?;
+    let mut excludes = Excludes::open(".")?;
+    let mut ignored: Vec<String> = vec![];
+
+    for pathspec in paths {
+        let spec = worktree::normalize(&pathspec);
+        let mut files = match force {
+            true => worktree::files(".", &spec)?,
+            false => worktree::unignored_files(".", &spec, &mut excludes)?,
+        };
+
+        // NOTE:
+        // Ignored files that are already tracked are still updated.
+        if !force {
+            files.extend(
+                index
+                    .entries()
+                    .iter()
+                    .map(|e| e.path().to_string())
+                    .filter(|path| worktree::matches(&spec, path) && !files.contains(path))
+                    .filter(|path| fs::symlink_metadata(path).is_ok_and(|meta| !meta.is_dir()))
+                    .collect::<Vec<String>>(),
+            );
+        }
+
+        // NOTE:
+        // Files that were staged but are gone from the working tree are
+        // removed from the index, like `git add` does.
+        let deleted: Vec<String> = index
+            .entries()
+            .iter()
+            .map(|e| e.path().to_string())
+            .filter(|path| worktree::matches(&spec, path) && !files.contains(path))
+            .collect();
+
+        if files.is_empty() && deleted.is_empty() {
+            let is_dir = fs::metadata(&spec).map(|meta| meta.is_dir());
+            if let Ok(is_dir) = is_dir {
+                if let Some(path) = excludes.ignored_path(&spec, is_dir)? {
+                    ignored.push(path.to_string());
+                    continue;
+                }
+            }
+            let msg = format!("pathspec '{pathspec}' did not match any files");
+            return Err(Error::from(msg.as_str()));
+        }
+
+        for path in deleted {
+            index.remove(&path);
+        }
+
+        for path in files {
+            index.add(worktree::stage(".", path)?);
+        }
+    }
+
+    index.commit()?;
+
+    // NOTE:
+    // Like `git add`, the other paths are staged before the ignored ones
+    // named on the command line are reported.
+    if !ignored.is_empty() {
+        let msg = format!(
+            "The following paths are ignored by one of your .gitignore files:\n{}\nhint: Use -f if you really want to add them.",
+            ignored.join("\n")
+        );
+        return Err(Error::from(msg.as_str()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{index::Index, testing};
+    use std::fs;
+
+    fn staged() -> Vec<String> {
+        let index = Index::open(".").unwrap();
+        index
+            .entries()
+            .iter()
+            .map(|e| e.path().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn it_leaves_ignored_files_out_unless_forced() {
+        let (_root, _cwd) = testing::command_repo("add-ignored");
+        fs::create_dir_all("target").unwrap();
+        fs::write(".gitignore", "target/\n*.log\n").unwrap();
+        fs::write("target/t", "t").unwrap();
+        fs::write("x.log", "x").unwrap();
+        fs::write("a", "a").unwrap();
+
+        testing::git(&["add", "."]).unwrap();
+        assert_eq!(staged(), vec![".gitignore", "a"]);
+
+        let err = testing::git(&["add", "target/t"]).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("ignored by one of your .gitignore files:\ntarget\n"));
+        assert_eq!(staged(), vec![".gitignore", "a"]);
+
+        testing::git(&["add", "-f", "x.log"]).unwrap();
+        assert_eq!(staged(), vec![".gitignore", "a", "x.log"]);
+
+        fs::write("x.log", "changed").unwrap();
+        fs::remove_file("a").unwrap();
+        testing::git(&["add", "."]).unwrap();
+        assert_eq!(staged(), vec![".gitignore", "x.log"]);
+    }
+}
@@ -0,0 +1,432 @@
+use super::{
+    history::{self, RevWalk},
+    ident, pretty,
+    refs::{self, Expected, Head, HEAD},
+    revision, Error, GitObject, Result,
+};
+use crate::{
+    config::{ConfigFile, Scope},
+    glob, reflog,
+};
+
+const BRANCH_PREFIX: &str = "refs/heads/";
+
+#[derive(Debug)]
+pub enum Action {
+    List {
+        patterns: Vec<String>,
+        verbose: usize,
+    },
+    Create {
+        name: String,
+        start: Option<String>,
+        force: bool,
+    },
+    Delete {
+        names: Vec<String>,
+        force: bool,
+    },
+    // NOTE:
+    // Without `from`, the current branch is renamed or copied.
+    Move {
+        from: Option<String>,
+        to: String,
+        force: bool,
+        copy: bool,
+    },
+    SetUpstream {
+        upstream: String,
+        branch: Option<String>,
+    },
+}
+
+pub(crate) fn run(action: Action) -> Result<()> {
+    match action {
+        Action::List { patterns, verbose } => list(&patterns, verbose),
+        Action::Create { name, start, force } => create(&name, start.as_deref(), force),
+        Action::Delete { names, force } => names.iter().try_for_each(|name| delete(name, force)),
+        Action::Move {
+            from,
+            to,
+            force,
+            copy,
+        } => rename(from, &to, force, copy),
+        Action::SetUpstream { upstream, branch } => set_upstream(&upstream, branch),
+    }
+}
+
+// NOTE:
+// A detached HEAD comes first, described like "(HEAD detached at v1)". With
+// `verbose`, each line also has the tip commit and how far it is from its
+// upstream, which is named too when `verbose` is more than 1.
+fn list(patterns: &[String], verbose: usize) -> Result<()> {
+    let head = refs::head(".")?;
+    let mut rows: Vec<(bool, String, String, String)> = vec![];
+    if let (Head::Detached(_), Some(hash)) = (&head, refs::resolve(".", HEAD)?) {
+        let name = match crate::status::detached_from(".")? {
+            Some((name, true)) => format!("(HEAD detached at {name})"),
+            Some((name, false)) => format!("(HEAD detached from {name})"),
+            None => "(no branch)".to_string(),
+        };
+        rows.push((true, name, hash, String::new()));
+    }
+    for (refname, hash) in refs::list(".", BRANCH_PREFIX)? {
+        let name = refs::short_name(&refname).to_string();
+        if !patterns.is_empty() && !patterns.iter().any(|p| glob::wildmatch(p, &name, false)) {
+            continue;
+        }
+        let tracking = match verbose {
+            0 => String::new(),
+            _ => tracking(&name, verbose > 1)?,
+        };
+        rows.push((head == Head::Branch(refname), name, hash, tracking));
+    }
+
+    let width = rows
+        .iter()
+        .map(|(_, name, ..)| name.len())
+        .max()
+        .unwrap_or(0);
+    for (current, name, hash, tracking) in rows.iter() {
+        let marker = if *current { '*' } else { ' ' };
+        if verbose == 0 {
+            println!("{marker} {name}");
+            continue;
+        }
+        let commit = GitObject::open_commit(".", hash)?;
+        println!(
+            "{marker} {name:<width$} {} {tracking}{}",
+            revision::abbrev(".", hash, None)?,
+            pretty::subject(commit.comment())
+        );
+    }
+    Ok(())
+}
+
+// NOTE:
+// Like "[ahead 1, behind 2] ", with the upstream name in front when `named`.
+fn tracking(branch: &str, named: bool) -> Result<String> {
+    let Ok(upstream) = revision::upstream(".", branch) else {
+        return Ok(String::new());
+    };
+    let name = upstream
+        .strip_prefix(BRANCH_PREFIX)
+        .or(upstream.strip_prefix("refs/remotes/"))
+        .unwrap_or(&upstream);
+
+    let counts = match refs::resolve(".", &upstream)? {
+        None => vec!["gone".to_string()],
+        Some(upstream) => {
+            let local = refs::resolve(".", &refs::branch_ref(branch))?.unwrap_or_default();
+            let ahead = count_only(&local, &upstream)?;
+            let behind = count_only(&upstream, &local)?;
+            let mut counts: Vec<String> = vec![];
+            if ahead > 0 {
+                counts.push(format!("ahead {ahead}"));
+            }
+            if behind > 0 {
+                counts.push(format!("behind {behind}"));
+            }
+            counts
+        }
+    };
+    Ok(match (named, counts.is_empty()) {
+        (false, true) => String::new(),
+        (false, false) => format!("[{}] ", counts.join(", ")),
+        (true, true) => format!("[{name}] "),
+        (true, false) => format!("[{name}: {}] ", counts.join(", ")),
+    })
+}
+
+// NOTE:
+// The number of commits reachable from `from` but not from `not`.
+fn count_only(from: &str, not: &str) -> Result<usize> {
+    let mut walk = RevWalk::new(".");
+    walk.hide(not)?;
+    walk.push(from)?;
+    walk.try_fold(0, |count, item| item.map(|_| count + 1))
+}
+
+fn create(name: &str, start: Option<&str>, force: bool) -> Result<()> {
+    refs::check_branch_name(name)?;
+    let refname = refs::branch_ref(name);
+    let old = refs::resolve(".", &refname)?;
+    let head = refs::head(".")?;
+    if old.is_some() {
+        if !force {
+            let msg = format!("a branch named '{name}' already exists");
+            return Err(Error::from(msg.as_str()));
+        }
+        if head == Head::Branch(refname.clone()) {
+            let msg = format!(
+                "cannot force update the branch '{name}' checked out at '{}'",
+                worktree_path()?
+            );
+            return Err(Error::from(msg.as_str()));
+        }
+    }
+
+    // NOTE:
+    // Without a start, the log names the current branch rather than HEAD.
+    let from = start.or(head.branch_name()).unwrap_or(HEAD);
+    let start = start.unwrap_or(HEAD);
+    let hash = revision::resolve_commit(".", start).map_err(|_| {
+        let msg = format!("not a valid object name: '{start}'");
+        Error::from(msg.as_str())
+    })?;
+    let expected = match old.as_deref() {
+        Some(old) if old == hash => return Ok(()),
+        Some(old) => Expected::Hash(old),
+        None => Expected::Missing,
+    };
+    refs::update(".", &refname, &hash, expected)?;
+    let message = match old {
+        Some(_) => format!("branch: Reset to {from}"),
+        None => format!("branch: Created from {from}"),
+    };
+    let committer = ident::reflog_committer(".")?;
+    reflog::append(".", &refname, old.as_deref(), &hash, &committer, &message)
+}
+
+// NOTE:
+// A branch is merged when its tip is reachable from its upstream,
+// or from HEAD when it has none. A warning tells when HEAD disagrees.
+fn delete(name: &str, force: bool) -> Result<()> {
+    let refname = refs::branch_ref(name);
+    if refs::head(".")? == Head::Branch(refname.clone()) {
+        let msg = format!(
+            "Cannot delete branch '{name}' checked out at '{}'",
+            worktree_path()?
+        );
+        return Err(Error::from(msg.as_str()));
+    }
+    let Some(hash) = refs::resolve(".", &refname)? else {
+        let msg = format!("branch '{name}' not found.");
+        return Err(Error::from(msg.as_str()));
+    };
+
+    if !force {
+        let head = refs::resolve(".", HEAD)?;
+        let upstream = match revision::upstream(".", name) {
+            Ok(upstream) => refs::resolve(".", &upstream)?.map(|hash| (upstream, hash)),
+            Err(_) => None,
+        };
+        let merged_to = |reference: Option<&str>| match reference {
+            Some(reference) => history::is_ancestor(".", &hash, reference),
+            None => Ok(false),
+        };
+        let in_head = merged_to(head.as_deref())?;
+        let merged = match upstream.as_ref() {
+            Some((upstream, reference)) => {
+                let merged = merged_to(Some(reference))?;
+                if merged != in_head {
+                    warn_merged(name, upstream, merged);
+                }
+                merged
+            }
+            None => in_head,
+        };
+        if !merged {
+            let msg = format!(
+                "The branch '{name}' is not fully merged.\n\
+                 If you are sure you want to delete it, run 'git branch -D {name}'."
+            );
+            return Err(Error::from(msg.as_str()));
+        }
+    }
+
+    refs::delete(".", &refname)?;
+    reflog::delete(".", &refname)?;
+    let mut config = ConfigFile::open(Scope::Local.path(".")?)?;
+    if config.rename_section(&format!("branch.{name}"), None)? {
+        config.write()?;
+    }
+    println!(
+        "Deleted branch {name} (was {}).",
+        revision::abbrev(".", &hash, None)?
+    );
+    Ok(())
+}
+
+fn warn_merged(name: &str, upstream: &str, merged: bool) {
+    if merged {
+        eprintln!(
+            "warning: deleting branch '{name}' that has been merged to\n         \
+             '{upstream}', but not yet merged to HEAD."
+        );
+    } else {
+        eprintln!(
+            "warning: not deleting branch '{name}' that is not yet merged to\n         \
+             '{upstream}', even though it is merged to HEAD."
+        );
+    }
+}
+
+// NOTE:
+// The ref, its log and its config section move to the new name, and HEAD
+// follows a renamed current branch. A copy leaves the old branch alone.
+fn rename(from: Option<String>, to: &str, force: bool, copy: bool) -> Result<()> {
+    let verb = if copy { "copy" } else { "rename" };
+    let head = refs::head(".")?;
+    let from = match (from, head.branch_name()) {
+        (Some(from), _) => from,
+        (None, Some(branch)) => branch.to_string(),
+        (None, None) => {
+            let msg = format!("cannot {verb} the current branch while not on any.");
+            return Err(Error::from(msg.as_str()));
+        }
+    };
+    let (old_ref, new_ref) = (refs::branch_ref(&from), refs::branch_ref(to));
+    let current = head == Head::Branch(old_ref.clone());
+
+    refs::check_branch_name(to)?;
+    let hash = refs::resolve(".", &old_ref)?;
+    if hash.is_none() && !current {
+        let msg = format!("No branch named '{from}'.");
+        return Err(Error::from(msg.as_str()));
+    }
+    if old_ref != new_ref && refs::resolve(".", &new_ref)?.is_some() {
+        if !force {
+            let msg = format!("a branch named '{to}' already exists");
+            return Err(Error::from(msg.as_str()));
+        }
+        if head == Head::Branch(new_ref.clone()) {
+            let msg = format!(
+                "cannot force update the branch '{to}' checked out at '{}'",
+                worktree_path()?
+            );
+            return Err(Error::from(msg.as_str()));
+        }
+        refs::delete(".", &new_ref)?;
+        reflog::delete(".", &new_ref)?;
+    }
+
+    // NOTE:
+    // The current branch can be renamed before its first commit.
+    if let Some(hash) = hash.as_deref() {
+        reflog::rename(".", &old_ref, &new_ref, copy)?;
+        if !copy {
+            refs::delete(".", &old_ref)?;
+        }
+        refs::write(".", &new_ref, hash)?;
+        let message = match copy {
+            true => format!("Branch: copied {old_ref} to {new_ref}"),
+            false => format!("Branch: renamed {old_ref} to {new_ref}"),
+        };
+        let committer = ident::reflog_committer(".")?;
+        reflog::append(".", &new_ref, Some(hash), hash, &committer, &message)?;
+        // NOTE:
+        // HEAD logs the old branch going away and the new one coming in.
+        if current && !copy {
+            let null = reflog::NULL_HASH;
+            reflog::append(".", HEAD, Some(hash), null, &committer, &message)?;
+            reflog::append(".", HEAD, None, hash, &committer, &message)?;
+        }
+    }
+    if current && !copy {
+        refs::write_head(".", &Head::Branch(new_ref))?;
+    }
+
+    let mut config = ConfigFile::open(Scope::Local.path(".")?)?;
+    let (section, new_section) = (format!("branch.{from}"), format!("branch.{to}"));
+    if copy {
+        config.copy_section(&section, &new_section)?;
+    } else {
+        config.rename_section(&section, Some(&new_section))?;
+    }
+    config.write()
+}
+
+// NOTE:
+// A local branch is tracked through the "." remote, and a remote tracking
+// branch like "origin/main" through its remote.
+fn set_upstream(upstream: &str, branch: Option<String>) -> Result<()> {
+    let branch = match (branch, refs::head(".")?.branch_name()) {
+        (Some(branch), _) => branch,
+        (None, Some(branch)) => branch.to_string(),
+        (None, None) => {
+            let msg = format!(
+                "could not set upstream of HEAD to {upstream} when it does not point to any branch."
+            );
+            return Err(Error::from(msg.as_str()));
+        }
+    };
+    if refs::resolve(".", &refs::branch_ref(&branch))?.is_none() {
+        let msg = format!("branch '{branch}' does not exist");
+        return Err(Error::from(msg.as_str()));
+    }
+
+    let refname = revision::dwim_ref(".", upstream)?.unwrap_or_default();
+    let (remote, merge) = if let Some(name) = refname.strip_prefix(BRANCH_PREFIX) {
+        (".".to_string(), refs::branch_ref(name))
+    } else if let Some((remote, name)) = refname
+        .strip_prefix("refs/remotes/")
+        .and_then(|name| name.split_once('/'))
+    {
+        (remote.to_string(), refs::branch_ref(name))
+    } else {
+        let msg = format!(
+            "the requested upstream branch '{upstream}' does not exist\n\
+             hint: \n\
+             hint: If you are planning on basing your work on an upstream\n\
+             hint: branch that already exists at the remote, you may need to\n\
+             hint: run \"git fetch\" to retrieve it.\n\
+             hint: \n\
+             hint: If you are planning to push out a new local branch that\n\
+             hint: will track its remote counterpart, you may want to use\n\
+             hint: \"git push -u\" to set the upstream config as you push.\n\
+             hint: Disable this message with \"git config advice.setUpstreamFailure false\""
+        );
+        return Err(Error::from(msg.as_str()));
+    };
+
+    let mut config = ConfigFile::open(Scope::Local.path(".")?)?;
+    config.set(&format!("branch.{branch}.remote"), &remote)?;
+    config.set(&format!("branch.{branch}.merge"), &merge)?;
+    config.write()?;
+    let name = match remote.as_str() {
+        "." => refs::short_name(&merge).to_string(),
+        remote => format!("{remote}/{}", refs::short_name(&merge)),
+    };
+    println!("branch '{branch}' set up to track '{name}'.");
+    Ok(())
+}
+
+fn worktree_path() -> Result<String> {
+    Ok(std::env::current_dir()?.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn it_only_logs_a_forced_update_that_moves_the_branch() {
+        let (_root, _cwd) = testing::command_repo("branch-force");
+        let first = testing::commit(&[], "first");
+        let second = testing::commit(&[], "second");
+
+        testing::git(&["branch", "topic"]).unwrap();
+        testing::git(&["branch", "-f", "topic", "main"]).unwrap();
+        assert_eq!(
+            testing::messages("refs/heads/topic"),
+            ["branch: Created from main"]
+        );
+
+        testing::git(&["branch", "-f", "topic", &first]).unwrap();
+        testing::git(&["branch", "-f", "topic", &first]).unwrap();
+        assert_eq!(
+            refs::resolve(".", "refs/heads/topic").unwrap(),
+            Some(first.clone())
+        );
+        assert_eq!(
+            testing::messages("refs/heads/topic"),
+            [
+                "branch: Created from main".to_string(),
+                format!("branch: Reset to {first}")
+            ]
+        );
+        assert!(testing::git(&["branch", "topic", &second]).is_err());
+    }
+}
@@ -1,6 +1,7 @@
-use super::{GitObject, Result};
+use super::{revision, GitObject, Result};
 
-pub(crate) fn run(hash: String) -> Result<()> {
+pub(crate) fn run(rev: String) -> Result<()> {
+    let hash = revision::resolve(".", &rev)?;
     let obj = GitObject::open_from_hash(".", &hash)?;
     print!("{obj}");
     Ok(())
@@ -0,0 +1,281 @@
+use super::{
+    history::RevWalk,
+    ident,
+    index::Index,
+    pretty,
+    refs::{self, Head, HEAD},
+    revision, Error, GitObject, Result,
+};
+use crate::{
+    config::Config,
+    diff::files::{self, Source},
+    reflog,
+};
+
+const ORPHAN_CUTOFF: usize = 4;
+
+// NOTE:
+// Without "--", the first argument is the branch or commit to switch to when
+// it names one, and the arguments after it are paths to check out from it.
+// Otherwise all of them are paths to check out from the index. The number of
+// paths checked out is only reported without "--".
+pub(crate) fn run(
+    revs: Vec<String>,
+    paths: Option<Vec<String>>,
+    new_branch: Option<String>,
+    detach: bool,
+    force: bool,
+) -> Result<()> {
+    let dash_dash = paths.is_some();
+    let mut paths = paths.unwrap_or_default();
+    let mut revs = revs.into_iter();
+    let first = revs
+        .next()
+        .map(|rev| revision::expand_previous(".", &rev))
+        .transpose()?;
+    let target = match first {
+        Some(rev) if !dash_dash && !is_target(&rev) => {
+            paths.push(rev);
+            None
+        }
+        target => target,
+    };
+    paths.splice(0..0, revs);
+
+    if paths.is_empty() {
+        return switch(target, new_branch, detach, force);
+    }
+    if new_branch.is_some() || detach {
+        let msg = format!(
+            "'{}' cannot be used with updating paths",
+            if detach { "--detach" } else { "-b" }
+        );
+        return Err(Error::from(msg.as_str()));
+    }
+
+    let tree = target
+        .map(|rev| revision::resolve_tree(".", &rev))
+        .transpose()?;
+    let mut index = Index::lock(".")?;
+    let count = crate::checkout::checkout_paths(".", &mut index, tree.as_deref(), &paths)?;
+    index.commit()?;
+
+    if dash_dash {
+        return Ok(());
+    }
+    let from = match tree {
+        Some(tree) => revision::abbrev(".", &tree, None)?,
+        None => "the index".to_string(),
+    };
+    let noun = if count == 1 { "path" } else { "paths" };
+    eprintln!("Updated {count} {noun} from {from}");
+    Ok(())
+}
+
+fn is_target(rev: &str) -> bool {
+    revision::resolve_commit(".", rev).is_ok()
+}
+
+// NOTE:
+// Moves HEAD to the branch or commit named by `target` (HEAD by default),
+// after updating the index and the working tree to its tree. With
+// `new_branch`, the branch is created at the target first, and with `detach`,
+// HEAD is detached even when the target is a branch.
+pub(crate) fn switch(
+    target: Option<String>,
+    new_branch: Option<String>,
+    detach: bool,
+    force: bool,
+) -> Result<()> {
+    let head = refs::head(".")?;
+    let old_hash = refs::resolve(".", HEAD)?;
+    let name = target.clone().unwrap_or_else(|| HEAD.to_string());
+
+    let (branch, hash) = if let Some(new_branch) = new_branch.as_deref() {
+        refs::check_branch_name(new_branch)?;
+        let refname = refs::branch_ref(new_branch);
+        if refs::resolve(".", &refname)?.is_some() {
+            let msg = format!("a branch named '{new_branch}' already exists");
+            return Err(Error::from(msg.as_str()));
+        }
+        let hash = match (target.as_deref(), old_hash.as_deref()) {
+            (None, None) => None,
+            (None, Some(hash)) => Some(hash.to_string()),
+            (Some(rev), _) => Some(revision::resolve_commit(".", rev)?),
+        };
+        (Some(refname), hash)
+    } else if name == HEAD && !detach {
+        (head.branch_name().map(refs::branch_ref), old_hash.clone())
+    } else {
+        let refname = refs::branch_ref(&name);
+        match refs::resolve(".", &refname)? {
+            Some(hash) if !detach => (Some(refname), Some(hash)),
+            _ => (None, Some(revision::resolve_commit(".", &name)?)),
+        }
+    };
+
+    // NOTE:
+    // A new branch on an unborn HEAD stays unborn, with nothing to check out.
+    let Some(hash) = hash else {
+        let refname = branch.expect("only a new branch can be unborn");
+        refs::write_head(".", &Head::Branch(refname.clone()))?;
+        eprintln!("Switched to a new branch '{}'", refs::short_name(&refname));
+        return Ok(());
+    };
+
+    let old_tree = old_hash
+        .as_deref()
+        .map(|hash| revision::resolve_tree(".", hash))
+        .transpose()?;
+    let new_tree = revision::resolve_tree(".", &hash)?;
+    let mut index = Index::lock(".")?;
+    crate::checkout::switch(".", &mut index, old_tree.as_deref(), &new_tree, force)?;
+    index.commit()?;
+    if !force {
+        show_local_changes(&new_tree)?;
+    }
+
+    if let (Head::Detached(_), Some(old_hash)) = (&head, old_hash.as_deref()) {
+        if old_hash != hash {
+            warn_orphaned(old_hash, &hash)?;
+        }
+    }
+
+    let committer = ident::reflog_committer(".")?;
+    if let (Some(new_branch), Some(refname)) = (new_branch.as_deref(), branch.as_deref()) {
+        refs::write(".", refname, &hash)?;
+        let message = format!("branch: Created from {name}");
+        reflog::append(".", refname, None, &hash, &committer, &message)?;
+        eprintln!("Switched to a new branch '{new_branch}'");
+    } else if name == HEAD && !detach {
+        return Ok(());
+    } else if let Some(refname) = branch.as_deref() {
+        if head == Head::Branch(refname.to_string()) {
+            eprintln!("Already on '{}'", refs::short_name(refname));
+        } else {
+            eprintln!("Switched to branch '{}'", refs::short_name(refname));
+        }
+    }
+
+    let new_head = match branch {
+        Some(refname) => Head::Branch(refname),
+        None => Head::Detached(hash.clone()),
+    };
+    refs::write_head(".", &new_head)?;
+    let from = match (head.branch_name(), old_hash.as_deref()) {
+        (Some(branch), _) => branch,
+        (None, Some(hash)) => hash,
+        (None, None) => "(invalid)",
+    };
+    let to = new_branch.as_deref().unwrap_or(&name);
+    let message = format!("checkout: moving from {from} to {to}");
+    reflog::append(".", HEAD, old_hash.as_deref(), &hash, &committer, &message)?;
+
+    if let Head::Detached(_) = new_head {
+        if head.branch_name().is_some() && !detach && advice_enabled()? {
+            eprint!("{}", detach_advice(&name));
+        }
+        eprintln!("HEAD is now at {}", describe(&hash)?);
+    }
+    Ok(())
+}
+
+// NOTE:
+// Lists the paths that differ between the new tree and the working tree,
+// which are the local changes carried over by the switch.
+fn show_local_changes(tree: &str) -> Result<()> {
+    let pairs = files::pairs(".", &Source::Tree(tree.to_string()), &Source::Worktree, &[])?;
+    for pair in pairs {
+        let code = match (pair.old_file(), pair.new_file()) {
+            (None, _) => 'A',
+            (_, None) => 'D',
+            _ if pair.is_type_change() => 'T',
+            _ => 'M',
+        };
+        println!("{code}\t{}", pair.path());
+    }
+    Ok(())
+}
+
+// NOTE:
+// Leaving a detached HEAD loses the commits that no ref can reach anymore.
+fn warn_orphaned(old: &str, new: &str) -> Result<()> {
+    let mut walk = RevWalk::new(".");
+    walk.hide(new)?;
+    for (_, hash) in refs::list(".", "refs/")? {
+        if let Ok(commit) = revision::resolve_commit(".", &hash) {
+            walk.hide(&commit)?;
+        }
+    }
+    walk.push(old)?;
+    let lost: Vec<String> = walk
+        .map(|item| item.map(|(hash, _)| hash))
+        .collect::<Result<_>>()?;
+
+    if lost.is_empty() {
+        eprintln!("Previous HEAD position was {}", describe(old)?);
+        return Ok(());
+    }
+
+    let mut orphans = String::new();
+    for (i, hash) in lost.iter().enumerate() {
+        if i == ORPHAN_CUTOFF && lost.len() > ORPHAN_CUTOFF + 1 {
+            orphans.push_str(&format!(" ... and {} more.\n", lost.len() - ORPHAN_CUTOFF));
+            break;
+        }
+        orphans.push_str(&format!("  {}\n", describe(hash)?));
+    }
+    let (count, it) = if lost.len() == 1 {
+        ("1 commit".to_string(), "it")
+    } else {
+        (format!("{} commits", lost.len()), "them")
+    };
+    eprint!(
+        "Warning: you are leaving {count} behind, not connected to\n\
+         any of your branches:\n\n{orphans}\n"
+    );
+    if advice_enabled()? {
+        eprint!(
+            "If you want to keep {it} by creating a new branch, this may be a good time\n\
+             to do so with:\n\n git branch <new-branch-name> {}\n\n",
+            revision::abbrev(".", old, None)?
+        );
+    }
+    Ok(())
+}
+
+fn describe(hash: &str) -> Result<String> {
+    let commit = GitObject::open_commit(".", hash)?;
+    Ok(format!(
+        "{} {}",
+        revision::abbrev(".", hash, None)?,
+        pretty::subject(commit.comment())
+    ))
+}
+
+fn advice_enabled() -> Result<bool> {
+    let config = Config::load(".")?;
+    Ok(!matches!(
+        config.get("advice.detachedHead"),
+        Some("false" | "no" | "off" | "0")
+    ))
+}
+
+fn detach_advice(name: &str) -> String {
+    format!(
+        "Note: switching to '{name}'.\n\
+         \n\
+         You are in 'detached HEAD' state. You can look around, make experimental\n\
+         changes and commit them, and you can discard any commits you make in this\n\
+         state without impacting any branches by switching back to a branch.\n\
+         \n\
+         If you want to create a new branch to retain commits you create, you may\n\
+         do so (now or later) by using -c with the switch command. Example:\n\
+         \n  git switch -c <new-branch-name>\n\
+         \n\
+         Or undo this operation with:\n\
+         \n  git switch -\n\
+         \n\
+         Turn off this advice by setting config variable advice.detachedHead to false\n\n"
+    )
+}
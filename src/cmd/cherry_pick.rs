@@ -0,0 +1,703 @@
+use super::reset::{self, Mode};
+use super::{
+    git_object::commit::User,
+    history::RevWalk,
+    ident,
+    index::Index,
+    pretty,
+    refs::{self, Expected, HEAD},
+    revision::{self, Rev},
+    Error, GitObject, Result, GIT_DIR,
+};
+use crate::{
+    merge::{self, MERGE_MSG},
+    reflog,
+    sequencer::{
+        self, Command, Instruction, Options, PickHead, Sequence, CHERRY_PICK_HEAD, REVERT_HEAD,
+    },
+};
+use std::fs;
+use std::path::Path;
+
+const ABORT_SAFETY: &str = "abort-safety";
+const SEQUENCE_HEAD: &str = "head";
+
+#[derive(Debug)]
+pub enum Action {
+    Start { revs: Vec<String>, options: Options },
+    Continue,
+    Skip,
+    Abort,
+}
+
+pub(crate) fn run(action: Action) -> Result<()> {
+    replay(Command::Pick, action)
+}
+
+// NOTE:
+// Cherry-picks and reverts share their state, so that either
+// command can go on with or abort the other.
+pub(crate) fn replay(command: Command, action: Action) -> Result<()> {
+    match action {
+        Action::Start { revs, options } => start(command, revs, options),
+        Action::Continue => resume(command),
+        Action::Skip => skip(command),
+        Action::Abort => abort(command),
+    }
+}
+
+fn name(command: Command) -> &'static str {
+    match command {
+        Command::Revert => "revert",
+        _ => "cherry-pick",
+    }
+}
+
+// NOTE:
+// Replays the given commits in order, or those of ranges oldest first when
+// picking and newest first when reverting. The state to go on is only kept
+// in ".git/sequencer" when there are several of them.
+fn start(command: Command, revs: Vec<String>, options: Options) -> Result<()> {
+    let mut index = Index::open(".")?;
+    if index.is_unmerged() {
+        let msg = format!(
+            "{} is not possible because you have unmerged files.\n\
+             hint: Fix them up in the work tree, and then use 'git add/rm <file>'\n\
+             hint: as appropriate to mark resolution and make a commit.",
+            match command {
+                Command::Revert => "Reverting",
+                _ => "Cherry-picking",
+            }
+        );
+        return Err(failed(command, &msg));
+    }
+    let todo = instructions(command, &revs)?;
+    if todo.is_empty() {
+        return Err(failed(command, "empty commit set passed"));
+    }
+    // NOTE:
+    // A single commit is replayed without looking at the sequence,
+    // and a commit left in CHERRY_PICK_HEAD only stops it through the index.
+    let current = match todo.len() {
+        1 => None,
+        _ => sequence_command()?,
+    };
+    if let Some(current) = current {
+        let msg = if current == command {
+            format!(
+                "{0} is already in progress\n\
+                 hint: try \"git {0} (--continue | --skip | --abort)\"",
+                name(command)
+            )
+        } else {
+            format!("cannot {} during a {}.", name(command), name(current))
+        };
+        return Err(failed(command, &msg));
+    }
+    let head = refs::resolve(".", HEAD)?.ok_or_else(|| {
+        let msg = format!("can't {} as initial commit", name(command));
+        Error::from(msg.as_str())
+    })?;
+    if !options.no_commit
+        && index.write_tree(".")?.hash().hex() != revision::resolve_tree(".", &head)?
+    {
+        return Err(dirty_index(command));
+    }
+
+    let sequence = match todo.len() {
+        1 => None,
+        _ => {
+            let sequence = Sequence::create(".")?;
+            sequence.write(SEQUENCE_HEAD, &format!("{head}\n"))?;
+            sequence.write(ABORT_SAFETY, &format!("{head}\n"))?;
+            sequence.write_options(&options)?;
+            Some(sequence)
+        }
+    };
+    run_todo(sequence, todo, options)
+}
+
+fn in_progress() -> Result<Option<Command>> {
+    match sequencer::pick_head(".")? {
+        Some((command, _)) => Ok(Some(command)),
+        None => sequence_command(),
+    }
+}
+
+fn sequence_command() -> Result<Option<Command>> {
+    Ok(match Sequence::open(".") {
+        Some(sequence) => Some(
+            sequence
+                .todo()?
+                .first()
+                .map_or(Command::Pick, Instruction::command),
+        ),
+        None => None,
+    })
+}
+
+fn instructions(command: Command, revs: &[String]) -> Result<Vec<Instruction>> {
+    let (mut hashes, mut exclude): (Vec<String>, Vec<String>) = (vec![], vec![]);
+    let mut ranges = false;
+    for rev in revs {
+        let parsed = revision::parse_revs(".", rev).map_err(|_| {
+            let msg = format!("bad revision '{rev}'");
+            Error::from(msg.as_str())
+        })?;
+        ranges |= parsed.len() > 1 || matches!(parsed[..], [Rev::Exclude(_)]);
+        for rev in parsed {
+            match rev {
+                Rev::Include(hash) => hashes.push(revision::resolve_commit(".", &hash)?),
+                Rev::Exclude(hash) => exclude.push(hash),
+            }
+        }
+    }
+    if ranges {
+        let mut walk = RevWalk::new(".");
+        for hash in &exclude {
+            walk.hide(hash)?;
+        }
+        for hash in &hashes {
+            walk.push(hash)?;
+        }
+        hashes = walk
+            .map(|item| item.map(|(hash, _)| hash))
+            .collect::<Result<_>>()?;
+        if command == Command::Pick {
+            hashes.reverse();
+        }
+    }
+
+    let mut instructions: Vec<Instruction> = vec![];
+    for hash in hashes {
+        let commit = GitObject::open_commit(".", &hash)?;
+        let subject = pretty::subject(commit.comment());
+        instructions.push(match command {
+            Command::Revert => Instruction::revert(hash, subject),
+            _ => Instruction::pick(hash, subject),
+        });
+    }
+    Ok(instructions)
+}
+
+// NOTE:
+// Replays the commits one after the other, keeping the todo list with the
+// current one first, so that it stays there when one stops on conflicts.
+fn run_todo(sequence: Option<Sequence>, todo: Vec<Instruction>, options: Options) -> Result<()> {
+    let mut todo = todo;
+    while !todo.is_empty() {
+        if let Some(sequence) = &sequence {
+            let head = refs::resolve(".", HEAD)?.unwrap_or_default();
+            sequence.write(ABORT_SAFETY, &format!("{head}\n"))?;
+            sequence.write_todo(&todo)?;
+        }
+        let instruction = todo.remove(0);
+        apply(&instruction, options)?;
+    }
+    match sequence {
+        Some(sequence) => sequence.clear(),
+        None => Ok(()),
+    }
+}
+
+// NOTE:
+// Merges the change of the commit, or its inverse, on top of HEAD and commits
+// the result unless `no_commit`. On conflicts the message is kept in MERGE_MSG
+// and the commit in CHERRY_PICK_HEAD or REVERT_HEAD for the commit that
+// concludes it.
+fn apply(instruction: &Instruction, options: Options) -> Result<()> {
+    let command = instruction.command();
+    let hash = instruction
+        .commit()
+        .expect("replayed instructions have a commit");
+    let commit = GitObject::open_commit(".", hash)?;
+    let parent = match sequencer::parent(hash, &commit, options.mainline) {
+        Ok(parent) => parent,
+        Err(Error::Other(err)) => return Err(failed(command, &err.to_string())),
+        Err(err) => return Err(err),
+    };
+    let (base, theirs) = sequencer::replay_trees(".", command, &commit, parent.as_deref())?;
+    let head = refs::resolve(".", HEAD)?.ok_or(Error::from("HEAD does not point to a commit"))?;
+    let head_tree = revision::resolve_tree(".", &head)?;
+    let mut index = Index::lock(".")?;
+    // NOTE:
+    // Without commits in between, each change is merged with what the ones
+    // before left in the index.
+    let ours = match options.no_commit {
+        true => index.write_tree(".")?.hash().hex(),
+        false => head_tree.clone(),
+    };
+
+    let abbrev = revision::abbrev(".", hash, None)?;
+    let subject = pretty::subject(commit.comment());
+    let (label, message, author) = match command {
+        Command::Revert => (
+            format!("parent of {abbrev} ({subject})"),
+            sequencer::revert_message(hash, &commit, parent.as_deref()),
+            ident::author(".")?,
+        ),
+        _ => (
+            format!("{abbrev} ({subject})"),
+            pick_message(hash, commit.comment(), options.record_origin),
+            commit.author().clone(),
+        ),
+    };
+
+    let result = sequencer::apply(
+        ".",
+        &mut index,
+        base.as_deref(),
+        &ours,
+        &theirs,
+        (HEAD, &label),
+    )?;
+    let mut index = index.commit()?;
+    for message in result.messages() {
+        println!("{message}");
+    }
+
+    let git_dir = Path::new(GIT_DIR);
+    let pick_head = match command {
+        Command::Revert => REVERT_HEAD,
+        _ => CHERRY_PICK_HEAD,
+    };
+    let conflicts = result.conflicts();
+    if !conflicts.is_empty() {
+        let mut merge_msg = format!("{}\n\n# Conflicts:\n", message.trim_end());
+        for path in conflicts {
+            merge_msg.push_str(&format!("#\t{path}\n"));
+        }
+        fs::write(git_dir.join(MERGE_MSG), merge_msg)?;
+        if !options.no_commit || command == Command::Revert {
+            fs::write(git_dir.join(pick_head), format!("{hash}\n"))?;
+        }
+        let verb = match command {
+            Command::Revert => "revert",
+            _ => "apply",
+        };
+        let msg = if options.no_commit {
+            format!(
+                "could not {verb} {abbrev}... {subject}\n\
+                 hint: after resolving the conflicts, mark the corrected paths\n\
+                 hint: with 'git add <paths>' or 'git rm <paths>'"
+            )
+        } else {
+            format!(
+                "could not {verb} {abbrev}... {subject}\n\
+                 hint: After resolving the conflicts, mark them with\n\
+                 hint: \"git add/rm <pathspec>\", then run\n\
+                 hint: \"git {0} --continue\".\n\
+                 hint: You can instead skip this commit with \"git {0} --skip\".\n\
+                 hint: To abort and get back to the state before \"git {0}\",\n\
+                 hint: run \"git {0} --abort\".",
+                name(command)
+            )
+        };
+        return Err(Error::from(msg.as_str()));
+    }
+
+    if options.no_commit {
+        fs::write(git_dir.join(MERGE_MSG), message)?;
+        // NOTE:
+        // git keeps REVERT_HEAD even then, unlike CHERRY_PICK_HEAD.
+        if command == Command::Revert {
+            fs::write(git_dir.join(pick_head), format!("{hash}\n"))?;
+        }
+        return Ok(());
+    }
+    let tree = index.write_tree(".")?.hash().hex();
+    if tree == head_tree && command == Command::Pick {
+        fs::write(git_dir.join(MERGE_MSG), &message)?;
+        fs::write(git_dir.join(pick_head), format!("{hash}\n"))?;
+        return Err(Error::from(
+            "The previous cherry-pick is now empty, possibly due to conflict resolution.\n\
+             If you wish to commit it anyway, use:\n\n    \
+                 git commit --allow-empty\n\n\
+             Otherwise, please use 'git cherry-pick --skip'",
+        ));
+    }
+    commit_tree(&tree, message, author, name(command))
+}
+
+// NOTE:
+// With `record_origin`, the picked commit is named in a line of its own after
+// the message, like `git cherry-pick -x`.
+fn pick_message(hash: &str, message: &str, record_origin: bool) -> String {
+    if !record_origin {
+        return message.to_string();
+    }
+    format!(
+        "{}\n\n(cherry picked from commit {hash})\n",
+        message.trim_end()
+    )
+}
+
+fn commit_tree(tree: &str, message: String, author: User, action: &str) -> Result<()> {
+    let head = refs::head(".")?;
+    let old = refs::resolve(".", HEAD)?.ok_or(Error::from("HEAD does not point to a commit"))?;
+    let obj = GitObject::new_commit(
+        tree.to_string(),
+        message,
+        vec![old.clone()],
+        author,
+        ident::committer(".")?,
+    )?;
+    obj.write(".")?;
+    let hash = obj.hash().hex();
+    let GitObject::Commit(ref commit) = obj else {
+        unreachable!()
+    };
+    refs::update(".", head.target(), &hash, Expected::Hash(&old))?;
+    let message = format!("{action}: {}", commit.summary());
+    let mut logged_refs = vec![head.target()];
+    if head.target() != HEAD {
+        logged_refs.push(HEAD);
+    }
+    for refname in logged_refs {
+        reflog::append(
+            ".",
+            refname,
+            Some(&old),
+            &hash,
+            commit.committer(),
+            &message,
+        )?;
+    }
+    println!(
+        "[{} {}] {}",
+        head.branch_name().unwrap_or("detached HEAD"),
+        &hash[..7],
+        commit.summary()
+    );
+    Ok(())
+}
+
+fn open(command: Command) -> Result<(Option<Sequence>, Option<PickHead>)> {
+    let sequence = Sequence::open(".");
+    let pick_head = sequencer::pick_head(".")?;
+    if sequence.is_none() && pick_head.is_none() {
+        return Err(failed(command, "no cherry-pick or revert in progress"));
+    }
+    Ok((sequence, pick_head))
+}
+
+// NOTE:
+// Commits what was staged to resolve the commit it stopped at, with the
+// message kept in MERGE_MSG, then goes on with the rest. The commit was
+// already concluded if HEAD moved since.
+fn resume(command: Command) -> Result<()> {
+    let (sequence, pick_head) = open(command)?;
+    let mut index = Index::lock(".")?;
+    if index.is_unmerged() {
+        return Err(Error::from(
+            "Committing is not possible because you have unmerged files.\n\
+             hint: Fix them up in the work tree, and then use 'git add/rm <file>'\n\
+             hint: as appropriate to mark resolution and make a commit.",
+        ));
+    }
+    let options = match &sequence {
+        Some(sequence) => sequence.read_options()?,
+        None => Options::default(),
+    };
+    let mut todo = match &sequence {
+        Some(sequence) => sequence.todo()?,
+        None => vec![],
+    };
+
+    let tree = index.write_tree(".")?.hash().hex();
+    index.commit()?;
+
+    let head = refs::resolve(".", HEAD)?.ok_or(Error::from("HEAD does not point to a commit"))?;
+    if let Some((command, hash)) = pick_head {
+        if tree == revision::resolve_tree(".", &head)? {
+            let msg = format!(
+                "The previous {0} is now empty, possibly due to conflict resolution.\n\
+                 If you wish to commit it anyway, use:\n\n    \
+                     git commit --allow-empty\n\n\
+                 Otherwise, please use 'git {0} --skip'",
+                name(command)
+            );
+            return Err(Error::from(msg.as_str()));
+        }
+        let message = merge::merge_message(".")?.unwrap_or_default();
+        let lines: Vec<&str> = message.lines().filter(|l| !l.starts_with('#')).collect();
+        let message = pretty::cleanup(&lines.join("\n"));
+        let (author, action) = match command {
+            Command::Revert => (ident::author(".")?, "commit"),
+            _ => (
+                GitObject::open_commit(".", &hash)?.author().clone(),
+                "commit (cherry-pick)",
+            ),
+        };
+        commit_tree(&tree, message, author, action)?;
+        clear_stop()?;
+        if let Some(sequence) = &sequence {
+            let head = refs::resolve(".", HEAD)?.unwrap_or_default();
+            sequence.write(ABORT_SAFETY, &format!("{head}\n"))?;
+        }
+    }
+    // NOTE:
+    // What was left staged after `no_commit` stops it.
+    let head = refs::resolve(".", HEAD)?.unwrap_or_default();
+    if sequence.is_some() && tree != revision::resolve_tree(".", &head)? {
+        return Err(dirty_index(command));
+    }
+    if !todo.is_empty() {
+        todo.remove(0);
+    }
+    run_todo(sequence, todo, options)
+}
+
+fn dirty_index(command: Command) -> Error {
+    let msg = format!(
+        "your local changes would be overwritten by {}.\n\
+         hint: commit your changes or stash them to proceed.",
+        name(command)
+    );
+    failed(command, &msg)
+}
+
+// NOTE:
+// The errors that stop before any change end by naming the command.
+fn failed(command: Command, msg: &str) -> Error {
+    let msg = format!("{msg}\n{} failed", name(command));
+    Error::from(msg.as_str())
+}
+
+fn clear_stop() -> Result<()> {
+    sequencer::clear_pick_head(".")?;
+    let path = Path::new(GIT_DIR).join(MERGE_MSG);
+    if path.is_file() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+fn skip(command: Command) -> Result<()> {
+    let no_progress = || {
+        let msg = format!("no {} in progress", name(command));
+        failed(command, &msg)
+    };
+    let (sequence, _) = open(command).map_err(|_| no_progress())?;
+    if in_progress()? != Some(command) {
+        return Err(no_progress());
+    }
+    let head = refs::resolve(".", HEAD)?.ok_or(Error::from("HEAD does not point to a commit"))?;
+    reset::reset(Mode::Hard, &head)?;
+    clear_stop()?;
+    let (options, mut todo) = match &sequence {
+        Some(sequence) => (sequence.read_options()?, sequence.todo()?),
+        None => (Options::default(), vec![]),
+    };
+    if !todo.is_empty() {
+        todo.remove(0);
+    }
+    run_todo(sequence, todo, options)
+}
+
+// NOTE:
+// Goes back to where HEAD was before, unless it was moved since the last
+// commit replayed.
+fn abort(command: Command) -> Result<()> {
+    let (sequence, _) = open(command)?;
+    let head = refs::resolve(".", HEAD)?.ok_or(Error::from("HEAD does not point to a commit"))?;
+    let (moved, target) = match &sequence {
+        Some(sequence) => (
+            sequence.read(ABORT_SAFETY)?.as_deref() != Some(head.as_str()),
+            sequence.read(SEQUENCE_HEAD)?.unwrap_or(head.clone()),
+        ),
+        None => (false, head.clone()),
+    };
+    match moved {
+        true => eprintln!("warning: You seem to have moved HEAD. Not rewinding, check your HEAD!"),
+        false => reset::reset(Mode::Hard, &target)?,
+    }
+    clear_stop()?;
+    match sequence {
+        Some(sequence) => sequence.clear(),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn head() -> String {
+        refs::resolve(".", HEAD).unwrap().unwrap()
+    }
+
+    fn ancestor(n: usize) -> String {
+        let mut hash = head();
+        for _ in 0..n {
+            hash = GitObject::open_commit(".", &hash).unwrap().parents()[0].clone();
+        }
+        hash
+    }
+
+    fn subjects(n: usize) -> Vec<String> {
+        (0..n)
+            .map(|i| {
+                let commit = GitObject::open_commit(".", &ancestor(i)).unwrap();
+                pretty::subject(commit.comment()).to_string()
+            })
+            .collect()
+    }
+
+    // NOTE:
+    // Three commits on "side" to pick onto "main", the first of which
+    // conflicts with the one on "main".
+    fn diverged(name: &str) -> (testing::TempDir, testing::CurrentDir, String) {
+        let (root, cwd) = testing::command_repo(name);
+        testing::commit(&[("a", "1\n")], "base");
+        testing::git(&["branch", "side"]).unwrap();
+        let main = testing::commit(&[("a", "main\n")], "main");
+        testing::git(&["switch", "side"]).unwrap();
+        testing::commit(&[("a", "side\n")], "one");
+        testing::commit(&[("b", "b\n")], "two");
+        testing::commit(&[("c", "c\n")], "three");
+        testing::git(&["switch", "main"]).unwrap();
+        (root, cwd, main)
+    }
+
+    fn stop(main: &str) -> String {
+        let err = testing::git(&["cherry-pick", "main..side"]).unwrap_err();
+        assert!(err.to_string().contains("could not apply "));
+        assert_eq!(head(), main);
+        let (command, hash) = sequencer::pick_head(".").unwrap().unwrap();
+        assert_eq!(command, Command::Pick);
+        let sequence = Sequence::open(".").unwrap();
+        let todo = sequence.todo().unwrap();
+        assert_eq!(todo.len(), 3);
+        assert_eq!(todo[0].commit(), Some(hash.as_str()));
+        assert_eq!(sequence.read(SEQUENCE_HEAD).unwrap().as_deref(), Some(main));
+        assert!(Index::open(".").unwrap().is_unmerged());
+        hash
+    }
+
+    #[test]
+    fn it_continues_a_sequence_stopped_on_conflicts() {
+        let (_root, _cwd, main) = diverged("cherry-pick-continue");
+        let one = stop(&main);
+
+        let err = testing::git(&["cherry-pick", "--continue"]).unwrap_err();
+        assert!(err.to_string().contains("you have unmerged files"));
+        fs::write("a", "resolved\n").unwrap();
+        testing::git(&["add", "a"]).unwrap();
+        testing::git(&["cherry-pick", "--continue"]).unwrap();
+
+        assert_eq!(subjects(4), ["three", "two", "one", "main"]);
+        let picked = GitObject::open_commit(".", &ancestor(2)).unwrap();
+        assert_eq!(picked.comment(), "one\n");
+        assert_eq!(
+            picked.author(),
+            GitObject::open_commit(".", &one).unwrap().author()
+        );
+        assert_eq!(fs::read_to_string("a").unwrap(), "resolved\n");
+        assert!(Sequence::open(".").is_none());
+        assert_eq!(sequencer::pick_head(".").unwrap(), None);
+        assert!(!Path::new(GIT_DIR).join(MERGE_MSG).exists());
+    }
+
+    #[test]
+    fn it_skips_or_aborts_a_sequence_stopped_on_conflicts() {
+        let (_root, _cwd, main) = diverged("cherry-pick-skip");
+        stop(&main);
+        let err = testing::git(&["revert", "--skip"]).unwrap_err();
+        assert!(err.to_string().contains("no revert in progress"));
+        testing::git(&["cherry-pick", "--skip"]).unwrap();
+        assert_eq!(subjects(3), ["three", "two", "main"]);
+        assert_eq!(fs::read_to_string("a").unwrap(), "main\n");
+        assert!(Sequence::open(".").is_none());
+        assert_eq!(sequencer::pick_head(".").unwrap(), None);
+
+        testing::git(&["reset", "-q", "--hard", &main]).unwrap();
+        stop(&main);
+        testing::git(&["cherry-pick", "--abort"]).unwrap();
+        assert_eq!(head(), main);
+        assert_eq!(fs::read_to_string("a").unwrap(), "main\n");
+        assert!(!Path::new("b").exists());
+        assert!(!Index::open(".").unwrap().is_unmerged());
+        assert!(Sequence::open(".").is_none());
+        assert_eq!(sequencer::pick_head(".").unwrap(), None);
+        let err = testing::git(&["cherry-pick", "--abort"]).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("no cherry-pick or revert in progress"));
+    }
+
+    #[test]
+    fn it_picks_and_reverts_merges_from_a_mainline() {
+        let (_root, _cwd) = testing::command_repo("cherry-pick-mainline");
+        testing::commit(&[("a", "1\n")], "base");
+        testing::git(&["branch", "side"]).unwrap();
+        let main = testing::commit(&[("c", "c\n")], "main");
+        testing::git(&["switch", "side"]).unwrap();
+        testing::commit(&[("b", "b\n")], "side");
+        testing::git(&["switch", "main"]).unwrap();
+        testing::git(&["merge", "--no-ff", "-m", "merge side", "side"]).unwrap();
+        let merge = head();
+
+        let err = testing::git(&["revert", &merge]).unwrap_err();
+        assert!(err.to_string().ends_with(&format!(
+            "commit {merge} is a merge but no -m option was given.\nrevert failed"
+        )));
+        let err = testing::git(&["cherry-pick", "-m", "3", &merge]).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains(&format!("commit {merge} does not have parent 3")));
+
+        testing::git(&["revert", "-m", "1", &merge]).unwrap();
+        let revert = GitObject::open_commit(".", &head()).unwrap();
+        assert_eq!(
+            revert.comment(),
+            format!(
+                "Revert \"merge side\"\n\nThis reverts commit {merge}, reversing\n\
+                 changes made to {main}.\n"
+            )
+        );
+        assert_eq!(revert.parents(), std::slice::from_ref(&merge));
+        assert!(!Path::new("b").exists());
+        assert_eq!(fs::read_to_string("c").unwrap(), "c\n");
+
+        testing::git(&["cherry-pick", "--mainline", "1", &merge]).unwrap();
+        assert_eq!(fs::read_to_string("b").unwrap(), "b\n");
+        assert_eq!(subjects(1), ["merge side"]);
+        let picked = GitObject::open_commit(".", &head()).unwrap();
+        assert_eq!(picked.parents().len(), 1);
+    }
+
+    #[test]
+    fn it_stages_the_changes_without_committing_them() {
+        let (_root, _cwd, main) = diverged("cherry-pick-no-commit");
+        testing::git(&["cherry-pick", "-n", "side~1", "side"]).unwrap();
+        assert_eq!(head(), main);
+        assert_eq!(
+            (testing::staged("b"), testing::staged("c")),
+            (testing::blob("b\n"), testing::blob("c\n"))
+        );
+        assert_eq!(sequencer::pick_head(".").unwrap(), None);
+        assert!(Sequence::open(".").is_none());
+        assert_eq!(
+            fs::read_to_string(Path::new(GIT_DIR).join(MERGE_MSG)).unwrap(),
+            "three\n"
+        );
+
+        testing::git(&["reset", "-q", "--hard"]).unwrap();
+        let two = revision::resolve(".", "side~1").unwrap();
+        testing::git(&["cherry-pick", &two]).unwrap();
+        let picked = head();
+        testing::git(&["revert", "--no-commit", &picked]).unwrap();
+        assert_eq!(head(), picked);
+        assert_eq!(testing::staged("b"), None);
+        assert_eq!(
+            sequencer::pick_head(".").unwrap(),
+            Some((Command::Revert, picked.clone()))
+        );
+        assert_eq!(
+            fs::read_to_string(Path::new(GIT_DIR).join(MERGE_MSG)).unwrap(),
+            format!("Revert \"two\"\n\nThis reverts commit {picked}.\n")
+        );
+    }
+}
@@ -1,15 +1,25 @@
 use super::{
-    git_protocol::{PackFile, PktLine, PktLines},
-    tree::FileTree,
-    Error, Result,
+    git_protocol::{Advertisement, PackFile, PackIndex, PktLine, PktLines, RemoteRef},
+    ident,
+    index::Index,
+    refs::{self, Expected, Head, PackedRef, PackedRefs, HEAD},
+    revision, Error, Result, GIT_PACK_DIR,
+};
+use crate::{
+    config::{ConfigFile, Scope},
+    reflog,
 };
 use bytes::Bytes;
 use futures_util::{Stream, StreamExt};
 use reqwest::header::{HeaderValue, CONTENT_TYPE};
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const REMOTE_PREFIX: &str = "refs/remotes/origin/";
+const REMOTE_FETCH: &str = "+refs/heads/*:refs/remotes/origin/*";
 
 pub async fn run(url: String, dir: String) -> Result<()> {
-    println!("url: {url}, dir: {dir}");
+    eprintln!("Cloning into '{dir}'...");
     let root_dir: PathBuf = format!("./{dir}").into();
     if !root_dir.exists() {
         std::fs::create_dir(&root_dir)?;
@@ -18,17 +28,29 @@ pub async fn run(url: String, dir: String) -> Result<()> {
 
     let res = reqwest::get(format!("{url}/info/refs?service=git-upload-pack"))
         .await?
+        .error_for_status()?
         .bytes()
         .await?;
+    let advertisement = Advertisement::parse(PktLines::from(res))?;
+    if advertisement.refs().is_empty() {
+        write_remote_config(&root_dir, &url, None)?;
+        eprintln!("warning: You appear to have cloned an empty repository.");
+        return Ok(());
+    }
 
-    let lines = PktLines::from(res);
-    let master_ref = get_master_ref(lines).ok_or(Error::from("Cannot find refs/heads/master"))?;
-
-    println!("refs/heads/master: {master_ref}");
+    let mut wants: Vec<&str> = advertisement.refs().iter().map(RemoteRef::hash).collect();
+    wants.sort();
+    wants.dedup();
+    let mut request = String::new();
+    for (i, hash) in wants.iter().enumerate() {
+        let line = match i {
+            0 => format!("want {hash} multi_ack_detailed side-band-64k ofs-delta\n"),
+            _ => format!("want {hash}\n"),
+        };
+        request.push_str(&PktLine::new(line.into_bytes()).to_string());
+    }
 
     let client = reqwest::Client::new();
-    //let mut objects: Vec<GitObject> = vec![];
-
     let stream = client
         .post(format!("{url}/git-upload-pack"))
         .header(
@@ -36,18 +58,14 @@ pub async fn run(url: String, dir: String) -> Result<()> {
             HeaderValue::from_static("application/x-git-upload-pack-request"),
         )
         .body(format!(
-            "{}{}{}{}",
-            PktLine::new(
-                format!("want {master_ref} multi_ack_detailed side-band-64k\n").into_bytes()
-            ),
-            PktLine::new(format!("want {master_ref}\n").into_bytes()),
+            "{request}{}{}",
             PktLine::flush(),
             PktLine::new(b"done\n".into()),
         ))
         .send()
         .await?
+        .error_for_status()?
         .bytes_stream();
-
     let mut pkt_stream = PktLineStream::new(stream);
     let mut packed_bytes: Vec<u8> = vec![];
 
@@ -65,10 +83,7 @@ pub async fn run(url: String, dir: String) -> Result<()> {
                     println!("{progress}")
                 }
             }
-            None => {
-                println!("[remote] Reached flush line!");
-                break;
-            }
+            None => break,
             _ => {
                 return Err(Error::Other(anyhow::anyhow!(
                     "Unexpected pkt line during unpacking pack file. {line}",
@@ -77,22 +92,85 @@ pub async fn run(url: String, dir: String) -> Result<()> {
         }
     }
 
-    let objects = PackFile::get_objects(packed_bytes);
+    let pack_checksum = PackFile::checksum(&packed_bytes)?;
+    let objects = PackFile::get_objects(packed_bytes.clone())?;
+    let index = PackIndex::new(&objects, pack_checksum);
+    write_pack(&root_dir, &index, &packed_bytes)?;
+
+    let head = write_refs(&root_dir, &url, &advertisement)?;
+    let Some(hash) = head else {
+        eprintln!("warning: remote HEAD refers to nonexistent ref, unable to checkout");
+        return Ok(());
+    };
+    let tree = revision::resolve_tree(&root_dir, &hash)?;
+    let mut index = Index::default();
+    crate::checkout::switch(&root_dir, &mut index, None, &tree, false)?;
+    index.write(&root_dir)
+}
+
+// NOTE:
+// The remote branches and tags go into "packed-refs" at once, and
+// only the branch checked out is created as a loose ref. Returns the commit to
+// check out, if the remote HEAD points to a branch.
+fn write_refs(root: &Path, url: &str, advertisement: &Advertisement) -> Result<Option<String>> {
+    let mut packed = PackedRefs::read(root)?;
+    for remote in advertisement.refs() {
+        let name = match remote.name().strip_prefix("refs/heads/") {
+            Some(branch) => format!("{REMOTE_PREFIX}{branch}"),
+            None if remote.name().starts_with("refs/tags/") => remote.name().to_string(),
+            None => continue,
+        };
+        let peeled = remote.peeled().map(String::from);
+        packed.insert(PackedRef::new(name, remote.hash().into(), peeled));
+    }
+    packed.write(root)?;
+
+    let head = advertisement.head();
+    write_remote_config(root, url, head)?;
+    let Some(refname) = head else {
+        return Ok(None);
+    };
+    let Some(remote) = advertisement.refs().iter().find(|r| r.name() == refname) else {
+        return Ok(None);
+    };
 
-    for object in objects.iter() {
-        object.write(&root_dir)?;
+    let hash = remote.hash();
+    let committer = ident::reflog_committer(root)?;
+    let message = format!("clone: from {url}");
+    let remote_head = format!("{REMOTE_PREFIX}{HEAD}");
+    let tracking = format!("{REMOTE_PREFIX}{}", refs::short_name(refname));
+    refs::update(root, refname, hash, Expected::Missing)?;
+    refs::write_symbolic(root, &remote_head, &tracking)?;
+    refs::write_head(root, &Head::Branch(refname.into()))?;
+    for name in [refname, &remote_head, HEAD] {
+        reflog::append(root, name, None, hash, &committer, &message)?;
     }
+    Ok(Some(hash.to_string()))
+}
 
-    let tree = FileTree::new(root_dir, &objects);
-    tree.write_all()
+fn write_remote_config(root: &Path, url: &str, head: Option<&str>) -> Result<()> {
+    let mut config = ConfigFile::open(Scope::Local.path(root)?)?;
+    config.set("remote.origin.url", url)?;
+    config.set("remote.origin.fetch", REMOTE_FETCH)?;
+    if let Some(refname) = head {
+        let branch = refs::short_name(refname);
+        config.set(&format!("branch.{branch}.remote"), "origin")?;
+        config.set(&format!("branch.{branch}.merge"), refname)?;
+    }
+    config.write()
 }
 
-fn get_master_ref(lines: PktLines) -> Option<String> {
-    lines
-        .into_iter()
-        .map(|line| format!("{line}"))
-        .find(|line| line.contains("refs/heads/master"))
-        .map(|line| line[4..44].to_string())
+// NOTE:
+// The received pack is kept as it is with its index, instead of exploding it
+// into loose objects, the same as `git clone` does.
+fn write_pack<P: AsRef<Path>>(root: P, index: &PackIndex, bytes: &[u8]) -> Result<()> {
+    let pack_dir = root.as_ref().join(GIT_PACK_DIR);
+    fs::create_dir_all(&pack_dir)?;
+
+    let name = index.pack_name();
+    fs::write(pack_dir.join(format!("{name}.pack")), bytes)?;
+    fs::write(pack_dir.join(format!("{name}.idx")), index.serialize())?;
+    Ok(())
 }
 
 fn format_progress(bytes: &[u8]) -> Vec<String> {
@@ -143,3 +221,58 @@ where
         Ok(None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn pkt(line: &str) -> String {
+        format!("{:04x}{line}", line.len() + 4)
+    }
+
+    #[test]
+    fn it_writes_the_remote_branches_and_tags() {
+        let (_root, _cwd) = testing::command_repo("clone-refs");
+        let commit = testing::commit(&[("a", "1\n")], "first");
+        testing::git(&["tag", "-m", "One", "v1"]).unwrap();
+        let tag = refs::resolve(".", "refs/tags/v1").unwrap().unwrap();
+        testing::git(&["tag", "-d", "v1"]).unwrap();
+
+        let text = [
+            pkt("# service=git-upload-pack\n"),
+            "0000".into(),
+            pkt(&format!("{commit} HEAD\0symref=HEAD:refs/heads/trunk\n")),
+            pkt(&format!("{commit} refs/heads/trunk\n")),
+            pkt(&format!("{tag} refs/tags/v1\n")),
+            pkt(&format!("{commit} refs/tags/v1^{{}}\n")),
+            "0000".into(),
+        ]
+        .concat();
+        let advertisement = Advertisement::parse(PktLines::new(text.into_bytes())).unwrap();
+
+        let url = "https://example.com/repo.git";
+        let head = write_refs(Path::new("."), url, &advertisement).unwrap();
+        assert_eq!(head, Some(commit.clone()));
+        assert_eq!(
+            refs::head(".").unwrap(),
+            Head::Branch("refs/heads/trunk".into())
+        );
+
+        let packed = PackedRefs::read(".").unwrap();
+        let v1 = packed.find("refs/tags/v1").unwrap();
+        assert_eq!(
+            (v1.hash(), v1.peeled()),
+            (tag.as_str(), Some(commit.as_str()))
+        );
+        assert!(packed.find("refs/remotes/origin/trunk").is_some());
+        assert_eq!(refs::resolve(".", "refs/tags/v1").unwrap(), Some(tag));
+        assert_eq!(revision::resolve_commit(".", "v1").unwrap(), commit);
+
+        testing::git(&["tag", "-d", "v1"]).unwrap();
+        assert!(PackedRefs::read(".")
+            .unwrap()
+            .find("refs/tags/v1")
+            .is_none());
+    }
+}
@@ -0,0 +1,196 @@
+use super::{
+    git_object::commit::Commit,
+    ident,
+    index::Index,
+    pretty,
+    refs::{self, Expected, HEAD},
+    worktree, Error, GitObject, Result,
+};
+use crate::{
+    merge, reflog,
+    sequencer::{self, Command},
+};
+use std::fs;
+
+pub(crate) fn run(
+    message: Option<String>,
+    all: bool,
+    amend: bool,
+    allow_empty: bool,
+) -> Result<()> {
+    let mut index = Index::lock(".")?;
+    if all {
+        stage_tracked(&mut index)?;
+    }
+    if index.is_unmerged() {
+        return Err(Error::from(
+            "Committing is not possible because you have unmerged files.",
+        ));
+    }
+
+    let head = refs::head(".")?;
+    let head_hash = refs::resolve(".", HEAD)?;
+    let merge_head = merge::merge_head(".")?;
+    if amend && merge_head.is_some() {
+        return Err(Error::from(
+            "You are in the middle of a merge -- cannot amend.",
+        ));
+    }
+    let pick_head = sequencer::pick_head(".")?;
+    if amend && matches!(pick_head, Some((Command::Pick, _))) {
+        return Err(Error::from(
+            "You are in the middle of a cherry-pick -- cannot amend.",
+        ));
+    }
+    let head_commit: Option<Commit> = head_hash
+        .as_ref()
+        .map(|hash| GitObject::open_commit(".", hash))
+        .transpose()?;
+
+    // NOTE:
+    // Amending keeps the original author and only replaces the committer.
+    // Concluding a merge adds the merged commit as a parent, and takes the
+    // message prepared by the merge without its comments when none is given.
+    // Concluding a cherry-pick keeps the author of the picked commit.
+    let (parents, message, author) = if amend {
+        let commit = head_commit
+            .as_ref()
+            .ok_or(Error::from("You have nothing to amend."))?;
+        let message = message.unwrap_or_else(|| commit.comment().into());
+        (commit.parents().to_vec(), message, commit.author().clone())
+    } else {
+        let message = match message {
+            Some(message) => message,
+            None => {
+                let message =
+                    merge::merge_message(".")?.ok_or(Error::from("argument \"m\" is required"))?;
+                let lines: Vec<&str> = message.lines().filter(|l| !l.starts_with('#')).collect();
+                pretty::cleanup(&lines.join("\n"))
+            }
+        };
+        let author = match pick_head.as_ref() {
+            Some((Command::Pick, hash)) => GitObject::open_commit(".", hash)?.author().clone(),
+            _ => ident::author(".")?,
+        };
+        (
+            head_hash.iter().chain(merge_head.iter()).cloned().collect(),
+            message,
+            author,
+        )
+    };
+    let committer = ident::committer(".")?;
+
+    let tree = index.write_tree(".")?;
+    let index = index.commit()?;
+
+    if !allow_empty && !amend && merge_head.is_none() {
+        let unchanged = match head_commit.as_ref() {
+            Some(commit) => commit.tree() == tree.hash().hex(),
+            None => index.entries().is_empty(),
+        };
+        if unchanged {
+            return Err(Error::from("nothing to commit, working tree clean"));
+        }
+    }
+
+    let obj = GitObject::new_commit(tree.hash().hex(), message, parents, author, committer)?;
+    obj.write(".")?;
+    let hash = obj.hash().hex();
+    let GitObject::Commit(ref commit) = obj else {
+        unreachable!()
+    };
+
+    let expected = match head_hash.as_deref() {
+        Some(old) => Expected::Hash(old),
+        None => Expected::Missing,
+    };
+    refs::update(".", head.target(), &hash, expected)?;
+
+    let kind = if amend {
+        "commit (amend)"
+    } else if merge_head.is_some() {
+        "commit (merge)"
+    } else if matches!(pick_head, Some((Command::Pick, _))) {
+        "commit (cherry-pick)"
+    } else if head_hash.is_none() {
+        "commit (initial)"
+    } else {
+        "commit"
+    };
+    let log_message = format!("{kind}: {}", commit.summary());
+    let mut logged_refs = vec![head.target()];
+    if head.target() != HEAD {
+        logged_refs.push(HEAD);
+    }
+    for refname in logged_refs {
+        reflog::append(
+            ".",
+            refname,
+            head_hash.as_deref(),
+            &hash,
+            commit.committer(),
+            &log_message,
+        )?;
+    }
+    merge::clear_state(".")?;
+    sequencer::clear_pick_head(".")?;
+
+    println!(
+        "[{}{} {}] {}",
+        head.branch_name().unwrap_or("detached HEAD"),
+        if commit.parents().is_empty() {
+            " (root-commit)"
+        } else {
+            ""
+        },
+        &hash[..7],
+        commit.summary()
+    );
+    Ok(())
+}
+
+// NOTE:
+// `commit -a` stages the modified and deleted files that are already tracked,
+// but not the untracked ones.
+fn stage_tracked(index: &mut Index) -> Result<()> {
+    let paths: Vec<String> = index
+        .entries()
+        .iter()
+        .map(|e| e.path().to_string())
+        .collect();
+
+    for path in paths {
+        if fs::symlink_metadata(&path).is_ok() {
+            index.add(worktree::stage(".", path)?);
+        } else {
+            index.remove(&path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn it_commits_tracked_changes_with_clustered_flags() {
+        let (_root, _cwd) = testing::command_repo("commit-am");
+        let first = testing::commit(&[("a", "1\n")], "init");
+        fs::write("a", "2\n").unwrap();
+        fs::write("untracked", "u\n").unwrap();
+
+        testing::git(&["commit", "-am", "second"]).unwrap();
+        let head = refs::resolve(".", HEAD).unwrap().unwrap();
+        let commit = GitObject::open_commit(".", &head).unwrap();
+        assert_eq!(commit.summary(), "second");
+        assert_eq!(commit.parents(), [first]);
+        let a = GitObject::find_in_tree(".", commit.tree(), "a").unwrap();
+        let blob = GitObject::new_blob(&b"2\n"[..]).unwrap();
+        assert_eq!(a.unwrap().hash(), blob.hash());
+        assert!(GitObject::find_in_tree(".", commit.tree(), "untracked")
+            .unwrap()
+            .is_none());
+    }
+}
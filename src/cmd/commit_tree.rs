@@ -1,7 +1,17 @@
-use super::{GitObject, Result};
+use super::{ident, revision, GitObject, Result};
 
 pub fn run(tree: String, comment: String, parent: Option<String>) -> Result<()> {
-    let obj = GitObject::new_commit(tree, comment, parent.into_iter().collect())?;
+    let tree = revision::resolve_tree(".", &tree)?;
+    let parents = parent
+        .map(|parent| revision::resolve_commit(".", &parent))
+        .transpose()?;
+    let obj = GitObject::new_commit(
+        tree,
+        comment,
+        parents.into_iter().collect(),
+        ident::author(".")?,
+        ident::committer(".")?,
+    )?;
     print!("{}", obj.hash().hex());
     obj.write(".")
 }
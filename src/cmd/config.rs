@@ -0,0 +1,91 @@
+use super::{Error, Result};
+pub use crate::config::Scope;
+use crate::config::{Config, ConfigFile};
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Action {
+    List,
+    Get { key: String },
+    GetAll { key: String },
+    Set { key: String, value: String },
+    Add { key: String, value: String },
+    Unset { key: String, all: bool },
+}
+
+// NOTE:
+// Which files to read from or write to. Reading without a location reads every
+// scope, and writing without one writes to the repository file.
+#[derive(Debug)]
+pub enum Location {
+    Default,
+    Scope(Scope),
+    File(PathBuf),
+}
+
+impl Location {
+    fn read(&self) -> Result<Config> {
+        match self {
+            Self::Default => Config::load("."),
+            Self::Scope(scope) => Config::load_scope(".", *scope),
+            Self::File(path) => Config::load_file(".", path),
+        }
+    }
+
+    fn open(&self) -> Result<ConfigFile> {
+        match self {
+            Self::Default => ConfigFile::open(Scope::Local.path(".")?),
+            Self::Scope(scope) => ConfigFile::open(scope.path(".")?),
+            Self::File(path) => ConfigFile::open(path),
+        }
+    }
+}
+
+pub(crate) fn run(action: Action, location: Location) -> Result<()> {
+    match action {
+        Action::List => {
+            for entry in location.read()?.entries() {
+                match entry.value() {
+                    Some(value) => println!("{}={value}", entry.key()),
+                    None => println!("{}", entry.key()),
+                }
+            }
+        }
+        Action::Get { key } => {
+            let config = location.read()?;
+            let value = config.get(&key).ok_or_else(|| not_found(&key))?;
+            println!("{value}");
+        }
+        Action::GetAll { key } => {
+            let config = location.read()?;
+            let values = config.get_all(&key);
+            if values.is_empty() {
+                return Err(not_found(&key));
+            }
+            for value in values {
+                println!("{value}");
+            }
+        }
+        Action::Set { key, value } => {
+            let mut file = location.open()?;
+            file.set(&key, &value)?;
+            file.write()?;
+        }
+        Action::Add { key, value } => {
+            let mut file = location.open()?;
+            file.add(&key, &value)?;
+            file.write()?;
+        }
+        Action::Unset { key, all } => {
+            let mut file = location.open()?;
+            file.unset(&key, all)?;
+            file.write()?;
+        }
+    }
+    Ok(())
+}
+
+fn not_found(key: &str) -> Error {
+    let msg = format!("key not found: {key}");
+    Error::from(msg.as_str())
+}
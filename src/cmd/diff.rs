@@ -0,0 +1,106 @@
+use super::{
+    refs::{self, HEAD},
+    revision::{self, Rev},
+    Error, Result,
+};
+use crate::diff::{
+    files::{self, Source},
+    patch,
+    rename::{self, Detection},
+    Algorithm,
+};
+use std::io::{self, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    Patch(patch::Format),
+    Stat,
+    NameOnly,
+}
+
+// NOTE:
+// Without revisions the index is compared with the working tree, or HEAD
+// with the index when `cached`. One revision replaces HEAD or the index, and
+// two revisions (or a range) are compared with each other. The first
+// argument that isn't a revision but exists on disk starts the paths.
+// Renames follow "diff.renames" unless `renames` is given.
+pub(crate) fn run(
+    revs: Vec<String>,
+    cached: bool,
+    mut paths: Vec<String>,
+    output: Output,
+    (context, algorithm): (usize, Algorithm),
+    renames: Option<Option<Detection>>,
+) -> Result<()> {
+    let mut trees: Vec<String> = vec![];
+    for (i, rev) in revs.iter().enumerate() {
+        if rev.contains("..") {
+            let revs = revision::parse_revs(".", rev)?;
+            let old = revs.iter().find_map(|rev| match rev {
+                Rev::Exclude(hash) => Some(hash),
+                Rev::Include(_) => None,
+            });
+            let new = revs.iter().find_map(|rev| match rev {
+                Rev::Include(hash) => Some(hash),
+                Rev::Exclude(_) => None,
+            });
+            for hash in old.into_iter().chain(new) {
+                trees.push(revision::resolve_tree(".", hash)?);
+            }
+            continue;
+        }
+        match revision::resolve_tree(".", rev) {
+            Ok(tree) => trees.push(tree),
+            Err(_) if Path::new(rev).exists() => {
+                paths.splice(0..0, revs[i..].iter().cloned());
+                break;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    let (old, new) = match (trees.as_slice(), cached) {
+        ([], false) => (Source::Index, Source::Worktree),
+        ([], true) => (head_tree()?, Source::Index),
+        ([tree], false) => (Source::Tree(tree.clone()), Source::Worktree),
+        ([tree], true) => (Source::Tree(tree.clone()), Source::Index),
+        ([old, new], false) => (Source::Tree(old.clone()), Source::Tree(new.clone())),
+        _ => {
+            return Err(Error::from(
+                "usage: git diff [<options>] [<commit> [<commit>]] [--] [<path>...]",
+            ))
+        }
+    };
+
+    let mut pairs = files::pairs(".", &old, &new, &paths)?;
+    let renames = match renames {
+        Some(renames) => renames,
+        None => Detection::configured(".", &["diff.renames"])?,
+    };
+    if let Some(detection) = renames {
+        pairs = rename::detect(".", pairs, detection)?;
+    }
+    let mut out = io::stdout().lock();
+    let result = match output {
+        Output::Patch(format) => pairs.iter().try_for_each(|pair| {
+            patch::write_patch(&mut out, ".", pair, format, context, algorithm)
+        }),
+        Output::Stat if pairs.is_empty() => Ok(()),
+        Output::Stat => patch::write_stat(&mut out, ".", &pairs, algorithm),
+        Output::NameOnly => pairs
+            .iter()
+            .try_for_each(|pair| Ok(writeln!(out, "{}", pair.path())?)),
+    };
+    match result {
+        Err(Error::Io(err)) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+fn head_tree() -> Result<Source> {
+    match refs::resolve(".", HEAD)? {
+        Some(hash) => Ok(Source::Tree(revision::resolve_tree(".", &hash)?)),
+        None => Ok(Source::Empty),
+    }
+}
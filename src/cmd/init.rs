@@ -1,12 +1,17 @@
 use super::{Result, GIT_DIR, GIT_OBJ_DIR, GIT_REF_DIR};
 use std::{fs, path::Path};
 
+// NOTE:
+// The same defaults stock git writes, so that it can read the repository too.
+const DEFAULT_CONFIG: &str = "[core]\n\trepositoryformatversion = 0\n\tfilemode = true\n\tbare = false\n\tlogallrefupdates = true\n";
+
 pub(crate) fn run<P: AsRef<Path>>(root: P) -> Result<()> {
     let path = root.as_ref();
     fs::create_dir(path.join(GIT_DIR))?;
     fs::create_dir(path.join(GIT_OBJ_DIR))?;
     fs::create_dir(path.join(GIT_REF_DIR))?;
     fs::write(path.join(GIT_DIR).join("HEAD"), "ref: refs/heads/main\n")?;
+    fs::write(path.join(GIT_DIR).join("config"), DEFAULT_CONFIG)?;
     println!("Initialized git directory");
     Ok(())
 }
@@ -0,0 +1,130 @@
+use super::{
+    git_object::commit::Commit,
+    graph::Graph,
+    history::{self, RevWalk},
+    pretty::Format,
+    refs::{self, HEAD},
+    revision::{self, Rev},
+    worktree, Error, Result,
+};
+use std::io::{self, Write};
+use std::path::Path;
+
+// NOTE:
+// The first argument that isn't a revision but exists on disk starts the
+// paths.
+pub(crate) fn run(
+    mut revs: Vec<String>,
+    max_count: Option<usize>,
+    format: Format,
+    graph: bool,
+    follow: bool,
+    mut paths: Vec<String>,
+) -> Result<()> {
+    if let Some(pos) = revs
+        .iter()
+        .position(|rev| revision::parse_revs(".", rev).is_err() && Path::new(rev).exists())
+    {
+        paths.splice(0..0, revs.drain(pos..));
+    }
+    if follow && paths.len() != 1 {
+        return Err(Error::from("--follow requires exactly one pathspec"));
+    }
+    let paths: Vec<String> = paths.iter().map(|p| worktree::normalize(p)).collect();
+    let mut walk = RevWalk::new(".").paths(paths).follow(follow);
+    let (mut include, mut exclude): (Vec<String>, Vec<String>) = (vec![], vec![]);
+    for rev in revs.iter() {
+        for rev in revision::parse_revs(".", rev)? {
+            match rev {
+                Rev::Include(hash) => include.push(revision::resolve_commit(".", &hash)?),
+                Rev::Exclude(hash) => exclude.push(hash),
+            }
+        }
+    }
+    if revs.is_empty() {
+        include.push(head()?);
+    }
+    for hash in exclude.iter() {
+        walk.hide(hash)?;
+    }
+    for hash in include.iter() {
+        walk.push(hash)?;
+    }
+    let max_count = max_count.unwrap_or(usize::MAX);
+
+    let result = if graph {
+        print_graph(walk, max_count, &format)
+    } else {
+        print_log(walk, max_count, &format)
+    };
+    match result {
+        Err(Error::Io(err)) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+fn print_log(walk: RevWalk, max_count: usize, format: &Format) -> Result<()> {
+    let mut out = io::stdout().lock();
+    for (i, entry) in walk.take(max_count).enumerate() {
+        let (hash, commit) = entry?;
+        if i > 0 {
+            write!(out, "{}", format.separator())?;
+        }
+        write!(
+            out,
+            "{}{}",
+            format.render(&hash, &commit),
+            format.terminator()
+        )?;
+    }
+    Ok(())
+}
+
+// NOTE:
+// The graph needs the whole history to show children before their parents.
+fn print_graph(mut walk: RevWalk, max_count: usize, format: &Format) -> Result<()> {
+    let commits: Vec<(String, Commit)> = walk.by_ref().collect::<Result<_>>()?;
+    let links: Vec<(String, Vec<String>)> = commits
+        .iter()
+        .map(|(hash, commit)| (hash.clone(), walk.rewrite_parents(commit.parents())))
+        .collect();
+    let order: Vec<usize> = history::topo_order(&links)
+        .into_iter()
+        .take(max_count)
+        .collect();
+
+    let mut out = io::stdout().lock();
+    let mut graph = Graph::new();
+    for (n, &i) in order.iter().enumerate() {
+        let (hash, commit) = &commits[i];
+        let mut text = format.render(hash, commit);
+        if n + 1 < order.len() {
+            text.push_str(format.separator());
+        }
+
+        for (k, line) in text.split('\n').enumerate() {
+            let prefix = if k == 0 {
+                graph.commit(hash, &links[i].1)
+            } else {
+                graph.next_prefix()
+            };
+            writeln!(out, "{prefix}{line}")?;
+        }
+        for line in graph.flush() {
+            writeln!(out, "{line}")?;
+        }
+    }
+    Ok(())
+}
+
+fn head() -> Result<String> {
+    if let Some(hash) = refs::resolve(".", HEAD)? {
+        return Ok(hash);
+    }
+    let head = refs::head(".")?;
+    let msg = format!(
+        "your current branch '{}' does not have any commits yet",
+        head.branch_name().unwrap_or(HEAD)
+    );
+    Err(Error::from(msg.as_str()))
+}
@@ -1,6 +1,7 @@
-use super::{GitObject, Result};
+use super::{revision, GitObject, Result};
 
-pub fn run(hash: String, name_only: bool) -> Result<()> {
+pub fn run(rev: String, name_only: bool) -> Result<()> {
+    let hash = revision::resolve_tree(".", &rev)?;
     let obj = GitObject::open_from_hash(".", &hash)?;
     for tree in obj.print_trees(name_only) {
         println!("{tree}");
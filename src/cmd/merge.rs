@@ -0,0 +1,355 @@
+use super::{
+    history, ident,
+    index::Index,
+    pretty,
+    refs::{self, Expected, Head, HEAD},
+    revision, Error, GitObject, Result,
+};
+use crate::{
+    checkout::{self, Operation},
+    diff::{
+        files::{self, Source},
+        patch,
+        rename::{self, Detection},
+        Algorithm,
+    },
+    merge::{self, ORIG_HEAD},
+    reflog,
+};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+const STRATEGY: &str = "ort";
+const SIGNATURE_START: &str = "-----BEGIN PGP SIGNATURE-----";
+const VIRTUAL_LABELS: (&str, &str) = ("Temporary merge branch 1", "Temporary merge branch 2");
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FastForward {
+    #[default]
+    Allow,
+    Never,
+    Only,
+}
+
+pub(crate) fn run(
+    rev: Option<String>,
+    message: Option<String>,
+    fast_forward: FastForward,
+    abort: bool,
+) -> Result<()> {
+    if abort {
+        return abort_merge();
+    }
+    if merge::merge_head(".")?.is_some() {
+        return Err(Error::from(
+            "You have not concluded your merge (MERGE_HEAD exists).\n\
+             Please, commit your changes before you merge.",
+        ));
+    }
+    let mut index = Index::lock(".")?;
+    if index.is_unmerged() {
+        return Err(Error::from(
+            "Merging is not possible because you have unmerged files.",
+        ));
+    }
+
+    let rev = rev.ok_or(Error::from("No remote for the current branch."))?;
+    let theirs = revision::resolve_commit(".", &rev).map_err(|_| {
+        let msg = format!("merge: {rev} - not something we can merge");
+        Error::from(msg.as_str())
+    })?;
+    let head = refs::head(".")?;
+    let Some(ours) = refs::resolve(".", HEAD)? else {
+        // NOTE:
+        // Merging into an unborn branch only checks the commit out.
+        let tree = revision::resolve_tree(".", &theirs)?;
+        checkout::fast_forward(".", &mut index, None, &tree)?;
+        index.commit()?;
+        return update_head(&head, None, &theirs, &format!("merge {rev}: Fast-forward"));
+    };
+    refs::write(".", ORIG_HEAD, &ours)?;
+
+    let bases = history::merge_bases(".", &ours, &theirs)?;
+    if bases.is_empty() {
+        return Err(Error::from("refusing to merge unrelated histories"));
+    }
+    if bases.contains(&theirs) {
+        println!("Already up to date.");
+        return Ok(());
+    }
+    if bases.contains(&ours) && fast_forward != FastForward::Never {
+        println!(
+            "Updating {}..{}",
+            revision::abbrev(".", &ours, None)?,
+            revision::abbrev(".", &theirs, None)?
+        );
+        let (old_tree, new_tree) = (
+            revision::resolve_tree(".", &ours)?,
+            revision::resolve_tree(".", &theirs)?,
+        );
+        checkout::fast_forward(".", &mut index, Some(&old_tree), &new_tree)?;
+        index.commit()?;
+        println!("Fast-forward");
+        update_head(
+            &head,
+            Some(&ours),
+            &theirs,
+            &format!("merge {rev}: Fast-forward"),
+        )?;
+        return print_stat(&old_tree, &new_tree);
+    }
+    if fast_forward == FastForward::Only {
+        return Err(Error::from("Not possible to fast-forward, aborting."));
+    }
+
+    let ours_tree = revision::resolve_tree(".", &ours)?;
+    let staged = staged_changes(&index, &ours_tree)?;
+    if !staged.is_empty() {
+        // NOTE:
+        // git logs putting HEAD back after the failed merge.
+        let committer = ident::reflog_committer(".")?;
+        let message = format!("merge {rev}: updating HEAD");
+        reflog::append(".", HEAD, Some(&ours), &ours, &committer, &message)?;
+        let msg = format!(
+            "Your local changes to the following files would be overwritten by merge:\n  {}\n\
+             Merge with strategy {STRATEGY} failed.",
+            staged.join("\n  ")
+        );
+        return Err(Error::from(msg.as_str()));
+    }
+
+    let base_tree = base_tree(&bases)?;
+    let theirs_tree = revision::resolve_tree(".", &theirs)?;
+    let result = merge::merge_trees(
+        ".",
+        Some(&base_tree),
+        &ours_tree,
+        &theirs_tree,
+        (HEAD, &rev),
+    )?;
+    let message = message.unwrap_or(default_message(&rev, &head)?);
+    merge::checkout(".", &mut index, &ours_tree, &result, Operation::Merge).map_err(
+        |err| match err {
+            Error::Other(err) => {
+                let msg = format!("{err}\nMerge with strategy {STRATEGY} failed.");
+                Error::from(msg.as_str())
+            }
+            err => err,
+        },
+    )?;
+    for message in result.messages() {
+        println!("{message}");
+    }
+
+    let conflicts = result.conflicts();
+    if !conflicts.is_empty() {
+        index.commit()?;
+        let mut message = format!("{message}\n\n# Conflicts:\n");
+        for path in conflicts {
+            message.push_str(&format!("#\t{path}\n"));
+        }
+        merge::write_state(".", &theirs, &message)?;
+        return Err(Error::from(
+            "Automatic merge failed; fix conflicts and then commit the result.",
+        ));
+    }
+
+    let tree = index.write_tree(".")?.hash().hex();
+    index.commit()?;
+    let obj = GitObject::new_commit(
+        tree.clone(),
+        message,
+        vec![ours.clone(), theirs],
+        ident::author(".")?,
+        ident::committer(".")?,
+    )?;
+    obj.write(".")?;
+    let hash = obj.hash().hex();
+    let strategy = format!("Merge made by the '{STRATEGY}' strategy.");
+    update_head(
+        &head,
+        Some(&ours),
+        &hash,
+        &format!("merge {rev}: {strategy}"),
+    )?;
+    println!("{strategy}");
+    print_stat(&ours_tree, &tree)
+}
+
+// NOTE:
+// The tree of the common ancestor. Several merge bases are merged into a
+// virtual one first, as the recursive and ort strategies do.
+fn base_tree(bases: &[String]) -> Result<String> {
+    let mut tree = revision::resolve_tree(".", &bases[0])?;
+    for other in bases[1..].iter() {
+        let inner = history::merge_bases(".", &bases[0], other)?;
+        let inner_tree = match inner.is_empty() {
+            true => None,
+            false => Some(base_tree(&inner)?),
+        };
+        let other_tree = revision::resolve_tree(".", other)?;
+        let merged = merge::merge_trees(
+            ".",
+            inner_tree.as_deref(),
+            &tree,
+            &other_tree,
+            VIRTUAL_LABELS,
+        )?;
+        tree = merged.write_tree(".")?;
+    }
+    Ok(tree)
+}
+
+// NOTE:
+// The paths whose staged version differs from the tree, which a merge
+// would overwrite.
+fn staged_changes(index: &Index, tree: &str) -> Result<Vec<String>> {
+    let files = checkout::tree_files(Path::new("."), Some(tree))?;
+    let staged: BTreeMap<String, checkout::Entry> = index
+        .entries()
+        .iter()
+        .map(|e| (e.path().to_string(), (e.mode(), e.hash())))
+        .collect();
+    let mut paths: Vec<&String> = files.keys().chain(staged.keys()).collect();
+    paths.sort();
+    paths.dedup();
+    Ok(paths
+        .into_iter()
+        .filter(|path| files.get(*path) != staged.get(*path))
+        .cloned()
+        .collect())
+}
+
+// NOTE:
+// Like git's fmt-merge-msg, the message names the merged branch, tag or
+// commit, with the message of an annotated tag as the body. An ancestor of a
+// branch is its "early part", and "into <branch>" is left out when merging
+// into main or master.
+fn default_message(rev: &str, head: &Head) -> Result<String> {
+    let (name, early) = match rev.find(['~', '^']) {
+        Some(pos) => (&rev[..pos], true),
+        None => (rev, false),
+    };
+    let refname = revision::dwim_ref(".", name)?.unwrap_or_default();
+    let mut message = if let Some(branch) = refname.strip_prefix("refs/heads/") {
+        match early {
+            true => format!("Merge branch '{branch}' (early part)"),
+            false => format!("Merge branch '{branch}'"),
+        }
+    } else if let Some(tag) = refname.strip_prefix("refs/tags/").filter(|_| !early) {
+        format!("Merge tag '{tag}'")
+    } else if let Some(branch) = refname.strip_prefix("refs/remotes/").filter(|_| !early) {
+        format!("Merge remote-tracking branch '{branch}'")
+    } else {
+        format!("Merge commit '{rev}'")
+    };
+    if let Some(branch) = head.branch_name() {
+        if branch != "main" && branch != "master" {
+            message.push_str(&format!(" into {branch}"));
+        }
+    }
+
+    if refname.starts_with("refs/tags/") && !early {
+        let hash = refs::resolve(".", &refname)?.unwrap_or_default();
+        if let GitObject::Tag(tag) = GitObject::open_from_hash(".", &hash)? {
+            let body = tag.message();
+            let body = body.find(SIGNATURE_START).map_or(body, |pos| &body[..pos]);
+            message.push_str(&format!("\n\n{}", pretty::cleanup(body).trim_end()));
+        }
+    }
+    Ok(message)
+}
+
+fn update_head(head: &Head, old: Option<&str>, new: &str, message: &str) -> Result<()> {
+    let expected = match old {
+        Some(old) => Expected::Hash(old),
+        None => Expected::Missing,
+    };
+    refs::update(".", head.target(), new, expected)?;
+    let committer = ident::reflog_committer(".")?;
+    let mut logged_refs = vec![head.target()];
+    if head.target() != HEAD {
+        logged_refs.push(HEAD);
+    }
+    for refname in logged_refs {
+        reflog::append(".", refname, old, new, &committer, message)?;
+    }
+    Ok(())
+}
+
+fn print_stat(old_tree: &str, new_tree: &str) -> Result<()> {
+    let pairs = files::pairs(
+        ".",
+        &Source::Tree(old_tree.to_string()),
+        &Source::Tree(new_tree.to_string()),
+        &[],
+    )?;
+    let pairs = rename::detect(".", pairs, Detection::renames())?;
+    if pairs.is_empty() {
+        return Ok(());
+    }
+    let mut out = io::stdout().lock();
+    patch::write_stat(&mut out, ".", &pairs, Algorithm::Myers)?;
+    patch::write_summary(&mut out, &pairs)
+}
+
+// NOTE:
+// Puts back the paths the merge changed in the index as they are in HEAD,
+// keeping the local changes to the others.
+fn abort_merge() -> Result<()> {
+    if merge::merge_head(".")?.is_none() {
+        return Err(Error::from(
+            "There is no merge to abort (MERGE_HEAD missing).",
+        ));
+    }
+    let root = Path::new(".");
+    let hash = refs::resolve(".", HEAD)?.ok_or(Error::from("HEAD does not point to a commit"))?;
+    let files = checkout::tree_files(root, Some(&revision::resolve_tree(".", &hash)?))?;
+    let mut index = Index::lock(".")?;
+    let mut paths: Vec<String> = index
+        .entries()
+        .iter()
+        .filter(|e| e.stage() != 0 || files.get(e.path()) != Some(&(e.mode(), e.hash())))
+        .map(|e| e.path().to_string())
+        .collect();
+    paths.extend(
+        files
+            .keys()
+            .filter(|path| !index.entries().iter().any(|e| e.path() == path.as_str()))
+            .cloned(),
+    );
+    paths.dedup();
+    for path in paths.iter() {
+        let staged = index
+            .entries()
+            .iter()
+            .any(|e| e.path() == path && e.stage() == 0);
+        if staged && checkout::is_modified(root, &index, path)? {
+            let msg = format!(
+                "Entry '{path}' not uptodate. Cannot merge.\n\
+                 Could not reset index file to revision 'HEAD'."
+            );
+            return Err(Error::from(msg.as_str()));
+        }
+    }
+
+    for path in paths {
+        index.remove(&path);
+        match files.get(&path) {
+            Some((mode, hash)) => index.add(checkout::write_file(root, &path, *mode, *hash)?),
+            None => crate::worktree::remove_file(root, &path)?,
+        }
+    }
+    index.commit()?;
+    let committer = ident::reflog_committer(".")?;
+    reflog::append(
+        ".",
+        HEAD,
+        Some(&hash),
+        &hash,
+        &committer,
+        "reset: moving to HEAD",
+    )?;
+    merge::clear_state(".")
+}
@@ -1,13 +1,35 @@
+mod add;
+mod branch;
 mod cat_file;
+mod checkout;
+mod cherry_pick;
 mod clone;
+mod commit;
 mod commit_tree;
+mod config;
+mod diff;
 mod hash_object;
 mod init;
+mod log;
 mod ls_tree;
+mod merge;
+mod rebase;
+mod reflog;
+mod reset;
+mod restore;
+mod rev_parse;
+mod revert;
+mod rm;
+mod stash;
+mod status;
+mod switch;
+mod tag;
 mod write_tree;
 
 use super::{
-    git_protocol, tree, Args, Error, GitObject, Result, GIT_DIR, GIT_OBJ_DIR, GIT_REF_DIR,
+    git_object, git_protocol, graph, history, ident, ignore, index, pretty, refs, revision,
+    worktree, Args, Error, GitObject, Result, Sha1Hash, GIT_DIR, GIT_OBJ_DIR, GIT_PACK_DIR,
+    GIT_REF_DIR,
 };
 
 #[derive(Debug)]
@@ -33,6 +55,105 @@ pub enum Command {
         url: String,
         dir: String,
     },
+    Add {
+        paths: Vec<String>,
+        force: bool,
+    },
+    Rm {
+        paths: Vec<String>,
+        cached: bool,
+        recursive: bool,
+        force: bool,
+    },
+    Commit {
+        message: Option<String>,
+        all: bool,
+        amend: bool,
+        allow_empty: bool,
+    },
+    Log {
+        revs: Vec<String>,
+        max_count: Option<usize>,
+        format: pretty::Format,
+        graph: bool,
+        follow: bool,
+        paths: Vec<String>,
+    },
+    Config {
+        action: config::Action,
+        location: config::Location,
+    },
+    RevParse {
+        revs: Vec<String>,
+        verify: bool,
+        short: Option<Option<usize>>,
+        abbrev_ref: bool,
+    },
+    Status {
+        format: status::Format,
+        branch: bool,
+    },
+    Diff {
+        revs: Vec<String>,
+        cached: bool,
+        paths: Vec<String>,
+        output: diff::Output,
+        context: usize,
+        algorithm: crate::diff::Algorithm,
+        renames: Option<Option<crate::diff::rename::Detection>>,
+    },
+    Checkout {
+        revs: Vec<String>,
+        paths: Option<Vec<String>>,
+        new_branch: Option<String>,
+        detach: bool,
+        force: bool,
+    },
+    Branch {
+        action: branch::Action,
+    },
+    Switch {
+        target: Option<String>,
+        new_branch: Option<String>,
+        detach: bool,
+        force: bool,
+    },
+    Reflog {
+        action: reflog::Action,
+    },
+    Tag {
+        action: tag::Action,
+    },
+    Merge {
+        rev: Option<String>,
+        message: Option<String>,
+        fast_forward: merge::FastForward,
+        abort: bool,
+    },
+    Rebase {
+        action: rebase::Action,
+    },
+    CherryPick {
+        action: cherry_pick::Action,
+    },
+    Revert {
+        action: cherry_pick::Action,
+    },
+    Reset {
+        mode: Option<reset::Mode>,
+        revs: Vec<String>,
+        paths: Option<Vec<String>>,
+        quiet: bool,
+    },
+    Restore {
+        source: Option<String>,
+        staged: bool,
+        worktree: bool,
+        paths: Vec<String>,
+    },
+    Stash {
+        action: stash::Action,
+    },
     Unknown,
 }
 
@@ -99,6 +220,693 @@ impl Command {
                     .ok_or(Error::from("position argument dir is required"))?;
                 Self::Clone { url, dir }
             }
+            Some("add") => {
+                let args = Args::builder()
+                    .flag("-f")
+                    .flag("--force")
+                    .positions(0, "paths")
+                    .build(&args[1..]);
+                let paths = args.values("paths");
+                if paths.is_empty() {
+                    return Err(Error::from("Nothing specified, nothing added."));
+                }
+                Self::Add {
+                    paths,
+                    force: args.flag("-f") || args.flag("--force"),
+                }
+            }
+            Some("rm") => {
+                let args = Args::builder()
+                    .flag("--cached")
+                    .flag("-r")
+                    .flag("-f")
+                    .flag("--force")
+                    .positions(0, "paths")
+                    .build(&args[1..]);
+                let paths = args.values("paths");
+                if paths.is_empty() {
+                    return Err(Error::from(
+                        "No pathspec was given. Which files should I remove?",
+                    ));
+                }
+                Self::Rm {
+                    paths,
+                    cached: args.flag("--cached"),
+                    recursive: args.flag("-r"),
+                    force: args.flag("-f") || args.flag("--force"),
+                }
+            }
+            Some("commit") => {
+                let args = Args::builder()
+                    .arg("-m")
+                    .flag("-a")
+                    .flag("--amend")
+                    .flag("--allow-empty")
+                    .build(&args[1..]);
+                Self::Commit {
+                    message: args.value("-m"),
+                    all: args.flag("-a"),
+                    amend: args.flag("--amend"),
+                    allow_empty: args.flag("--allow-empty"),
+                }
+            }
+            Some("log") => {
+                // NOTE:
+                // Paths come after "--", and "-<n>" is the same as "-n <n>".
+                let (options, paths) = match args[1..].iter().position(|v| v == "--") {
+                    Some(pos) => (&args[1..(pos + 1)], args[(pos + 2)..].to_vec()),
+                    None => (&args[1..], vec![]),
+                };
+                let mut options = options.to_vec();
+                let mut max_count: Option<usize> = None;
+                if let Some(pos) = options.iter().position(|v| {
+                    v.len() > 1 && v.starts_with('-') && v[1..].bytes().all(|b| b.is_ascii_digit())
+                }) {
+                    max_count = Some(options.remove(pos)[1..].parse()?);
+                }
+
+                let args = Args::builder()
+                    .arg("-n")
+                    .arg("--max-count")
+                    .arg("--format")
+                    .arg("--pretty")
+                    .flag("--oneline")
+                    .flag("--graph")
+                    .flag("--follow")
+                    .positions(0, "revs")
+                    .build(&options);
+                if let Some(n) = args.value("-n").or(args.value("--max-count")) {
+                    max_count = Some(n.parse()?);
+                }
+                let format = match args.value("--format").or(args.value("--pretty")) {
+                    Some(format) => pretty::Format::parse(&format)?,
+                    None if args.flag("--oneline") => pretty::Format::tformat("%h %s"),
+                    None => pretty::Format::Medium,
+                };
+                Self::Log {
+                    revs: args.values("revs"),
+                    max_count,
+                    format,
+                    graph: args.flag("--graph"),
+                    follow: args.flag("--follow"),
+                    paths,
+                }
+            }
+            Some("config") => {
+                let args = Args::builder()
+                    .flag("--global")
+                    .flag("--system")
+                    .flag("--local")
+                    .flag("--get")
+                    .flag("--get-all")
+                    .flag("--unset")
+                    .flag("--unset-all")
+                    .flag("--add")
+                    .flag("--list")
+                    .flag("-l")
+                    .arg("--file")
+                    .arg("-f")
+                    .position(0, "name")
+                    .position(1, "value")
+                    .build(&args[1..]);
+
+                let location = if let Some(file) = args.value("--file").or(args.value("-f")) {
+                    config::Location::File(file.into())
+                } else if args.flag("--global") {
+                    config::Location::Scope(config::Scope::Global)
+                } else if args.flag("--system") {
+                    config::Location::Scope(config::Scope::System)
+                } else if args.flag("--local") {
+                    config::Location::Scope(config::Scope::Local)
+                } else {
+                    config::Location::Default
+                };
+
+                let name = args.value("name");
+                let value = args.value("value");
+                let action = if args.flag("--list") || args.flag("-l") {
+                    config::Action::List
+                } else {
+                    let key = name.ok_or(Error::from("error: key is required"))?;
+                    let all = args.flag("--unset-all");
+                    match value {
+                        _ if args.flag("--unset") || all => config::Action::Unset { key, all },
+                        _ if args.flag("--get-all") => config::Action::GetAll { key },
+                        _ if args.flag("--get") => config::Action::Get { key },
+                        Some(value) if args.flag("--add") => config::Action::Add { key, value },
+                        Some(value) => config::Action::Set { key, value },
+                        None if args.flag("--add") => {
+                            return Err(Error::from("error: wrong number of arguments"));
+                        }
+                        None => config::Action::Get { key },
+                    }
+                };
+                Self::Config { action, location }
+            }
+            Some("rev-parse") => {
+                // NOTE:
+                // "--short" takes an optional length as "--short=<n>".
+                let mut revs: Vec<String> = vec![];
+                let mut short: Option<Option<usize>> = None;
+                for arg in &args[1..] {
+                    if arg == "--short" {
+                        short = Some(None);
+                    } else if let Some(len) = arg.strip_prefix("--short=") {
+                        short = Some(Some(len.parse()?));
+                    } else {
+                        revs.push(arg.clone());
+                    }
+                }
+
+                let args = Args::builder()
+                    .flag("--verify")
+                    .flag("--abbrev-ref")
+                    .positions(0, "revs")
+                    .build(&revs);
+                Self::RevParse {
+                    revs: args.values("revs"),
+                    verify: args.flag("--verify"),
+                    short,
+                    abbrev_ref: args.flag("--abbrev-ref"),
+                }
+            }
+            Some("status") => {
+                let mut format = status::Format::Long;
+                let mut options: Vec<String> = vec![];
+                for arg in &args[1..] {
+                    match arg.as_str() {
+                        "--porcelain" | "--porcelain=v1" => format = status::Format::PorcelainV1,
+                        "--porcelain=v2" => format = status::Format::PorcelainV2,
+                        arg if arg.starts_with("--porcelain=") => {
+                            let msg = format!("unsupported porcelain version '{}'", &arg[12..]);
+                            return Err(Error::from(msg.as_str()));
+                        }
+                        // NOTE:
+                        // Short flags can be bundled like "-sb".
+                        arg if arg.len() > 2 && !arg.starts_with("--") && arg.starts_with('-') => {
+                            options.extend(arg[1..].chars().map(|c| format!("-{c}")));
+                        }
+                        _ => options.push(arg.clone()),
+                    }
+                }
+
+                let args = Args::builder()
+                    .flag("-s")
+                    .flag("--short")
+                    .flag("-b")
+                    .flag("--branch")
+                    .build(&options);
+                if format == status::Format::Long && (args.flag("-s") || args.flag("--short")) {
+                    format = status::Format::Short;
+                }
+                Self::Status {
+                    format,
+                    branch: args.flag("-b") || args.flag("--branch"),
+                }
+            }
+            Some("diff") => {
+                // NOTE:
+                // Paths come after "--", and the context is given as "-U<n>".
+                let (options, paths) = match args[1..].iter().position(|v| v == "--") {
+                    Some(pos) => (&args[1..(pos + 1)], args[(pos + 2)..].to_vec()),
+                    None => (&args[1..], vec![]),
+                };
+                let mut options = options.to_vec();
+                let mut context = crate::diff::DEFAULT_CONTEXT;
+                if let Some(pos) = options
+                    .iter()
+                    .position(|v| v.len() > 2 && v.starts_with("-U"))
+                {
+                    context = options.remove(pos)[2..].parse()?;
+                }
+
+                // NOTE:
+                // "-M" and "-C" take an optional score as "-M<n>", and the long
+                // forms as "--find-renames=<n>". Without any of them, the
+                // detection follows "diff.renames".
+                let mut renames: Option<Option<crate::diff::rename::Detection>> = None;
+                let mut rest: Vec<String> = vec![];
+                for arg in options {
+                    let (detection, score) = if let Some(score) = arg
+                        .strip_prefix("--find-renames")
+                        .or(arg.strip_prefix("-M").filter(|_| !arg.starts_with("--")))
+                    {
+                        (crate::diff::rename::Detection::renames(), score)
+                    } else if let Some(score) = arg
+                        .strip_prefix("--find-copies")
+                        .or(arg.strip_prefix("-C").filter(|_| !arg.starts_with("--")))
+                    {
+                        (crate::diff::rename::Detection::copies(), score)
+                    } else {
+                        if arg == "--no-renames" {
+                            renames = Some(None);
+                        } else {
+                            rest.push(arg);
+                        }
+                        continue;
+                    };
+                    let score = match score.strip_prefix('=') {
+                        Some(score) => score,
+                        None if arg.starts_with("--") && !score.is_empty() => {
+                            rest.push(arg);
+                            continue;
+                        }
+                        None => score,
+                    };
+                    renames = Some(Some(if score.is_empty() {
+                        detection
+                    } else {
+                        let score = crate::diff::rename::Detection::parse_score(score)?;
+                        detection.min_score(score)
+                    }));
+                }
+                let options = rest;
+
+                let args = Args::builder()
+                    .flag("--cached")
+                    .flag("--staged")
+                    .flag("--stat")
+                    .flag("--name-only")
+                    .flag("--word-diff")
+                    .flag("--patience")
+                    .flag("--histogram")
+                    .arg("--word-diff")
+                    .arg("--unified")
+                    .arg("--diff-algorithm")
+                    .positions(0, "revs")
+                    .build(&options);
+                if let Some(n) = args.value("--unified") {
+                    context = n.parse()?;
+                }
+                let algorithm = match args.value("--diff-algorithm") {
+                    Some(name) => crate::diff::Algorithm::parse(&name)?,
+                    None if args.flag("--patience") => crate::diff::Algorithm::Patience,
+                    None if args.flag("--histogram") => crate::diff::Algorithm::Histogram,
+                    None => crate::diff::Algorithm::Myers,
+                };
+                let word_diff = match args.value("--word-diff").as_deref() {
+                    Some("plain") => true,
+                    Some(mode) => {
+                        let msg = format!("unsupported --word-diff mode '{mode}'");
+                        return Err(Error::from(msg.as_str()));
+                    }
+                    None => args.flag("--word-diff"),
+                };
+                let output = if args.flag("--stat") {
+                    diff::Output::Stat
+                } else if args.flag("--name-only") {
+                    diff::Output::NameOnly
+                } else if word_diff {
+                    diff::Output::Patch(crate::diff::patch::Format::WordDiff)
+                } else {
+                    diff::Output::Patch(crate::diff::patch::Format::Unified)
+                };
+                Self::Diff {
+                    revs: args.values("revs"),
+                    cached: args.flag("--cached") || args.flag("--staged"),
+                    paths,
+                    output,
+                    context,
+                    algorithm,
+                    renames,
+                }
+            }
+            Some("checkout") => {
+                // NOTE:
+                // Paths come after "--".
+                let (options, paths) = match args[1..].iter().position(|v| v == "--") {
+                    Some(pos) => (&args[1..(pos + 1)], Some(args[(pos + 2)..].to_vec())),
+                    None => (&args[1..], None),
+                };
+                let args = Args::builder()
+                    .arg("-b")
+                    .flag("--detach")
+                    .flag("-f")
+                    .flag("--force")
+                    .positions(0, "revs")
+                    .build(options);
+                Self::Checkout {
+                    revs: args.values("revs"),
+                    paths,
+                    new_branch: args.value("-b"),
+                    detach: args.flag("--detach"),
+                    force: args.flag("-f") || args.flag("--force"),
+                }
+            }
+            Some("branch") => {
+                // NOTE:
+                // "-u" is the same as "--set-upstream-to", and "-vv" shows the
+                // names of the upstreams too.
+                let args = Args::builder()
+                    .flag("-d")
+                    .flag("--delete")
+                    .flag("-D")
+                    .flag("-m")
+                    .flag("--move")
+                    .flag("-M")
+                    .flag("-c")
+                    .flag("--copy")
+                    .flag("-C")
+                    .flag("-f")
+                    .flag("--force")
+                    .flag("-l")
+                    .flag("--list")
+                    .flag("-v")
+                    .flag("--verbose")
+                    .flag("-vv")
+                    .arg("-u")
+                    .arg("--set-upstream-to")
+                    .positions(0, "names")
+                    .build(&args[1..]);
+                let mut names = args.values("names");
+                let force = args.flag("-f") || args.flag("--force");
+                let name_required = || Error::from("branch name required");
+
+                let action = if args.flag("-d") || args.flag("--delete") || args.flag("-D") {
+                    if names.is_empty() {
+                        return Err(name_required());
+                    }
+                    branch::Action::Delete {
+                        names,
+                        force: force || args.flag("-D"),
+                    }
+                } else if ["-m", "--move", "-M", "-c", "--copy", "-C"]
+                    .iter()
+                    .any(|flag| args.flag(flag))
+                {
+                    let to = names.pop().ok_or_else(name_required)?;
+                    if names.len() > 1 {
+                        return Err(Error::from("too many arguments for a rename operation"));
+                    }
+                    branch::Action::Move {
+                        from: names.pop(),
+                        to,
+                        force: force || args.flag("-M") || args.flag("-C"),
+                        copy: args.flag("-c") || args.flag("--copy") || args.flag("-C"),
+                    }
+                } else if let Some(upstream) = args.value("-u").or(args.value("--set-upstream-to"))
+                {
+                    branch::Action::SetUpstream {
+                        upstream,
+                        branch: names.pop(),
+                    }
+                } else if names.is_empty() || args.flag("-l") || args.flag("--list") {
+                    let verbose = if args.flag("-vv") {
+                        2
+                    } else {
+                        usize::from(args.flag("-v") || args.flag("--verbose"))
+                    };
+                    branch::Action::List {
+                        patterns: names,
+                        verbose,
+                    }
+                } else {
+                    let mut names = names.into_iter();
+                    let name = names.next().ok_or_else(name_required)?;
+                    branch::Action::Create {
+                        name,
+                        start: names.next(),
+                        force,
+                    }
+                };
+                Self::Branch { action }
+            }
+            Some("switch") => {
+                let args = Args::builder()
+                    .arg("-c")
+                    .arg("--create")
+                    .flag("-d")
+                    .flag("--detach")
+                    .flag("-f")
+                    .flag("--force")
+                    .flag("--discard-changes")
+                    .position(0, "target")
+                    .build(&args[1..]);
+                Self::Switch {
+                    target: args.value("target"),
+                    new_branch: args.value("-c").or(args.value("--create")),
+                    detach: args.flag("-d") || args.flag("--detach"),
+                    force: args.flag("-f")
+                        || args.flag("--force")
+                        || args.flag("--discard-changes"),
+                }
+            }
+            Some("reflog") => {
+                // NOTE:
+                // Without a subcommand, the arguments are the ones of "show".
+                let args = Args::builder()
+                    .flag("--all")
+                    .flag("-n")
+                    .flag("--dry-run")
+                    .arg("--expire")
+                    .arg("--expire-unreachable")
+                    .positions(0, "args")
+                    .build(&args[1..]);
+                let mut rest = args.values("args");
+                let subcommand = match rest.first().map(String::as_str) {
+                    Some("show" | "expire" | "delete") => Some(rest.remove(0)),
+                    _ => None,
+                };
+                let dry_run = args.flag("-n") || args.flag("--dry-run");
+                let action = match subcommand.as_deref() {
+                    Some("expire") => reflog::Action::Expire {
+                        refs: rest,
+                        all: args.flag("--all"),
+                        expire: args.value("--expire"),
+                        expire_unreachable: args.value("--expire-unreachable"),
+                        dry_run,
+                    },
+                    Some("delete") => reflog::Action::Delete {
+                        revs: rest,
+                        dry_run,
+                    },
+                    _ => reflog::Action::Show {
+                        rev: rest.into_iter().next(),
+                    },
+                };
+                Self::Reflog { action }
+            }
+            Some("tag") => {
+                let args = Args::builder()
+                    .flag("-a")
+                    .flag("--annotate")
+                    .flag("-d")
+                    .flag("--delete")
+                    .flag("-f")
+                    .flag("--force")
+                    .flag("-l")
+                    .flag("--list")
+                    .flag("-v")
+                    .flag("--verify")
+                    .arg("-m")
+                    .arg("--message")
+                    .positions(0, "names")
+                    .build(&args[1..]);
+                let names = args.values("names");
+                let action = if args.flag("-d") || args.flag("--delete") {
+                    tag::Action::Delete { names }
+                } else if args.flag("-v") || args.flag("--verify") {
+                    tag::Action::Verify { names }
+                } else if names.is_empty() || args.flag("-l") || args.flag("--list") {
+                    tag::Action::List { patterns: names }
+                } else {
+                    let mut names = names.into_iter();
+                    let name = names.next().expect("names must not be empty");
+                    tag::Action::Create {
+                        name,
+                        rev: names.next(),
+                        message: args.value("-m").or(args.value("--message")),
+                        annotate: args.flag("-a") || args.flag("--annotate"),
+                        force: args.flag("-f") || args.flag("--force"),
+                    }
+                };
+                Self::Tag { action }
+            }
+            Some("merge") => {
+                let args = Args::builder()
+                    .arg("-m")
+                    .flag("--ff")
+                    .flag("--no-ff")
+                    .flag("--ff-only")
+                    .flag("--abort")
+                    .position(0, "rev")
+                    .build(&args[1..]);
+                let fast_forward = if args.flag("--no-ff") {
+                    merge::FastForward::Never
+                } else if args.flag("--ff-only") {
+                    merge::FastForward::Only
+                } else {
+                    merge::FastForward::Allow
+                };
+                Self::Merge {
+                    rev: args.value("rev"),
+                    message: args.value("-m"),
+                    fast_forward,
+                    abort: args.flag("--abort"),
+                }
+            }
+            Some("rebase") => {
+                let args = Args::builder()
+                    .flag("-i")
+                    .flag("--interactive")
+                    .flag("--continue")
+                    .flag("--skip")
+                    .flag("--abort")
+                    .arg("--onto")
+                    .arg("--todo")
+                    .position(0, "upstream")
+                    .build(&args[1..]);
+                let action = if args.flag("--continue") {
+                    rebase::Action::Continue
+                } else if args.flag("--skip") {
+                    rebase::Action::Skip
+                } else if args.flag("--abort") {
+                    rebase::Action::Abort
+                } else {
+                    rebase::Action::Start {
+                        upstream: args.value("upstream"),
+                        onto: args.value("--onto"),
+                        interactive: args.flag("-i") || args.flag("--interactive"),
+                        todo: args.value("--todo"),
+                    }
+                };
+                Self::Rebase { action }
+            }
+            Some(name @ ("cherry-pick" | "revert")) => {
+                let args = Args::builder()
+                    .flag("-n")
+                    .flag("--no-commit")
+                    .flag("-x")
+                    .arg("-m")
+                    .arg("--mainline")
+                    .flag("--continue")
+                    .flag("--skip")
+                    .flag("--abort")
+                    .positions(0, "revs")
+                    .build(&args[1..]);
+                let action = if args.flag("--continue") {
+                    cherry_pick::Action::Continue
+                } else if args.flag("--skip") {
+                    cherry_pick::Action::Skip
+                } else if args.flag("--abort") {
+                    cherry_pick::Action::Abort
+                } else {
+                    let mainline = match args.value("-m").or(args.value("--mainline")) {
+                        Some(n) => Some(n.parse::<usize>().ok().filter(|&n| n > 0).ok_or(
+                            Error::from("option `mainline' expects a number greater than zero"),
+                        )?),
+                        None => None,
+                    };
+                    cherry_pick::Action::Start {
+                        revs: args.values("revs"),
+                        options: crate::sequencer::Options {
+                            no_commit: args.flag("-n") || args.flag("--no-commit"),
+                            mainline,
+                            record_origin: name == "cherry-pick" && args.flag("-x"),
+                        },
+                    }
+                };
+                match name {
+                    "revert" => Self::Revert { action },
+                    _ => Self::CherryPick { action },
+                }
+            }
+            Some("reset") => {
+                // NOTE:
+                // Paths come after "--".
+                let (options, paths) = match args[1..].iter().position(|v| v == "--") {
+                    Some(pos) => (&args[1..(pos + 1)], Some(args[(pos + 2)..].to_vec())),
+                    None => (&args[1..], None),
+                };
+                let args = Args::builder()
+                    .flag("--soft")
+                    .flag("--mixed")
+                    .flag("--hard")
+                    .flag("--keep")
+                    .flag("-q")
+                    .flag("--quiet")
+                    .positions(0, "revs")
+                    .build(options);
+                let mode = [
+                    ("--soft", reset::Mode::Soft),
+                    ("--mixed", reset::Mode::Mixed),
+                    ("--hard", reset::Mode::Hard),
+                    ("--keep", reset::Mode::Keep),
+                ]
+                .into_iter()
+                .find(|(flag, _)| args.flag(flag))
+                .map(|(_, mode)| mode);
+                Self::Reset {
+                    mode,
+                    revs: args.values("revs"),
+                    paths,
+                    quiet: args.flag("-q") || args.flag("--quiet"),
+                }
+            }
+            Some("restore") => {
+                // NOTE:
+                // Paths come after "--" too.
+                let (options, paths) = match args[1..].iter().position(|v| v == "--") {
+                    Some(pos) => (&args[1..(pos + 1)], args[(pos + 2)..].to_vec()),
+                    None => (&args[1..], vec![]),
+                };
+                let args = Args::builder()
+                    .arg("-s")
+                    .arg("--source")
+                    .flag("-S")
+                    .flag("--staged")
+                    .flag("-W")
+                    .flag("--worktree")
+                    .positions(0, "paths")
+                    .build(options);
+                Self::Restore {
+                    source: args.value("-s").or(args.value("--source")),
+                    staged: args.flag("-S") || args.flag("--staged"),
+                    worktree: args.flag("-W") || args.flag("--worktree"),
+                    paths: [args.values("paths"), paths].concat(),
+                }
+            }
+            Some("stash") => {
+                // NOTE:
+                // Without a subcommand, the arguments are the ones of "push".
+                let args = Args::builder()
+                    .arg("-m")
+                    .arg("--message")
+                    .flag("-u")
+                    .flag("--include-untracked")
+                    .flag("-q")
+                    .flag("--quiet")
+                    .flag("--index")
+                    .positions(0, "args")
+                    .build(&args[1..]);
+                let mut rest = args.values("args");
+                let subcommand = match rest.first().map(String::as_str) {
+                    Some("push" | "list" | "apply" | "pop" | "drop") => Some(rest.remove(0)),
+                    _ => None,
+                };
+                let quiet = args.flag("-q") || args.flag("--quiet");
+                let stash = rest.into_iter().next();
+                let action = match subcommand.as_deref() {
+                    Some("list") => stash::Action::List,
+                    Some("apply") => stash::Action::Apply {
+                        stash,
+                        index: args.flag("--index"),
+                        quiet,
+                    },
+                    Some("pop") => stash::Action::Pop {
+                        stash,
+                        index: args.flag("--index"),
+                        quiet,
+                    },
+                    Some("drop") => stash::Action::Drop { stash, quiet },
+                    _ => stash::Action::Push {
+                        message: args.value("-m").or(args.value("--message")),
+                        include_untracked: args.flag("-u") || args.flag("--include-untracked"),
+                        quiet,
+                    },
+                };
+                Self::Stash { action }
+            }
             _ => Self::Unknown,
         };
         Ok(cmd)
@@ -117,6 +925,82 @@ impl Command {
                 parent,
             } => commit_tree::run(tree, comment, parent),
             Self::Clone { url, dir } => clone::run(url, dir).await,
+            Self::Add { paths, force } => add::run(paths, force),
+            Self::Rm {
+                paths,
+                cached,
+                recursive,
+                force,
+            } => rm::run(paths, cached, recursive, force),
+            Self::Commit {
+                message,
+                all,
+                amend,
+                allow_empty,
+            } => commit::run(message, all, amend, allow_empty),
+            Self::Log {
+                revs,
+                max_count,
+                format,
+                graph,
+                follow,
+                paths,
+            } => log::run(revs, max_count, format, graph, follow, paths),
+            Self::Config { action, location } => config::run(action, location),
+            Self::RevParse {
+                revs,
+                verify,
+                short,
+                abbrev_ref,
+            } => rev_parse::run(revs, verify, short, abbrev_ref),
+            Self::Status { format, branch } => status::run(format, branch),
+            Self::Diff {
+                revs,
+                cached,
+                paths,
+                output,
+                context,
+                algorithm,
+                renames,
+            } => diff::run(revs, cached, paths, output, (context, algorithm), renames),
+            Self::Checkout {
+                revs,
+                paths,
+                new_branch,
+                detach,
+                force,
+            } => checkout::run(revs, paths, new_branch, detach, force),
+            Self::Branch { action } => branch::run(action),
+            Self::Reflog { action } => reflog::run(action),
+            Self::Tag { action } => tag::run(action),
+            Self::Merge {
+                rev,
+                message,
+                fast_forward,
+                abort,
+            } => merge::run(rev, message, fast_forward, abort),
+            Self::Rebase { action } => rebase::run(action),
+            Self::CherryPick { action } => cherry_pick::run(action),
+            Self::Revert { action } => revert::run(action),
+            Self::Reset {
+                mode,
+                revs,
+                paths,
+                quiet,
+            } => reset::run(mode, revs, paths, quiet),
+            Self::Restore {
+                source,
+                staged,
+                worktree,
+                paths,
+            } => restore::run(source, staged, worktree, paths),
+            Self::Stash { action } => stash::run(action),
+            Self::Switch {
+                target,
+                new_branch,
+                detach,
+                force,
+            } => switch::run(target, new_branch, detach, force),
             Self::Unknown => Err(anyhow::anyhow!("Unknown command").into()),
         }
     }
@@ -0,0 +1,987 @@
+use super::{
+    git_object::commit::{Commit, User},
+    history::{self, RevWalk},
+    ident,
+    index::Index,
+    pretty,
+    refs::{self, Expected, Head, HEAD},
+    revision, Error, GitObject, Result, GIT_DIR,
+};
+use crate::{
+    checkout, editor,
+    merge::{MERGE_MSG, ORIG_HEAD},
+    reflog,
+    sequencer::{self, Command, Instruction, Rebase, REBASE_HEAD},
+    status::Status,
+};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+const DETACHED: &str = "detached HEAD";
+const COMMIT_EDITMSG: &str = "COMMIT_EDITMSG";
+const TODO_HELP: &str = "\
+#
+# Commands:
+# p, pick <commit> = use commit
+# r, reword <commit> = use commit, but edit the commit message
+# e, edit <commit> = use commit, but stop for amending
+# s, squash <commit> = use commit, but meld into previous commit
+# f, fixup <commit> = like \"squash\" but keep only the previous
+#                    commit's log message
+# x, exec <command> = run command (the rest of the line) using shell
+# b, break = stop here (continue rebase later with 'git rebase --continue')
+# d, drop <commit> = remove commit
+#
+# These lines can be re-ordered; they are executed from top to bottom.
+#
+# If you remove a line here THAT COMMIT WILL BE LOST.
+#
+# However, if you remove everything, the rebase will be aborted.
+#
+";
+
+#[derive(Debug)]
+pub enum Action {
+    Start {
+        upstream: Option<String>,
+        onto: Option<String>,
+        interactive: bool,
+        todo: Option<String>,
+    },
+    Continue,
+    Skip,
+    Abort,
+}
+
+pub(crate) fn run(action: Action) -> Result<()> {
+    match action {
+        Action::Start {
+            upstream,
+            onto,
+            interactive,
+            todo,
+        } => start(upstream, onto, interactive, todo.as_deref()),
+        Action::Continue => resume(),
+        Action::Skip => skip(),
+        Action::Abort => abort(),
+    }
+}
+
+// NOTE:
+// Replays the commits of HEAD that aren't in `upstream` onto `onto` (the
+// upstream by default), leaving out merges and the commits whose change is
+// already upstream. The todo list of an interactive rebase is edited with
+// the sequence editor, or read from the `todo` file.
+fn start(
+    upstream: Option<String>,
+    onto: Option<String>,
+    interactive: bool,
+    todo: Option<&str>,
+) -> Result<()> {
+    if Rebase::open(".").is_some() {
+        let msg = format!(
+            "It seems that there is already a rebase-merge directory, and\n\
+             I wonder if you are in the middle of another rebase.  If that is the\n\
+             case, please try\n\
+             \tgit rebase (--continue | --abort | --skip)\n\
+             If that is not the case, please\n\
+             \trm -fr \"{GIT_DIR}/rebase-merge\"\n\
+             and run me again.  I am stopping in case you still have something\n\
+             valuable there.\n"
+        );
+        return Err(Error::from(msg.as_str()));
+    }
+    let head = refs::head(".")?;
+    let upstream_name = match upstream {
+        Some(upstream) => upstream,
+        None => revision::upstream(".", HEAD).map_err(|_| no_upstream(&head))?,
+    };
+    let upstream = revision::resolve_commit(".", &upstream_name).map_err(|_| {
+        let msg = format!("invalid upstream '{upstream_name}'");
+        Error::from(msg.as_str())
+    })?;
+    let (onto_name, onto) = match onto {
+        Some(name) => {
+            let hash = revision::resolve_commit(".", &name).map_err(|_| {
+                let msg = format!("Does not point to a valid commit '{name}'");
+                Error::from(msg.as_str())
+            })?;
+            (name, hash)
+        }
+        None => (upstream_name, upstream.clone()),
+    };
+    let orig = refs::resolve(".", HEAD)?.ok_or(Error::from("invalid upstream 'HEAD'"))?;
+    check_clean()?;
+
+    if !interactive
+        && history::merge_bases(".", &onto, &orig)? == [onto.as_str()]
+        && history::merge_bases(".", &upstream, &orig)? == [onto.as_str()]
+    {
+        match head.branch_name() {
+            Some(branch) => println!("Current branch {branch} is up to date."),
+            None => println!("HEAD is up to date."),
+        }
+        return Ok(());
+    }
+
+    let mut instructions = commits_to_pick(&upstream, &orig)?;
+    let head_name = match &head {
+        Head::Branch(refname) => refname.clone(),
+        Head::Detached(_) => DETACHED.to_string(),
+    };
+    let rebase = Rebase::create(".")?;
+    if interactive {
+        instructions = match edit_todo(&rebase, &instructions, (&upstream, &orig, &onto), todo) {
+            Ok(instructions) if !instructions.is_empty() => instructions,
+            Ok(_) => {
+                rebase.clear()?;
+                return Err(Error::from("nothing to do"));
+            }
+            Err(err) => {
+                rebase.clear()?;
+                return Err(err);
+            }
+        };
+    }
+
+    rebase.write("head-name", &format!("{head_name}\n"))?;
+    rebase.write("onto", &format!("{onto}\n"))?;
+    rebase.write("orig-head", &format!("{orig}\n"))?;
+    rebase.write("interactive", "")?;
+    if !interactive {
+        rebase.write("drop_redundant_commits", "")?;
+    }
+    rebase.write("end", &format!("{}\n", instructions.len()))?;
+    refs::write(".", ORIG_HEAD, &orig)?;
+
+    // NOTE:
+    // The first commits that would be picked onto their own parent are kept
+    // as they are, like git's skip_unnecessary_picks.
+    let mut start = onto;
+    let mut skipped = 0;
+    for instruction in instructions.iter() {
+        let Some(commit) = instruction.commit() else {
+            break;
+        };
+        if instruction.command() != Command::Pick
+            || GitObject::open_commit(".", commit)?.parents() != [start.as_str()]
+        {
+            break;
+        }
+        start = commit.to_string();
+        skipped += 1;
+    }
+    rebase.write_done(&instructions[..skipped])?;
+    rebase.write_todo(&instructions[skipped..])?;
+    rebase.write("msgnum", &format!("{skipped}\n"))?;
+
+    let mut index = Index::lock(".")?;
+    let (orig_tree, start_tree) = (
+        revision::resolve_tree(".", &orig)?,
+        revision::resolve_tree(".", &start)?,
+    );
+    if let Err(err) = checkout::switch(".", &mut index, Some(&orig_tree), &start_tree, false) {
+        rebase.clear()?;
+        return Err(err);
+    }
+    index.commit()?;
+    refs::write_head(".", &Head::Detached(start.clone()))?;
+    let committer = ident::reflog_committer(".")?;
+    let message = format!("rebase (start): checkout {onto_name}");
+    reflog::append(".", HEAD, Some(&orig), &start, &committer, &message)?;
+    run_todo(rebase)
+}
+
+fn no_upstream(head: &Head) -> Error {
+    let msg = format!(
+        "There is no tracking information for the current branch.\n\
+         Please specify which branch you want to rebase against.\n\
+         See git-rebase(1) for details.\n\n    \
+             git rebase '<branch>'\n\n\
+         If you wish to set tracking information for this branch you can do so with:\n\n    \
+             git branch --set-upstream-to=<remote>/<branch> {}\n",
+        head.branch_name().unwrap_or("<branch>")
+    );
+    Error::from(msg.as_str())
+}
+
+fn check_clean() -> Result<()> {
+    let status = Status::collect(".")?;
+    let files = status.files();
+    if files.iter().any(|f| f.unstaged().is_some()) || !status.conflicts().is_empty() {
+        return Err(Error::from(
+            "cannot rebase: You have unstaged changes.\nerror: Please commit or stash them.",
+        ));
+    }
+    if files.iter().any(|f| f.staged().is_some()) {
+        return Err(Error::from(
+            "cannot rebase: Your index contains uncommitted changes.\n\
+             error: Please commit or stash them.",
+        ));
+    }
+    Ok(())
+}
+
+// NOTE:
+// The commits of `head` that aren't in `upstream`, oldest first. Those whose
+// patch is the same as a commit of `upstream` since they forked were already
+// applied there and are skipped with a warning.
+fn commits_to_pick(upstream: &str, head: &str) -> Result<Vec<Instruction>> {
+    let mut walk = RevWalk::new(".");
+    walk.hide(upstream)?;
+    walk.push(head)?;
+    let mut commits: Vec<(String, Commit)> = walk.collect::<Result<_>>()?;
+    commits.reverse();
+    commits.retain(|(_, commit)| commit.parents().len() <= 1);
+
+    let mut walk = RevWalk::new(".");
+    walk.hide(head)?;
+    walk.push(upstream)?;
+    let mut applied: HashSet<_> = HashSet::new();
+    for item in walk {
+        let (_, commit) = item?;
+        if let Some(id) = sequencer::patch_id(".", &commit)? {
+            applied.insert(id);
+        }
+    }
+
+    let mut instructions: Vec<Instruction> = vec![];
+    let mut skipped = false;
+    for (hash, commit) in commits {
+        if !applied.is_empty()
+            && sequencer::patch_id(".", &commit)?.is_some_and(|id| applied.contains(&id))
+        {
+            eprintln!(
+                "warning: skipped previously applied commit {}",
+                revision::abbrev(".", &hash, None)?
+            );
+            skipped = true;
+            continue;
+        }
+        instructions.push(Instruction::pick(hash, pretty::subject(commit.comment())));
+    }
+    if skipped {
+        eprintln!("hint: use --reapply-cherry-picks to include skipped commits");
+        eprintln!("hint: Disable this message with \"git config advice.skippedCherryPicks false\"");
+    }
+    Ok(instructions)
+}
+
+// NOTE:
+// Writes the todo list with shortened hashes and help for the sequence editor
+// and reads it back, or reads the given todo file instead.
+fn edit_todo(
+    rebase: &Rebase,
+    instructions: &[Instruction],
+    (upstream, head, onto): (&str, &str, &str),
+    todo: Option<&str>,
+) -> Result<Vec<Instruction>> {
+    let content = match todo {
+        Some(path) => fs::read_to_string(path).map_err(|_| {
+            let msg = format!("could not read '{path}'.");
+            Error::from(msg.as_str())
+        })?,
+        None => {
+            let mut content = String::new();
+            for instruction in instructions {
+                content.push_str(&instruction.abbreviated(".")?);
+                content.push('\n');
+            }
+            let count = instructions.len();
+            content.push_str(&format!(
+                "\n# Rebase {}..{} onto {} ({count} command{})\n{TODO_HELP}",
+                revision::abbrev(".", upstream, None)?,
+                revision::abbrev(".", head, None)?,
+                revision::abbrev(".", onto, None)?,
+                if count == 1 { "" } else { "s" },
+            ));
+            let path = rebase.dir().join("git-rebase-todo");
+            fs::write(&path, content)?;
+            if let Some(editor) = editor::sequence_editor(".")? {
+                editor::edit(".", &editor, &path)?;
+            }
+            fs::read_to_string(&path)?
+        }
+    };
+    let instructions = sequencer::parse_todo(".", &content)?;
+    sequencer::check_todo(&instructions)?;
+    Ok(instructions)
+}
+
+// NOTE:
+// Runs the instructions left in the todo list, each moved to the done list
+// first. It returns early when one stops the rebase.
+fn run_todo(rebase: Rebase) -> Result<()> {
+    let end = rebase.read("end")?.unwrap_or_default();
+    loop {
+        let mut todo = rebase.todo()?;
+        if todo.is_empty() {
+            return finish(rebase);
+        }
+        let instruction = todo.remove(0);
+        let mut done = rebase.done()?;
+        done.push(instruction.clone());
+        rebase.write_done(&done)?;
+        rebase.write_todo(&todo)?;
+        rebase.write("msgnum", &format!("{}\n", done.len()))?;
+        eprint!("Rebasing ({}/{end})\r", done.len());
+
+        match instruction.command() {
+            Command::Drop => {}
+            Command::Break => {
+                let head = refs::resolve(".", HEAD)?
+                    .ok_or(Error::from("HEAD does not point to a commit"))?;
+                let commit = GitObject::open_commit(".", &head)?;
+                eprintln!(
+                    "Stopped at {} ({})",
+                    revision::abbrev(".", &head, None)?,
+                    pretty::subject(commit.comment())
+                );
+                return Ok(());
+            }
+            Command::Exec => {
+                eprintln!("Executing: {}", instruction.text());
+                let status = std::process::Command::new("sh")
+                    .arg("-c")
+                    .arg(instruction.text())
+                    .status();
+                if !status.is_ok_and(|status| status.success()) {
+                    let msg = format!(
+                        "execution failed: {}\n\
+                         You can fix the problem, and then run\n\n  \
+                           git rebase --continue\n",
+                        instruction.text()
+                    );
+                    return Err(Error::from(msg.as_str()));
+                }
+            }
+            _ => {
+                if let Err(err) = pick(&rebase, &instruction) {
+                    if !rebase.has("stopped-sha") {
+                        // NOTE:
+                        // Nothing was applied, so it's tried again on --continue.
+                        done.pop();
+                        todo.insert(0, instruction);
+                        rebase.write_done(&done)?;
+                        rebase.write_todo(&todo)?;
+                        rebase.write("msgnum", &format!("{}\n", done.len()))?;
+                    }
+                    return Err(err);
+                }
+                if instruction.command() == Command::Edit {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+// NOTE:
+// Applies the change of the commit on top of HEAD. On conflicts, the message
+// and author for the commit that concludes it are kept in the state.
+fn pick(rebase: &Rebase, instruction: &Instruction) -> Result<()> {
+    let hash = instruction.commit().expect("pick has a commit");
+    let commit = GitObject::open_commit(".", hash)?;
+    let head = refs::resolve(".", HEAD)?.ok_or(Error::from("HEAD does not point to a commit"))?;
+    let head_commit = GitObject::open_commit(".", &head)?;
+    let parent = commit.parents().first().cloned();
+    let (base, theirs) =
+        sequencer::replay_trees(".", instruction.command(), &commit, parent.as_deref())?;
+    let abbrev = revision::abbrev(".", hash, None)?;
+    let subject = pretty::subject(commit.comment());
+    let label = match instruction.command() {
+        Command::Revert => format!("parent of {abbrev} ({subject})"),
+        _ => format!("{abbrev} ({subject})"),
+    };
+
+    let mut index = Index::lock(".")?;
+    let result = sequencer::apply(
+        ".",
+        &mut index,
+        base.as_deref(),
+        head_commit.tree(),
+        &theirs,
+        (HEAD, &label),
+    )?;
+    let mut index = index.commit()?;
+    for message in result.messages() {
+        println!("{message}");
+    }
+
+    let (message, author) = match instruction.command() {
+        Command::Squash | Command::Fixup => (
+            squash_message(instruction.command(), &head_commit, &commit),
+            head_commit.author().clone(),
+        ),
+        Command::Revert => (
+            sequencer::revert_message(hash, &commit, parent.as_deref()),
+            ident::author(".")?,
+        ),
+        _ => (commit.comment().to_string(), commit.author().clone()),
+    };
+    let stopped = format!("{abbrev}...  {}", instruction.text());
+    let abbrev = format!("{abbrev}... {}", instruction.text());
+    if !result.conflicts().is_empty() {
+        stop(rebase, hash, &message, &author, &result.conflicts())?;
+        eprintln!("error: could not apply {abbrev}");
+        eprintln!("hint: Resolve all conflicts manually, mark them as resolved with");
+        eprintln!("hint: \"git add/rm <conflicted_files>\", then run \"git rebase --continue\".");
+        eprintln!("hint: You can instead skip this commit: run \"git rebase --skip\".");
+        eprintln!(
+            "hint: To abort and get back to the state before \"git rebase\", \
+             run \"git rebase --abort\"."
+        );
+        let msg = format!("Could not apply {abbrev}");
+        return Err(Error::from(msg.as_str()));
+    }
+
+    let changed = base.as_deref() != Some(theirs.as_str());
+    let tree = index.write_tree(".")?.hash().hex();
+    if tree == head_commit.tree()
+        && changed
+        && !matches!(instruction.command(), Command::Squash | Command::Fixup)
+    {
+        if rebase.has("drop_redundant_commits") {
+            eprintln!("dropping {hash} {subject} -- patch contents already upstream");
+            return Ok(());
+        }
+        stop(rebase, hash, &message, &author, &[])?;
+        let msg = format!(
+            "The previous cherry-pick is now empty, possibly due to conflict resolution.\n\
+             If you wish to commit it anyway, use:\n\n    \
+                 git commit --allow-empty\n\n\
+             Otherwise, please use 'git rebase --skip'\n\
+             Could not apply {abbrev}"
+        );
+        return Err(Error::from(msg.as_str()));
+    }
+
+    let edit = instruction.command() == Command::Edit;
+    if edit {
+        rebase.write("message", &message)?;
+        rebase.write_author(&author)?;
+    }
+    let new = commit_step(instruction.command(), &tree, message, author, None)?;
+    if edit {
+        rebase.write("amend", &format!("{new}\n"))?;
+        rebase.write("stopped-sha", &format!("{hash}\n"))?;
+        fs::write(Path::new(GIT_DIR).join(REBASE_HEAD), format!("{hash}\n"))?;
+        eprintln!(
+            "Stopped at {stopped}\n\
+             You can amend the commit now, with\n\n  \
+               git commit --amend \n\n\
+             Once you are satisfied with your changes, run\n\n  \
+               git rebase --continue"
+        );
+    }
+    Ok(())
+}
+
+// NOTE:
+// Keeps what's needed to commit the picked commit once the user is done with
+// it, with the message also in MERGE_MSG.
+fn stop(
+    rebase: &Rebase,
+    hash: &str,
+    message: &str,
+    author: &User,
+    conflicts: &[&str],
+) -> Result<()> {
+    rebase.write("message", message)?;
+    rebase.write_author(author)?;
+    rebase.write("stopped-sha", &format!("{hash}\n"))?;
+    fs::write(Path::new(GIT_DIR).join(REBASE_HEAD), format!("{hash}\n"))?;
+    let mut merge_msg = message.to_string();
+    if !conflicts.is_empty() {
+        merge_msg = format!("{}\n\n# Conflicts:\n", message.trim_end());
+        for path in conflicts {
+            merge_msg.push_str(&format!("#\t{path}\n"));
+        }
+    }
+    fs::write(Path::new(GIT_DIR).join(MERGE_MSG), merge_msg)?;
+    Ok(())
+}
+
+// NOTE:
+// Commits the tree on top of HEAD, or in place of HEAD when squashing into
+// it, and moves the detached HEAD there. The reflog is named after the
+// command, or `action` when concluding a stopped one.
+fn commit_step(
+    command: Command,
+    tree: &str,
+    message: String,
+    author: User,
+    action: Option<&str>,
+) -> Result<String> {
+    let head = refs::resolve(".", HEAD)?.ok_or(Error::from("HEAD does not point to a commit"))?;
+    let head_commit = GitObject::open_commit(".", &head)?;
+    let (parents, message) = match command {
+        Command::Squash | Command::Fixup => (
+            head_commit.parents().to_vec(),
+            edit_message(message, command == Command::Squash)?,
+        ),
+        Command::Reword => (vec![head.clone()], edit_message(message, true)?),
+        _ => (vec![head.clone()], message),
+    };
+    let obj = GitObject::new_commit(
+        tree.to_string(),
+        message,
+        parents,
+        author,
+        ident::committer(".")?,
+    )?;
+    obj.write(".")?;
+    let hash = obj.hash().hex();
+    let GitObject::Commit(ref commit) = obj else {
+        unreachable!()
+    };
+    refs::update(".", HEAD, &hash, Expected::Hash(&head))?;
+    let message = format!(
+        "rebase ({}): {}",
+        action.unwrap_or(command.name()),
+        commit.summary()
+    );
+    reflog::append(".", HEAD, Some(&head), &hash, commit.committer(), &message)?;
+    // NOTE:
+    // The commits that went through the editor are shown, like `git commit`
+    // does.
+    if action.is_some() || matches!(command, Command::Squash | Command::Reword) {
+        println!("[{DETACHED} {}] {}", &hash[..7], commit.summary());
+    }
+    Ok(hash)
+}
+
+// NOTE:
+// Squashing keeps both messages and fixing up only the first one, with the
+// other commented out.
+fn squash_message(command: Command, head: &Commit, commit: &Commit) -> String {
+    let comment =
+        |message: &str| -> String { message.lines().map(|line| format!("# {line}\n")).collect() };
+    let second = match command {
+        Command::Fixup => format!(
+            "# The commit message #2 will be skipped:\n\n{}",
+            comment(commit.comment())
+        ),
+        _ => format!("# This is the commit message #2:\n\n{}", commit.comment()),
+    };
+    format!(
+        "# This is a combination of 2 commits.\n\
+         # This is the 1st commit message:\n\n{}\n{second}",
+        head.comment()
+    )
+}
+
+// NOTE:
+// Runs the editor on the message when there is one and `edit` is set. The
+// comments are removed in any case.
+fn edit_message(message: String, edit: bool) -> Result<String> {
+    let mut message = message;
+    if let Some(editor) = editor::editor(".")?.filter(|_| edit) {
+        let path = Path::new(GIT_DIR).join(COMMIT_EDITMSG);
+        fs::write(&path, &message)?;
+        editor::edit(".", &editor, &path)?;
+        message = fs::read_to_string(&path)?;
+    }
+    let lines: Vec<&str> = message.lines().filter(|l| !l.starts_with('#')).collect();
+    let message = pretty::cleanup(&lines.join("\n"));
+    if message.is_empty() {
+        return Err(Error::from("Aborting commit due to empty commit message."));
+    }
+    Ok(message)
+}
+
+// NOTE:
+// Moves the branch to where the rebase ended and checks it out again.
+fn finish(rebase: Rebase) -> Result<()> {
+    let head_name = rebase.read("head-name")?.unwrap_or(DETACHED.to_string());
+    let onto = rebase.read("onto")?.unwrap_or_default();
+    let head = refs::resolve(".", HEAD)?.ok_or(Error::from("HEAD does not point to a commit"))?;
+    if head_name != DETACHED {
+        let committer = ident::reflog_committer(".")?;
+        let old = refs::resolve(".", &head_name)?;
+        let expected = match old.as_deref() {
+            Some(old) => Expected::Hash(old),
+            None => Expected::Missing,
+        };
+        refs::update(".", &head_name, &head, expected)?;
+        let message = format!("rebase (finish): {head_name} onto {onto}");
+        reflog::append(".", &head_name, old.as_deref(), &head, &committer, &message)?;
+        refs::write_head(".", &Head::Branch(head_name.clone()))?;
+        let message = format!("rebase (finish): returning to {head_name}");
+        reflog::append(".", HEAD, Some(&head), &head, &committer, &message)?;
+    }
+    rebase.clear()?;
+    eprintln!("Successfully rebased and updated {head_name}.");
+    Ok(())
+}
+
+fn open() -> Result<Rebase> {
+    Rebase::open(".").ok_or(Error::from("No rebase in progress?"))
+}
+
+fn clear_stop(rebase: &Rebase) -> Result<()> {
+    for name in ["message", "author-script", "stopped-sha", "amend"] {
+        rebase.remove(name)?;
+    }
+    for name in [REBASE_HEAD, MERGE_MSG] {
+        let path = Path::new(GIT_DIR).join(name);
+        if path.is_file() {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+// NOTE:
+// Commits what was staged to resolve the commit the rebase stopped at, and
+// goes on with the rest of the todo list. When it stopped to edit a commit,
+// the changes are expected to be committed already.
+fn resume() -> Result<()> {
+    let rebase = open()?;
+    let mut index = Index::lock(".")?;
+    if index.is_unmerged() {
+        let mut paths: Vec<&str> = index
+            .entries()
+            .iter()
+            .filter(|e| e.stage() != 0)
+            .map(|e| e.path())
+            .collect();
+        paths.dedup();
+        let msg = format!(
+            "{}\nYou must edit all merge conflicts and then\n\
+             mark them as resolved using git add",
+            paths
+                .iter()
+                .map(|p| format!("{p}: needs merge"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+        return Err(Error::from(msg.as_str()));
+    }
+
+    let head = refs::resolve(".", HEAD)?.ok_or(Error::from("HEAD does not point to a commit"))?;
+    let head_tree = GitObject::open_commit(".", &head)?.tree().to_string();
+    let tree = index.write_tree(".")?.hash().hex();
+    index.commit()?;
+    let amend = rebase.read("amend")?;
+    if amend.as_deref() == Some(head.as_str()) && tree != head_tree {
+        // NOTE:
+        // The changes staged at an edit stop go into the commit it stopped at.
+        let head_commit = GitObject::open_commit(".", &head)?;
+        let (message, author) = (
+            head_commit.comment().to_string(),
+            head_commit.author().clone(),
+        );
+        commit_step(Command::Fixup, &tree, message, author, Some("continue"))?;
+    } else if amend.is_some() {
+        if tree != head_tree {
+            return Err(Error::from(
+                "You have staged changes in your working tree. If these changes are meant to be\n\
+                 squashed into the previous commit, run:\n\n  \
+                   git commit --amend \n\n\
+                 If they are meant to go into a new commit, run:\n\n  \
+                   git commit \n\n\
+                 In both cases, once you're done, continue with:\n\n  \
+                   git rebase --continue\n",
+            ));
+        }
+    } else if rebase.has("stopped-sha") && tree != head_tree {
+        let command = rebase
+            .done()?
+            .last()
+            .map_or(Command::Pick, Instruction::command);
+        let message = rebase.read("message")?.unwrap_or_default();
+        let author = match rebase.read_author()? {
+            Some(author) => author,
+            None => ident::author(".")?,
+        };
+        commit_step(command, &tree, message, author, Some("continue"))?;
+    }
+    clear_stop(&rebase)?;
+    run_todo(rebase)
+}
+
+fn skip() -> Result<()> {
+    let rebase = open()?;
+    let head = refs::resolve(".", HEAD)?.ok_or(Error::from("HEAD does not point to a commit"))?;
+    let tree = revision::resolve_tree(".", &head)?;
+    let mut index = Index::lock(".")?;
+    checkout::switch(".", &mut index, Some(&tree), &tree, true)?;
+    index.commit()?;
+    clear_stop(&rebase)?;
+    run_todo(rebase)
+}
+
+fn abort() -> Result<()> {
+    let rebase = open()?;
+    let orig = rebase
+        .read("orig-head")?
+        .ok_or(Error::from("could not read 'orig-head'"))?;
+    let head_name = rebase.read("head-name")?.unwrap_or(DETACHED.to_string());
+    let head = refs::resolve(".", HEAD)?.ok_or(Error::from("HEAD does not point to a commit"))?;
+
+    let mut index = Index::lock(".")?;
+    let (head_tree, orig_tree) = (
+        revision::resolve_tree(".", &head)?,
+        revision::resolve_tree(".", &orig)?,
+    );
+    checkout::switch(".", &mut index, Some(&head_tree), &orig_tree, true)?;
+    index.commit()?;
+    let (new_head, target) = match head_name.as_str() {
+        DETACHED => (Head::Detached(orig.clone()), orig.clone()),
+        refname => (Head::Branch(refname.to_string()), refname.to_string()),
+    };
+    refs::write_head(".", &new_head)?;
+    let committer = ident::reflog_committer(".")?;
+    let message = format!("rebase (abort): returning to {target}");
+    reflog::append(".", HEAD, Some(&head), &orig, &committer, &message)?;
+    clear_stop(&rebase)?;
+    rebase.clear()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn head() -> String {
+        refs::resolve(".", HEAD).unwrap().unwrap()
+    }
+
+    fn ancestor(n: usize) -> String {
+        let mut hash = head();
+        for _ in 0..n {
+            hash = GitObject::open_commit(".", &hash).unwrap().parents()[0].clone();
+        }
+        hash
+    }
+
+    fn subjects(n: usize) -> Vec<String> {
+        (0..n)
+            .map(|i| {
+                let commit = GitObject::open_commit(".", &ancestor(i)).unwrap();
+                pretty::subject(commit.comment()).to_string()
+            })
+            .collect()
+    }
+
+    fn last_message(refname: &str) -> String {
+        let entries = reflog::read(".", refname).unwrap();
+        entries.last().unwrap().message().to_string()
+    }
+
+    #[test]
+    fn it_rebases_a_branch_onto_another_base() {
+        let (_root, _cwd) = testing::command_repo("rebase-onto");
+        testing::commit(&[("a", "1\n")], "base");
+        testing::git(&["branch", "next"]).unwrap();
+        let main = testing::commit(&[("m", "m\n")], "main");
+        testing::git(&["switch", "next"]).unwrap();
+        testing::commit(&[("n", "n\n")], "next");
+        testing::git(&["branch", "topic"]).unwrap();
+        testing::git(&["switch", "topic"]).unwrap();
+        testing::commit(&[("t", "1\n")], "one");
+        let orig = testing::commit(&[("t", "2\n")], "two");
+
+        testing::git(&["rebase", "--onto", "main", "next"]).unwrap();
+        assert_eq!(subjects(4), ["two", "one", "main", "base"]);
+        assert_eq!(ancestor(2), main);
+        assert!(!Path::new("n").exists());
+        assert_eq!(fs::read_to_string("t").unwrap(), "2\n");
+        assert_eq!(
+            refs::head(".").unwrap(),
+            Head::Branch("refs/heads/topic".into())
+        );
+        assert_eq!(
+            refs::resolve(".", "refs/heads/topic").unwrap(),
+            Some(head())
+        );
+        assert_eq!(refs::resolve(".", ORIG_HEAD).unwrap(), Some(orig));
+        assert_eq!(
+            last_message("refs/heads/topic"),
+            format!("rebase (finish): refs/heads/topic onto {main}")
+        );
+        assert!(Rebase::open(".").is_none());
+
+        let err = testing::git(&["rebase", "--onto", "nowhere", "next"]).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("Does not point to a valid commit 'nowhere'"));
+    }
+
+    #[test]
+    fn it_drops_the_commits_already_upstream() {
+        let (_root, _cwd) = testing::command_repo("rebase-upstream");
+        testing::commit(&[("a", "1\n2\n3\n")], "base");
+        testing::git(&["branch", "topic"]).unwrap();
+        testing::commit(&[("a", "1\n2\n3\nupstream\n")], "picked");
+        let main = testing::commit(&[("m", "m\n")], "main");
+        testing::git(&["switch", "topic"]).unwrap();
+        testing::commit(&[("a", "1\n2\n3\nupstream\n")], "one");
+        testing::commit(&[("t", "t\n")], "two");
+
+        testing::git(&["rebase", "main"]).unwrap();
+        assert_eq!(subjects(4), ["two", "main", "picked", "base"]);
+        assert_eq!(ancestor(1), main);
+        assert_eq!(fs::read_to_string("a").unwrap(), "1\n2\n3\nupstream\n");
+
+        testing::git(&["rebase", "main"]).unwrap();
+        assert_eq!(ancestor(1), main);
+    }
+
+    #[test]
+    fn it_runs_each_command_of_the_todo_list() {
+        let (_root, _cwd) = testing::command_repo("rebase-todo");
+        let base = testing::commit(&[("a", "a\n")], "base");
+        let one = testing::commit(&[("1", "1\n")], "one");
+        let two = testing::commit(&[("2", "2\n")], "two");
+        let three = testing::commit(&[("3", "3\n")], "three");
+        let four = testing::commit(&[("4", "4\n")], "four");
+        let five = testing::commit(&[("5", "5\n")], "five");
+        let six = testing::commit(&[("6", "6\n")], "six");
+        let todo = format!(
+            "# the whole list\n\
+             pick {one} one\n\
+             r {two}\n\
+             squash {three}\n\
+             f {four}\n\
+             exec touch ran\n\
+             d {five}\n\
+             edit {six}\n\
+             break\n"
+        );
+        fs::write(Path::new(GIT_DIR).join("todo"), todo).unwrap();
+
+        testing::git(&["rebase", "-i", &base, "--todo", ".git/todo"]).unwrap();
+        assert_eq!(
+            fs::read_to_string(Path::new(GIT_DIR).join(REBASE_HEAD)).unwrap(),
+            format!("{six}\n")
+        );
+        assert_eq!(subjects(4), ["six", "two", "one", "base"]);
+        assert_eq!(ancestor(2), one);
+        let squashed = GitObject::open_commit(".", &ancestor(1)).unwrap();
+        assert_eq!(squashed.comment(), "two\n\nthree\n");
+        assert!(Path::new("ran").exists());
+        assert!(!Path::new("5").exists());
+        assert!(Path::new("4").exists());
+
+        fs::write("6", "amended\n").unwrap();
+        testing::git(&["add", "6"]).unwrap();
+        testing::git(&["rebase", "--continue"]).unwrap();
+        assert!(Rebase::open(".").is_some());
+        assert_eq!(refs::head(".").unwrap(), Head::Detached(head()));
+        assert_eq!(subjects(2), ["six", "two"]);
+
+        testing::git(&["rebase", "--continue"]).unwrap();
+        assert!(Rebase::open(".").is_none());
+        assert_eq!(
+            refs::head(".").unwrap(),
+            Head::Branch("refs/heads/main".into())
+        );
+        assert_eq!(subjects(4), ["six", "two", "one", "base"]);
+        assert_eq!(fs::read_to_string("6").unwrap(), "amended\n");
+
+        fs::write(Path::new(GIT_DIR).join("todo"), format!("fixup {two}\n")).unwrap();
+        let err = testing::git(&["rebase", "-i", &base, "--todo", ".git/todo"]).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("cannot 'fixup' without a previous commit"));
+        assert!(Rebase::open(".").is_none());
+    }
+
+    // NOTE:
+    // "topic" changes "a" like "main" does, so that rebasing it onto "main"
+    // stops at its first commit.
+    fn stop() -> (String, String) {
+        let orig = refs::resolve(".", "refs/heads/topic").unwrap().unwrap();
+        let err = testing::git(&["rebase", "main"]).unwrap_err();
+        assert!(err.to_string().contains("Could not apply "));
+        let rebase = Rebase::open(".").unwrap();
+        let one = revision::resolve(".", "topic~1").unwrap();
+        assert_eq!(rebase.read("stopped-sha").unwrap(), Some(one.clone()));
+        assert_eq!(rebase.read("orig-head").unwrap(), Some(orig.clone()));
+        assert_eq!(
+            rebase.read("head-name").unwrap().as_deref(),
+            Some("refs/heads/topic")
+        );
+        assert_eq!(
+            fs::read_to_string(Path::new(GIT_DIR).join(REBASE_HEAD)).unwrap(),
+            format!("{one}\n")
+        );
+        assert_eq!(refs::resolve(".", ORIG_HEAD).unwrap(), Some(orig.clone()));
+        assert!(matches!(refs::head(".").unwrap(), Head::Detached(_)));
+        assert!(Index::open(".").unwrap().is_unmerged());
+        (orig, one)
+    }
+
+    fn diverged(name: &str) -> (testing::TempDir, testing::CurrentDir, String) {
+        let (root, cwd) = testing::command_repo(name);
+        testing::commit(&[("a", "1\n")], "base");
+        testing::git(&["branch", "topic"]).unwrap();
+        let main = testing::commit(&[("a", "main\n")], "main");
+        testing::git(&["switch", "topic"]).unwrap();
+        testing::commit(&[("a", "topic\n")], "one");
+        testing::commit(&[("b", "b\n")], "two");
+        (root, cwd, main)
+    }
+
+    #[test]
+    fn it_continues_a_rebase_stopped_on_conflicts() {
+        let (_root, _cwd, main) = diverged("rebase-continue");
+        let (_, one) = stop();
+
+        let err = testing::git(&["rebase", "--continue"]).unwrap_err();
+        assert!(err.to_string().contains("a: needs merge"));
+        fs::write("a", "resolved\n").unwrap();
+        testing::git(&["add", "a"]).unwrap();
+        testing::git(&["rebase", "--continue"]).unwrap();
+
+        assert_eq!(subjects(3), ["two", "one", "main"]);
+        assert_eq!(ancestor(2), main);
+        let picked = GitObject::open_commit(".", &ancestor(1)).unwrap();
+        let original = GitObject::open_commit(".", &one).unwrap();
+        assert_eq!(picked.comment(), original.comment());
+        assert_eq!(picked.author(), original.author());
+        assert_eq!(fs::read_to_string("a").unwrap(), "resolved\n");
+        assert_eq!(
+            refs::head(".").unwrap(),
+            Head::Branch("refs/heads/topic".into())
+        );
+        assert!(Rebase::open(".").is_none());
+        assert!(!Path::new(GIT_DIR).join(REBASE_HEAD).exists());
+        assert!(!Path::new(GIT_DIR).join(MERGE_MSG).exists());
+    }
+
+    #[test]
+    fn it_skips_or_aborts_a_rebase_stopped_on_conflicts() {
+        let (_root, _cwd, main) = diverged("rebase-skip");
+        let (orig, _) = stop();
+        testing::git(&["rebase", "--skip"]).unwrap();
+        assert_eq!(subjects(2), ["two", "main"]);
+        assert_eq!(ancestor(1), main);
+        assert_eq!(fs::read_to_string("a").unwrap(), "main\n");
+        assert!(Rebase::open(".").is_none());
+        assert!(!Path::new(GIT_DIR).join(REBASE_HEAD).exists());
+
+        testing::git(&["reset", "-q", "--hard", &orig]).unwrap();
+        stop();
+        testing::git(&["rebase", "--abort"]).unwrap();
+        assert_eq!(
+            refs::head(".").unwrap(),
+            Head::Branch("refs/heads/topic".into())
+        );
+        assert_eq!(head(), orig);
+        assert_eq!(refs::resolve(".", ORIG_HEAD).unwrap(), Some(orig));
+        assert_eq!(fs::read_to_string("a").unwrap(), "topic\n");
+        assert!(!Index::open(".").unwrap().is_unmerged());
+        assert_eq!(
+            last_message(HEAD),
+            "rebase (abort): returning to refs/heads/topic"
+        );
+        assert!(Rebase::open(".").is_none());
+        assert!(!Path::new(GIT_DIR).join(REBASE_HEAD).exists());
+        let err = testing::git(&["rebase", "--abort"]).unwrap_err();
+        assert!(err.to_string().ends_with("No rebase in progress?"));
+    }
+}
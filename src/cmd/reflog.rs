@@ -0,0 +1,176 @@
+use super::{
+    history,
+    refs::{self, HEAD},
+    revision, Error, Result,
+};
+use crate::{date, reflog};
+
+const DEFAULT_EXPIRE: &str = "90.days.ago";
+const DEFAULT_EXPIRE_UNREACHABLE: &str = "30.days.ago";
+
+#[derive(Debug)]
+pub enum Action {
+    Show {
+        rev: Option<String>,
+    },
+    Expire {
+        refs: Vec<String>,
+        all: bool,
+        expire: Option<String>,
+        expire_unreachable: Option<String>,
+        dry_run: bool,
+    },
+    Delete {
+        revs: Vec<String>,
+        dry_run: bool,
+    },
+}
+
+pub(crate) fn run(action: Action) -> Result<()> {
+    match action {
+        Action::Show { rev } => show(rev.as_deref().unwrap_or(HEAD)),
+        Action::Expire {
+            refs,
+            all,
+            expire,
+            expire_unreachable,
+            dry_run,
+        } => {
+            let expire = cutoff(expire.as_deref().unwrap_or(DEFAULT_EXPIRE))?;
+            let unreachable = cutoff(
+                expire_unreachable
+                    .as_deref()
+                    .unwrap_or(DEFAULT_EXPIRE_UNREACHABLE),
+            )?;
+            let refnames = match all {
+                true => reflog::list(".")?,
+                false => refs
+                    .iter()
+                    .map(|name| {
+                        revision::dwim_ref(".", name)?.ok_or_else(|| {
+                            let msg = format!("{name} points nowhere!");
+                            Error::from(msg.as_str())
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+            };
+            for refname in refnames {
+                expire_log(&refname, expire, unreachable, dry_run)?;
+            }
+            Ok(())
+        }
+        Action::Delete { revs, dry_run } => {
+            if revs.is_empty() {
+                return Err(Error::from("no reflog specified to delete"));
+            }
+            for rev in revs {
+                delete(&rev, dry_run)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+// NOTE:
+// Lists the entries newest first as "<hash> <ref>@{N}: <message>", starting
+// from N when the ref is given as "<ref>@{N}".
+fn show(rev: &str) -> Result<()> {
+    let (name, skip) = match split_entry(rev) {
+        Some((name, n)) => (name, n),
+        None => (rev, 0),
+    };
+    let refname = match revision::dwim_ref(".", name)? {
+        Some(refname) => refname,
+        None if name == HEAD => {
+            let head = refs::head(".")?;
+            let msg = format!(
+                "your current branch '{}' does not have any commits yet",
+                head.branch_name().unwrap_or(HEAD)
+            );
+            return Err(Error::from(msg.as_str()));
+        }
+        None => return revision::resolve(".", name).map(|_| ()),
+    };
+
+    let entries = reflog::read(".", &refname)?;
+    for (i, entry) in entries.iter().rev().enumerate().skip(skip) {
+        println!(
+            "{} {name}@{{{i}}}: {}",
+            revision::abbrev(".", entry.hash(), None)?,
+            entry.message()
+        );
+    }
+    Ok(())
+}
+
+// NOTE:
+// Prunes the entries older than `expire`, and the ones older than
+// `unreachable` that moved the ref from or to a commit it can't reach anymore.
+// What any ref can reach counts for HEAD.
+fn expire_log(refname: &str, expire: u64, unreachable: u64, dry_run: bool) -> Result<()> {
+    let mut tips: Vec<String> = refs::resolve(".", refname)?.into_iter().collect();
+    if refname == HEAD {
+        tips.extend(refs::list(".", "refs/")?.into_iter().map(|(_, hash)| hash));
+    }
+    let reachable = |hash: &str| {
+        hash == reflog::NULL_HASH
+            || tips
+                .iter()
+                .any(|tip| history::is_ancestor(".", hash, tip).unwrap_or(false))
+    };
+
+    let entries = reflog::read(".", refname)?;
+    let kept: Vec<reflog::Entry> = entries
+        .iter()
+        .filter(|entry| {
+            let timestamp = entry.committer().timestamp();
+            timestamp >= expire
+                && (timestamp >= unreachable || (reachable(entry.old()) && reachable(entry.hash())))
+        })
+        .cloned()
+        .collect();
+
+    if dry_run || kept.len() == entries.len() {
+        return Ok(());
+    }
+    reflog::write(".", refname, &kept)
+}
+
+fn delete(rev: &str, dry_run: bool) -> Result<()> {
+    let no_reflog = || {
+        let msg = format!("no reflog for '{rev}'");
+        Error::from(msg.as_str())
+    };
+    if !rev.contains("@{") {
+        let msg = format!("not a reflog: {rev}");
+        return Err(Error::from(msg.as_str()));
+    }
+    let (name, n) = split_entry(rev).ok_or_else(no_reflog)?;
+    let refname = revision::dwim_ref(".", name)?.ok_or_else(no_reflog)?;
+
+    let mut entries = reflog::read(".", &refname)?;
+    if n >= entries.len() || dry_run {
+        return Ok(());
+    }
+    entries.remove(entries.len() - 1 - n);
+    reflog::write(".", &refname, &entries)
+}
+
+// NOTE:
+// Splits "<ref>@{N}" into the ref and N, with HEAD for a missing ref.
+fn split_entry(rev: &str) -> Option<(&str, usize)> {
+    let (name, suffix) = rev.split_once("@{")?;
+    let n = suffix.strip_suffix('}')?.parse().ok()?;
+    Some((if name.is_empty() { HEAD } else { name }, n))
+}
+
+// NOTE:
+// The time before which entries are pruned. "never" keeps them all, and "all"
+// prunes them all.
+fn cutoff(value: &str) -> Result<u64> {
+    match value {
+        "never" | "false" => Ok(0),
+        "all" => Ok(u64::MAX),
+        value => date::approxidate(value, date::now()),
+    }
+}
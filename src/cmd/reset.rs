@@ -0,0 +1,356 @@
+use super::{
+    ident,
+    index::Index,
+    pretty,
+    refs::{self, Expected, HEAD},
+    revision, Error, GitObject, Result,
+};
+use crate::{
+    checkout,
+    diff::files::{self, Source},
+    merge::{self, ORIG_HEAD},
+    reflog, sequencer,
+};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Soft,
+    Mixed,
+    Hard,
+    Keep,
+}
+
+impl Mode {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Soft => "soft",
+            Self::Mixed => "mixed",
+            Self::Hard => "hard",
+            Self::Keep => "keep",
+        }
+    }
+}
+
+// NOTE:
+// Without "--", the first argument is the commit to reset to unless it's
+// only the name of a file, and the arguments after it are paths. With paths,
+// only their index entries are reset and the branch stays where it is.
+pub(crate) fn run(
+    mode: Option<Mode>,
+    revs: Vec<String>,
+    paths: Option<Vec<String>>,
+    quiet: bool,
+) -> Result<()> {
+    let dash_dash = paths.is_some();
+    let mut revs = revs.into_iter();
+    let (rev, path) = match revs.next() {
+        Some(rev) if !dash_dash && Path::new(&rev).exists() => {
+            if revision::resolve_commit(".", &rev).is_ok() {
+                let msg = format!(
+                    "ambiguous argument '{rev}': both revision and filename\n\
+                     Use '--' to separate paths from revisions, like this:\n\
+                     'git <command> [<revision>...] -- [<file>...]'"
+                );
+                return Err(Error::from(msg.as_str()));
+            }
+            (None, Some(rev))
+        }
+        rev => (rev, None),
+    };
+    let paths: Vec<String> = path
+        .into_iter()
+        .chain(revs)
+        .chain(paths.unwrap_or_default())
+        .collect();
+    let rev = rev.unwrap_or_else(|| HEAD.to_string());
+
+    if !paths.is_empty() {
+        if let Some(mode) = mode.filter(|mode| *mode != Mode::Mixed) {
+            let msg = format!("Cannot do {} reset with paths.", mode.name());
+            return Err(Error::from(msg.as_str()));
+        }
+        let tree = match (rev.as_str(), refs::resolve(".", HEAD)?) {
+            (HEAD, None) => None,
+            _ => Some(revision::resolve_tree(".", &rev)?),
+        };
+        let mut index = Index::lock(".")?;
+        checkout::reset_index(".", &mut index, tree.as_deref(), &paths)?;
+        index.commit()?;
+        return match quiet {
+            true => Ok(()),
+            false => show_unstaged(),
+        };
+    }
+
+    let mode = mode.unwrap_or(Mode::Mixed);
+    if mode == Mode::Soft && merge::merge_head(".")?.is_some() {
+        return Err(Error::from(
+            "Cannot do a soft reset in the middle of a merge.",
+        ));
+    }
+    reset(mode, &rev)?;
+    merge::clear_state(".")?;
+    sequencer::clear_pick_head(".")?;
+    if quiet {
+        return Ok(());
+    }
+    match mode {
+        Mode::Hard => {
+            let Some(hash) = refs::resolve(".", HEAD)? else {
+                return Ok(());
+            };
+            let commit = GitObject::open_commit(".", &hash)?;
+            println!(
+                "HEAD is now at {} {}",
+                revision::abbrev(".", &hash, None)?,
+                pretty::subject(commit.comment())
+            );
+            Ok(())
+        }
+        Mode::Mixed => show_unstaged(),
+        _ => Ok(()),
+    }
+}
+
+// NOTE:
+// Moves the current branch, or HEAD when detached, to the commit after
+// resetting the index for Mixed, and the working tree too for Hard and Keep.
+// Keep refuses to lose the local changes of the files that differ between
+// both commits, and leaves the others alone. The commit HEAD was at is kept
+// in ORIG_HEAD. On an unborn branch, resetting to HEAD only empties the index.
+pub(crate) fn reset(mode: Mode, rev: &str) -> Result<()> {
+    let head = refs::head(".")?;
+    let old = refs::resolve(".", HEAD)?;
+    let target = match (rev, old.as_deref()) {
+        (HEAD, None) => None,
+        _ => Some(revision::resolve_commit(".", rev)?),
+    };
+
+    let old_tree = old
+        .as_deref()
+        .map(|hash| revision::resolve_tree(".", hash))
+        .transpose()?;
+    let new_tree = match target.as_deref() {
+        Some(hash) => revision::resolve_tree(".", hash)?,
+        None => Index::default().write_tree(".")?.hash().hex(),
+    };
+    if mode != Mode::Soft {
+        let mut index = Index::lock(".")?;
+        match mode {
+            Mode::Mixed => checkout::reset_index(".", &mut index, Some(&new_tree), &[])?,
+            Mode::Hard => checkout::switch(".", &mut index, old_tree.as_deref(), &new_tree, true)?,
+            Mode::Keep => {
+                check_keep(&index, old_tree.as_deref(), &new_tree, rev)?;
+                checkout::switch(".", &mut index, old_tree.as_deref(), &new_tree, false)?;
+            }
+            Mode::Soft => {}
+        }
+        index.commit()?;
+    }
+
+    let Some(target) = target else {
+        return Ok(());
+    };
+    if let Some(old) = old.as_deref() {
+        refs::write(".", ORIG_HEAD, old)?;
+    }
+    let expected = match old.as_deref() {
+        Some(old) => Expected::Hash(old),
+        None => Expected::Missing,
+    };
+    refs::update(".", head.target(), &target, expected)?;
+
+    let committer = ident::reflog_committer(".")?;
+    let message = format!("reset: moving to {rev}");
+    // NOTE:
+    // A branch left where it was only gets a line in HEAD's log, and a
+    // detached HEAD left where it was gets none.
+    let moved = old.as_deref() != Some(target.as_str());
+    let mut logged_refs = vec![];
+    if moved || head.target() != HEAD {
+        logged_refs.push(HEAD);
+    }
+    if head.target() != HEAD && moved {
+        logged_refs.insert(0, head.target());
+    }
+    for refname in logged_refs {
+        reflog::append(".", refname, old.as_deref(), &target, &committer, &message)?;
+    }
+    Ok(())
+}
+
+// NOTE:
+// The files that differ between both trees can't have changes in the index
+// or in the working tree.
+fn check_keep(index: &Index, old: Option<&str>, new: &str, rev: &str) -> Result<()> {
+    let root = Path::new(".");
+    let old_files = checkout::tree_files(root, old)?;
+    let new_files = checkout::tree_files(root, Some(new))?;
+    let mut errors: Vec<String> = vec![];
+    for entry in index.entries().iter().filter(|e| e.stage() == 0) {
+        let path = entry.path();
+        let (old, new) = (old_files.get(path), new_files.get(path));
+        if old == new {
+            continue;
+        }
+        if old != Some(&(entry.mode(), entry.hash())) {
+            errors.push(format!(
+                "Entry '{path}' would be overwritten by merge. Cannot merge."
+            ));
+        } else if checkout::is_modified(root, index, path)? {
+            errors.push(format!("Entry '{path}' not uptodate. Cannot merge."));
+        }
+    }
+    if errors.is_empty() {
+        return Ok(());
+    }
+    errors.push(format!("Could not reset index file to revision '{rev}'."));
+    Err(Error::from(errors.join("\n").as_str()))
+}
+
+// NOTE:
+// Lists the files whose changes are left in the working tree.
+fn show_unstaged() -> Result<()> {
+    let pairs = files::pairs(".", &Source::Index, &Source::Worktree, &[])?;
+    if pairs.is_empty() {
+        return Ok(());
+    }
+    println!("Unstaged changes after reset:");
+    for pair in pairs {
+        let code = match pair.new_file() {
+            None => 'D',
+            Some(_) if pair.is_type_change() => 'T',
+            Some(_) => 'M',
+        };
+        println!("{code}\t{}", pair.path());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::fs;
+
+    #[test]
+    fn it_resets_the_branch_index_and_working_tree_by_mode() {
+        let (_root, _cwd) = testing::command_repo("reset-modes");
+        let first = testing::commit(&[("a", "1\n")], "first");
+        let second = testing::commit(&[("a", "2\n"), ("b", "2\n")], "second");
+        let head = || refs::resolve(".", HEAD).unwrap().unwrap();
+
+        testing::git(&["reset", "--soft", &first]).unwrap();
+        assert_eq!(head(), first);
+        assert_eq!(
+            (testing::staged("a"), testing::staged("b")),
+            (testing::blob("2\n"), testing::blob("2\n"))
+        );
+        assert_eq!(fs::read_to_string("a").unwrap(), "2\n");
+
+        testing::git(&["reset", "--hard", &second]).unwrap();
+        testing::git(&["reset", &first]).unwrap();
+        assert_eq!(head(), first);
+        assert_eq!(
+            (testing::staged("a"), testing::staged("b")),
+            (testing::blob("1\n"), None)
+        );
+        assert_eq!(fs::read_to_string("b").unwrap(), "2\n");
+
+        testing::git(&["reset", "--hard", &second]).unwrap();
+        testing::git(&["reset", "--hard", &first]).unwrap();
+        assert_eq!(head(), first);
+        assert_eq!(
+            (testing::staged("a"), testing::staged("b")),
+            (testing::blob("1\n"), None)
+        );
+        assert_eq!(fs::read_to_string("a").unwrap(), "1\n");
+        assert!(!Path::new("b").exists());
+        assert_eq!(refs::resolve(".", ORIG_HEAD).unwrap(), Some(second));
+    }
+
+    #[test]
+    fn it_keeps_local_changes_or_refuses_to_lose_them() {
+        let (_root, _cwd) = testing::command_repo("reset-keep");
+        let first = testing::commit(&[("a", "1\n"), ("c", "1\n")], "first");
+        let second = testing::commit(&[("a", "2\n")], "second");
+
+        fs::write("c", "local\n").unwrap();
+        testing::git(&["reset", "--keep", &first]).unwrap();
+        assert_eq!(refs::resolve(".", HEAD).unwrap(), Some(first.clone()));
+        assert_eq!(fs::read_to_string("a").unwrap(), "1\n");
+        assert_eq!(fs::read_to_string("c").unwrap(), "local\n");
+
+        testing::git(&["reset", "--keep", &second]).unwrap();
+        fs::write("a", "local\n").unwrap();
+        let err = testing::git(&["reset", "--keep", &first]).unwrap_err();
+        assert!(err.to_string().contains(&format!(
+            "Entry 'a' not uptodate. Cannot merge.\n\
+             Could not reset index file to revision '{first}'."
+        )));
+        assert_eq!(refs::resolve(".", HEAD).unwrap(), Some(second));
+        assert_eq!(fs::read_to_string("a").unwrap(), "local\n");
+    }
+
+    #[test]
+    fn it_resets_only_the_index_entries_of_paths() {
+        let (_root, _cwd) = testing::command_repo("reset-paths");
+        let first = testing::commit(&[("a", "1\n"), ("b", "1\n")], "first");
+        let second = testing::commit(&[("a", "2\n"), ("b", "2\n")], "second");
+        fs::write("n", "new\n").unwrap();
+        testing::git(&["add", "n"]).unwrap();
+
+        testing::git(&["reset", &first, "--", "a"]).unwrap();
+        testing::git(&["reset", "n"]).unwrap();
+        assert_eq!(refs::resolve(".", HEAD).unwrap(), Some(second));
+        assert_eq!(
+            (testing::staged("a"), testing::staged("b")),
+            (testing::blob("1\n"), testing::blob("2\n"))
+        );
+        assert_eq!(testing::staged("n"), None);
+        assert_eq!(fs::read_to_string("a").unwrap(), "2\n");
+        assert!(testing::git(&["reset", "--hard", &first, "--", "a"]).is_err());
+    }
+
+    #[test]
+    fn it_logs_the_refs_that_moved() {
+        let (_root, _cwd) = testing::command_repo("reset-reflog");
+        let first = testing::commit(&[("a", "1\n")], "first");
+        testing::commit(&[("a", "2\n")], "second");
+
+        testing::git(&["reset", "--hard", "HEAD~1"]).unwrap();
+        let moved = "reset: moving to HEAD~1";
+        assert_eq!(testing::messages("refs/heads/main").last().unwrap(), moved);
+        assert_eq!(testing::messages(HEAD).last().unwrap(), moved);
+        let entry = reflog::read(".", HEAD).unwrap().pop().unwrap();
+        assert_eq!(entry.hash(), first);
+
+        testing::git(&["reset", "--hard"]).unwrap();
+        assert_eq!(testing::messages("refs/heads/main").last().unwrap(), moved);
+        assert_eq!(
+            testing::messages(HEAD).last().unwrap(),
+            "reset: moving to HEAD"
+        );
+    }
+
+    #[test]
+    fn it_leaves_the_log_of_a_detached_head_that_did_not_move() {
+        let (_root, _cwd) = testing::command_repo("reset-detached");
+        let first = testing::commit(&[("a", "1\n")], "first");
+        testing::commit(&[("a", "2\n")], "second");
+        testing::git(&["checkout", "--detach", &first]).unwrap();
+        let logged = testing::messages(HEAD).len();
+
+        testing::git(&["reset", "--hard"]).unwrap();
+        testing::git(&["reset", "--hard", &first]).unwrap();
+        assert_eq!(testing::messages(HEAD).len(), logged);
+
+        testing::git(&["reset", "--hard", "main"]).unwrap();
+        assert_eq!(testing::messages(HEAD).len(), logged + 1);
+        assert_eq!(
+            testing::messages(HEAD).last().unwrap(),
+            "reset: moving to main"
+        );
+    }
+}
@@ -0,0 +1,31 @@
+use super::{index::Index, refs::HEAD, revision, Error, Result};
+use crate::checkout;
+
+// NOTE:
+// Restores the working tree from the index by default. With `staged`, the
+// index is restored from HEAD instead, and both of them with `worktree` too.
+// A `source` commit or tree replaces the index or HEAD as the source.
+pub(crate) fn run(
+    source: Option<String>,
+    staged: bool,
+    worktree: bool,
+    paths: Vec<String>,
+) -> Result<()> {
+    if paths.is_empty() {
+        return Err(Error::from("you must specify path(s) to restore"));
+    }
+    let worktree = worktree || !staged;
+    let source = source.or_else(|| staged.then(|| HEAD.to_string()));
+    let tree = match source {
+        Some(rev) => Some(revision::resolve_tree(".", &rev).map_err(|_| {
+            let msg = format!("could not resolve {rev}");
+            Error::from(msg.as_str())
+        })?),
+        None => None,
+    };
+
+    let mut index = Index::lock(".")?;
+    checkout::restore_paths(".", &mut index, tree.as_deref(), &paths, staged, worktree)?;
+    index.commit()?;
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use super::{
+    refs::{self, Head, HEAD},
+    revision::{self, Rev},
+    Error, Result,
+};
+
+pub(crate) fn run(
+    revs: Vec<String>,
+    verify: bool,
+    short: Option<Option<usize>>,
+    abbrev_ref: bool,
+) -> Result<()> {
+    let format = |hash: &str| match short {
+        Some(len) => revision::abbrev(".", hash, len),
+        None => Ok(hash.to_string()),
+    };
+
+    if verify {
+        let [rev] = revs.as_slice() else {
+            return Err(Error::from("Needed a single revision"));
+        };
+        let hash =
+            revision::resolve(".", rev).map_err(|_| Error::from("Needed a single revision"))?;
+        println!("{}", format(&hash)?);
+        return Ok(());
+    }
+
+    for rev in revs {
+        if abbrev_ref {
+            if let Some(refname) = revision::dwim_ref(".", &rev)? {
+                println!("{}", abbrev_refname(&refname)?);
+                continue;
+            }
+        }
+        for rev in revision::parse_revs(".", &rev)? {
+            match rev {
+                Rev::Include(hash) => println!("{}", format(&hash)?),
+                Rev::Exclude(hash) => println!("^{}", format(&hash)?),
+            }
+        }
+    }
+    Ok(())
+}
+
+// NOTE:
+// HEAD is shown as the branch it points to.
+fn abbrev_refname(refname: &str) -> Result<String> {
+    if refname == HEAD {
+        return Ok(match refs::head(".")? {
+            Head::Branch(refname) => refs::short_name(&refname).to_string(),
+            Head::Detached(_) => HEAD.to_string(),
+        });
+    }
+    let name = ["refs/heads/", "refs/tags/", "refs/remotes/"]
+        .iter()
+        .find_map(|prefix| refname.strip_prefix(prefix))
+        .unwrap_or(refname);
+    Ok(name.to_string())
+}
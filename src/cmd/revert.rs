@@ -0,0 +1,7 @@
+use super::cherry_pick::{self, Action};
+use super::Result;
+use crate::sequencer::Command;
+
+pub(crate) fn run(action: Action) -> Result<()> {
+    cherry_pick::replay(Command::Revert, action)
+}
@@ -0,0 +1,191 @@
+use super::{
+    git_object::tree::Mode,
+    index::Index,
+    refs::{self, HEAD},
+    revision, worktree, Error, Result,
+};
+use crate::checkout;
+use std::fs;
+use std::path::Path;
+
+pub(crate) fn run(paths: Vec<String>, cached: bool, recursive: bool, force: bool) -> Result<()> {
+    let mut index = Index::lock(".")?;
+    let mut removed: Vec<String> = vec![];
+
+    for pathspec in paths {
+        let spec = worktree::normalize(&pathspec);
+        let matched: Vec<String> = index
+            .entries()
+            .iter()
+            .map(|e| e.path().to_string())
+            .filter(|path| worktree::matches(&spec, path))
+            .collect();
+
+        if matched.is_empty() {
+            let msg = format!("pathspec '{pathspec}' did not match any files");
+            return Err(Error::from(msg.as_str()));
+        }
+
+        if !recursive && matched.iter().any(|path| *path != spec) {
+            let msg = format!("not removing '{pathspec}' recursively without -r");
+            return Err(Error::from(msg.as_str()));
+        }
+
+        removed.extend(matched);
+    }
+    if !force {
+        check_local_changes(&index, &removed, cached)?;
+    }
+
+    for path in removed {
+        if index.remove(&path) {
+            if !cached {
+                worktree::remove_file(".", &path)?;
+            }
+            println!("rm '{path}'");
+        }
+    }
+
+    index.commit()?;
+    Ok(())
+}
+
+// NOTE:
+// Without -f, a file is only removed when what is staged for it is in HEAD
+// and in the working tree, so that nothing is lost. With --cached the file
+// stays in the working tree, and only an entry matching neither is refused.
+// Files already gone from the working tree have nothing to lose.
+fn check_local_changes(index: &Index, paths: &[String], cached: bool) -> Result<()> {
+    let root = Path::new(".");
+    let head = match refs::resolve(root, HEAD)? {
+        Some(hash) => Some(revision::resolve_tree(root, &hash)?),
+        None => None,
+    };
+    let head_files = checkout::tree_files(root, head.as_deref())?;
+
+    let (mut both, mut staged, mut local): (Vec<&str>, Vec<&str>, Vec<&str>) =
+        (vec![], vec![], vec![]);
+    for path in paths {
+        let Some(entry) = index
+            .entries()
+            .iter()
+            .find(|e| e.path() == path && e.stage() == 0)
+        else {
+            continue;
+        };
+        match fs::symlink_metadata(root.join(path)) {
+            Err(_) => continue,
+            Ok(meta) if meta.is_dir() && entry.mode() != Mode::Gitlink => continue,
+            Ok(_) => {}
+        }
+
+        let local_changes = checkout::is_modified(root, index, path)?;
+        let staged_changes = head_files.get(path) != Some(&(entry.mode(), entry.hash()));
+        if local_changes && staged_changes {
+            both.push(path);
+        } else if !cached {
+            if staged_changes {
+                staged.push(path);
+            }
+            if local_changes {
+                local.push(path);
+            }
+        }
+    }
+
+    let mut errors: Vec<String> = vec![];
+    let lists = [
+        (
+            both,
+            "staged content different from both the\nfile and the HEAD",
+            "(use -f to force removal)",
+        ),
+        (
+            staged,
+            "changes staged in the index",
+            "(use --cached to keep the file, or -f to force removal)",
+        ),
+        (
+            local,
+            "local modifications",
+            "(use --cached to keep the file, or -f to force removal)",
+        ),
+    ];
+    for (files, what, hint) in lists.iter().filter(|(files, _, _)| !files.is_empty()) {
+        let (noun, verb) = match files.len() {
+            1 => ("file", "has"),
+            _ => ("files", "have"),
+        };
+        errors.push(format!(
+            "the following {noun} {verb} {what}:\n    {}\n{hint}",
+            files.join("\n    ")
+        ));
+    }
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(Error::from(errors.join("\n").as_str())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn staged_paths() -> Vec<String> {
+        let index = Index::open(".").unwrap();
+        index
+            .entries()
+            .iter()
+            .map(|e| e.path().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn it_refuses_to_lose_changes_without_force() {
+        let (_root, _cwd) = testing::command_repo("rm-refuse");
+        testing::commit(&[("a", "a\n"), ("b", "b\n"), ("c", "c\n")], "init");
+        fs::write("a", "local\n").unwrap();
+        fs::write("b", "staged\n").unwrap();
+        testing::git(&["add", "b"]).unwrap();
+        fs::write("b", "both\n").unwrap();
+        fs::write("n", "new\n").unwrap();
+        testing::git(&["add", "n"]).unwrap();
+
+        let err = run(vec![".".into()], false, true, false).unwrap_err();
+        assert!(err.to_string().ends_with(
+            "the following file has staged content different from both the\n\
+             file and the HEAD:\n    b\n(use -f to force removal)\n\
+             the following file has changes staged in the index:\n    n\n\
+             (use --cached to keep the file, or -f to force removal)\n\
+             the following file has local modifications:\n    a\n\
+             (use --cached to keep the file, or -f to force removal)"
+        ));
+        assert_eq!(staged_paths(), vec!["a", "b", "c", "n"]);
+
+        let err = run(vec!["a".into(), "b".into()], true, false, false).unwrap_err();
+        assert!(err.to_string().contains("the following file has staged"));
+
+        run(vec!["a".into(), "n".into()], true, false, false).unwrap();
+        assert_eq!(staged_paths(), vec!["b", "c"]);
+        assert!(Path::new("a").exists() && Path::new("n").exists());
+
+        run(vec!["b".into(), "c".into()], false, false, true).unwrap();
+        assert!(staged_paths().is_empty());
+        assert!(!Path::new("b").exists() && !Path::new("c").exists());
+    }
+
+    #[test]
+    fn it_removes_files_deleted_from_the_working_tree() {
+        let (_root, _cwd) = testing::command_repo("rm-deleted");
+        testing::commit(&[("dir/a", "a\n"), ("dir/b", "b\n")], "init");
+        fs::write("dir/b", "changed\n").unwrap();
+        testing::git(&["add", "dir/b"]).unwrap();
+        fs::remove_file("dir/b").unwrap();
+
+        assert!(run(vec!["dir".into()], false, false, false).is_err());
+        run(vec!["dir".into()], false, true, false).unwrap();
+        assert!(staged_paths().is_empty());
+        assert!(!Path::new("dir").exists());
+    }
+}
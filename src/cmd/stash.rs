@@ -0,0 +1,561 @@
+use super::{
+    git_object::commit::Commit,
+    ident,
+    ignore::Excludes,
+    index::Index,
+    pretty,
+    refs::{self, Expected, HEAD},
+    reset::{self, Mode},
+    revision,
+    status::{self, Format},
+    worktree, Error, GitObject, Result,
+};
+use crate::{
+    checkout,
+    diff::files::{self, Source},
+    merge, reflog, sequencer,
+};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+const STASH_REF: &str = "refs/stash";
+const LABELS: (&str, &str) = ("Updated upstream", "Stashed changes");
+
+#[derive(Debug)]
+pub enum Action {
+    Push {
+        message: Option<String>,
+        include_untracked: bool,
+        quiet: bool,
+    },
+    List,
+    Apply {
+        stash: Option<String>,
+        index: bool,
+        quiet: bool,
+    },
+    Pop {
+        stash: Option<String>,
+        index: bool,
+        quiet: bool,
+    },
+    Drop {
+        stash: Option<String>,
+        quiet: bool,
+    },
+}
+
+pub(crate) fn run(action: Action) -> Result<()> {
+    match action {
+        Action::Push {
+            message,
+            include_untracked,
+            quiet,
+        } => push(message, include_untracked, quiet),
+        Action::List => {
+            for (i, entry) in reflog::read(".", STASH_REF)?.iter().rev().enumerate() {
+                println!("stash@{{{i}}}: {}", entry.message());
+            }
+            Ok(())
+        }
+        Action::Apply {
+            stash,
+            index,
+            quiet,
+        } => {
+            let (name, hash) = find(stash)?;
+            apply(&name, &hash, index, quiet)
+        }
+        Action::Pop {
+            stash,
+            index,
+            quiet,
+        } => {
+            let (name, hash) = find(stash)?;
+            let n = entry(&name)?;
+            if let Err(err) = apply(&name, &hash, index, quiet) {
+                println!("The stash entry is kept in case you need it again.");
+                return Err(err);
+            }
+            drop_entry(&name, &hash, n, quiet)
+        }
+        Action::Drop { stash, quiet } => {
+            let (name, hash) = find(stash)?;
+            let n = entry(&name)?;
+            drop_entry(&name, &hash, n, quiet)
+        }
+    }
+}
+
+// NOTE:
+// A stash is a commit of the working tree whose parents are HEAD, a
+// commit of the index on top of HEAD, and with `include_untracked` a root
+// commit of the untracked files. It goes on top of the log of refs/stash, and
+// the working tree is reset to HEAD after it, without the untracked files.
+fn push(message: Option<String>, include_untracked: bool, quiet: bool) -> Result<()> {
+    let head = refs::head(".")?;
+    let Some(head_hash) = refs::resolve(".", HEAD)? else {
+        return Err(Error::from("You do not have the initial commit yet"));
+    };
+    let head_tree = revision::resolve_tree(".", &head_hash)?;
+    let mut index = Index::open(".")?;
+    let index_tree = index.write_tree(".")?.hash().hex();
+    let changes = files::pairs(".", &Source::Index, &Source::Worktree, &[])?;
+    let untracked = match include_untracked {
+        true => untracked_files(&index)?,
+        false => vec![],
+    };
+    if index_tree == head_tree && changes.is_empty() && untracked.is_empty() {
+        if !quiet {
+            println!("No local changes to save");
+        }
+        return Ok(());
+    }
+
+    let commit = GitObject::open_commit(".", &head_hash)?;
+    let branch = head.branch_name().unwrap_or("(no branch)");
+    let on = format!(
+        "{branch}: {} {}",
+        revision::abbrev(".", &head_hash, None)?,
+        pretty::subject(commit.comment())
+    );
+    let index_commit = write_commit(
+        index_tree,
+        format!("index on {on}\n"),
+        vec![head_hash.clone()],
+    )?;
+    let mut parents = vec![head_hash, index_commit];
+    if !untracked.is_empty() {
+        let mut untracked_index = Index::default();
+        for path in untracked.iter() {
+            untracked_index.add(worktree::stage(".", path.clone())?);
+        }
+        let tree = untracked_index.write_tree(".")?.hash().hex();
+        parents.push(write_commit(
+            tree,
+            format!("untracked files on {on}\n"),
+            vec![],
+        )?);
+    }
+
+    // NOTE:
+    // The files of HEAD that are only removed from the index are kept.
+    let tracked: BTreeSet<&str> = index.entries().iter().map(|e| e.path()).collect();
+    let kept: Vec<String> = checkout::tree_files(Path::new("."), Some(&head_tree))?
+        .into_keys()
+        .filter(|path| !tracked.contains(path.as_str()) && fs::symlink_metadata(path).is_ok())
+        .collect();
+    for path in kept {
+        index.add(worktree::stage(".", path)?);
+    }
+    for pair in changes.iter() {
+        match pair.new_file() {
+            Some(_) => index.add(worktree::stage(".", pair.path().to_string())?),
+            None => {
+                index.remove(pair.path());
+            }
+        }
+    }
+    let worktree_tree = index.write_tree(".")?.hash().hex();
+    let message = match message {
+        Some(message) => format!("On {branch}: {message}"),
+        None => format!("WIP on {on}"),
+    };
+    let hash = write_commit(worktree_tree, message.clone(), parents)?;
+
+    let old = refs::resolve(".", STASH_REF)?;
+    let expected = match old.as_deref() {
+        Some(old) => Expected::Hash(old),
+        None => Expected::Missing,
+    };
+    refs::update(".", STASH_REF, &hash, expected)?;
+    let committer = ident::committer(".")?;
+    reflog::append(".", STASH_REF, old.as_deref(), &hash, &committer, &message)?;
+    if !quiet {
+        println!("Saved working directory and index state {message}");
+    }
+
+    reset::reset(Mode::Hard, HEAD)?;
+    for path in untracked {
+        worktree::remove_file(".", &path)?;
+    }
+    Ok(())
+}
+
+// NOTE:
+// The untracked files one by one, including the ones of untracked directories.
+fn untracked_files(index: &Index) -> Result<Vec<String>> {
+    let tracked: Vec<&str> = index.entries().iter().map(|e| e.path()).collect();
+    let mut excludes = Excludes::open(".")?;
+    let mut paths: Vec<String> = vec![];
+    for path in worktree::untracked(".", &tracked)? {
+        match path.strip_suffix('/') {
+            Some(dir) => paths.extend(worktree::unignored_files(".", dir, &mut excludes)?),
+            None => paths.push(path),
+        }
+    }
+    Ok(paths)
+}
+
+// NOTE:
+// The message is written as it is, since git doesn't end the one of the stash
+// itself with a newline, unlike the ones of the other commits.
+fn write_commit(tree: String, message: String, parents: Vec<String>) -> Result<String> {
+    let verbatim = !message.ends_with('\n');
+    let commit = Commit::new(
+        tree,
+        message,
+        parents,
+        ident::author(".")?,
+        ident::committer(".")?,
+    );
+    let mut bytes = commit.serialize();
+    if verbatim {
+        bytes.pop();
+    }
+    let obj = GitObject::Commit(Box::new(Commit::from_bytes(&bytes)));
+    obj.write(".")?;
+    Ok(obj.hash().hex())
+}
+
+// NOTE:
+// The entry is given as "stash@{N}" or only N, and is the latest one without
+// it. Returns the name it's shown with and its commit.
+fn find(stash: Option<String>) -> Result<(String, String)> {
+    if refs::resolve(".", STASH_REF)?.is_none() {
+        return Err(Error::from("No stash entries found."));
+    }
+    let name = match stash {
+        None => format!("{STASH_REF}@{{0}}"),
+        Some(n) if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) => {
+            format!("{STASH_REF}@{{{n}}}")
+        }
+        Some(name) => name,
+    };
+    let hash = match revision::resolve(".", &name) {
+        Ok(hash) => hash,
+        Err(err) if name.contains("@{") => return Err(err),
+        Err(_) => {
+            let msg = format!("{name} is not a valid reference");
+            return Err(Error::from(msg.as_str()));
+        }
+    };
+    let commit = GitObject::open_commit(".", &hash)?;
+    if !(2..=3).contains(&commit.parents().len()) {
+        let msg = format!("'{name}' is not a stash-like commit");
+        return Err(Error::from(msg.as_str()));
+    }
+    Ok((name, hash))
+}
+
+// NOTE:
+// N of "stash@{N}", since only the entries of refs/stash can be dropped.
+fn entry(name: &str) -> Result<usize> {
+    let (refname, n) = match name.split_once("@{") {
+        Some((refname, suffix)) => (
+            refname,
+            suffix.strip_suffix('}').and_then(|n| n.parse().ok()),
+        ),
+        None => (name, Some(0)),
+    };
+    match (revision::dwim_ref(".", refname)?.as_deref(), n) {
+        (Some(STASH_REF), Some(n)) => Ok(n),
+        _ => {
+            let msg = format!("'{name}' is not a stash reference");
+            Err(Error::from(msg.as_str()))
+        }
+    }
+}
+
+// NOTE:
+// Merges the changes of the working tree since the stash's HEAD into the
+// current index and working tree. Then the changes are left unstaged, apart
+// from the new files, unless `restore_index` where the changes of the index
+// are applied to it as well. The untracked files can't be in the way.
+fn apply(name: &str, hash: &str, restore_index: bool, quiet: bool) -> Result<()> {
+    let stash = GitObject::open_commit(".", hash)?;
+    let mut index = Index::lock(".")?;
+    if index.is_unmerged() {
+        let paths: BTreeSet<&str> = index
+            .entries()
+            .iter()
+            .filter(|e| e.stage() != 0)
+            .map(|e| e.path())
+            .collect();
+        let msg: Vec<String> = paths
+            .into_iter()
+            .map(|path| format!("{path}: needs merge"))
+            .collect();
+        return Err(Error::from(msg.join("\n").as_str()));
+    }
+    let current_tree = index.write_tree(".")?.hash().hex();
+    let base_tree = revision::resolve_tree(".", &stash.parents()[0])?;
+    let index_tree = revision::resolve_tree(".", &stash.parents()[1])?;
+
+    // NOTE:
+    // An index like the one of the stash's HEAD or the current one has nothing
+    // to apply.
+    let staged_tree = match restore_index && index_tree != base_tree && index_tree != current_tree {
+        true => {
+            let result =
+                merge::merge_trees(".", Some(&base_tree), &current_tree, &index_tree, LABELS)?;
+            if !result.conflicts().is_empty() {
+                return Err(Error::from("conflicts in index. Try without --index."));
+            }
+            // NOTE:
+            // git resets to HEAD at this point, which only shows in the logs.
+            reset::reset(Mode::Soft, HEAD)?;
+            Some(result.write_tree(".")?)
+        }
+        false => None,
+    };
+
+    // NOTE:
+    // git shows this even when quiet.
+    if stash.tree() == base_tree {
+        println!("Already up to date.");
+    }
+    let merged = sequencer::apply(
+        ".",
+        &mut index,
+        Some(&base_tree),
+        &current_tree,
+        stash.tree(),
+        LABELS,
+    )
+    .and_then(|result| {
+        index.commit()?;
+        if !quiet {
+            for message in result.messages() {
+                println!("{message}");
+            }
+        }
+        if !result.conflicts().is_empty() {
+            let msg = match restore_index {
+                true => "Index was not unstashed.".to_string(),
+                false => format!("could not apply {name}"),
+            };
+            return Err(Error::from(msg.as_str()));
+        }
+        let mut index = Index::lock(".")?;
+        match staged_tree.as_deref() {
+            Some(tree) => checkout::reset_index(".", &mut index, Some(tree), &[])?,
+            None => unstage(&mut index, &current_tree)?,
+        }
+        index.commit()?;
+        Ok(())
+    });
+    let untracked = match stash.parents().get(2) {
+        Some(parent) => restore_untracked(parent),
+        None => Ok(()),
+    };
+    if !quiet {
+        status::run(Format::Long, false)?;
+    }
+    merged.and(untracked)
+}
+
+// NOTE:
+// The files that are not new are put back in the index like they were.
+fn unstage(index: &mut Index, tree: &str) -> Result<()> {
+    let paths: Vec<String> = files::pairs(".", &Source::Tree(tree.into()), &Source::Index, &[])?
+        .iter()
+        .filter(|pair| pair.old_file().is_some())
+        .map(|pair| pair.path().to_string())
+        .collect();
+    if paths.is_empty() {
+        return Ok(());
+    }
+    checkout::reset_index(".", index, Some(tree), &paths)
+}
+
+// NOTE:
+// The files that are in the way are left alone, and the others are written
+// all the same.
+fn restore_untracked(hash: &str) -> Result<()> {
+    let root = Path::new(".");
+    let tree = revision::resolve_tree(root, hash)?;
+    let mut errors: Vec<String> = vec![];
+    for (path, (mode, hash)) in checkout::tree_files(root, Some(&tree))? {
+        if fs::symlink_metadata(&path).is_ok() {
+            errors.push(format!("{path} already exists, no checkout"));
+            continue;
+        }
+        checkout::write_file(root, &path, mode, hash)?;
+    }
+    if errors.is_empty() {
+        return Ok(());
+    }
+    errors.push("could not restore untracked files from stash".into());
+    Err(Error::from(errors.join("\n").as_str()))
+}
+
+// NOTE:
+// Removes the entry from the log of refs/stash, which then points to the
+// latest one left, or goes away with the last one.
+fn drop_entry(name: &str, hash: &str, n: usize, quiet: bool) -> Result<()> {
+    match reflog::remove(".", STASH_REF, n)?.last() {
+        Some(latest) => refs::update(".", STASH_REF, latest.hash(), Expected::Any)?,
+        None => {
+            refs::delete(".", STASH_REF)?;
+            reflog::delete(".", STASH_REF)?;
+        }
+    }
+    if !quiet {
+        println!("Dropped {name} ({hash})");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn files(commit: &str) -> Vec<(String, String)> {
+        let tree = revision::resolve_tree(".", commit).unwrap();
+        checkout::tree_files(Path::new("."), Some(&tree))
+            .unwrap()
+            .into_iter()
+            .map(|(path, (_, hash))| (path, hash.hex()))
+            .collect()
+    }
+
+    fn file(path: &str, content: &str) -> (String, String) {
+        (path.to_string(), testing::blob(content).unwrap())
+    }
+
+    #[test]
+    fn it_stashes_the_index_the_working_tree_and_the_untracked_files() {
+        let (_root, _cwd) = testing::command_repo("stash-commits");
+        let head = testing::commit(&[("a", "1\n"), ("b", "1\n")], "first");
+        fs::write("a", "2\n").unwrap();
+        testing::git(&["add", "a"]).unwrap();
+        fs::write("a", "3\n").unwrap();
+        fs::write("b", "2\n").unwrap();
+        fs::write("u", "new\n").unwrap();
+        fs::create_dir_all(".git/info").unwrap();
+        fs::write(".git/info/exclude", "*.log\n").unwrap();
+        fs::write("x.log", "ignored\n").unwrap();
+
+        testing::git(&["stash", "push", "-q", "-u", "-m", "work"]).unwrap();
+        let stash = refs::resolve(".", STASH_REF).unwrap().unwrap();
+        let commit = GitObject::open_commit(".", &stash).unwrap();
+        assert_eq!(commit.comment(), "On main: work");
+        assert_eq!(commit.parents().len(), 3);
+        assert_eq!(commit.parents()[0], head);
+        assert_eq!(files(&stash), vec![file("a", "3\n"), file("b", "2\n")]);
+
+        let index = GitObject::open_commit(".", &commit.parents()[1]).unwrap();
+        assert_eq!(index.parents(), std::slice::from_ref(&head));
+        assert_eq!(
+            index.comment(),
+            format!("index on main: {} first\n", &head[..7])
+        );
+        assert_eq!(
+            files(&commit.parents()[1]),
+            vec![file("a", "2\n"), file("b", "1\n")]
+        );
+
+        let untracked = GitObject::open_commit(".", &commit.parents()[2]).unwrap();
+        assert!(untracked.parents().is_empty());
+        assert_eq!(
+            untracked.comment(),
+            format!("untracked files on main: {} first\n", &head[..7])
+        );
+        assert_eq!(files(&commit.parents()[2]), vec![file("u", "new\n")]);
+
+        assert_eq!(fs::read_to_string("a").unwrap(), "1\n");
+        assert_eq!(fs::read_to_string("b").unwrap(), "1\n");
+        assert!(!Path::new("u").exists());
+        assert!(Path::new("x.log").exists());
+        assert_eq!(refs::resolve(".", HEAD).unwrap(), Some(head));
+    }
+
+    #[test]
+    fn it_pushes_lists_applies_pops_and_drops_entries() {
+        let (_root, _cwd) = testing::command_repo("stash-entries");
+        testing::commit(&[("a", "1\n")], "first");
+        testing::git(&["stash", "-q"]).unwrap();
+        assert_eq!(refs::resolve(".", STASH_REF).unwrap(), None);
+
+        fs::write("a", "2\n").unwrap();
+        testing::git(&["stash", "-q"]).unwrap();
+        fs::write("a", "3\n").unwrap();
+        testing::git(&["stash", "push", "-q", "-m", "three"]).unwrap();
+        let latest = refs::resolve(".", STASH_REF).unwrap().unwrap();
+        let messages = testing::messages(STASH_REF);
+        assert_eq!(messages[1], "On main: three");
+        assert!(messages[0].starts_with("WIP on main: "));
+        assert!(messages[0].ends_with(" first"));
+        testing::git(&["stash", "list"]).unwrap();
+
+        testing::git(&["stash", "apply", "-q", "1"]).unwrap();
+        assert_eq!(fs::read_to_string("a").unwrap(), "2\n");
+        assert_eq!(testing::staged("a"), testing::blob("1\n"));
+        assert_eq!(testing::messages(STASH_REF).len(), 2);
+
+        testing::git(&["reset", "-q", "--hard"]).unwrap();
+        testing::git(&["stash", "drop", "-q", "stash@{1}"]).unwrap();
+        assert_eq!(testing::messages(STASH_REF), ["On main: three"]);
+        assert_eq!(refs::resolve(".", STASH_REF).unwrap(), Some(latest));
+
+        testing::git(&["stash", "pop", "-q"]).unwrap();
+        assert_eq!(fs::read_to_string("a").unwrap(), "3\n");
+        assert!(testing::messages(STASH_REF).is_empty());
+        assert_eq!(refs::resolve(".", STASH_REF).unwrap(), None);
+        let err = testing::git(&["stash", "pop"]).unwrap_err();
+        assert!(err.to_string().ends_with("No stash entries found."));
+    }
+
+    #[test]
+    fn it_restores_the_index_only_when_asked_to() {
+        let (_root, _cwd) = testing::command_repo("stash-index");
+        testing::commit(&[("a", "1\n"), ("b", "1\n")], "first");
+        fs::write("a", "2\n").unwrap();
+        fs::write("c", "new\n").unwrap();
+        testing::git(&["add", "a", "c"]).unwrap();
+        fs::write("b", "2\n").unwrap();
+        testing::git(&["stash", "-q"]).unwrap();
+        assert_eq!(testing::staged("a"), testing::blob("1\n"));
+        assert_eq!(testing::staged("c"), None);
+
+        testing::git(&["stash", "apply", "-q"]).unwrap();
+        assert_eq!(testing::staged("a"), testing::blob("1\n"));
+        assert_eq!(testing::staged("b"), testing::blob("1\n"));
+        assert_eq!(testing::staged("c"), testing::blob("new\n"));
+        assert_eq!(fs::read_to_string("a").unwrap(), "2\n");
+
+        testing::git(&["reset", "-q", "--hard"]).unwrap();
+        testing::git(&["stash", "pop", "-q", "--index"]).unwrap();
+        assert_eq!(testing::staged("a"), testing::blob("2\n"));
+        assert_eq!(testing::staged("b"), testing::blob("1\n"));
+        assert_eq!(testing::staged("c"), testing::blob("new\n"));
+        assert_eq!(fs::read_to_string("b").unwrap(), "2\n");
+        assert!(testing::messages(STASH_REF).is_empty());
+    }
+
+    #[test]
+    fn it_keeps_an_entry_that_does_not_apply_cleanly() {
+        let (_root, _cwd) = testing::command_repo("stash-conflict");
+        testing::commit(&[("a", "1\n")], "first");
+        fs::write("a", "2\n").unwrap();
+        testing::git(&["stash", "-q"]).unwrap();
+        let stash = refs::resolve(".", STASH_REF).unwrap();
+        testing::commit(&[("a", "3\n")], "second");
+
+        let err = testing::git(&["stash", "pop", "-q"]).unwrap_err();
+        assert!(err.to_string().ends_with("could not apply refs/stash@{0}"));
+        assert_eq!(refs::resolve(".", STASH_REF).unwrap(), stash);
+        assert_eq!(testing::messages(STASH_REF).len(), 1);
+        assert!(Index::open(".").unwrap().is_unmerged());
+        assert!(fs::read_to_string("a")
+            .unwrap()
+            .contains("<<<<<<< Updated upstream\n3\n=======\n2\n>>>>>>> Stashed changes\n"));
+
+        let err = testing::git(&["stash", "pop", "-q"]).unwrap_err();
+        assert!(err.to_string().ends_with("a: needs merge"));
+        assert_eq!(testing::messages(STASH_REF).len(), 1);
+    }
+}
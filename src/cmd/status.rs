@@ -0,0 +1,408 @@
+use super::{
+    git_object::tree::Mode,
+    refs::{self, Head, HEAD},
+    revision, Result, Sha1Hash,
+};
+use crate::{
+    merge,
+    sequencer::{self, Command, Instruction, PickHead, Rebase, Sequence},
+    status::{Change, Conflict, FileStatus, Status},
+};
+
+const ZERO_HASH: &str = "0000000000000000000000000000000000000000";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Long,
+    Short,
+    PorcelainV1,
+    PorcelainV2,
+}
+
+pub(crate) fn run(format: Format, branch: bool) -> Result<()> {
+    let status = Status::collect(".")?;
+    let head = refs::head(".")?;
+    let head_hash = refs::resolve(".", HEAD)?;
+
+    match format {
+        Format::Long => print_long(&status, &head, head_hash.as_deref()),
+        Format::Short | Format::PorcelainV1 => {
+            if branch {
+                print_short_branch(&head, head_hash.as_deref());
+            }
+            print_short(&status);
+            Ok(())
+        }
+        Format::PorcelainV2 => {
+            if branch {
+                println!(
+                    "# branch.oid {}",
+                    head_hash.as_deref().unwrap_or("(initial)")
+                );
+                println!(
+                    "# branch.head {}",
+                    head.branch_name().unwrap_or("(detached)")
+                );
+            }
+            print_porcelain_v2(&status);
+            Ok(())
+        }
+    }
+}
+
+fn print_long(status: &Status, head: &Head, head_hash: Option<&str>) -> Result<()> {
+    let rebase = Rebase::open(".");
+    if let Some(rebase) = &rebase {
+        let onto = rebase.read("onto")?.unwrap_or_default();
+        println!(
+            "interactive rebase in progress; onto {}",
+            revision::abbrev(".", &onto, None)?
+        );
+    } else {
+        match (head, crate::status::detached_from(".")?) {
+            (Head::Branch(_), _) => println!("On branch {}", head.branch_name().unwrap_or(HEAD)),
+            (Head::Detached(_), Some((name, true))) => println!("HEAD detached at {name}"),
+            (Head::Detached(_), Some((name, false))) => println!("HEAD detached from {name}"),
+            (Head::Detached(_), None) => println!("Not currently on any branch."),
+        }
+    }
+    if head_hash.is_none() {
+        println!("\nNo commits yet\n");
+    }
+    let merging = merge::merge_head(".")?.is_some();
+    if merging && !status.conflicts().is_empty() {
+        println!("You have unmerged paths.");
+        println!("  (fix conflicts and run \"git commit\")");
+        println!("  (use \"git merge --abort\" to abort the merge)\n");
+    } else if merging {
+        println!("All conflicts fixed but you are still merging.");
+        println!("  (use \"git commit\" to conclude merge)\n");
+    }
+    if let Some(rebase) = &rebase {
+        print_rebase(rebase, status)?;
+    }
+    let pick_head = sequencer::pick_head(".")?;
+    print_pick(pick_head.as_ref(), status)?;
+    // NOTE:
+    // git shows the conflicts of a revert as those of a commit.
+    let merging = merging || matches!(pick_head, Some((Command::Pick, _)));
+
+    let staged: Vec<(&FileStatus, Change)> = status
+        .files()
+        .iter()
+        .filter_map(|file| file.staged().map(|change| (file, change)))
+        .collect();
+    let unstaged: Vec<(&FileStatus, Change)> = status
+        .files()
+        .iter()
+        .filter_map(|file| file.unstaged().map(|change| (file, change)))
+        .collect();
+
+    if !staged.is_empty() {
+        println!("Changes to be committed:");
+        if head_hash.is_none() {
+            println!("  (use \"git rm --cached <file>...\" to unstage)");
+        } else if !merging {
+            println!("  (use \"git restore --staged <file>...\" to unstage)");
+        }
+        for (file, change) in staged.iter() {
+            let label = format!("{}:", label(*change));
+            println!("\t{label:<12}{}", name(file));
+        }
+        println!();
+    }
+
+    if !status.conflicts().is_empty() {
+        println!("Unmerged paths:");
+        if !merging {
+            println!("  (use \"git restore --staged <file>...\" to unstage)");
+        }
+        let removed = status
+            .conflicts()
+            .iter()
+            .any(|c| c.stages()[1].is_none() || c.stages()[2].is_none());
+        if removed {
+            println!("  (use \"git add/rm <file>...\" as appropriate to mark resolution)");
+        } else {
+            println!("  (use \"git add <file>...\" to mark resolution)");
+        }
+        for conflict in status.conflicts() {
+            let label = format!("{}:", conflict_label(conflict));
+            println!("\t{label:<17}{}", conflict.path());
+        }
+        println!();
+    }
+
+    if !unstaged.is_empty() {
+        println!("Changes not staged for commit:");
+        if unstaged
+            .iter()
+            .any(|(_, change)| *change == Change::Deleted)
+        {
+            println!("  (use \"git add/rm <file>...\" to update what will be committed)");
+        } else {
+            println!("  (use \"git add <file>...\" to update what will be committed)");
+        }
+        println!("  (use \"git restore <file>...\" to discard changes in working directory)");
+        for (file, change) in unstaged.iter() {
+            let label = format!("{}:", label(*change));
+            println!("\t{label:<12}{}", file.path());
+        }
+        println!();
+    }
+
+    if !status.untracked().is_empty() {
+        println!("Untracked files:");
+        println!("  (use \"git add <file>...\" to include in what will be committed)");
+        for path in status.untracked() {
+            println!("\t{path}");
+        }
+        println!();
+    }
+
+    if !staged.is_empty() {
+        return Ok(());
+    }
+    if !unstaged.is_empty() || !status.conflicts().is_empty() {
+        println!("no changes added to commit (use \"git add\" and/or \"git commit -a\")");
+    } else if !status.untracked().is_empty() {
+        println!("nothing added to commit but untracked files present (use \"git add\" to track)");
+    } else if head_hash.is_none() {
+        println!("nothing to commit (create/copy files and use \"git add\" to track)");
+    } else {
+        println!("nothing to commit, working tree clean");
+    }
+    Ok(())
+}
+
+// NOTE:
+// The last commands done and the next ones of the rebase in progress, then
+// what to do to go on with it.
+fn print_rebase(rebase: &Rebase, status: &Status) -> Result<()> {
+    let (done, todo) = (rebase.done()?, rebase.todo()?);
+    let lines = |instructions: &[Instruction]| -> Result<()> {
+        for instruction in instructions {
+            println!("   {}", instruction.abbreviated(".")?);
+        }
+        Ok(())
+    };
+    match done.len() {
+        0 => println!("No commands done."),
+        1 => println!("Last command done (1 command done):"),
+        n => println!("Last commands done ({n} commands done):"),
+    }
+    lines(&done[done.len().saturating_sub(2)..])?;
+    if done.len() > 2 {
+        println!(
+            "  (see more in file {})",
+            rebase.dir().join("done").display()
+        );
+    }
+    match todo.len() {
+        0 => println!("No commands remaining."),
+        1 => println!("Next command to do (1 remaining command):"),
+        n => println!("Next commands to do ({n} remaining commands):"),
+    }
+    lines(&todo[..todo.len().min(2)])?;
+
+    let head_name = rebase.read("head-name")?.unwrap_or_default();
+    let onto = rebase.read("onto")?.unwrap_or_default();
+    let target = match head_name.strip_prefix("refs/heads/") {
+        Some(branch) => format!(
+            " branch '{branch}' on '{}'",
+            revision::abbrev(".", &onto, None)?
+        ),
+        None => String::new(),
+    };
+    if !status.conflicts().is_empty() {
+        println!("You are currently rebasing{target}.");
+        println!("  (fix conflicts and then run \"git rebase --continue\")");
+        println!("  (use \"git rebase --skip\" to skip this patch)");
+        println!("  (use \"git rebase --abort\" to check out the original branch)");
+    } else if merge::merge_message(".")?.is_some() {
+        println!("You are currently rebasing{target}.");
+        println!("  (all conflicts fixed: run \"git rebase --continue\")");
+    } else if target.is_empty() {
+        println!("You are currently editing a commit during a rebase.");
+        print_edit_hints();
+    } else {
+        println!("You are currently editing a commit while rebasing{target}.");
+        print_edit_hints();
+    }
+    println!();
+    Ok(())
+}
+
+// NOTE:
+// A cherry-pick or revert of several commits is told apart by its todo list,
+// and the commit it stopped at is only named for a single one.
+fn print_pick(pick_head: Option<&PickHead>, status: &Status) -> Result<()> {
+    let sequence = match Sequence::open(".") {
+        Some(sequence) => sequence.todo()?.first().map(Instruction::command),
+        None => None,
+    };
+    let (command, hash) = match (sequence, pick_head) {
+        (Some(command), _) => (command, None),
+        (None, Some((command, hash))) => (*command, Some(hash)),
+        (None, None) => return Ok(()),
+    };
+    let (name, title, verb) = match command {
+        Command::Revert => ("revert", "Revert", "reverting"),
+        _ => ("cherry-pick", "Cherry-pick", "cherry-picking"),
+    };
+    match hash {
+        Some(hash) => println!(
+            "You are currently {verb} commit {}.",
+            revision::abbrev(".", hash, None)?
+        ),
+        None => println!("{title} currently in progress."),
+    }
+    if !status.conflicts().is_empty() {
+        println!("  (fix conflicts and run \"git {name} --continue\")");
+    } else if hash.is_none() {
+        println!("  (run \"git {name} --continue\" to continue)");
+    } else {
+        println!("  (all conflicts fixed: run \"git {name} --continue\")");
+    }
+    println!("  (use \"git {name} --skip\" to skip this patch)");
+    println!("  (use \"git {name} --abort\" to cancel the {name} operation)\n");
+    Ok(())
+}
+
+fn print_edit_hints() {
+    println!("  (use \"git commit --amend\" to amend the current commit)");
+    println!("  (use \"git rebase --continue\" once you are satisfied with your changes)");
+}
+
+fn print_short_branch(head: &Head, head_hash: Option<&str>) {
+    match (head.branch_name(), head_hash) {
+        (Some(branch), None) => println!("## No commits yet on {branch}"),
+        (Some(branch), Some(_)) => println!("## {branch}"),
+        (None, _) => println!("## HEAD (no branch)"),
+    }
+}
+
+// NOTE:
+// Tracked and unmerged paths are sorted together, untracked ones come last.
+fn print_short(status: &Status) {
+    let mut lines: Vec<(&str, String)> = vec![];
+    for file in status.files() {
+        let code = |change: Option<Change>| change.map_or(' ', |c| c.code());
+        let line = format!(
+            "{}{} {}",
+            code(file.staged()),
+            code(file.unstaged()),
+            name(file)
+        );
+        lines.push((file.path(), line));
+    }
+    for conflict in status.conflicts() {
+        lines.push((
+            conflict.path(),
+            format!("{} {}", conflict.code(), conflict.path()),
+        ));
+    }
+    lines.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (_, line) in lines {
+        println!("{line}");
+    }
+    for path in status.untracked() {
+        println!("?? {path}");
+    }
+}
+
+// NOTE:
+// "1 XY sub mH mI mW hH hI path" for changed paths, "2 XY sub mH mI mW hH hI
+// Xscore path<tab>origPath" for renamed or copied ones, "u XY sub m1 m2 m3 mW
+// h1 h2 h3 path" for unmerged ones and "? path" for untracked ones.
+fn print_porcelain_v2(status: &Status) {
+    let mut lines: Vec<(&str, String)> = vec![];
+    for file in status.files() {
+        let code = |change: Option<Change>| change.map_or('.', |c| c.code());
+        let (kind, score, orig) = match (file.staged(), file.rename()) {
+            (Some(change), Some(rename)) => (
+                '2',
+                format!(" {}{}", change.code(), rename.similarity()),
+                format!("\t{}", rename.from()),
+            ),
+            _ => ('1', String::new(), String::new()),
+        };
+        let line = format!(
+            "{kind} {}{} N... {} {} {} {} {}{score} {}{orig}",
+            code(file.staged()),
+            code(file.unstaged()),
+            mode(file.head().map(|(mode, _)| mode)),
+            mode(file.index().map(|(mode, _)| mode)),
+            mode(file.worktree()),
+            hash(file.head().map(|(_, hash)| hash)),
+            hash(file.index().map(|(_, hash)| hash)),
+            file.path()
+        );
+        lines.push((file.path(), line));
+    }
+    for conflict in status.conflicts() {
+        let stages = conflict.stages();
+        let line = format!(
+            "u {} N... {} {} {} {} {} {} {} {}",
+            conflict.code(),
+            mode(stages[0].map(|(mode, _)| mode)),
+            mode(stages[1].map(|(mode, _)| mode)),
+            mode(stages[2].map(|(mode, _)| mode)),
+            mode(conflict.worktree()),
+            hash(stages[0].map(|(_, hash)| hash)),
+            hash(stages[1].map(|(_, hash)| hash)),
+            hash(stages[2].map(|(_, hash)| hash)),
+            conflict.path()
+        );
+        lines.push((conflict.path(), line));
+    }
+    lines.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (_, line) in lines {
+        println!("{line}");
+    }
+    for path in status.untracked() {
+        println!("? {path}");
+    }
+}
+
+fn label(change: Change) -> &'static str {
+    match change {
+        Change::Added => "new file",
+        Change::Modified => "modified",
+        Change::Deleted => "deleted",
+        Change::TypeChanged => "typechange",
+        Change::Renamed => "renamed",
+        Change::Copied => "copied",
+    }
+}
+
+// NOTE:
+// A staged rename or copy is shown as "old -> new".
+fn name(file: &FileStatus) -> String {
+    match file.rename() {
+        Some(rename) => format!("{} -> {}", rename.from(), file.path()),
+        None => file.path().to_string(),
+    }
+}
+
+fn conflict_label(conflict: &Conflict) -> &'static str {
+    match conflict.code() {
+        "DD" => "both deleted",
+        "AU" => "added by us",
+        "UD" => "deleted by them",
+        "UA" => "added by them",
+        "DU" => "deleted by us",
+        "AA" => "both added",
+        _ => "both modified",
+    }
+}
+
+fn mode(mode: Option<Mode>) -> String {
+    format!("{:06o}", mode.map_or(0, u32::from))
+}
+
+fn hash(hash: Option<Sha1Hash>) -> String {
+    hash.map_or_else(|| ZERO_HASH.to_string(), |hash| hash.hex())
+}
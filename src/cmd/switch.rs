@@ -0,0 +1,33 @@
+use super::{checkout, refs, revision, Error, Result};
+
+// NOTE:
+// Unlike checkout, switching to a commit has to be asked for with `detach`.
+pub(crate) fn run(
+    target: Option<String>,
+    new_branch: Option<String>,
+    detach: bool,
+    force: bool,
+) -> Result<()> {
+    let target = target
+        .map(|name| revision::expand_previous(".", &name))
+        .transpose()?;
+    if new_branch.is_none() && !detach {
+        let name = target
+            .as_deref()
+            .ok_or(Error::from("missing branch or commit argument"))?;
+        if refs::resolve(".", &refs::branch_ref(name))?.is_none() {
+            let msg = if refs::resolve(".", &format!("refs/tags/{name}"))?.is_some() {
+                format!("a branch is expected, got tag '{name}'")
+            } else if revision::resolve_commit(".", name).is_ok() {
+                format!(
+                    "a branch is expected, got commit '{name}'\n\
+                     hint: If you want to detach HEAD at the commit, try again with the --detach option."
+                )
+            } else {
+                format!("invalid reference: {name}")
+            };
+            return Err(Error::from(msg.as_str()));
+        }
+    }
+    checkout::switch(target, new_branch, detach, force)
+}
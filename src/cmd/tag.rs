@@ -0,0 +1,304 @@
+use super::{
+    ident, pretty,
+    refs::{self, Expected, HEAD},
+    revision, Error, GitObject, Result,
+};
+use crate::{config::Config, glob};
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+const TAG_PREFIX: &str = "refs/tags/";
+const SIGNATURE_START: &str = "-----BEGIN PGP SIGNATURE-----";
+const DEFAULT_GPG: &str = "gpg";
+
+#[derive(Debug)]
+pub enum Action {
+    List {
+        patterns: Vec<String>,
+    },
+    Create {
+        name: String,
+        rev: Option<String>,
+        message: Option<String>,
+        annotate: bool,
+        force: bool,
+    },
+    Delete {
+        names: Vec<String>,
+    },
+    Verify {
+        names: Vec<String>,
+    },
+}
+
+pub(crate) fn run(action: Action) -> Result<()> {
+    match action {
+        Action::List { patterns } => list(&patterns),
+        Action::Create {
+            name,
+            rev,
+            message,
+            annotate,
+            force,
+        } => create(&name, rev.as_deref(), message, annotate, force),
+        Action::Delete { names } => names.iter().try_for_each(|name| delete(name)),
+        Action::Verify { names } => names.iter().try_for_each(|name| verify(name)),
+    }
+}
+
+fn list(patterns: &[String]) -> Result<()> {
+    for name in matching(patterns)? {
+        println!("{name}");
+    }
+    Ok(())
+}
+
+// NOTE:
+// The names of the tags matching any of the patterns, or all of them without
+// a pattern.
+fn matching(patterns: &[String]) -> Result<Vec<String>> {
+    Ok(refs::list(".", TAG_PREFIX)?
+        .into_iter()
+        .map(|(refname, _)| refname[TAG_PREFIX.len()..].to_string())
+        .filter(|name| {
+            patterns.is_empty()
+                || patterns
+                    .iter()
+                    .any(|pattern| glob::wildmatch(pattern, name, false))
+        })
+        .collect())
+}
+
+// NOTE:
+// A lightweight tag is a ref pointing at the object itself, and an annotated
+// one (with a message) points at a tag object that names it.
+fn create(
+    name: &str,
+    rev: Option<&str>,
+    message: Option<String>,
+    annotate: bool,
+    force: bool,
+) -> Result<()> {
+    refs::check_tag_name(name)?;
+    let refname = format!("{TAG_PREFIX}{name}");
+    let old = refs::resolve(".", &refname)?;
+    if old.is_some() && !force {
+        let msg = format!("tag '{name}' already exists");
+        return Err(Error::from(msg.as_str()));
+    }
+
+    let rev = rev.unwrap_or(HEAD);
+    let object = revision::resolve(".", rev).map_err(|_| {
+        let msg = format!("Failed to resolve '{rev}' as a valid ref.");
+        Error::from(msg.as_str())
+    })?;
+    let hash = if annotate || message.is_some() {
+        let message = message.ok_or(Error::from("no tag message?"))?;
+        let kind = GitObject::open_from_hash(".", &object)?.type_name();
+        let obj = GitObject::new_tag(
+            object,
+            kind.into(),
+            name.into(),
+            ident::committer(".")?,
+            pretty::cleanup(&message),
+        )?;
+        obj.write(".")?;
+        obj.hash().hex()
+    } else {
+        object
+    };
+
+    let expected = match old.as_deref() {
+        Some(old) if old == hash => return Ok(()),
+        Some(old) => Expected::Hash(old),
+        None => Expected::Missing,
+    };
+    refs::update(".", &refname, &hash, expected)?;
+    if let Some(old) = old {
+        println!(
+            "Updated tag '{name}' (was {})",
+            revision::abbrev(".", &old, None)?
+        );
+    }
+    Ok(())
+}
+
+fn delete(name: &str) -> Result<()> {
+    let refname = format!("{TAG_PREFIX}{name}");
+    let Some(hash) = refs::resolve(".", &refname)? else {
+        let msg = format!("tag '{name}' not found.");
+        return Err(Error::from(msg.as_str()));
+    };
+    refs::delete(".", &refname)?;
+    println!(
+        "Deleted tag '{name}' (was {})",
+        revision::abbrev(".", &hash, None)?
+    );
+    Ok(())
+}
+
+// NOTE:
+// Prints the tag without its signature, and has gpg (or "gpg.program") check
+// the signature against it.
+fn verify(name: &str) -> Result<()> {
+    let refname = format!("{TAG_PREFIX}{name}");
+    let hash = refs::resolve(".", &refname)?.ok_or_else(|| {
+        let msg = format!("tag '{name}' not found.");
+        Error::from(msg.as_str())
+    })?;
+    let tag = match GitObject::open_from_hash(".", &hash)? {
+        GitObject::Tag(tag) => tag,
+        obj => {
+            let msg = format!(
+                "{name}: cannot verify a non-tag object of type {}.",
+                obj.type_name()
+            );
+            return Err(Error::from(msg.as_str()));
+        }
+    };
+
+    let bytes = tag.serialize();
+    let message = tag.message();
+    let start = match message.find(SIGNATURE_START) {
+        Some(pos) if pos == 0 || message[..pos].ends_with('\n') => {
+            bytes.len() - message.len() + pos
+        }
+        _ => {
+            print!("{}", String::from_utf8_lossy(&bytes));
+            return Err(Error::from("no signature found"));
+        }
+    };
+    let (payload, signature) = bytes.split_at(start);
+    print!("{}", String::from_utf8_lossy(payload));
+
+    let path = std::env::temp_dir().join(format!("git-tag-signature-{}", std::process::id()));
+    fs::write(&path, signature)?;
+    let program = Config::load(".")?
+        .get("gpg.program")
+        .unwrap_or(DEFAULT_GPG)
+        .to_string();
+    let verified = Command::new(program)
+        .arg("--verify")
+        .arg(&path)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .and_then(|mut child| {
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(payload)?;
+            }
+            child.wait()
+        });
+    fs::remove_file(&path)?;
+
+    match verified {
+        Ok(status) if status.success() => Ok(()),
+        Ok(_) => {
+            let msg = format!("could not verify the tag '{name}'");
+            Err(Error::from(msg.as_str()))
+        }
+        Err(_) => Err(Error::from("cannot run gpg: No such file or directory")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const SIGNATURE: &str =
+        "-----BEGIN PGP SIGNATURE-----\n\nc2lnbmF0dXJl\n-----END PGP SIGNATURE-----\n";
+
+    fn tag(name: &str) -> Option<String> {
+        refs::resolve(".", &format!("{TAG_PREFIX}{name}")).unwrap()
+    }
+
+    #[test]
+    fn it_creates_lightweight_and_annotated_tags() {
+        let (_root, _cwd) = testing::command_repo("tag-create");
+        let first = testing::commit(&[("a", "1\n")], "first");
+        let second = testing::commit(&[("a", "2\n")], "second");
+
+        testing::git(&["tag", "v1", &first]).unwrap();
+        assert_eq!(tag("v1"), Some(first.clone()));
+        let err = testing::git(&["tag", "v1"]).unwrap_err();
+        assert!(err.to_string().ends_with("tag 'v1' already exists"));
+        testing::git(&["tag", "-f", "v1"]).unwrap();
+        assert_eq!(tag("v1"), Some(second.clone()));
+
+        testing::git(&["tag", "-a", "-m", "Release\n\n\n", "v2"]).unwrap();
+        let hash = tag("v2").unwrap();
+        let GitObject::Tag(annotated) = GitObject::open_from_hash(".", &hash).unwrap() else {
+            panic!("v2 is not an annotated tag");
+        };
+        assert_eq!(annotated.object(), second);
+        assert_eq!(annotated.message(), "Release\n");
+        let text = String::from_utf8(annotated.serialize()).unwrap();
+        assert!(text.contains("type commit\ntag v2\ntagger A U Thor <author@example.com>"));
+        assert_eq!(revision::resolve_commit(".", "v2").unwrap(), second);
+
+        let err = testing::git(&["tag", "-a", "v3"]).unwrap_err();
+        assert!(err.to_string().ends_with("no tag message?"));
+        assert_eq!(tag("v3"), None);
+    }
+
+    #[test]
+    fn it_deletes_tags() {
+        let (_root, _cwd) = testing::command_repo("tag-delete");
+        testing::commit(&[("a", "1\n")], "first");
+        testing::git(&["tag", "v1"]).unwrap();
+        testing::git(&["tag", "-m", "two", "v2"]).unwrap();
+
+        testing::git(&["tag", "-d", "v1", "v2"]).unwrap();
+        assert_eq!((tag("v1"), tag("v2")), (None, None));
+        let err = testing::git(&["tag", "--delete", "v1"]).unwrap_err();
+        assert!(err.to_string().ends_with("tag 'v1' not found."));
+    }
+
+    #[test]
+    fn it_lists_tags_matching_patterns() {
+        let (_root, _cwd) = testing::command_repo("tag-list");
+        testing::commit(&[("a", "1\n")], "first");
+        for name in ["v1.0", "v1.1", "v2.0", "nightly"] {
+            testing::git(&["tag", name]).unwrap();
+        }
+
+        assert_eq!(matching(&[]).unwrap(), ["nightly", "v1.0", "v1.1", "v2.0"]);
+        assert_eq!(matching(&["v1.*".into()]).unwrap(), ["v1.0", "v1.1"]);
+        assert_eq!(
+            matching(&["*.0".into(), "night*".into()]).unwrap(),
+            ["nightly", "v1.0", "v2.0"]
+        );
+        assert!(matching(&["v3*".into()]).unwrap().is_empty());
+        testing::git(&["tag", "-l", "v1.*"]).unwrap();
+    }
+
+    #[test]
+    fn it_verifies_signed_tags_with_the_configured_program() {
+        let (_root, _cwd) = testing::command_repo("tag-verify");
+        testing::commit(&[("a", "1\n")], "first");
+        let message = format!("Signed\n{SIGNATURE}");
+        testing::git(&["tag", "-m", &message, "signed"]).unwrap();
+        testing::git(&["tag", "-m", "Unsigned", "unsigned"]).unwrap();
+        testing::git(&["tag", "light"]).unwrap();
+
+        testing::git(&["config", "gpg.program", "true"]).unwrap();
+        testing::git(&["tag", "-v", "signed"]).unwrap();
+        testing::git(&["config", "gpg.program", "false"]).unwrap();
+        let err = testing::git(&["tag", "--verify", "signed"]).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("could not verify the tag 'signed'"));
+
+        let err = testing::git(&["tag", "-v", "unsigned"]).unwrap_err();
+        assert!(err.to_string().ends_with("no signature found"));
+        let err = testing::git(&["tag", "-v", "light"]).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("light: cannot verify a non-tag object of type commit."));
+        let err = testing::git(&["tag", "-v", "missing"]).unwrap_err();
+        assert!(err.to_string().ends_with("tag 'missing' not found."));
+    }
+}
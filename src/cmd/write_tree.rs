@@ -1,7 +1,19 @@
-use super::{GitObject, Result};
+use super::{index::Index, GitObject, Result};
 
+// NOTE:
+// Trees are built from the staged content when there is an index.
+// Without it, the working directory is hashed as it is.
 pub fn run() -> Result<()> {
-    let obj = GitObject::new_tree(".")?;
+    let obj = if Index::exists(".") {
+        let mut index = Index::lock(".")?;
+        let obj = index.write_tree(".")?;
+        index.commit()?;
+        obj
+    } else {
+        let obj = GitObject::new_tree(".")?;
+        obj.write(".")?;
+        obj
+    };
     print!("{}", obj.hash().hex());
-    obj.write(".")
+    Ok(())
 }
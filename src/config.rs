@@ -0,0 +1,1020 @@
+use super::{glob, refs, Error, Result, GIT_DIR};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CONFIG_FILE: &str = "config";
+const SYSTEM_CONFIG: &str = "/etc/gitconfig";
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    System,
+    Global,
+    Local,
+}
+
+impl Scope {
+    // NOTE:
+    // The files of the scope in the order they are read. The global scope has
+    // both the XDG file and ~/.gitconfig, and the latter wins.
+    fn paths<P: AsRef<Path>>(&self, root: P) -> Vec<PathBuf> {
+        match self {
+            Self::System => {
+                if env::var_os("GIT_CONFIG_NOSYSTEM").is_some() {
+                    return vec![];
+                }
+                match env::var_os("GIT_CONFIG_SYSTEM") {
+                    Some(path) => vec![path.into()],
+                    None => vec![SYSTEM_CONFIG.into()],
+                }
+            }
+            Self::Global => match env::var_os("GIT_CONFIG_GLOBAL") {
+                Some(path) => vec![path.into()],
+                None => [
+                    xdg_path(CONFIG_FILE),
+                    home_dir().map(|home| home.join(".gitconfig")),
+                ]
+                .into_iter()
+                .flatten()
+                .collect(),
+            },
+            Self::Local => vec![root.as_ref().join(GIT_DIR).join(CONFIG_FILE)],
+        }
+    }
+
+    // NOTE:
+    // The file that `config --<scope>` writes to. Git only writes to the XDG file
+    // when it exists and ~/.gitconfig doesn't.
+    pub fn path<P: AsRef<Path>>(&self, root: P) -> Result<PathBuf> {
+        let mut paths = self.paths(root);
+        match (self, paths.len()) {
+            (Self::Global, 2) if !paths[1].exists() && paths[0].exists() => Ok(paths.remove(0)),
+            (_, 0) => Err(Error::from("no config file to write")),
+            _ => Ok(paths.remove(paths.len() - 1)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    key: String,
+    value: Option<String>,
+}
+
+impl Entry {
+    pub fn key(&self) -> &str {
+        self.key.as_str()
+    }
+
+    // NOTE:
+    // A key without "=" has no value. It means true as a boolean.
+    pub fn value(&self) -> Option<&str> {
+        self.value.as_deref()
+    }
+}
+
+// NOTE:
+// Keys are kept as "<section>.<name>" or "<section>.<subsection>.<name>" where
+// section and name are lowercased because git treats them case-insensitively.
+// Subsections are case-sensitive.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    entries: Vec<Entry>,
+}
+
+impl Config {
+    // NOTE:
+    // System, global and repository files are read in this order so that the
+    // later ones override the earlier ones.
+    pub fn load<P: AsRef<Path>>(root: P) -> Result<Self> {
+        let root = root.as_ref();
+        let mut config = Self::default();
+        for scope in [Scope::System, Scope::Global, Scope::Local] {
+            config
+                .entries
+                .extend(Self::load_scope(root, scope)?.entries);
+        }
+        Ok(config)
+    }
+
+    pub fn load_scope<P: AsRef<Path>>(root: P, scope: Scope) -> Result<Self> {
+        let root = root.as_ref();
+        let mut config = Self::default();
+        for path in scope.paths(root) {
+            config.entries.extend(Self::load_file(root, path)?.entries);
+        }
+        Ok(config)
+    }
+
+    // NOTE:
+    // Reads the file with its includes. A missing file is the same as an empty one.
+    pub fn load_file<P: AsRef<Path>, Q: AsRef<Path>>(root: P, path: Q) -> Result<Self> {
+        let mut config = Self::default();
+        let context = IncludeContext::new(root.as_ref());
+        config.read_file(&context, path.as_ref(), 0)?;
+        Ok(config)
+    }
+
+    // NOTE:
+    // The last value wins when the key is set more than once.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.get_all(key).pop()
+    }
+
+    // NOTE:
+    // A pathname value. "~/" is the home directory and relative paths are
+    // relative to the root of the working tree.
+    pub fn get_path<P: AsRef<Path>>(&self, root: P, key: &str) -> Option<PathBuf> {
+        self.get(key).map(|value| expand_path(value, root.as_ref()))
+    }
+
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        let Ok(key) = Key::parse(key) else {
+            return vec![];
+        };
+        let key = key.canonical();
+        self.entries
+            .iter()
+            .filter(|entry| entry.key == key)
+            .map(|entry| entry.value().unwrap_or_default())
+            .collect()
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    // NOTE:
+    // Parses the text of a single file without following its includes, and
+    // fails with the number of the first bad line.
+    #[cfg(test)]
+    pub(crate) fn parse(text: &str) -> std::result::Result<Self, usize> {
+        parse_items(text).map(Self::from_items)
+    }
+
+    fn from_items(items: Vec<Item>) -> Self {
+        let entries = items
+            .into_iter()
+            .filter_map(|item| match item {
+                Item::Entry { key, value, .. } => Some(Entry { key, value }),
+                Item::Section { .. } => None,
+            })
+            .collect();
+        Self { entries }
+    }
+
+    fn read_file(&mut self, context: &IncludeContext, path: &Path, depth: usize) -> Result<()> {
+        if depth > MAX_INCLUDE_DEPTH {
+            let msg = format!(
+                "exceeded maximum include depth ({MAX_INCLUDE_DEPTH}) while including {}",
+                path.display()
+            );
+            return Err(Error::from(msg.as_str()));
+        }
+        if !path.is_file() {
+            return Ok(());
+        }
+
+        let text = fs::read_to_string(path)?;
+        let items = parse_items(&text).map_err(|line| {
+            let msg = format!("bad config line {line} in file {}", path.display());
+            Error::from(msg.as_str())
+        })?;
+        let base = path.parent().unwrap_or(Path::new("."));
+
+        for entry in Self::from_items(items).entries {
+            let include = match entry.value() {
+                Some(value) if context.includes(&entry.key, base) => Some(expand_path(value, base)),
+                _ => None,
+            };
+            self.entries.push(entry);
+            if let Some(include) = include {
+                self.read_file(context, &include, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// NOTE:
+// What the conditions of "includeIf" are evaluated against.
+#[derive(Debug)]
+struct IncludeContext {
+    git_dir: Option<String>,
+    branch: Option<String>,
+}
+
+impl IncludeContext {
+    fn new(root: &Path) -> Self {
+        let git_dir = root.join(GIT_DIR);
+        let git_dir = git_dir
+            .canonicalize()
+            .ok()
+            .map(|path| path.to_string_lossy().to_string());
+        let branch = refs::head(root)
+            .ok()
+            .and_then(|head| head.branch_name().map(String::from));
+        Self { git_dir, branch }
+    }
+
+    // NOTE:
+    // Whether the key is an "include.path" or an "includeIf.<condition>.path"
+    // whose condition holds.
+    fn includes(&self, key: &str, base: &Path) -> bool {
+        if key == "include.path" {
+            return true;
+        }
+        let Some(condition) = key
+            .strip_prefix("includeif.")
+            .and_then(|rest| rest.strip_suffix(".path"))
+        else {
+            return false;
+        };
+
+        if let Some(pattern) = condition.strip_prefix("gitdir:") {
+            self.matches_git_dir(pattern, base, false)
+        } else if let Some(pattern) = condition.strip_prefix("gitdir/i:") {
+            self.matches_git_dir(pattern, base, true)
+        } else if let Some(pattern) = condition.strip_prefix("onbranch:") {
+            let pattern = with_trailing_stars(pattern.to_string());
+            self.branch
+                .as_ref()
+                .is_some_and(|branch| glob::wildmatch(&pattern, branch, true))
+        } else {
+            false
+        }
+    }
+
+    // NOTE:
+    // Relative patterns match at any depth, "./" is relative to the including
+    // file and a trailing "/" matches everything inside the directory.
+    fn matches_git_dir(&self, pattern: &str, base: &Path, ignore_case: bool) -> bool {
+        let Some(git_dir) = self.git_dir.as_ref() else {
+            return false;
+        };
+
+        let pattern = if let Some(rest) = pattern.strip_prefix("./") {
+            base.join(rest).to_string_lossy().to_string()
+        } else {
+            expand_path(pattern, Path::new(""))
+                .to_string_lossy()
+                .to_string()
+        };
+        let pattern = if pattern.starts_with('/') {
+            pattern
+        } else {
+            format!("**/{pattern}")
+        };
+        let pattern = with_trailing_stars(pattern);
+
+        if ignore_case {
+            glob::wildmatch(&pattern.to_lowercase(), &git_dir.to_lowercase(), true)
+        } else {
+            glob::wildmatch(&pattern, git_dir, true)
+        }
+    }
+}
+
+fn with_trailing_stars(pattern: String) -> String {
+    if pattern.ends_with('/') {
+        format!("{pattern}**")
+    } else {
+        pattern
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME").map(PathBuf::from)
+}
+
+// NOTE:
+// A file of git under the XDG config directory, like "config" or "ignore".
+pub fn xdg_path(name: &str) -> Option<PathBuf> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home_dir()?.join(".config"),
+    };
+    Some(config_home.join("git").join(name))
+}
+
+// NOTE:
+// "~/" is the home directory and relative paths are relative to the base.
+fn expand_path(value: &str, base: &Path) -> PathBuf {
+    match (value.strip_prefix("~/"), home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => base.join(value),
+    }
+}
+
+// NOTE:
+// A key given on the command line like "remote.origin.url". The section and the
+// name are the parts before the first and after the last dot.
+#[derive(Debug, Clone, PartialEq)]
+struct Key {
+    section: String,
+    subsection: Option<String>,
+    name: String,
+}
+
+impl Key {
+    fn parse(key: &str) -> Result<Self> {
+        let (Some((section, _)), Some((rest, name))) = (key.split_once('.'), key.rsplit_once('.'))
+        else {
+            let msg = format!("key does not contain a section: {key}");
+            return Err(Error::from(msg.as_str()));
+        };
+
+        let subsection = (rest.len() > section.len()).then(|| rest[(section.len() + 1)..].into());
+        let valid = !section.is_empty()
+            && section.chars().all(is_section_char)
+            && name.starts_with(|c: char| c.is_ascii_alphabetic())
+            && name.chars().all(is_name_char)
+            && !subsection
+                .as_ref()
+                .is_some_and(|s: &String| s.contains('\n'));
+        if !valid {
+            let msg = format!("invalid key: {key}");
+            return Err(Error::from(msg.as_str()));
+        }
+
+        Ok(Self {
+            section: section.into(),
+            subsection,
+            name: name.into(),
+        })
+    }
+
+    fn prefix(&self) -> String {
+        canonical_prefix(&self.section, self.subsection.as_deref())
+    }
+
+    fn canonical(&self) -> String {
+        format!("{}.{}", self.prefix(), self.name.to_lowercase())
+    }
+
+    fn header(&self) -> String {
+        match self.subsection.as_ref() {
+            Some(subsection) => {
+                let subsection = subsection.replace('\\', "\\\\").replace('"', "\\\"");
+                format!("[{} \"{subsection}\"]", self.section)
+            }
+            None => format!("[{}]", self.section),
+        }
+    }
+
+    fn line(&self, value: &str) -> String {
+        format!("\t{} = {}", self.name, quote(value))
+    }
+}
+
+fn section_key(name: &str) -> Result<Key> {
+    Key::parse(&format!("{name}.name"))
+}
+
+fn canonical_prefix(section: &str, subsection: Option<&str>) -> String {
+    match subsection {
+        Some(subsection) => format!("{}.{subsection}", section.to_lowercase()),
+        None => section.to_lowercase(),
+    }
+}
+
+fn is_section_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '.'
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-'
+}
+
+// NOTE:
+// Values with leading or trailing spaces or comment characters are written in
+// double quotes, and newlines, tabs, quotes and backslashes are escaped.
+fn quote(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    if value.starts_with(' ') || value.ends_with(' ') || value.contains(['#', ';']) {
+        format!("\"{escaped}\"")
+    } else {
+        escaped
+    }
+}
+
+// NOTE:
+// Line numbers are 0-based. An entry spans several lines when its value is
+// continued with a backslash at the end of line.
+#[derive(Debug, Clone, PartialEq)]
+enum Item {
+    Section {
+        prefix: String,
+        line: usize,
+    },
+    Entry {
+        key: String,
+        value: Option<String>,
+        lines: (usize, usize),
+    },
+}
+
+impl Item {
+    fn prefix(&self) -> &str {
+        match self {
+            Self::Section { prefix, .. } => prefix,
+            Self::Entry { key, .. } => key.rsplit_once('.').map_or("", |(prefix, _)| prefix),
+        }
+    }
+
+    fn last_line(&self) -> usize {
+        match self {
+            Self::Section { line, .. } => *line,
+            Self::Entry { lines, .. } => lines.1,
+        }
+    }
+}
+
+// NOTE:
+// Returns the 1-based line number of the first error.
+fn parse_items(text: &str) -> std::result::Result<Vec<Item>, usize> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        pos: 0,
+        line: 0,
+    };
+    parser.parse().map_err(|_| parser.line + 1)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_line(&mut self) {
+        while let Some(c) = self.next() {
+            if c == '\n' {
+                break;
+            }
+        }
+    }
+
+    fn skip_blanks(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\r')) {
+            self.next();
+        }
+    }
+
+    fn parse(&mut self) -> std::result::Result<Vec<Item>, ()> {
+        let mut items: Vec<Item> = vec![];
+        let mut prefix: Option<String> = None;
+
+        // NOTE:
+        // A UTF-8 BOM at the start of the file is skipped.
+        if self.peek() == Some('\u{feff}') {
+            self.next();
+        }
+
+        while let Some(c) = self.peek() {
+            match c {
+                c if c.is_whitespace() => {
+                    self.next();
+                }
+                '#' | ';' => self.skip_line(),
+                '[' => {
+                    let line = self.line;
+                    let section = self.parse_section()?;
+                    items.push(Item::Section {
+                        prefix: section.clone(),
+                        line,
+                    });
+                    prefix = Some(section);
+                }
+                c if c.is_ascii_alphabetic() => {
+                    let prefix = prefix.as_ref().ok_or(())?;
+                    let start = self.line;
+                    let (name, value) = self.parse_entry()?;
+                    items.push(Item::Entry {
+                        key: format!("{prefix}.{name}"),
+                        value,
+                        lines: (start, self.line),
+                    });
+                    // NOTE:
+                    // The entry ends with the newline, which belongs to it.
+                    if self.chars.get(self.pos.wrapping_sub(1)) == Some(&'\n') {
+                        if let Some(Item::Entry { lines, .. }) = items.last_mut() {
+                            lines.1 -= 1;
+                        }
+                    }
+                }
+                _ => return Err(()),
+            }
+        }
+
+        Ok(items)
+    }
+
+    // NOTE:
+    // "[section]", "[section \"subsection\"]" or the deprecated "[section.subsection]"
+    // whose subsection is case-insensitive.
+    fn parse_section(&mut self) -> std::result::Result<String, ()> {
+        self.next();
+        let mut name = String::new();
+        loop {
+            match self.next().ok_or(())? {
+                ']' => {
+                    return match name.split_once('.') {
+                        Some((section, subsection)) if !section.is_empty() => {
+                            Ok(canonical_prefix(section, Some(&subsection.to_lowercase())))
+                        }
+                        _ if !name.is_empty() && !name.starts_with('.') => Ok(name.to_lowercase()),
+                        _ => Err(()),
+                    };
+                }
+                c if is_section_char(c) => name.push(c),
+                ' ' | '\t' if !name.is_empty() => break,
+                _ => return Err(()),
+            }
+        }
+
+        self.skip_blanks();
+        if self.next() != Some('"') {
+            return Err(());
+        }
+        let mut subsection = String::new();
+        loop {
+            match self.next().ok_or(())? {
+                '"' => break,
+                '\n' => return Err(()),
+                '\\' => match self.next().ok_or(())? {
+                    '\n' => return Err(()),
+                    c => subsection.push(c),
+                },
+                c => subsection.push(c),
+            }
+        }
+        if self.next() != Some(']') {
+            return Err(());
+        }
+        Ok(canonical_prefix(&name, Some(&subsection)))
+    }
+
+    fn parse_entry(&mut self) -> std::result::Result<(String, Option<String>), ()> {
+        let mut name = String::new();
+        while let Some(c) = self.peek().filter(|&c| is_name_char(c)) {
+            name.push(c.to_ascii_lowercase());
+            self.next();
+        }
+
+        self.skip_blanks();
+        match self.peek() {
+            None => Ok((name, None)),
+            Some('\n') => {
+                self.next();
+                Ok((name, None))
+            }
+            Some('#' | ';') => {
+                self.skip_line();
+                Ok((name, None))
+            }
+            Some('=') => {
+                self.next();
+                Ok((name, Some(self.parse_value()?)))
+            }
+            _ => Err(()),
+        }
+    }
+
+    // NOTE:
+    // Whitespace is trimmed at both ends and collapsed into spaces unless quoted.
+    // A backslash at the end of line continues the value on the next line.
+    fn parse_value(&mut self) -> std::result::Result<String, ()> {
+        let mut value = String::new();
+        let mut quoted = false;
+        let mut spaces = 0;
+
+        while let Some(c) = self.next() {
+            match c {
+                '\n' if quoted => return Err(()),
+                '\n' => break,
+                '#' | ';' if !quoted => {
+                    self.skip_line();
+                    break;
+                }
+                c if !quoted && c.is_whitespace() => {
+                    if !value.is_empty() {
+                        spaces += 1;
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+
+            value.extend(std::iter::repeat(' ').take(spaces));
+            spaces = 0;
+            match c {
+                '\\' => match self.next().ok_or(())? {
+                    '\n' => {}
+                    't' => value.push('\t'),
+                    'b' => value.push('\x08'),
+                    'n' => value.push('\n'),
+                    '"' => value.push('"'),
+                    '\\' => value.push('\\'),
+                    _ => return Err(()),
+                },
+                '"' => quoted = !quoted,
+                c => value.push(c),
+            }
+        }
+
+        if quoted {
+            return Err(());
+        }
+        Ok(value)
+    }
+}
+
+// NOTE:
+// A single config file edited in place. Lines other than the ones set or unset
+// are kept as they are, including comments.
+#[derive(Debug)]
+pub struct ConfigFile {
+    path: PathBuf,
+    lines: Vec<String>,
+}
+
+impl ConfigFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let lines = if path.is_file() {
+            fs::read_to_string(path)?
+                .lines()
+                .map(String::from)
+                .collect()
+        } else {
+            vec![]
+        };
+        Ok(Self {
+            path: path.into(),
+            lines,
+        })
+    }
+
+    // NOTE:
+    // Replaces the value of the key, refusing to overwrite more than one value.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let key = Key::parse(key)?;
+        let entries = self.entries_of(&key)?;
+        match entries.as_slice() {
+            [] => self.insert(&key, value),
+            [(start, end)] => {
+                self.lines.splice(start..=end, [key.line(value)]);
+                Ok(())
+            }
+            _ => {
+                let msg = format!(
+                    "warning: {0} has multiple values\nerror: cannot overwrite multiple values with a single value\n       Use --add to add a value to {0}.",
+                    key.canonical()
+                );
+                Err(Error::from(msg.as_str()))
+            }
+        }
+    }
+
+    pub fn add(&mut self, key: &str, value: &str) -> Result<()> {
+        let key = Key::parse(key)?;
+        self.insert(&key, value)
+    }
+
+    pub fn unset(&mut self, key: &str, all: bool) -> Result<()> {
+        let key = Key::parse(key)?;
+        let entries = self.entries_of(&key)?;
+        if entries.is_empty() {
+            let msg = format!("key not found: {}", key.canonical());
+            return Err(Error::from(msg.as_str()));
+        }
+        if entries.len() > 1 && !all {
+            let msg = format!("warning: {} has multiple values", key.canonical());
+            return Err(Error::from(msg.as_str()));
+        }
+
+        for (start, end) in entries.into_iter().rev() {
+            self.lines.drain(start..=end);
+        }
+        Ok(())
+    }
+
+    // NOTE:
+    // Renames every section named like "branch.main", or removes it with the
+    // entries under it when there's no new name. Returns whether it was found.
+    pub fn rename_section(&mut self, name: &str, new_name: Option<&str>) -> Result<bool> {
+        let prefix = section_key(name)?.prefix();
+        let header = new_name
+            .map(section_key)
+            .transpose()?
+            .map(|key| key.header());
+        let items = self.items()?;
+
+        let mut found = false;
+        let mut removed: Vec<usize> = vec![];
+        for (i, item) in items.iter().enumerate() {
+            let Item::Section { line, .. } = item else {
+                continue;
+            };
+            if item.prefix() != prefix {
+                continue;
+            }
+            found = true;
+            match header.as_ref() {
+                Some(header) => self.lines[*line] = header.clone(),
+                None => {
+                    let end = items[(i + 1)..]
+                        .iter()
+                        .find_map(|item| match item {
+                            Item::Section { line, .. } => Some(*line),
+                            Item::Entry { .. } => None,
+                        })
+                        .unwrap_or(self.lines.len());
+                    removed.extend(*line..end);
+                }
+            }
+        }
+        for line in removed.into_iter().rev() {
+            self.lines.remove(line);
+        }
+        Ok(found)
+    }
+
+    // NOTE:
+    // Appends a section with the new name holding the entries of the old one.
+    pub fn copy_section(&mut self, name: &str, new_name: &str) -> Result<()> {
+        let prefix = section_key(name)?.prefix();
+        let mut lines: Vec<String> = vec![];
+        for item in self.items()? {
+            if let Item::Entry {
+                lines: (start, end),
+                ..
+            } = item
+            {
+                if item.prefix() == prefix {
+                    lines.extend(self.lines[start..=end].iter().cloned());
+                }
+            }
+        }
+        if !lines.is_empty() {
+            self.lines.push(section_key(new_name)?.header());
+            self.lines.extend(lines);
+        }
+        Ok(())
+    }
+
+    pub fn write(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut text = self.lines.join("\n");
+        text.push('\n');
+
+        let lock = self.path.with_extension("lock");
+        fs::write(&lock, text)?;
+        fs::rename(lock, &self.path)?;
+        Ok(())
+    }
+
+    fn items(&self) -> Result<Vec<Item>> {
+        parse_items(&self.lines.join("\n")).map_err(|line| {
+            let msg = format!("bad config line {line} in file {}", self.path.display());
+            Error::from(msg.as_str())
+        })
+    }
+
+    fn entries_of(&self, key: &Key) -> Result<Vec<(usize, usize)>> {
+        let canonical = key.canonical();
+        Ok(self
+            .items()?
+            .into_iter()
+            .filter_map(|item| match item {
+                Item::Entry { key, lines, .. } if key == canonical => Some(lines),
+                _ => None,
+            })
+            .collect())
+    }
+
+    // NOTE:
+    // New values go at the end of the last section for the key, or into a new
+    // section at the end of the file.
+    fn insert(&mut self, key: &Key, value: &str) -> Result<()> {
+        let prefix = key.prefix();
+        let last = self
+            .items()?
+            .iter()
+            .filter(|item| item.prefix() == prefix)
+            .map(Item::last_line)
+            .max();
+
+        match last {
+            Some(line) => self.lines.insert(line + 1, key.line(value)),
+            None => {
+                self.lines.push(key.header());
+                self.lines.push(key.line(value));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn it_gets_values_from_sections() {
+        let config = Config::parse(
+            "# comment\n[user]\n\tname = Paul Kuruvilla\n\tEmail = rohitpaulk@gmail.com\n[remote \"origin\"]\n\turl = https://example.com/repo.git\n[core]\n\tbare\n",
+        )
+        .unwrap();
+        assert_eq!(config.get("user.name"), Some("Paul Kuruvilla"));
+        assert_eq!(config.get("User.email"), Some("rohitpaulk@gmail.com"));
+        assert_eq!(
+            config.get("remote.origin.url"),
+            Some("https://example.com/repo.git")
+        );
+        assert_eq!(config.get("core.bare"), Some(""));
+        assert_eq!(config.entries()[3].value(), None);
+        assert_eq!(config.get("user.signingkey"), None);
+    }
+
+    #[test]
+    fn it_prefers_last_value() {
+        let config = Config::parse("[user]\nname = Global\n[user]\nname = Local\n").unwrap();
+        assert_eq!(config.get("user.name"), Some("Local"));
+        assert_eq!(config.get_all("user.name"), vec!["Global", "Local"]);
+    }
+
+    #[test]
+    fn it_parses_subsections() {
+        let config = Config::parse(
+            "[branch \"Feature\"]\n\tremote = origin\n[Branch.Topic]\n\tremote = upstream\n[a \"sub \\\"q\\\" \\\\\"]\n\tk = v\n",
+        )
+        .unwrap();
+        assert_eq!(config.get("branch.Feature.remote"), Some("origin"));
+        assert_eq!(config.get("branch.feature.remote"), None);
+        assert_eq!(config.get("BRANCH.topic.remote"), Some("upstream"));
+        assert_eq!(config.get("a.sub \"q\" \\.k"), Some("v"));
+    }
+
+    #[test]
+    fn it_parses_quotes_escapes_and_comments() {
+        let config = Config::parse(
+            "[a]\n\tx = \"  padded  \" ; comment\n\ty = one   two # comment\n\tz = tab\\there \"#not comment\"\n\tw = line\\nbreak\n\tlong = first \\\n  second\n",
+        )
+        .unwrap();
+        assert_eq!(config.get("a.x"), Some("  padded  "));
+        assert_eq!(config.get("a.y"), Some("one   two"));
+        assert_eq!(config.get("a.z"), Some("tab\there #not comment"));
+        assert_eq!(config.get("a.w"), Some("line\nbreak"));
+        assert_eq!(config.get("a.long"), Some("first   second"));
+    }
+
+    #[test]
+    fn it_rejects_bad_lines() {
+        assert!(Config::parse("key = outside\n").is_err());
+        assert!(Config::parse("[a]\n\tk = \"unterminated\n").is_err());
+        assert!(Config::parse("[a]\n\tk = bad \\q escape\n").is_err());
+        assert!(Config::parse("[a \"sub]\n").is_err());
+        assert!(Config::parse("[a]\n\t1k = v\n").is_err());
+    }
+
+    #[test]
+    fn it_follows_includes() {
+        let root = testing::git_dir("config-include");
+        fs::write(root.join("extra"), "[user]\n\tname = Included\n").unwrap();
+        fs::write(
+            root.join("nested"),
+            "[user]\n\temail = nested@example.com\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join(".git/work"),
+            "[include]\n\tpath = ../nested\n[core]\n\teditor = vi\n",
+        )
+        .unwrap();
+        fs::write(root.join(GIT_DIR).join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(
+            root.join(".git/config"),
+            "[user]\n\tname = Local\n[include]\n\tpath = ../extra\n[includeIf \"gitdir:git-config-include-*/.git\"]\n\tpath = work\n[includeIf \"gitdir:/nowhere/\"]\n\tpath = ../extra\n[includeIf \"onbranch:ma*\"]\n\tpath = ../missing\n",
+        )
+        .unwrap();
+
+        let config = Config::load_file(&root, root.join(".git/config")).unwrap();
+        assert_eq!(config.get("user.name"), Some("Included"));
+        assert_eq!(config.get("user.email"), Some("nested@example.com"));
+        assert_eq!(config.get("core.editor"), Some("vi"));
+        assert_eq!(config.get_all("user.name"), vec!["Local", "Included"]);
+    }
+
+    #[test]
+    fn it_limits_include_depth() {
+        let root = testing::git_dir("config-include-loop");
+        fs::write(root.join(".git/config"), "[include]\n\tpath = config\n").unwrap();
+        assert!(Config::load_file(&root, root.join(".git/config")).is_err());
+    }
+
+    #[test]
+    fn it_sets_values_in_place() {
+        let root = testing::git_dir("config-set");
+        let path = root.join(".git/config");
+        fs::write(
+            &path,
+            "# keep me\n[core]\n\tbare = false\n[remote \"origin\"]\n\turl = old\n",
+        )
+        .unwrap();
+
+        let mut file = ConfigFile::open(&path).unwrap();
+        file.set("remote.origin.url", "new").unwrap();
+        file.set("core.editor", "vi -f").unwrap();
+        file.set("branch.main.remote", " origin;").unwrap();
+        file.add("remote.origin.fetch", "a").unwrap();
+        file.add("remote.origin.fetch", "b").unwrap();
+        assert!(file.set("remote.origin.fetch", "c").is_err());
+        file.write().unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# keep me\n[core]\n\tbare = false\n\teditor = vi -f\n[remote \"origin\"]\n\turl = new\n\tfetch = a\n\tfetch = b\n[branch \"main\"]\n\tremote = \" origin;\"\n"
+        );
+        let config = Config::load_file(&root, &path).unwrap();
+        assert_eq!(config.get("branch.main.remote"), Some(" origin;"));
+    }
+
+    #[test]
+    fn it_unsets_values() {
+        let root = testing::git_dir("config-unset");
+        let path = root.join(".git/config");
+        fs::write(
+            &path,
+            "[a]\n\tk = 1\n\tm = 1\n\tm = 2\n\tlong = x \\\n y\n\tz = 3\n",
+        )
+        .unwrap();
+
+        let mut file = ConfigFile::open(&path).unwrap();
+        assert!(file.unset("a.m", false).is_err());
+        assert!(file.unset("a.missing", false).is_err());
+        file.unset("a.m", true).unwrap();
+        file.unset("a.long", false).unwrap();
+        file.unset("a.k", false).unwrap();
+        file.write().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[a]\n\tz = 3\n");
+    }
+
+    #[test]
+    fn it_renames_and_removes_sections() {
+        let root = testing::git_dir("config-section");
+        let path = root.join(".git/config");
+        fs::write(
+            &path,
+            "[core]\n\tbare = false\n[branch \"a\"]\n\tremote = .\n\tmerge = refs/heads/main\n[user]\n\tname = x\n",
+        )
+        .unwrap();
+
+        let mut file = ConfigFile::open(&path).unwrap();
+        file.copy_section("branch.a", "branch.c").unwrap();
+        assert!(file.rename_section("branch.a", Some("branch.b")).unwrap());
+        assert!(file.rename_section("branch.c", None).unwrap());
+        assert!(!file.rename_section("branch.missing", None).unwrap());
+        file.write().unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "[core]\n\tbare = false\n[branch \"b\"]\n\tremote = .\n\tmerge = refs/heads/main\n[user]\n\tname = x\n"
+        );
+    }
+
+    #[test]
+    fn it_validates_keys() {
+        assert!(Key::parse("nosection").is_err());
+        assert!(Key::parse("a.1bad").is_err());
+        assert!(Key::parse("a_b.c").is_err());
+        assert_eq!(
+            Key::parse("Remote.Origin.URL").unwrap().canonical(),
+            "remote.Origin.url"
+        );
+    }
+}
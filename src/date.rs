@@ -0,0 +1,386 @@
+use super::{Error, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DAY: u64 = 86400;
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+// NOTE:
+// Returns the offset of the local timezone (honoring TZ) in minutes east of UTC.
+#[cfg(unix)]
+pub fn local_offset(timestamp: u64) -> i32 {
+    let time = timestamp as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    let result = unsafe { libc::localtime_r(&time, &mut tm) };
+    if result.is_null() {
+        0
+    } else {
+        (tm.tm_gmtoff / 60) as i32
+    }
+}
+
+#[cfg(not(unix))]
+pub fn local_offset(_timestamp: u64) -> i32 {
+    0
+}
+
+pub fn format_offset(minutes: i32) -> String {
+    let sign = if minutes < 0 { '-' } else { '+' };
+    let minutes = minutes.abs();
+    format!("{sign}{:02}{:02}", minutes / 60, minutes % 60)
+}
+
+// NOTE:
+// Git's default date format like "Thu Apr 7 15:13:13 2005 -0700", shown in the
+// timezone of the offset.
+pub fn format(timestamp: u64, offset: i32) -> String {
+    let local = timestamp as i64 + offset as i64 * 60;
+    let days = local.div_euclid(86400);
+    let secs = local.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    let weekday = WEEKDAYS[(days + 4).rem_euclid(7) as usize];
+    format!(
+        "{weekday} {} {day} {:02}:{:02}:{:02} {year} {}",
+        MONTHS[month as usize - 1],
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60,
+        format_offset(offset)
+    )
+}
+
+// NOTE:
+// Like "2005-04-07 15:13:13 -0700" or with `strict` "2005-04-07T15:13:13-07:00".
+pub fn format_iso(timestamp: u64, offset: i32, strict: bool) -> String {
+    let local = timestamp as i64 + offset as i64 * 60;
+    let (year, month, day) = civil_from_days(local.div_euclid(86400));
+    let secs = local.rem_euclid(86400);
+    let time = format!(
+        "{year:04}-{month:02}-{day:02}{}{:02}:{:02}:{:02}",
+        if strict { 'T' } else { ' ' },
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    );
+    let zone = format_offset(offset);
+    if strict {
+        format!("{time}{}:{}", &zone[..3], &zone[3..])
+    } else {
+        format!("{time} {zone}")
+    }
+}
+
+// NOTE:
+// Like "Thu, 7 Apr 2005 15:13:13 -0700", the format of emails.
+pub fn format_rfc2822(timestamp: u64, offset: i32) -> String {
+    let local = timestamp as i64 + offset as i64 * 60;
+    let days = local.div_euclid(86400);
+    let secs = local.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{}, {day} {} {year} {:02}:{:02}:{:02} {}",
+        WEEKDAYS[(days + 4).rem_euclid(7) as usize],
+        MONTHS[month as usize - 1],
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60,
+        format_offset(offset)
+    )
+}
+
+// NOTE:
+// Accepts "+0530", "-07:00", "+09" and "Z".
+pub fn parse_offset(value: &str) -> Option<i32> {
+    if value == "Z" || value == "z" {
+        return Some(0);
+    }
+
+    let (sign, digits) = match value.split_at_checked(1)? {
+        ("+", rest) => (1, rest),
+        ("-", rest) => (-1, rest),
+        _ => return None,
+    };
+    let digits = digits.replace(':', "");
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let (hours, minutes) = match digits.len() {
+        2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (
+            digits[..2].parse::<i32>().ok()?,
+            digits[2..].parse::<i32>().ok()?,
+        ),
+        _ => return None,
+    };
+    if minutes >= 60 {
+        return None;
+    }
+    Some(sign * (hours * 60 + minutes))
+}
+
+// NOTE:
+// Parses the date formats git accepts in GIT_AUTHOR_DATE and GIT_COMMITTER_DATE:
+//   "<unix timestamp> <offset>" (optionally prefixed with "@"),
+//   RFC 2822 like "Thu, 07 Apr 2005 22:13:13 +0200" and
+//   ISO 8601 like "2005-04-07T22:13:13+02:00".
+// Returns the timestamp and the timezone offset in minutes.
+pub fn parse(value: &str) -> Result<(u64, i32)> {
+    let value = value.trim();
+    parse_raw(value)
+        .or_else(|| parse_rfc2822(value))
+        .or_else(|| parse_iso8601(value))
+        .ok_or_else(|| {
+            let msg = format!("invalid date format: {value}");
+            Error::from(msg.as_str())
+        })
+}
+
+// NOTE:
+// On top of the formats `parse` knows, the relative dates git accepts where a
+// point in time is expected, like "now", "yesterday" and "2.weeks.ago" (or
+// "2 weeks ago"). Months and years are counted as 30 and 365 days.
+pub fn approxidate(value: &str, now: u64) -> Result<u64> {
+    let value = value.trim();
+    match value {
+        "now" => return Ok(now),
+        "yesterday" => return Ok(now.saturating_sub(DAY)),
+        _ => {}
+    }
+
+    let words: Vec<&str> = value.split(['.', ' ']).collect();
+    if let [count, unit, "ago"] = words[..] {
+        let unit = match unit.strip_suffix('s').unwrap_or(unit) {
+            "second" => Some(1),
+            "minute" => Some(60),
+            "hour" => Some(3600),
+            "day" => Some(DAY),
+            "week" => Some(7 * DAY),
+            "month" => Some(30 * DAY),
+            "year" => Some(365 * DAY),
+            _ => None,
+        };
+        if let (Ok(count), Some(unit)) = (count.parse::<u64>(), unit) {
+            return Ok(now.saturating_sub(count * unit));
+        }
+    }
+    parse(value).map(|(timestamp, _)| timestamp)
+}
+
+fn parse_raw(value: &str) -> Option<(u64, i32)> {
+    let value = value.strip_prefix('@').unwrap_or(value);
+    let mut parts = value.split_whitespace();
+    let timestamp = parts.next()?;
+    if !timestamp.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let timestamp = timestamp.parse::<u64>().ok()?;
+    let offset = match parts.next() {
+        Some(offset) => parse_offset(offset)?,
+        None => local_offset(timestamp),
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((timestamp, offset))
+}
+
+fn parse_rfc2822(value: &str) -> Option<(u64, i32)> {
+    let value = match value.split_once(", ") {
+        Some((_weekday, rest)) => rest,
+        None => value,
+    };
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    let [day, month, year, time, offset] = parts[..] else {
+        return None;
+    };
+
+    let month = MONTHS.iter().position(|m| m.eq_ignore_ascii_case(month))? as u32 + 1;
+    let (hour, minute, second) = parse_time(time)?;
+    let offset = parse_offset(offset)?;
+    let local = to_timestamp(
+        year.parse().ok()?,
+        month,
+        day.parse().ok()?,
+        hour,
+        minute,
+        second,
+    )?;
+    Some(((local - offset as i64 * 60).try_into().ok()?, offset))
+}
+
+fn parse_iso8601(value: &str) -> Option<(u64, i32)> {
+    let (date, rest) = if value.len() > 10 {
+        value.split_at_checked(10)?
+    } else {
+        (value, "")
+    };
+    let mut ymd = date.split('-');
+    let year = ymd.next()?.parse::<i64>().ok()?;
+    let month = ymd.next()?.parse::<u32>().ok()?;
+    let day = ymd.next()?.parse::<u32>().ok()?;
+
+    let rest = rest.trim_start_matches(['T', ' ']);
+    let time_len = rest
+        .find(|c: char| !(c.is_ascii_digit() || c == ':' || c == '.'))
+        .unwrap_or(rest.len());
+    let (time, offset) = rest.split_at(time_len);
+    let (hour, minute, second) = if time.is_empty() {
+        (0, 0, 0)
+    } else {
+        parse_time(time.split('.').next()?)?
+    };
+
+    let local = to_timestamp(year, month, day, hour, minute, second)?;
+    let offset = match offset.trim() {
+        "" => local_offset(local.max(0) as u64),
+        offset => parse_offset(offset)?,
+    };
+    Some(((local - offset as i64 * 60).try_into().ok()?, offset))
+}
+
+fn parse_time(time: &str) -> Option<(u32, u32, u32)> {
+    let mut hms = time.split(':');
+    let hour = hms.next()?.parse::<u32>().ok()?;
+    let minute = hms.next()?.parse::<u32>().ok()?;
+    let second = hms.next().map_or(Some(0), |s| s.parse::<u32>().ok())?;
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    Some((hour, minute, second))
+}
+
+fn to_timestamp(
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+) -> Option<i64> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    Some(days * 86400 + (hour * 3600 + minute * 60 + second) as i64)
+}
+
+// NOTE:
+// The number of days since 1970-01-01 for the proleptic Gregorian calendar date.
+// See http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// NOTE:
+// The inverse of days_from_civil.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = if days >= 0 { days } else { days - 146096 } / 146097;
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_formats_offset() {
+        assert_eq!(format_offset(0), "+0000");
+        assert_eq!(format_offset(330), "+0530");
+        assert_eq!(format_offset(-420), "-0700");
+    }
+
+    #[test]
+    fn it_parses_offset() {
+        assert_eq!(parse_offset("+0530"), Some(330));
+        assert_eq!(parse_offset("-07:00"), Some(-420));
+        assert_eq!(parse_offset("+09"), Some(540));
+        assert_eq!(parse_offset("Z"), Some(0));
+        assert_eq!(parse_offset("0530"), None);
+        assert_eq!(parse_offset("+0575"), None);
+    }
+
+    #[test]
+    fn it_parses_raw_date() {
+        assert_eq!(parse("1587032850 +0530").unwrap(), (1587032850, 330));
+        assert_eq!(parse("@1587032850 -0700").unwrap(), (1587032850, -420));
+    }
+
+    #[test]
+    fn it_parses_rfc2822_date() {
+        assert_eq!(
+            parse("Thu, 07 Apr 2005 22:13:13 +0200").unwrap(),
+            (1112904793, 120)
+        );
+    }
+
+    #[test]
+    fn it_parses_iso8601_date() {
+        assert_eq!(
+            parse("2005-04-07T22:13:13+02:00").unwrap(),
+            (1112904793, 120)
+        );
+        assert_eq!(
+            parse("2005-04-07 22:13:13 +0200").unwrap(),
+            (1112904793, 120)
+        );
+        assert_eq!(parse("2005-04-07T20:13:13Z").unwrap(), (1112904793, 0));
+    }
+
+    #[test]
+    fn it_formats_dates() {
+        assert_eq!(format(1112900000, -420), "Thu Apr 7 11:53:20 2005 -0700");
+        assert_eq!(format(946684800, 330), "Sat Jan 1 05:30:00 2000 +0530");
+        assert_eq!(
+            format_iso(1112900000, -420, false),
+            "2005-04-07 11:53:20 -0700"
+        );
+        assert_eq!(
+            format_iso(1112900000, -420, true),
+            "2005-04-07T11:53:20-07:00"
+        );
+    }
+
+    #[test]
+    fn it_parses_approximate_dates() {
+        let now = 1700000000;
+        assert_eq!(approxidate("now", now).unwrap(), now);
+        assert_eq!(approxidate("yesterday", now).unwrap(), now - 86400);
+        assert_eq!(approxidate("2.weeks.ago", now).unwrap(), now - 14 * 86400);
+        assert_eq!(approxidate("1 hour ago", now).unwrap(), now - 3600);
+        assert_eq!(approxidate("1587032850", now).unwrap(), 1587032850);
+        assert!(approxidate("2.fortnights.ago", now).is_err());
+        assert_eq!(
+            format_rfc2822(1700086400, 0),
+            "Wed, 15 Nov 2023 22:13:20 +0000"
+        );
+    }
+
+    #[test]
+    fn it_rejects_unknown_date() {
+        assert!(parse("yesterday-ish").is_err());
+    }
+}
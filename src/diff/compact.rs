@@ -0,0 +1,337 @@
+// NOTE:
+// A group of changed lines can often slide up or down over lines equal to its
+// ends without changing the diff, like an added function whose closing brace
+// could be taken from the one before. Groups are slid as far down
+// as possible, then lined up with a group on the other side if there's one,
+// and otherwise placed where the indentation of the surrounding lines makes
+// the most readable diff (git's "indent heuristic").
+
+const MAX_INDENT: isize = 200;
+const MAX_BLANKS: isize = 20;
+const INDENT_HEURISTIC_MAX_SLIDING: usize = 100;
+
+const START_OF_FILE_PENALTY: isize = 1;
+const END_OF_FILE_PENALTY: isize = 21;
+const TOTAL_BLANK_WEIGHT: isize = -30;
+const POST_BLANK_WEIGHT: isize = 6;
+const RELATIVE_INDENT_PENALTY: isize = -4;
+const RELATIVE_INDENT_WITH_BLANK_PENALTY: isize = 10;
+const RELATIVE_OUTDENT_PENALTY: isize = 24;
+const RELATIVE_OUTDENT_WITH_BLANK_PENALTY: isize = 17;
+const RELATIVE_DEDENT_PENALTY: isize = 23;
+const RELATIVE_DEDENT_WITH_BLANK_PENALTY: isize = 17;
+const INDENT_WEIGHT: isize = 60;
+
+// NOTE:
+// One side of the diff. `changed` has a false sentinel at both ends so that
+// the line i is at `changed[i + 1]`.
+pub(super) struct Side<'a> {
+    ids: &'a [usize],
+    lines: &'a [&'a [u8]],
+    changed: Vec<bool>,
+}
+
+impl<'a> Side<'a> {
+    pub(super) fn new(ids: &'a [usize], lines: &'a [&'a [u8]], changed: &[bool]) -> Self {
+        let mut sentinel = vec![false; changed.len() + 2];
+        sentinel[1..(changed.len() + 1)].copy_from_slice(changed);
+        Self {
+            ids,
+            lines,
+            changed: sentinel,
+        }
+    }
+
+    pub(super) fn into_changed(self) -> Vec<bool> {
+        let len = self.changed.len();
+        self.changed[1..(len - 1)].to_vec()
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn is_changed(&self, i: usize) -> bool {
+        self.changed[i + 1]
+    }
+
+    fn set_changed(&mut self, i: usize, value: bool) {
+        self.changed[i + 1] = value;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Group {
+    start: usize,
+    end: usize,
+}
+
+impl Group {
+    fn first(side: &Side) -> Self {
+        let mut end = 0;
+        while side.is_changed(end) {
+            end += 1;
+        }
+        Self { start: 0, end }
+    }
+
+    fn next(&mut self, side: &Side) -> bool {
+        if self.end == side.len() {
+            return false;
+        }
+        self.start = self.end + 1;
+        self.end = self.start;
+        while self.end < side.len() && side.is_changed(self.end) {
+            self.end += 1;
+        }
+        true
+    }
+
+    fn previous(&mut self, side: &Side) -> bool {
+        if self.start == 0 {
+            return false;
+        }
+        self.end = self.start - 1;
+        self.start = self.end;
+        while self.start > 0 && side.is_changed(self.start - 1) {
+            self.start -= 1;
+        }
+        true
+    }
+
+    fn slide_down(&mut self, side: &mut Side) -> bool {
+        if self.end < side.len() && side.ids[self.start] == side.ids[self.end] {
+            side.set_changed(self.start, false);
+            side.set_changed(self.end, true);
+            self.start += 1;
+            self.end += 1;
+            while self.end < side.len() && side.is_changed(self.end) {
+                self.end += 1;
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    fn slide_up(&mut self, side: &mut Side) -> bool {
+        if self.start > 0 && side.ids[self.start - 1] == side.ids[self.end - 1] {
+            self.start -= 1;
+            self.end -= 1;
+            side.set_changed(self.start, true);
+            side.set_changed(self.end, false);
+            while self.start > 0 && side.is_changed(self.start - 1) {
+                self.start -= 1;
+            }
+            true
+        } else {
+            false
+        }
+    }
+}
+
+pub(super) fn compact(side: &mut Side, other: &mut Side) {
+    let mut g = Group::first(side);
+    let mut go = Group::first(other);
+
+    loop {
+        if g.end != g.start {
+            let mut group_size;
+            let mut earliest_end;
+            let mut end_matching_other: Option<usize>;
+            loop {
+                group_size = g.end - g.start;
+                end_matching_other = None;
+
+                while g.slide_up(side) {
+                    go.previous(other);
+                }
+                earliest_end = g.end;
+                if go.end > go.start {
+                    end_matching_other = Some(g.end);
+                }
+
+                while g.slide_down(side) {
+                    go.next(other);
+                    if go.end > go.start {
+                        end_matching_other = Some(g.end);
+                    }
+                }
+
+                if group_size == g.end - g.start {
+                    break;
+                }
+            }
+
+            if g.end == earliest_end {
+                // NOTE:
+                // The group can't slide.
+            } else if end_matching_other.is_some() {
+                while go.end == go.start {
+                    g.slide_up(side);
+                    go.previous(other);
+                }
+            } else {
+                let end = g.end as isize;
+                let shift_start = (earliest_end as isize)
+                    .max(end - group_size as isize - 1)
+                    .max(end - INDENT_HEURISTIC_MAX_SLIDING as isize);
+                let mut shift = shift_start as usize;
+
+                let mut best: Option<(usize, Score)> = None;
+                while shift <= g.end {
+                    let mut score = Score::default();
+                    score.add(&measure_split(side, shift));
+                    score.add(&measure_split(side, shift - group_size));
+                    if best.as_ref().map_or(true, |(_, best)| score.cmp(best) <= 0) {
+                        best = Some((shift, score));
+                    }
+                    shift += 1;
+                }
+
+                if let Some((best_shift, _)) = best {
+                    while g.end > best_shift {
+                        g.slide_up(side);
+                        go.previous(other);
+                    }
+                }
+            }
+        }
+
+        if !g.next(side) {
+            break;
+        }
+        go.next(other);
+    }
+}
+
+#[derive(Debug, Default)]
+struct Measurement {
+    end_of_file: bool,
+    indent: isize,
+    pre_blank: isize,
+    pre_indent: isize,
+    post_blank: isize,
+    post_indent: isize,
+}
+
+#[derive(Debug, Default)]
+struct Score {
+    effective_indent: isize,
+    penalty: isize,
+}
+
+impl Score {
+    fn add(&mut self, m: &Measurement) {
+        if m.pre_indent == -1 && m.pre_blank == 0 {
+            self.penalty += START_OF_FILE_PENALTY;
+        }
+        if m.end_of_file {
+            self.penalty += END_OF_FILE_PENALTY;
+        }
+
+        let post_blank = if m.indent == -1 { 1 + m.post_blank } else { 0 };
+        let total_blank = m.pre_blank + post_blank;
+        self.penalty += TOTAL_BLANK_WEIGHT * total_blank;
+        self.penalty += POST_BLANK_WEIGHT * post_blank;
+
+        let indent = if m.indent != -1 {
+            m.indent
+        } else {
+            m.post_indent
+        };
+        let any_blanks = total_blank != 0;
+        self.effective_indent += indent;
+
+        if indent == -1 || m.pre_indent == -1 || indent == m.pre_indent {
+            // NOTE:
+            // No adjustment.
+        } else if indent > m.pre_indent {
+            self.penalty += if any_blanks {
+                RELATIVE_INDENT_WITH_BLANK_PENALTY
+            } else {
+                RELATIVE_INDENT_PENALTY
+            };
+        } else if m.post_indent != -1 && m.post_indent > indent {
+            self.penalty += if any_blanks {
+                RELATIVE_OUTDENT_WITH_BLANK_PENALTY
+            } else {
+                RELATIVE_OUTDENT_PENALTY
+            };
+        } else {
+            self.penalty += if any_blanks {
+                RELATIVE_DEDENT_WITH_BLANK_PENALTY
+            } else {
+                RELATIVE_DEDENT_PENALTY
+            };
+        }
+    }
+
+    fn cmp(&self, other: &Self) -> isize {
+        let indents = (self.effective_indent > other.effective_indent) as isize
+            - (self.effective_indent < other.effective_indent) as isize;
+        INDENT_WEIGHT * indents + (self.penalty - other.penalty)
+    }
+}
+
+// NOTE:
+// Describes the lines around a split before the line `split`.
+fn measure_split(side: &Side, split: usize) -> Measurement {
+    let mut m = Measurement::default();
+    if split >= side.len() {
+        m.end_of_file = true;
+        m.indent = -1;
+    } else {
+        m.indent = indent(side.lines[split]);
+    }
+
+    m.pre_indent = -1;
+    for i in (0..split).rev() {
+        let indent = indent(side.lines[i]);
+        if indent != -1 {
+            m.pre_indent = indent;
+            break;
+        }
+        m.pre_blank += 1;
+        if m.pre_blank == MAX_BLANKS {
+            m.pre_indent = 0;
+            break;
+        }
+    }
+
+    m.post_indent = -1;
+    for i in (split + 1)..side.len() {
+        let indent = indent(side.lines[i]);
+        if indent != -1 {
+            m.post_indent = indent;
+            break;
+        }
+        m.post_blank += 1;
+        if m.post_blank == MAX_BLANKS {
+            m.post_indent = 0;
+            break;
+        }
+    }
+    m
+}
+
+// NOTE:
+// The width of the leading whitespace with tabs to the next multiple of 8,
+// or -1 for a blank line.
+fn indent(line: &[u8]) -> isize {
+    let mut width = 0;
+    for &c in line {
+        if !c.is_ascii_whitespace() {
+            return width;
+        }
+        if c == b' ' {
+            width += 1;
+        } else if c == b'\t' {
+            width += 8 - width % 8;
+        }
+        if width >= MAX_INDENT {
+            return MAX_INDENT;
+        }
+    }
+    -1
+}
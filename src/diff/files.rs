@@ -0,0 +1,326 @@
+use super::rename;
+use crate::{
+    git_object::tree::Mode,
+    index::{Index, IndexEntry},
+    worktree, Error, GitObject, Result, Sha1Hash,
+};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// NOTE:
+// The working tree only has the files tracked by the index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Tree(String),
+    Index,
+    Worktree,
+    // NOTE:
+    // The tree of HEAD before the first commit.
+    Empty,
+}
+
+// NOTE:
+// A file on one side of the diff. The content of a file that changed in the
+// working tree is read from disk, as its blob is not in the database.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiffFile {
+    mode: Mode,
+    hash: Sha1Hash,
+    on_disk: bool,
+}
+
+impl DiffFile {
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn hash(&self) -> Sha1Hash {
+        self.hash
+    }
+
+    // NOTE:
+    // A gitlink has no blob, and git shows the commit it is at instead.
+    pub fn content<P: AsRef<Path>>(&self, root: P, path: &str) -> Result<Vec<u8>> {
+        if self.mode == Mode::Gitlink {
+            return Ok(format!("Subproject commit {}\n", self.hash.hex()).into_bytes());
+        }
+        let root = root.as_ref();
+        let obj = if self.on_disk {
+            GitObject::new_blob_from_path(root.join(path), self.mode)?
+        } else {
+            GitObject::open_from_hash(root, &self.hash.hex())?
+        };
+        match obj {
+            GitObject::Blob(blob) => Ok(blob.as_ref().to_vec()),
+            obj => {
+                let msg = format!("{} is a {}, not a blob", self.hash.hex(), obj.type_name());
+                Err(Error::from(msg.as_str()))
+            }
+        }
+    }
+
+    fn same(&self, other: &Self) -> bool {
+        self.mode == other.mode && self.hash == other.hash
+    }
+}
+
+// NOTE:
+// The file a renamed or copied file came from, with their similarity as a
+// fraction of rename::MAX_SCORE.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rename {
+    from: String,
+    score: usize,
+    copy: bool,
+}
+
+impl Rename {
+    pub fn new(from: String, score: usize, copy: bool) -> Self {
+        Self { from, score, copy }
+    }
+
+    pub fn from(&self) -> &str {
+        self.from.as_str()
+    }
+
+    pub fn is_copy(&self) -> bool {
+        self.copy
+    }
+
+    // NOTE:
+    // Rounded down to a whole percentage.
+    pub fn similarity(&self) -> usize {
+        self.score * 100 / rename::MAX_SCORE
+    }
+}
+
+// NOTE:
+// A path that differs between the two sides. It's missing on the old side
+// when added, and on the new side when deleted. The old side of a renamed or
+// copied file is the file it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilePair {
+    path: String,
+    old: Option<DiffFile>,
+    new: Option<DiffFile>,
+    rename: Option<Rename>,
+}
+
+impl FilePair {
+    pub fn path(&self) -> &str {
+        self.path.as_str()
+    }
+
+    pub fn old_path(&self) -> &str {
+        self.rename.as_ref().map_or(self.path(), Rename::from)
+    }
+
+    pub fn rename(&self) -> Option<&Rename> {
+        self.rename.as_ref()
+    }
+
+    pub fn with_rename(self, old: DiffFile, rename: Rename) -> Self {
+        Self {
+            old: Some(old),
+            rename: Some(rename),
+            ..self
+        }
+    }
+
+    pub fn old_file(&self) -> Option<DiffFile> {
+        self.old
+    }
+
+    pub fn new_file(&self) -> Option<DiffFile> {
+        self.new
+    }
+
+    // NOTE:
+    // A file replaced by a symlink or the other way around.
+    pub fn is_type_change(&self) -> bool {
+        match (self.old, self.new) {
+            (Some(old), Some(new)) => (old.mode == Mode::Symlink) != (new.mode == Mode::Symlink),
+            _ => false,
+        }
+    }
+}
+
+// NOTE:
+// Pairs the files of both sides by path, keeping the ones under the
+// pathspecs that differ, sorted by path.
+pub fn pairs<P: AsRef<Path>>(
+    root: P,
+    old: &Source,
+    new: &Source,
+    pathspecs: &[String],
+) -> Result<Vec<FilePair>> {
+    collect(root.as_ref(), old, new, pathspecs, false)
+}
+
+// NOTE:
+// All the files of both sides, with the unmodified ones that can be the
+// sources of copies (git's --find-copies-harder).
+pub fn all_pairs<P: AsRef<Path>>(root: P, old: &Source, new: &Source) -> Result<Vec<FilePair>> {
+    collect(root.as_ref(), old, new, &[], true)
+}
+
+fn collect(
+    root: &Path,
+    old: &Source,
+    new: &Source,
+    pathspecs: &[String],
+    unmodified: bool,
+) -> Result<Vec<FilePair>> {
+    let index = Index::open(root)?;
+    let old_files = files(root, old, &index)?;
+    let new_files = files(root, new, &index)?;
+    let pathspecs: Vec<String> = pathspecs.iter().map(|p| worktree::normalize(p)).collect();
+
+    let mut paths: Vec<&String> = old_files.keys().chain(new_files.keys()).collect();
+    paths.sort();
+    paths.dedup();
+
+    let mut pairs: Vec<FilePair> = vec![];
+    for path in paths {
+        if !pathspecs.is_empty() && !pathspecs.iter().any(|spec| worktree::matches(spec, path)) {
+            continue;
+        }
+        let old = old_files.get(path).copied();
+        let new = new_files.get(path).copied();
+        if let (Some(old), Some(new)) = (old, new) {
+            if old.same(&new) && !unmodified {
+                continue;
+            }
+        }
+        pairs.push(FilePair {
+            path: path.clone(),
+            old,
+            new,
+            rename: None,
+        });
+    }
+    Ok(pairs)
+}
+
+fn files(root: &Path, source: &Source, index: &Index) -> Result<BTreeMap<String, DiffFile>> {
+    let mut files: BTreeMap<String, DiffFile> = BTreeMap::new();
+    match source {
+        Source::Tree(tree) => {
+            for node in GitObject::flatten_tree(root, tree)? {
+                let file = DiffFile {
+                    mode: node.mode(),
+                    hash: node.hash(),
+                    on_disk: false,
+                };
+                files.insert(node.name().to_string(), file);
+            }
+        }
+        // NOTE:
+        // Intent-to-add entries have no staged content, so their file shows
+        // up as new in the working tree.
+        Source::Index => {
+            let staged = index
+                .entries()
+                .iter()
+                .filter(|e| e.stage() == 0 && !e.is_intent_to_add());
+            for entry in staged {
+                let file = DiffFile {
+                    mode: entry.mode(),
+                    hash: entry.hash(),
+                    on_disk: false,
+                };
+                files.insert(entry.path().to_string(), file);
+            }
+        }
+        Source::Worktree => {
+            for entry in index.entries().iter().filter(|e| e.stage() == 0) {
+                if let Some(file) = worktree_file(root, index, entry)? {
+                    files.insert(entry.path().to_string(), file);
+                }
+            }
+        }
+        Source::Empty => {}
+    }
+    Ok(files)
+}
+
+// NOTE:
+// Files whose stat data matches the index entry are not hashed.
+fn worktree_file(root: &Path, index: &Index, entry: &IndexEntry) -> Result<Option<DiffFile>> {
+    let full_path = root.join(entry.path());
+    if entry.mode() == Mode::Gitlink {
+        return Ok(full_path.is_dir().then_some(DiffFile {
+            mode: Mode::Gitlink,
+            hash: entry.hash(),
+            on_disk: false,
+        }));
+    }
+    let Some(mode) = Mode::from_path(&full_path)
+        .ok()
+        .filter(|mode| *mode != Mode::Directory)
+    else {
+        return Ok(None);
+    };
+
+    let meta = fs::symlink_metadata(&full_path)?;
+    if mode == entry.mode() && index.is_up_to_date(entry, &meta) {
+        return Ok(Some(DiffFile {
+            mode,
+            hash: entry.hash(),
+            on_disk: false,
+        }));
+    }
+
+    let hash = GitObject::new_blob_from_path(&full_path, mode)?.hash();
+    Ok(Some(DiffFile {
+        mode,
+        hash,
+        on_disk: true,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn it_pairs_index_and_worktree_files() {
+        let root = testing::repo("diff-pairs");
+        fs::create_dir_all(root.join("dir")).unwrap();
+        for path in ["a", "b", "dir/c"] {
+            fs::write(root.join(path), path).unwrap();
+        }
+        let mut index = Index::open(&root).unwrap();
+        for path in ["a", "b", "dir/c"] {
+            index.add(worktree::stage(&root, path.to_string()).unwrap());
+        }
+        index.write(&root).unwrap();
+        let tree = index.write_tree(&root).unwrap().hash().hex();
+
+        fs::write(root.join("a"), "changed\n").unwrap();
+        fs::remove_file(root.join("b")).unwrap();
+        fs::write(root.join("untracked"), "u").unwrap();
+
+        let pairs = pairs(&root, &Source::Index, &Source::Worktree, &[]).unwrap();
+        let paths: Vec<&str> = pairs.iter().map(FilePair::path).collect();
+        assert_eq!(paths, vec!["a", "b"]);
+        assert!(pairs[1].new_file().is_none());
+        assert_eq!(
+            pairs[0].new_file().unwrap().content(&root, "a").unwrap(),
+            b"changed\n"
+        );
+        assert_eq!(
+            pairs[0].old_file().unwrap().content(&root, "a").unwrap(),
+            b"a"
+        );
+
+        let staged = super::pairs(&root, &Source::Tree(tree), &Source::Index, &[]).unwrap();
+        assert!(staged.is_empty());
+
+        let only_dir =
+            super::pairs(&root, &Source::Index, &Source::Worktree, &["dir".into()]).unwrap();
+        assert!(only_dir.is_empty());
+    }
+}
@@ -0,0 +1,153 @@
+use super::myers;
+use std::collections::HashMap;
+use std::ops::Range;
+
+// NOTE:
+// Git gives up on a range and falls back to Myers when every common line
+// occurs more often than this in the old range.
+const MAX_CHAIN_LENGTH: usize = 64;
+
+// NOTE:
+// The common region found in both ranges, and the lowest number of times one
+// of its lines occurs in the old range.
+struct Region {
+    old: Range<usize>,
+    new: Range<usize>,
+    count: usize,
+}
+
+// NOTE:
+// Like patience, but the lines anchoring the split are the longest common
+// region among those whose lines occur the fewest times in the old range, so
+// that there is always an anchor when a line is common to both ranges. Ranges
+// where all the common lines are too frequent fall back to Myers.
+pub(super) fn diff(
+    old: &[usize],
+    new: &[usize],
+    mut old_range: Range<usize>,
+    mut new_range: Range<usize>,
+    old_changed: &mut [bool],
+    new_changed: &mut [bool],
+) {
+    loop {
+        if old_range.is_empty() || new_range.is_empty() {
+            old_changed[old_range].fill(true);
+            new_changed[new_range].fill(true);
+            return;
+        }
+        let region = match longest_common(old, new, old_range.clone(), new_range.clone()) {
+            Ok(Some(region)) => region,
+            Ok(None) => {
+                old_changed[old_range].fill(true);
+                new_changed[new_range].fill(true);
+                return;
+            }
+            Err(()) => {
+                myers::diff(old, new, old_range, new_range, old_changed, new_changed);
+                return;
+            }
+        };
+        diff(
+            old,
+            new,
+            old_range.start..region.old.start,
+            new_range.start..region.new.start,
+            old_changed,
+            new_changed,
+        );
+        old_range.start = region.old.end;
+        new_range.start = region.new.end;
+    }
+}
+
+// NOTE:
+// Returns None when no line is common to both ranges, and an error when all
+// the common lines occur too often in the old range.
+fn longest_common(
+    old: &[usize],
+    new: &[usize],
+    old_range: Range<usize>,
+    new_range: Range<usize>,
+) -> Result<Option<Region>, ()> {
+    // NOTE:
+    // The first position of each line in the old range, with how many times
+    // it's there, and the next position of the line after each one.
+    let mut first: HashMap<usize, (usize, usize)> = HashMap::new();
+    let mut next: Vec<Option<usize>> = vec![None; old_range.len()];
+    for i in old_range.clone().rev() {
+        let entry = first.entry(old[i]).or_insert((i, 0));
+        if entry.1 > 0 {
+            next[i - old_range.start] = Some(entry.0);
+        }
+        entry.0 = i;
+        entry.1 += 1;
+    }
+    let count = |i: usize| first[&old[i]].1;
+
+    let mut best: Option<Region> = None;
+    let mut has_common = false;
+    let mut j = new_range.start;
+    while j < new_range.end {
+        let mut next_j = j + 1;
+        let Some(&(start, occurrences)) = first.get(&new[j]) else {
+            j = next_j;
+            continue;
+        };
+        has_common = true;
+        let limit = best.as_ref().map_or(MAX_CHAIN_LENGTH + 1, |b| b.count);
+        if occurrences > limit {
+            j = next_j;
+            continue;
+        }
+
+        let mut candidate = Some(start);
+        while let Some(i) = candidate {
+            let (mut old_start, mut new_start) = (i, j);
+            let (mut old_end, mut new_end) = (i + 1, j + 1);
+            let mut lowest = occurrences;
+            while old_start > old_range.start
+                && new_start > new_range.start
+                && old[old_start - 1] == new[new_start - 1]
+            {
+                old_start -= 1;
+                new_start -= 1;
+                if lowest > 1 {
+                    lowest = lowest.min(count(old_start));
+                }
+            }
+            while old_end < old_range.end && new_end < new_range.end && old[old_end] == new[new_end]
+            {
+                if lowest > 1 {
+                    lowest = lowest.min(count(old_end));
+                }
+                old_end += 1;
+                new_end += 1;
+            }
+
+            next_j = next_j.max(new_end);
+            let better = best.as_ref().map_or(true, |b| {
+                b.old.len() < old_end - old_start || lowest < b.count
+            });
+            if better {
+                best = Some(Region {
+                    old: old_start..old_end,
+                    new: new_start..new_end,
+                    count: lowest,
+                });
+            }
+
+            candidate = next[i - old_range.start];
+            while let Some(n) = candidate.filter(|&n| n < old_end) {
+                candidate = next[n - old_range.start];
+            }
+        }
+        j = next_j;
+    }
+
+    match best {
+        Some(region) if region.count <= MAX_CHAIN_LENGTH => Ok(Some(region)),
+        Some(_) => Err(()),
+        None if has_common => Err(()),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,258 @@
+mod compact;
+pub mod files;
+mod histogram;
+mod myers;
+pub mod patch;
+mod patience;
+pub mod rename;
+
+use super::{Error, Result};
+use std::collections::HashMap;
+use std::ops::Range;
+
+pub const DEFAULT_CONTEXT: usize = 3;
+
+// NOTE:
+// Git looks for a NUL byte in the first 8000 bytes to tell binary files.
+const BINARY_CHECK_SIZE: usize = 8000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Algorithm {
+    #[default]
+    Myers,
+    Patience,
+    Histogram,
+}
+
+impl Algorithm {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "myers" | "default" => Ok(Self::Myers),
+            "patience" => Ok(Self::Patience),
+            "histogram" => Ok(Self::Histogram),
+            _ => {
+                let msg = format!("unknown diff algorithm: {value}");
+                Err(Error::from(msg.as_str()))
+            }
+        }
+    }
+}
+
+// NOTE:
+// The old lines in `old` are replaced by the new lines in `new`. One of the
+// ranges is empty for a pure deletion or insertion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+// NOTE:
+// Consecutive edits shown together with `context` unchanged lines around them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+    pub edits: Vec<Edit>,
+}
+
+// NOTE:
+// Splits the content in lines that keep their "\n", so that a last line
+// without it differs from the same line with it.
+pub fn lines(content: &[u8]) -> Vec<&[u8]> {
+    content.split_inclusive(|&b| b == b'\n').collect()
+}
+
+pub fn is_binary(content: &[u8]) -> bool {
+    content[..content.len().min(BINARY_CHECK_SIZE)].contains(&0)
+}
+
+pub fn diff<T: AsRef<[u8]>>(old: &[T], new: &[T], algorithm: Algorithm) -> Vec<Edit> {
+    let old: Vec<&[u8]> = old.iter().map(AsRef::as_ref).collect();
+    let new: Vec<&[u8]> = new.iter().map(AsRef::as_ref).collect();
+
+    let mut ids: HashMap<&[u8], usize> = HashMap::new();
+    let mut old_ids: Vec<usize> = Vec::with_capacity(old.len());
+    let mut new_ids: Vec<usize> = Vec::with_capacity(new.len());
+    for (lines, line_ids) in [(&old, &mut old_ids), (&new, &mut new_ids)] {
+        for line in lines.iter() {
+            let next = ids.len();
+            line_ids.push(*ids.entry(line).or_insert(next));
+        }
+    }
+
+    let mut old_changed = vec![false; old.len()];
+    let mut new_changed = vec![false; new.len()];
+    let (old_range, new_range) = (0..old.len(), 0..new.len());
+    match algorithm {
+        Algorithm::Myers => myers::diff(
+            &old_ids,
+            &new_ids,
+            old_range,
+            new_range,
+            &mut old_changed,
+            &mut new_changed,
+        ),
+        Algorithm::Patience => patience::diff(
+            &old_ids,
+            &new_ids,
+            old_range,
+            new_range,
+            &mut old_changed,
+            &mut new_changed,
+        ),
+        Algorithm::Histogram => histogram::diff(
+            &old_ids,
+            &new_ids,
+            old_range,
+            new_range,
+            &mut old_changed,
+            &mut new_changed,
+        ),
+    }
+
+    let mut old_side = compact::Side::new(&old_ids, &old, &old_changed);
+    let mut new_side = compact::Side::new(&new_ids, &new, &new_changed);
+    compact::compact(&mut old_side, &mut new_side);
+    compact::compact(&mut new_side, &mut old_side);
+    edits(&old_side.into_changed(), &new_side.into_changed())
+}
+
+// NOTE:
+// Groups the edits that are at most 2 * `context` lines apart.
+pub fn hunks(edits: &[Edit], old_len: usize, new_len: usize, context: usize) -> Vec<Hunk> {
+    let mut hunks: Vec<Hunk> = vec![];
+    for edit in edits {
+        if let Some(hunk) = hunks.last_mut() {
+            let last = hunk.edits.last().expect("hunk has edits");
+            if edit.old.start - last.old.end <= 2 * context {
+                hunk.edits.push(edit.clone());
+                continue;
+            }
+        }
+        hunks.push(Hunk {
+            old: 0..0,
+            new: 0..0,
+            edits: vec![edit.clone()],
+        });
+    }
+
+    for hunk in hunks.iter_mut() {
+        let (first, last) = (&hunk.edits[0], &hunk.edits[hunk.edits.len() - 1]);
+        hunk.old = first.old.start.saturating_sub(context)..(last.old.end + context).min(old_len);
+        hunk.new = first.new.start.saturating_sub(context)..(last.new.end + context).min(new_len);
+    }
+    hunks
+}
+
+fn edits(old_changed: &[bool], new_changed: &[bool]) -> Vec<Edit> {
+    let mut edits: Vec<Edit> = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old_changed.len() || j < new_changed.len() {
+        let old_start = i;
+        let new_start = j;
+        while i < old_changed.len() && old_changed[i] {
+            i += 1;
+        }
+        while j < new_changed.len() && new_changed[j] {
+            j += 1;
+        }
+
+        if i > old_start || j > new_start {
+            edits.push(Edit {
+                old: old_start..i,
+                new: new_start..j,
+            });
+        } else {
+            i += 1;
+            j += 1;
+        }
+    }
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_lines(text: &str) -> Vec<&[u8]> {
+        lines(text.as_bytes())
+    }
+
+    fn edit(old: Range<usize>, new: Range<usize>) -> Edit {
+        Edit { old, new }
+    }
+
+    #[test]
+    fn it_diffs_lines_with_myers() {
+        let old = text_lines("a\nb\nc\nd\n");
+        let new = text_lines("a\nc\nd\ne\n");
+        assert_eq!(
+            diff(&old, &new, Algorithm::Myers),
+            vec![edit(1..2, 1..1), edit(4..4, 3..4)]
+        );
+        assert!(diff(&old, &old, Algorithm::Myers).is_empty());
+        assert_eq!(diff(&old, &[], Algorithm::Myers), vec![edit(0..4, 0..0)]);
+    }
+
+    #[test]
+    fn it_keeps_last_line_without_newline_apart() {
+        let old = text_lines("a\nb");
+        let new = text_lines("a\nb\n");
+        assert_eq!(diff(&old, &new, Algorithm::Myers), vec![edit(1..2, 1..2)]);
+    }
+
+    #[test]
+    fn it_slides_groups_with_indent_heuristic() {
+        let old = text_lines("fn a() {\n    a();\n}\n");
+        let new = text_lines("fn a() {\n    a();\n}\n\nfn b() {\n    b();\n}\n");
+        assert_eq!(diff(&old, &new, Algorithm::Myers), vec![edit(3..3, 3..7)]);
+
+        let old = text_lines("if x {\n    a();\n}\n");
+        let new = text_lines("if x {\n    a();\n}\nif y {\n    a();\n}\n");
+        assert_eq!(diff(&old, &new, Algorithm::Myers), vec![edit(3..3, 3..6)]);
+    }
+
+    #[test]
+    fn it_anchors_unique_lines_with_patience() {
+        let old = text_lines("}\nfn a() {\n}\nfn b() {\n}\n");
+        let new = text_lines("}\nfn b() {\n}\nfn a() {\n}\n");
+        let edits = diff(&old, &new, Algorithm::Patience);
+        let removed: usize = edits.iter().map(|e| e.old.len()).sum();
+        let added: usize = edits.iter().map(|e| e.new.len()).sum();
+        assert_eq!((removed, added), (2, 2));
+    }
+
+    #[test]
+    fn it_anchors_rare_lines_with_histogram() {
+        let old = text_lines("x\na\ne\nc\nd\ne\nb\nd\nx\na\n");
+        let new = text_lines("a\ne\nc\nd\na\nb\na\n");
+        assert_eq!(
+            diff(&old, &new, Algorithm::Histogram),
+            vec![edit(0..1, 0..0), edit(5..9, 4..6)]
+        );
+        assert_eq!(
+            diff(&old, &new, Algorithm::Myers),
+            vec![edit(0..1, 0..0), edit(5..6, 4..5), edit(7..9, 6..6)]
+        );
+    }
+
+    #[test]
+    fn it_groups_edits_in_hunks() {
+        let edits = vec![edit(1..2, 1..2), edit(7..8, 7..8), edit(20..21, 20..20)];
+        let hunks = hunks(&edits, 30, 29, 3);
+        assert_eq!(hunks.len(), 2);
+        assert_eq!((hunks[0].old.clone(), hunks[0].new.clone()), (0..11, 0..11));
+        assert_eq!(hunks[0].edits.len(), 2);
+        assert_eq!(
+            (hunks[1].old.clone(), hunks[1].new.clone()),
+            (17..24, 17..23)
+        );
+    }
+
+    #[test]
+    fn it_detects_binary_content() {
+        assert!(is_binary(b"a\0b"));
+        assert!(!is_binary(b"text\n"));
+    }
+}
@@ -0,0 +1,489 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+// NOTE:
+// Lines with more matches than this on the other side may be discarded.
+const MAX_EQLIMIT: usize = 1024;
+const SIMSCAN_WINDOW: usize = 100;
+const KPDIS_RUN: usize = 4;
+const MAX_COST_MIN: isize = 256;
+const HEUR_MIN_COST: isize = 256;
+const SNAKE_CNT: isize = 20;
+const K_HEUR: isize = 4;
+const LINE_MAX: isize = isize::MAX;
+
+// NOTE:
+// Myers' O(ND) algorithm in linear space like git's xdiff: the middle snake
+// of the edit script splits the problem in two halves that are solved
+// recursively. Before that, the common ends are trimmed and the lines that
+// are not on the other side at all are marked as changed and left out, as
+// well as lines with many matches among such lines. When the cost gets too
+// high the split falls back to heuristics, so the result isn't always the
+// shortest. Lines are compared by their ids, and the removed and added
+// lines are marked in `old_changed` and `new_changed`.
+pub(super) fn diff(
+    old: &[usize],
+    new: &[usize],
+    old_range: Range<usize>,
+    new_range: Range<usize>,
+    old_changed: &mut [bool],
+    new_changed: &mut [bool],
+) {
+    let mut counts: HashMap<usize, (usize, usize)> = HashMap::new();
+    for &id in &old[old_range.clone()] {
+        counts.entry(id).or_default().0 += 1;
+    }
+    for &id in &new[new_range.clone()] {
+        counts.entry(id).or_default().1 += 1;
+    }
+
+    let len = old_range.len().min(new_range.len());
+    let mut start = 0;
+    while start < len && old[old_range.start + start] == new[new_range.start + start] {
+        start += 1;
+    }
+    let mut end = 0;
+    while end < len - start && old[old_range.end - 1 - end] == new[new_range.end - 1 - end] {
+        end += 1;
+    }
+    let old_inner = (old_range.start + start)..(old_range.end - end);
+    let new_inner = (new_range.start + start)..(new_range.end - end);
+
+    let old_kept = keep(old, old_inner, old_range.len(), old_changed, |id| {
+        counts[&id].1
+    });
+    let new_kept = keep(new, new_inner, new_range.len(), new_changed, |id| {
+        counts[&id].0
+    });
+
+    let ha1: Vec<usize> = old_kept.iter().map(|&i| old[i]).collect();
+    let ha2: Vec<usize> = new_kept.iter().map(|&i| new[i]).collect();
+    let size = ha1.len() + ha2.len() + 3;
+    let mut ctx = Context {
+        ha1: &ha1,
+        ha2: &ha2,
+        kvdf: vec![0; size],
+        kvdb: vec![0; size],
+        offset: ha2.len() as isize + 1,
+        mxcost: bogosqrt(size).max(MAX_COST_MIN),
+        changed1: vec![false; ha1.len()],
+        changed2: vec![false; ha2.len()],
+    };
+    ctx.compare(0, ha1.len() as isize, 0, ha2.len() as isize, false);
+
+    for (i, &changed) in ctx.changed1.iter().enumerate() {
+        old_changed[old_kept[i]] |= changed;
+    }
+    for (i, &changed) in ctx.changed2.iter().enumerate() {
+        new_changed[new_kept[i]] |= changed;
+    }
+}
+
+fn bogosqrt(mut n: usize) -> isize {
+    let mut i = 1;
+    while n > 0 {
+        i <<= 1;
+        n >>= 2;
+    }
+    i
+}
+
+// NOTE:
+// Returns the lines of the range to diff. A line without any match on the
+// other side is marked as changed right away, and so is a line with many
+// matches in the middle of a run of such lines (git's xdl_cleanup_records).
+fn keep(
+    ids: &[usize],
+    range: Range<usize>,
+    len: usize,
+    changed: &mut [bool],
+    matches: impl Fn(usize) -> usize,
+) -> Vec<usize> {
+    let mlim = (bogosqrt(len) as usize).min(MAX_EQLIMIT);
+    let dis: Vec<u8> = range
+        .clone()
+        .map(|i| match matches(ids[i]) {
+            0 => 0,
+            nm if nm >= mlim => 2,
+            _ => 1,
+        })
+        .collect();
+
+    let mut kept: Vec<usize> = vec![];
+    for (j, i) in range.enumerate() {
+        if dis[j] == 1 || (dis[j] == 2 && !clean_mmatch(&dis, j)) {
+            kept.push(i);
+        } else {
+            changed[i] = true;
+        }
+    }
+    kept
+}
+
+fn clean_mmatch(dis: &[u8], i: usize) -> bool {
+    let s = i.saturating_sub(SIMSCAN_WINDOW);
+    let e = (i + SIMSCAN_WINDOW).min(dis.len() - 1);
+
+    let (mut rdis0, mut rpdis0) = (0, 1);
+    for r in 1..=(i - s) {
+        match dis[i - r] {
+            0 => rdis0 += 1,
+            2 => rpdis0 += 1,
+            _ => break,
+        }
+    }
+    if rdis0 == 0 {
+        return false;
+    }
+
+    let (mut rdis1, mut rpdis1) = (0, 1);
+    for r in 1..=(e - i) {
+        match dis[i + r] {
+            0 => rdis1 += 1,
+            2 => rpdis1 += 1,
+            _ => break,
+        }
+    }
+    if rdis1 == 0 {
+        return false;
+    }
+    rdis1 += rdis0;
+    rpdis1 += rpdis0;
+    rpdis1 * KPDIS_RUN < rpdis1 + rdis1
+}
+
+struct Split {
+    i1: isize,
+    i2: isize,
+    min_lo: bool,
+    min_hi: bool,
+}
+
+// NOTE:
+// The box to split, as (off1, lim1, off2, lim2).
+type Bounds = (isize, isize, isize, isize);
+
+// NOTE:
+// The forward and backward furthest reaching paths are indexed by diagonal,
+// the diagonal k being the points where i1 - i2 = k.
+struct Context<'a> {
+    ha1: &'a [usize],
+    ha2: &'a [usize],
+    kvdf: Vec<isize>,
+    kvdb: Vec<isize>,
+    offset: isize,
+    mxcost: isize,
+    changed1: Vec<bool>,
+    changed2: Vec<bool>,
+}
+
+impl Context<'_> {
+    fn ha1(&self, i: isize) -> usize {
+        self.ha1[i as usize]
+    }
+
+    fn ha2(&self, i: isize) -> usize {
+        self.ha2[i as usize]
+    }
+
+    fn kvdf(&self, k: isize) -> isize {
+        self.kvdf[(k + self.offset) as usize]
+    }
+
+    fn kvdb(&self, k: isize) -> isize {
+        self.kvdb[(k + self.offset) as usize]
+    }
+
+    fn set_kvdf(&mut self, k: isize, value: isize) {
+        let offset = self.offset;
+        self.kvdf[(k + offset) as usize] = value;
+    }
+
+    fn set_kvdb(&mut self, k: isize, value: isize) {
+        let offset = self.offset;
+        self.kvdb[(k + offset) as usize] = value;
+    }
+
+    fn compare(
+        &mut self,
+        mut off1: isize,
+        mut lim1: isize,
+        mut off2: isize,
+        mut lim2: isize,
+        need_min: bool,
+    ) {
+        while off1 < lim1 && off2 < lim2 && self.ha1(off1) == self.ha2(off2) {
+            off1 += 1;
+            off2 += 1;
+        }
+        while off1 < lim1 && off2 < lim2 && self.ha1(lim1 - 1) == self.ha2(lim2 - 1) {
+            lim1 -= 1;
+            lim2 -= 1;
+        }
+
+        if off1 == lim1 {
+            self.changed2[(off2 as usize)..(lim2 as usize)].fill(true);
+        } else if off2 == lim2 {
+            self.changed1[(off1 as usize)..(lim1 as usize)].fill(true);
+        } else {
+            let split = self.split((off1, lim1, off2, lim2), need_min);
+            self.compare(off1, split.i1, off2, split.i2, split.min_lo);
+            self.compare(split.i1, lim1, split.i2, lim2, split.min_hi);
+        }
+    }
+
+    fn split(&mut self, bounds: Bounds, need_min: bool) -> Split {
+        let (off1, lim1, off2, lim2) = bounds;
+        let (dmin, dmax) = (off1 - lim2, lim1 - off2);
+        let (fmid, bmid) = (off1 - off2, lim1 - lim2);
+        let odd = (fmid - bmid) & 1 != 0;
+        let (mut fmin, mut fmax) = (fmid, fmid);
+        let (mut bmin, mut bmax) = (bmid, bmid);
+
+        self.set_kvdf(fmid, off1);
+        self.set_kvdb(bmid, lim1);
+
+        let mut ec = 1;
+        loop {
+            let mut got_snake = false;
+
+            // NOTE:
+            // The domain of diagonals grows by one on each side, or shrinks
+            // when it would leave the box. The values just outside of it are
+            // set so that they're never picked.
+            if fmin > dmin {
+                fmin -= 1;
+                self.set_kvdf(fmin - 1, -1);
+            } else {
+                fmin += 1;
+            }
+            if fmax < dmax {
+                fmax += 1;
+                self.set_kvdf(fmax + 1, -1);
+            } else {
+                fmax -= 1;
+            }
+
+            let mut d = fmax;
+            while d >= fmin {
+                let mut i1 = if self.kvdf(d - 1) >= self.kvdf(d + 1) {
+                    self.kvdf(d - 1) + 1
+                } else {
+                    self.kvdf(d + 1)
+                };
+                let prev1 = i1;
+                let mut i2 = i1 - d;
+                while i1 < lim1 && i2 < lim2 && self.ha1(i1) == self.ha2(i2) {
+                    i1 += 1;
+                    i2 += 1;
+                }
+                if i1 - prev1 > SNAKE_CNT {
+                    got_snake = true;
+                }
+                self.set_kvdf(d, i1);
+                if odd && bmin <= d && d <= bmax && self.kvdb(d) <= i1 {
+                    return Split {
+                        i1,
+                        i2,
+                        min_lo: true,
+                        min_hi: true,
+                    };
+                }
+                d -= 2;
+            }
+
+            if bmin > dmin {
+                bmin -= 1;
+                self.set_kvdb(bmin - 1, LINE_MAX);
+            } else {
+                bmin += 1;
+            }
+            if bmax < dmax {
+                bmax += 1;
+                self.set_kvdb(bmax + 1, LINE_MAX);
+            } else {
+                bmax -= 1;
+            }
+
+            let mut d = bmax;
+            while d >= bmin {
+                let mut i1 = if self.kvdb(d - 1) < self.kvdb(d + 1) {
+                    self.kvdb(d - 1)
+                } else {
+                    self.kvdb(d + 1) - 1
+                };
+                let prev1 = i1;
+                let mut i2 = i1 - d;
+                while i1 > off1 && i2 > off2 && self.ha1(i1 - 1) == self.ha2(i2 - 1) {
+                    i1 -= 1;
+                    i2 -= 1;
+                }
+                if prev1 - i1 > SNAKE_CNT {
+                    got_snake = true;
+                }
+                self.set_kvdb(d, i1);
+                if !odd && fmin <= d && d <= fmax && i1 <= self.kvdf(d) {
+                    return Split {
+                        i1,
+                        i2,
+                        min_lo: true,
+                        min_hi: true,
+                    };
+                }
+                d -= 2;
+            }
+
+            if !need_min {
+                if got_snake && ec > HEUR_MIN_COST {
+                    let forward = (fmin, fmax, fmid);
+                    let backward = (bmin, bmax, bmid);
+                    if let Some(split) = self.snake_split(ec, bounds, forward, backward) {
+                        return split;
+                    }
+                }
+                if ec >= self.mxcost {
+                    return self.furthest_split(bounds, (fmin, fmax), (bmin, bmax));
+                }
+            }
+            ec += 1;
+        }
+    }
+
+    // NOTE:
+    // When the cost is high, a diagonal that got far from its corner while
+    // staying close to the middle one and ends with a long snake is taken
+    // as the split.
+    fn snake_split(
+        &self,
+        ec: isize,
+        (off1, lim1, off2, lim2): Bounds,
+        (fmin, fmax, fmid): (isize, isize, isize),
+        (bmin, bmax, bmid): (isize, isize, isize),
+    ) -> Option<Split> {
+        let mut best = 0;
+        let mut found: Option<(isize, isize)> = None;
+        let mut d = fmax;
+        while d >= fmin {
+            let dd = (d - fmid).abs();
+            let i1 = self.kvdf(d);
+            let i2 = i1 - d;
+            let v = (i1 - off1) + (i2 - off2) - dd;
+            if v > K_HEUR * ec
+                && v > best
+                && off1 + SNAKE_CNT <= i1
+                && i1 < lim1
+                && off2 + SNAKE_CNT <= i2
+                && i2 < lim2
+            {
+                let mut k = 1;
+                while self.ha1(i1 - k) == self.ha2(i2 - k) {
+                    if k == SNAKE_CNT {
+                        best = v;
+                        found = Some((i1, i2));
+                        break;
+                    }
+                    k += 1;
+                }
+            }
+            d -= 2;
+        }
+        if let Some((i1, i2)) = found {
+            return Some(Split {
+                i1,
+                i2,
+                min_lo: true,
+                min_hi: false,
+            });
+        }
+
+        let mut best = 0;
+        let mut d = bmax;
+        while d >= bmin {
+            let dd = (d - bmid).abs();
+            let i1 = self.kvdb(d);
+            let i2 = i1 - d;
+            let v = (lim1 - i1) + (lim2 - i2) - dd;
+            if v > K_HEUR * ec
+                && v > best
+                && off1 < i1
+                && i1 <= lim1 - SNAKE_CNT
+                && off2 < i2
+                && i2 <= lim2 - SNAKE_CNT
+            {
+                let mut k = 0;
+                while self.ha1(i1 + k) == self.ha2(i2 + k) {
+                    if k == SNAKE_CNT - 1 {
+                        best = v;
+                        found = Some((i1, i2));
+                        break;
+                    }
+                    k += 1;
+                }
+            }
+            d -= 2;
+        }
+        found.map(|(i1, i2)| Split {
+            i1,
+            i2,
+            min_lo: false,
+            min_hi: true,
+        })
+    }
+
+    // NOTE:
+    // When the cost is too high, the path that got the furthest is taken.
+    fn furthest_split(
+        &self,
+        (off1, lim1, off2, lim2): Bounds,
+        (fmin, fmax): (isize, isize),
+        (bmin, bmax): (isize, isize),
+    ) -> Split {
+        let (mut fbest, mut fbest1) = (-1, -1);
+        let mut d = fmax;
+        while d >= fmin {
+            let mut i1 = self.kvdf(d).min(lim1);
+            let mut i2 = i1 - d;
+            if lim2 < i2 {
+                i1 = lim2 + d;
+                i2 = lim2;
+            }
+            if fbest < i1 + i2 {
+                fbest = i1 + i2;
+                fbest1 = i1;
+            }
+            d -= 2;
+        }
+
+        let (mut bbest, mut bbest1) = (LINE_MAX, LINE_MAX);
+        let mut d = bmax;
+        while d >= bmin {
+            let mut i1 = self.kvdb(d).max(off1);
+            let mut i2 = i1 - d;
+            if i2 < off2 {
+                i1 = off2 + d;
+                i2 = off2;
+            }
+            if i1 + i2 < bbest {
+                bbest = i1 + i2;
+                bbest1 = i1;
+            }
+            d -= 2;
+        }
+
+        if (lim1 + lim2) - bbest < fbest - (off1 + off2) {
+            Split {
+                i1: fbest1,
+                i2: fbest - fbest1,
+                min_lo: true,
+                min_hi: false,
+            }
+        } else {
+            Split {
+                i1: bbest1,
+                i2: bbest - bbest1,
+                min_lo: false,
+                min_hi: true,
+            }
+        }
+    }
+}
@@ -0,0 +1,671 @@
+use super::{
+    diff,
+    files::{DiffFile, FilePair, Rename},
+    hunks, is_binary, lines, Algorithm, Edit, Hunk,
+};
+use crate::{pretty, Result};
+use std::io::Write;
+use std::ops::Range;
+use std::path::Path;
+
+const NULL_ABBREV: &str = "0000000";
+const DEV_NULL: &str = "/dev/null";
+const NO_NEWLINE: &[u8] = b"\\ No newline at end of file\n";
+
+// NOTE:
+// The `diff --stat` output fits in 80 columns, as git's does without a
+// terminal.
+const STAT_WIDTH: usize = 80;
+const FUNC_LINE_MAX: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Unified,
+    WordDiff,
+}
+
+// NOTE:
+// Writes the patch of the pair. A file replaced by a symlink (or the other
+// way around) is shown as a deletion followed by an addition.
+pub fn write_patch<P: AsRef<Path>, W: Write>(
+    out: &mut W,
+    root: P,
+    pair: &FilePair,
+    format: Format,
+    context: usize,
+    algorithm: Algorithm,
+) -> Result<()> {
+    let root = root.as_ref();
+    let options = (format, context, algorithm);
+    if pair.is_type_change() {
+        let path = pair.path();
+        write_file_patch(
+            out,
+            root,
+            (path, pair.old_file()),
+            (path, None),
+            None,
+            options,
+        )?;
+        write_file_patch(
+            out,
+            root,
+            (path, None),
+            (path, pair.new_file()),
+            None,
+            options,
+        )
+    } else {
+        write_file_patch(
+            out,
+            root,
+            (pair.old_path(), pair.old_file()),
+            (pair.path(), pair.new_file()),
+            pair.rename(),
+            options,
+        )
+    }
+}
+
+fn write_file_patch<W: Write>(
+    out: &mut W,
+    root: &Path,
+    (old_path, old): (&str, Option<DiffFile>),
+    (new_path, new): (&str, Option<DiffFile>),
+    rename: Option<&Rename>,
+    (format, context, algorithm): (Format, usize, Algorithm),
+) -> Result<()> {
+    writeln!(out, "diff --git a/{old_path} b/{new_path}")?;
+    let hashes = |old: Option<DiffFile>, new: Option<DiffFile>| {
+        let abbrev = |file: Option<DiffFile>| match file {
+            Some(file) => pretty::abbrev(&file.hash().hex()).to_string(),
+            None => NULL_ABBREV.to_string(),
+        };
+        format!("index {}..{}", abbrev(old), abbrev(new))
+    };
+    match (old, new) {
+        (None, Some(new)) => {
+            writeln!(out, "new file mode {:06o}", u32::from(new.mode()))?;
+            writeln!(out, "{}", hashes(None, Some(new)))?;
+        }
+        (Some(old), None) => {
+            writeln!(out, "deleted file mode {:06o}", u32::from(old.mode()))?;
+            writeln!(out, "{}", hashes(Some(old), None))?;
+        }
+        (Some(old), Some(new)) => {
+            if old.mode() != new.mode() {
+                writeln!(out, "old mode {:06o}", u32::from(old.mode()))?;
+                writeln!(out, "new mode {:06o}", u32::from(new.mode()))?;
+            }
+            if let Some(rename) = rename {
+                let kind = if rename.is_copy() { "copy" } else { "rename" };
+                writeln!(out, "similarity index {}%", rename.similarity())?;
+                writeln!(out, "{kind} from {old_path}")?;
+                writeln!(out, "{kind} to {new_path}")?;
+            }
+            if old.hash() == new.hash() {
+                return Ok(());
+            }
+            if old.mode() == new.mode() {
+                let mode = u32::from(new.mode());
+                writeln!(out, "{} {mode:06o}", hashes(Some(old), Some(new)))?;
+            } else {
+                writeln!(out, "{}", hashes(Some(old), Some(new)))?;
+            }
+        }
+        (None, None) => return Ok(()),
+    }
+
+    let old_content = match old {
+        Some(file) => file.content(root, old_path)?,
+        None => vec![],
+    };
+    let new_content = match new {
+        Some(file) => file.content(root, new_path)?,
+        None => vec![],
+    };
+    let old_name = old.map_or(DEV_NULL.to_string(), |_| format!("a/{old_path}"));
+    let new_name = new.map_or(DEV_NULL.to_string(), |_| format!("b/{new_path}"));
+
+    if is_binary(&old_content) || is_binary(&new_content) {
+        writeln!(out, "Binary files {old_name} and {new_name} differ")?;
+        return Ok(());
+    }
+
+    let old_lines = lines(&old_content);
+    let new_lines = lines(&new_content);
+    let edits = diff(&old_lines, &new_lines, algorithm);
+    if edits.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out, "--- {old_name}")?;
+    writeln!(out, "+++ {new_name}")?;
+    for hunk in hunks(&edits, old_lines.len(), new_lines.len(), context) {
+        write_hunk_header(out, &hunk, &old_lines)?;
+        match format {
+            Format::Unified => write_hunk(out, &hunk, &old_lines, &new_lines)?,
+            Format::WordDiff => write_word_hunk(out, &hunk, &old_lines, &new_lines, algorithm)?,
+        }
+    }
+    Ok(())
+}
+
+// NOTE:
+// A range of one line has no count, and an empty range starts at
+// the line before it. The header ends with the closest line before the hunk
+// that starts like a function definition.
+fn write_hunk_header<W: Write>(out: &mut W, hunk: &Hunk, old_lines: &[&[u8]]) -> Result<()> {
+    let range = |range: &Range<usize>| match range.len() {
+        0 => format!("{},0", range.start),
+        1 => format!("{}", range.start + 1),
+        len => format!("{},{len}", range.start + 1),
+    };
+    write!(out, "@@ -{} +{} @@", range(&hunk.old), range(&hunk.new))?;
+
+    let func_line = old_lines[..hunk.old.start].iter().rev().find(|line| {
+        line.first()
+            .is_some_and(|&c| c.is_ascii_alphabetic() || c == b'_' || c == b'$')
+    });
+    if let Some(line) = func_line {
+        let line = &line[..line.len().min(FUNC_LINE_MAX)];
+        out.write_all(b" ")?;
+        out.write_all(line.trim_ascii_end())?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+fn write_hunk<W: Write>(
+    out: &mut W,
+    hunk: &Hunk,
+    old_lines: &[&[u8]],
+    new_lines: &[&[u8]],
+) -> Result<()> {
+    let mut write_line = |prefix: u8, line: &[u8]| -> Result<()> {
+        out.write_all(&[prefix])?;
+        out.write_all(line)?;
+        if !line.ends_with(b"\n") {
+            out.write_all(b"\n")?;
+            out.write_all(NO_NEWLINE)?;
+        }
+        Ok(())
+    };
+
+    let mut old = hunk.old.start;
+    for edit in &hunk.edits {
+        for line in &old_lines[old..edit.old.start] {
+            write_line(b' ', line)?;
+        }
+        for line in &old_lines[edit.old.clone()] {
+            write_line(b'-', line)?;
+        }
+        for line in &new_lines[edit.new.clone()] {
+            write_line(b'+', line)?;
+        }
+        old = edit.old.end;
+    }
+    for line in &old_lines[old..hunk.old.end] {
+        write_line(b' ', line)?;
+    }
+    Ok(())
+}
+
+// NOTE:
+// Context lines are written as they are, and each run of changed lines is
+// diffed word by word, a word being a run of non-whitespace characters.
+// Removed words are wrapped in "[-...-]" and added ones in "{+...+}".
+fn write_word_hunk<W: Write>(
+    out: &mut W,
+    hunk: &Hunk,
+    old_lines: &[&[u8]],
+    new_lines: &[&[u8]],
+    algorithm: Algorithm,
+) -> Result<()> {
+    let write_context = |out: &mut W, lines: &[&[u8]]| -> Result<()> {
+        for line in lines {
+            out.write_all(line)?;
+            if !line.ends_with(b"\n") {
+                out.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    };
+
+    let mut old = hunk.old.start;
+    for edit in &hunk.edits {
+        write_context(out, &old_lines[old..edit.old.start])?;
+        let old_text = old_lines[edit.old.clone()].concat();
+        let new_text = new_lines[edit.new.clone()].concat();
+        write_words(out, &old_text, &new_text, algorithm)?;
+        old = edit.old.end;
+    }
+    write_context(out, &old_lines[old..hunk.old.end])
+}
+
+fn write_words<W: Write>(
+    out: &mut W,
+    old_text: &[u8],
+    new_text: &[u8],
+    algorithm: Algorithm,
+) -> Result<()> {
+    // NOTE:
+    // Removed lines are wrapped whole, with their whitespace.
+    if new_text.is_empty() {
+        write_wrapped(out, old_text, "[-", "-]")?;
+        if !old_text.ends_with(b"\n") {
+            out.write_all(b"\n")?;
+        }
+        return Ok(());
+    }
+
+    let old_words = words(old_text);
+    let new_words = words(new_text);
+    let old_tokens: Vec<&[u8]> = old_words.iter().map(|w| &old_text[w.clone()]).collect();
+    let new_tokens: Vec<&[u8]> = new_words.iter().map(|w| &new_text[w.clone()]).collect();
+
+    let mut current = 0;
+    for Edit { old, new } in diff(&old_tokens, &new_tokens, algorithm) {
+        let plus_begin = match new.start.checked_sub(1) {
+            _ if !new.is_empty() => new_words[new.start].start,
+            Some(previous) => new_words[previous].end,
+            None => 0,
+        };
+        let plus_end = match new.end.checked_sub(1) {
+            Some(last) if !new.is_empty() => new_words[last].end,
+            _ => plus_begin,
+        };
+
+        out.write_all(&new_text[current..plus_begin])?;
+        if !old.is_empty() {
+            let removed = old_words[old.start].start..old_words[old.end - 1].end;
+            write_wrapped(out, &old_text[removed], "[-", "-]")?;
+        }
+        if !new.is_empty() {
+            write_wrapped(out, &new_text[plus_begin..plus_end], "{+", "+}")?;
+        }
+        current = plus_end;
+    }
+    out.write_all(&new_text[current..])?;
+    if !new_text[current..].ends_with(b"\n") {
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+// NOTE:
+// Each line of the text is wrapped on its own so that markers never span
+// lines.
+fn write_wrapped<W: Write>(out: &mut W, text: &[u8], open: &str, close: &str) -> Result<()> {
+    for (i, line) in text.split(|&c| c == b'\n').enumerate() {
+        if i > 0 {
+            out.write_all(b"\n")?;
+        }
+        if !line.is_empty() {
+            out.write_all(open.as_bytes())?;
+            out.write_all(line)?;
+            out.write_all(close.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+fn words(text: &[u8]) -> Vec<Range<usize>> {
+    let mut words: Vec<Range<usize>> = vec![];
+    let mut start: Option<usize> = None;
+    for (i, c) in text.iter().enumerate() {
+        match (c.is_ascii_whitespace(), start) {
+            (true, Some(s)) => {
+                words.push(s..i);
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        words.push(s..text.len());
+    }
+    words
+}
+
+struct FileStat {
+    path: String,
+    added: usize,
+    deleted: usize,
+    binary: bool,
+}
+
+// NOTE:
+// Writes git's diffstat: one line per file with the number of changed lines
+// and a graph scaled down to fit in the width, then a summary line. Binary
+// files show their sizes in bytes instead.
+pub fn write_stat<P: AsRef<Path>, W: Write>(
+    out: &mut W,
+    root: P,
+    pairs: &[FilePair],
+    algorithm: Algorithm,
+) -> Result<()> {
+    let root = root.as_ref();
+    let mut stats: Vec<FileStat> = vec![];
+    for pair in pairs {
+        let old = match pair.old_file() {
+            Some(file) => file.content(root, pair.old_path())?,
+            None => vec![],
+        };
+        let new = match pair.new_file() {
+            Some(file) => file.content(root, pair.path())?,
+            None => vec![],
+        };
+
+        let (added, deleted, binary) = if is_binary(&old) || is_binary(&new) {
+            (new.len(), old.len(), true)
+        } else {
+            let edits = diff(&lines(&old), &lines(&new), algorithm);
+            let added = edits.iter().map(|e| e.new.len()).sum();
+            let deleted = edits.iter().map(|e| e.old.len()).sum();
+            (added, deleted, false)
+        };
+        let path = match pair.rename() {
+            Some(rename) => rename_name(rename.from(), pair.path()),
+            None => pair.path().to_string(),
+        };
+        stats.push(FileStat {
+            path,
+            added,
+            deleted,
+            binary,
+        });
+    }
+
+    let decimal_width = |n: usize| n.to_string().len();
+    let max_len = stats
+        .iter()
+        .map(|s| s.path.chars().count())
+        .max()
+        .unwrap_or(0);
+    let max_change = stats
+        .iter()
+        .filter(|s| !s.binary)
+        .map(|s| s.added + s.deleted)
+        .max()
+        .unwrap_or(0);
+    let bin_width = stats
+        .iter()
+        .filter(|s| s.binary)
+        .map(|s| 14 + decimal_width(s.added) + decimal_width(s.deleted))
+        .max()
+        .unwrap_or(0);
+    let mut number_width = decimal_width(max_change);
+    if stats.iter().any(|s| s.binary) {
+        number_width = number_width.max(3);
+    }
+
+    let width = STAT_WIDTH.max(16 + 6 + number_width);
+    let mut graph_width = if max_change + 4 > bin_width {
+        max_change
+    } else {
+        bin_width - 4
+    };
+    let mut name_width = max_len;
+    if name_width + number_width + 6 + graph_width > width {
+        let limit = (width * 3 / 8).saturating_sub(number_width + 6);
+        if graph_width > limit {
+            graph_width = limit.max(6);
+        }
+        if name_width > width - number_width - 6 - graph_width {
+            name_width = width - number_width - 6 - graph_width;
+        } else {
+            graph_width = width - number_width - 6 - name_width;
+        }
+    }
+
+    let scale = |it: usize| {
+        if it == 0 {
+            0
+        } else {
+            1 + it * (graph_width - 1) / max_change
+        }
+    };
+
+    let (mut insertions, mut deletions) = (0, 0);
+    for stat in &stats {
+        let name = truncate(&stat.path, name_width);
+        let padding = name_width.saturating_sub(name.chars().count());
+        write!(out, " {name}{} |", " ".repeat(padding))?;
+
+        if stat.binary {
+            write!(out, " {:>number_width$}", "Bin")?;
+            if stat.added != 0 || stat.deleted != 0 {
+                write!(out, " {} -> {} bytes", stat.deleted, stat.added)?;
+            }
+            writeln!(out)?;
+            continue;
+        }
+
+        insertions += stat.added;
+        deletions += stat.deleted;
+        let total = stat.added + stat.deleted;
+        let (mut add, mut del) = (stat.added, stat.deleted);
+        if graph_width <= max_change {
+            let mut scaled = scale(total);
+            if scaled < 2 && add > 0 && del > 0 {
+                scaled = 2;
+            }
+            if add < del {
+                add = scale(add);
+                del = scaled - add;
+            } else {
+                del = scale(del);
+                add = scaled - del;
+            }
+        }
+        let space = if total > 0 { " " } else { "" };
+        writeln!(
+            out,
+            " {total:>number_width$}{space}{}{}",
+            "+".repeat(add),
+            "-".repeat(del)
+        )?;
+    }
+
+    let plural = |n: usize, one: &str, many: &str| {
+        if n == 1 {
+            one.to_string()
+        } else {
+            many.to_string()
+        }
+    };
+    write!(
+        out,
+        " {} {} changed",
+        stats.len(),
+        plural(stats.len(), "file", "files")
+    )?;
+    if insertions > 0 || deletions == 0 {
+        let word = plural(insertions, "insertion", "insertions");
+        write!(out, ", {insertions} {word}(+)")?;
+    }
+    if deletions > 0 || insertions == 0 {
+        let word = plural(deletions, "deletion", "deletions");
+        write!(out, ", {deletions} {word}(-)")?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+// NOTE:
+// Writes git's summary of the created, deleted, renamed and copied files and
+// the mode changes, like `--summary`.
+pub fn write_summary<W: Write>(out: &mut W, pairs: &[FilePair]) -> Result<()> {
+    for pair in pairs {
+        match (pair.old_file(), pair.new_file(), pair.rename()) {
+            (None, Some(new), _) => {
+                let mode = u32::from(new.mode());
+                writeln!(out, " create mode {mode:06o} {}", pair.path())?;
+            }
+            (Some(old), None, _) => {
+                let mode = u32::from(old.mode());
+                writeln!(out, " delete mode {mode:06o} {}", pair.path())?;
+            }
+            (Some(old), Some(new), Some(rename)) => {
+                let kind = if rename.is_copy() { "copy" } else { "rename" };
+                writeln!(
+                    out,
+                    " {kind} {} ({}%)",
+                    rename_name(rename.from(), pair.path()),
+                    rename.similarity()
+                )?;
+                if old.mode() != new.mode() {
+                    let (old, new) = (u32::from(old.mode()), u32::from(new.mode()));
+                    writeln!(out, " mode change {old:06o} => {new:06o}")?;
+                }
+            }
+            (Some(old), Some(new), None) if old.mode() != new.mode() => {
+                let (old, new) = (u32::from(old.mode()), u32::from(new.mode()));
+                writeln!(out, " mode change {old:06o} => {new:06o} {}", pair.path())?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// NOTE:
+// A name too long for the column keeps its end after "...", starting at a
+// directory boundary when there's one.
+fn truncate(name: &str, width: usize) -> String {
+    let len = name.chars().count();
+    if len <= width {
+        return name.to_string();
+    }
+    let tail: String = name.chars().skip(len - width.saturating_sub(3)).collect();
+    match tail.find('/') {
+        Some(pos) => format!("...{}", &tail[pos..]),
+        None => format!("...{tail}"),
+    }
+}
+
+// NOTE:
+// The common leading and trailing directories of both paths are
+// written once around braces, as in "dir/{old => new}/file".
+fn rename_name(old: &str, new: &str) -> String {
+    let (a, b) = (old.as_bytes(), new.as_bytes());
+    let prefix = a
+        .iter()
+        .zip(b)
+        .take_while(|(x, y)| x == y)
+        .enumerate()
+        .filter(|(_, (&c, _))| c == b'/')
+        .last()
+        .map_or(0, |(i, _)| i + 1);
+
+    // NOTE:
+    // The end of both paths is compared too, and the suffix may start at the
+    // slash that ends the prefix.
+    let at = |s: &[u8], i: usize| s.get(i).copied().unwrap_or(0);
+    let floor = prefix.saturating_sub(1);
+    let (mut i, mut j, mut suffix) = (a.len(), b.len(), 0);
+    loop {
+        if at(a, i) != at(b, j) {
+            break;
+        }
+        if at(a, i) == b'/' {
+            suffix = a.len() - i;
+        }
+        if i == floor || j == floor {
+            break;
+        }
+        i -= 1;
+        j -= 1;
+    }
+
+    let old_mid = &old[prefix..a.len().saturating_sub(suffix).max(prefix)];
+    let new_mid = &new[prefix..b.len().saturating_sub(suffix).max(prefix)];
+    if prefix + suffix == 0 {
+        return format!("{old_mid} => {new_mid}");
+    }
+    format!(
+        "{}{{{old_mid} => {new_mid}}}{}",
+        &old[..prefix],
+        &old[a.len() - suffix..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hunk_header(old: &str, hunk: Hunk) -> String {
+        let old_lines = lines(old.as_bytes());
+        let mut out: Vec<u8> = vec![];
+        write_hunk_header(&mut out, &hunk, &old_lines).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn it_writes_hunk_headers() {
+        let hunk = Hunk {
+            old: 1..4,
+            new: 1..2,
+            edits: vec![],
+        };
+        assert_eq!(
+            hunk_header("fn main() {   \n  a\n  b\n  c\n", hunk),
+            "@@ -2,3 +2 @@ fn main() {\n"
+        );
+
+        let hunk = Hunk {
+            old: 0..0,
+            new: 0..2,
+            edits: vec![],
+        };
+        assert_eq!(hunk_header("", hunk), "@@ -0,0 +1,2 @@\n");
+    }
+
+    #[test]
+    fn it_writes_unified_hunks() {
+        let old = lines(b"a\nb\nc");
+        let new = lines(b"a\nB\nc\n");
+        let edits = diff(&old, &new, Algorithm::Myers);
+        let hunk = hunks(&edits, old.len(), new.len(), 3).remove(0);
+        let mut out: Vec<u8> = vec![];
+        write_hunk(&mut out, &hunk, &old, &new).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            " a\n-b\n-c\n\\ No newline at end of file\n+B\n+c\n"
+        );
+    }
+
+    #[test]
+    fn it_writes_word_diffs() {
+        let mut out: Vec<u8> = vec![];
+        write_words(
+            &mut out,
+            b"the quick fox\njumps\n",
+            b"the slow fox\njumps high\n",
+            Algorithm::Myers,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "the [-quick-]{+slow+} fox\njumps {+high+}\n"
+        );
+    }
+
+    #[test]
+    fn it_truncates_long_names() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("some/long/path/name", 12), ".../name");
+        assert_eq!(truncate("averylongname", 8), "...gname");
+    }
+
+    #[test]
+    fn it_names_renames() {
+        assert_eq!(rename_name("a.txt", "b.txt"), "a.txt => b.txt");
+        assert_eq!(rename_name("dir/a", "dir/b"), "dir/{a => b}");
+        assert_eq!(rename_name("old/sub/f", "new/sub/f"), "{old => new}/sub/f");
+        assert_eq!(rename_name("a/f", "a/b/f"), "a/{ => b}/f");
+        assert_eq!(rename_name("f", "dir/f"), "f => dir/f");
+    }
+}
@@ -0,0 +1,139 @@
+use super::myers;
+use std::collections::HashMap;
+use std::ops::Range;
+
+// NOTE:
+// Lines that occur exactly once on each side are matched first, keeping the
+// longest sequence of them in the same order on both sides. The gaps between
+// those anchors are diffed recursively, and ranges without any unique common
+// line fall back to Myers.
+pub(super) fn diff(
+    old: &[usize],
+    new: &[usize],
+    old_range: Range<usize>,
+    new_range: Range<usize>,
+    old_changed: &mut [bool],
+    new_changed: &mut [bool],
+) {
+    if old_range.is_empty() || new_range.is_empty() {
+        old_changed[old_range].fill(true);
+        new_changed[new_range].fill(true);
+        return;
+    }
+
+    let Some(anchors) = unique_common(old, new, old_range.clone(), new_range.clone()) else {
+        old_changed[old_range].fill(true);
+        new_changed[new_range].fill(true);
+        return;
+    };
+    if anchors.is_empty() {
+        myers::diff(old, new, old_range, new_range, old_changed, new_changed);
+        return;
+    }
+
+    let (mut line1, mut line2) = (old_range.start, new_range.start);
+    let mut anchors = anchors.into_iter().peekable();
+    loop {
+        let (next1, next2) = match anchors.peek() {
+            Some(&(mut next1, mut next2)) => {
+                while next1 > line1 && next2 > line2 && old[next1 - 1] == new[next2 - 1] {
+                    next1 -= 1;
+                    next2 -= 1;
+                }
+                (next1, next2)
+            }
+            None => (old_range.end, new_range.end),
+        };
+        while line1 < next1 && line2 < next2 && old[line1] == new[line2] {
+            line1 += 1;
+            line2 += 1;
+        }
+        if next1 > line1 || next2 > line2 {
+            diff(
+                old,
+                new,
+                line1..next1,
+                line2..next2,
+                old_changed,
+                new_changed,
+            );
+        }
+
+        let Some(mut anchor) = anchors.next() else {
+            return;
+        };
+        while let Some(&next) = anchors.peek() {
+            if next != (anchor.0 + 1, anchor.1 + 1) {
+                break;
+            }
+            anchor = next;
+            anchors.next();
+        }
+        line1 = anchor.0 + 1;
+        line2 = anchor.1 + 1;
+    }
+}
+
+// NOTE:
+// Returns None when no line at all is common to both ranges, otherwise the
+// longest increasing sequence of the lines unique on both sides.
+fn unique_common(
+    old: &[usize],
+    new: &[usize],
+    old_range: Range<usize>,
+    new_range: Range<usize>,
+) -> Option<Vec<(usize, usize)>> {
+    // NOTE:
+    // Each line of the old range with its first position, how many times it's
+    // in the old range and its position in the new range if it's there once.
+    let mut order: Vec<usize> = vec![];
+    let mut lines: HashMap<usize, (usize, usize, Option<usize>, usize)> = HashMap::new();
+    for i in old_range {
+        let entry = lines.entry(old[i]).or_insert_with(|| {
+            order.push(old[i]);
+            (i, 0, None, 0)
+        });
+        entry.1 += 1;
+    }
+
+    let mut has_matches = false;
+    for j in new_range {
+        if let Some(entry) = lines.get_mut(&new[j]) {
+            has_matches = true;
+            entry.2 = Some(j);
+            entry.3 += 1;
+        }
+    }
+    if !has_matches {
+        return None;
+    }
+
+    // NOTE:
+    // Patience sorting: each line goes on the leftmost pile whose top is
+    // further in the new range, linked to the top of the pile on its left.
+    let mut piles: Vec<usize> = vec![];
+    let mut candidates: Vec<(usize, usize, Option<usize>)> = vec![];
+    for id in order {
+        let (line1, count1, line2, count2) = lines[&id];
+        let Some(line2) = line2.filter(|_| count1 == 1 && count2 == 1) else {
+            continue;
+        };
+        let pos = piles.partition_point(|&c| candidates[c].1 < line2);
+        let previous = pos.checked_sub(1).map(|p| piles[p]);
+        candidates.push((line1, line2, previous));
+        if pos == piles.len() {
+            piles.push(candidates.len() - 1);
+        } else {
+            piles[pos] = candidates.len() - 1;
+        }
+    }
+
+    let mut sequence: Vec<(usize, usize)> = vec![];
+    let mut next = piles.last().copied();
+    while let Some(c) = next {
+        sequence.push((candidates[c].0, candidates[c].1));
+        next = candidates[c].2;
+    }
+    sequence.reverse();
+    Some(sequence)
+}
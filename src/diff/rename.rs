@@ -0,0 +1,542 @@
+use super::{
+    files::{self, DiffFile, FilePair, Rename},
+    is_binary,
+};
+use crate::{config::Config, git_object::tree::Mode, Error, Result, Sha1Hash};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::Path;
+
+// NOTE:
+// Scores are fractions of MAX_SCORE like in git, so that "-M50%" is 30000.
+pub const MAX_SCORE: usize = 60000;
+const DEFAULT_MIN_SCORE: usize = 30000;
+const NUM_CANDIDATES: usize = 4;
+const RENAME_LIMIT: usize = 1000;
+const HASHBASE: u32 = 107927;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Detection {
+    copies: bool,
+    min_score: usize,
+}
+
+impl Detection {
+    pub fn renames() -> Self {
+        Self {
+            copies: false,
+            min_score: DEFAULT_MIN_SCORE,
+        }
+    }
+
+    pub fn copies() -> Self {
+        Self {
+            copies: true,
+            min_score: DEFAULT_MIN_SCORE,
+        }
+    }
+
+    pub fn min_score(mut self, min_score: usize) -> Self {
+        self.min_score = min_score;
+        self
+    }
+
+    // NOTE:
+    // Reads the first of the keys that is set, like "diff.renames", which is
+    // a boolean or "copies". Renames are detected when none is set.
+    pub fn configured<P: AsRef<Path>>(root: P, keys: &[&str]) -> Result<Option<Self>> {
+        let config = Config::load(root)?;
+        let Some(value) = keys.iter().find_map(|key| config.get(key)) else {
+            return Ok(Some(Self::renames()));
+        };
+        Ok(match value.to_lowercase().as_str() {
+            "false" | "no" | "off" | "0" => None,
+            "copies" | "copy" => Some(Self::copies()),
+            _ => Some(Self::renames()),
+        })
+    }
+
+    // NOTE:
+    // "90%" is 90% and a number without "%" is read as a fraction
+    // after a decimal point, so "5" is 50% and "05" is 5%.
+    pub fn parse_score(value: &str) -> Result<usize> {
+        let (mut num, mut scale, mut dot) = (0, 1, false);
+        let mut chars = value.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '.' if !dot => {
+                    scale = 1;
+                    dot = true;
+                }
+                '%' if chars.peek().is_none() => {
+                    scale = if dot { scale * 100 } else { 100 };
+                }
+                '0'..='9' => {
+                    if scale < 100000 {
+                        scale *= 10;
+                        num = num * 10 + c.to_digit(10).expect("digit") as usize;
+                    }
+                }
+                _ => {
+                    let msg = format!("invalid similarity score: {value}");
+                    return Err(Error::from(msg.as_str()));
+                }
+            }
+        }
+        Ok(if num >= scale {
+            MAX_SCORE
+        } else {
+            MAX_SCORE * num / scale
+        })
+    }
+}
+
+struct SourceFile {
+    path: String,
+    file: DiffFile,
+    used: usize,
+}
+
+struct Target {
+    pair: usize,
+    matched: Option<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    score: usize,
+    same_basename: bool,
+    dst: usize,
+    src: usize,
+}
+
+// NOTE:
+// Empty slots sort last, then the higher scores and the same basenames first.
+fn compare(a: &Option<Candidate>, b: &Option<Candidate>) -> Ordering {
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) => b
+            .score
+            .cmp(&a.score)
+            .then(b.same_basename.cmp(&a.same_basename)),
+    }
+}
+
+// NOTE:
+// Pairs the added files with the files they came from, like git's
+// diffcore-rename. Identical blobs are matched first, then files with the
+// same basename if it's unique on both sides, and then the most similar
+// files. With copies, the modified files are sources too and a source can be
+// used more than once. The pairs keep their order, and the deletions of the
+// sources that were used are dropped. A source used several times is copied
+// to all but the last file, which is a rename if the source was deleted.
+pub fn detect<P: AsRef<Path>>(
+    root: P,
+    pairs: Vec<FilePair>,
+    detection: Detection,
+) -> Result<Vec<FilePair>> {
+    let mut srcs: Vec<SourceFile> = vec![];
+    let mut dsts: Vec<Target> = vec![];
+    let mut deleted: HashMap<usize, usize> = HashMap::new();
+    for (i, pair) in pairs.iter().enumerate() {
+        match (pair.old_file(), pair.new_file()) {
+            (None, Some(_)) => dsts.push(Target {
+                pair: i,
+                matched: None,
+            }),
+            (Some(file), None) => {
+                deleted.insert(i, srcs.len());
+                srcs.push(SourceFile {
+                    path: pair.path().to_string(),
+                    file,
+                    used: 0,
+                });
+            }
+            (Some(file), Some(_)) if detection.copies => srcs.push(SourceFile {
+                path: pair.path().to_string(),
+                file,
+                used: 1,
+            }),
+            _ => {}
+        }
+    }
+    if srcs.is_empty() || dsts.is_empty() {
+        return Ok(pairs);
+    }
+
+    let mut matcher = Matcher::new(root.as_ref(), detection.min_score);
+    let dst_files: Vec<(&str, DiffFile)> = dsts
+        .iter()
+        .map(|dst| {
+            let pair = &pairs[dst.pair];
+            (pair.path(), pair.new_file().expect("added file"))
+        })
+        .collect();
+
+    // NOTE:
+    // Identical files, preferring the sources not used yet and then the ones
+    // with the same basename.
+    for (d, (path, file)) in dst_files.iter().enumerate() {
+        let mut best: Option<(usize, usize)> = None;
+        for (s, src) in srcs.iter().enumerate() {
+            if src.file.hash() != file.hash()
+                || (!(is_regular(src.file.mode()) && is_regular(file.mode()))
+                    && src.file.mode() != file.mode())
+                || (src.used > 0 && !detection.copies)
+            {
+                continue;
+            }
+            let score = (src.used == 0) as usize + basename_same(&src.path, path) as usize;
+            if best.map_or(true, |(_, best)| score > best) {
+                best = Some((s, score));
+                if score == 2 {
+                    break;
+                }
+            }
+        }
+        if let Some((s, _)) = best {
+            dsts[d].matched = Some((s, MAX_SCORE));
+            srcs[s].used += 1;
+        }
+    }
+
+    if detection.min_score < MAX_SCORE {
+        // NOTE:
+        // A file moved to another directory needs a score halfway between the
+        // minimum and an exact match.
+        if !detection.copies {
+            let min_score = detection.min_score + (MAX_SCORE - detection.min_score) / 2;
+            let mut src_names: HashMap<String, Option<usize>> = HashMap::new();
+            for (s, src) in srcs.iter().enumerate().filter(|(_, src)| src.used == 0) {
+                let name = basename(&src.path).to_string();
+                src_names
+                    .entry(name)
+                    .and_modify(|s| *s = None)
+                    .or_insert(Some(s));
+            }
+            let mut dst_names: HashMap<&str, Option<usize>> = HashMap::new();
+            for (d, (path, _)) in dst_files.iter().enumerate() {
+                if dsts[d].matched.is_none() {
+                    let name = basename(path);
+                    dst_names
+                        .entry(name)
+                        .and_modify(|d| *d = None)
+                        .or_insert(Some(d));
+                }
+            }
+            for (s, src) in srcs.iter_mut().enumerate() {
+                let name = basename(&src.path);
+                let (Some(Some(unique)), Some(Some(d))) =
+                    (src_names.get(name), dst_names.get(name))
+                else {
+                    continue;
+                };
+                if *unique != s || dsts[*d].matched.is_some() {
+                    continue;
+                }
+                let score = matcher.similarity((&src.path, src.file), dst_files[*d])?;
+                if score >= min_score {
+                    dsts[*d].matched = Some((s, score));
+                    src.used += 1;
+                }
+            }
+        }
+
+        let sources: Vec<usize> = (0..srcs.len())
+            .filter(|&s| detection.copies || srcs[s].used == 0)
+            .collect();
+        let targets: Vec<usize> = (0..dsts.len())
+            .filter(|&d| dsts[d].matched.is_none())
+            .collect();
+        // NOTE:
+        // Only the best candidates of each file are kept, and nothing is
+        // compared when there are too many files.
+        if sources.len() * targets.len() <= RENAME_LIMIT * RENAME_LIMIT {
+            let mut matrix: Vec<Option<Candidate>> = vec![];
+            for &d in targets.iter() {
+                let mut slots = [None; NUM_CANDIDATES];
+                for &s in sources.iter() {
+                    let src = (srcs[s].path.as_str(), srcs[s].file);
+                    let candidate = Some(Candidate {
+                        score: matcher.similarity(src, dst_files[d])?,
+                        same_basename: basename_same(&srcs[s].path, dst_files[d].0),
+                        dst: d,
+                        src: s,
+                    });
+                    let mut worst = 0;
+                    for i in 1..NUM_CANDIDATES {
+                        if compare(&slots[i], &slots[worst]).is_gt() {
+                            worst = i;
+                        }
+                    }
+                    if compare(&slots[worst], &candidate).is_gt() {
+                        slots[worst] = candidate;
+                    }
+                }
+                matrix.extend(slots);
+            }
+            matrix.sort_by(compare);
+
+            // NOTE:
+            // Sources that weren't renamed yet are preferred over copies.
+            for copies in [false, true] {
+                if copies && !detection.copies {
+                    break;
+                }
+                for candidate in matrix.iter() {
+                    let Some(candidate) = candidate.filter(|c| c.score >= detection.min_score)
+                    else {
+                        break;
+                    };
+                    if dsts[candidate.dst].matched.is_some()
+                        || (!copies && srcs[candidate.src].used > 0)
+                    {
+                        continue;
+                    }
+                    dsts[candidate.dst].matched = Some((candidate.src, candidate.score));
+                    srcs[candidate.src].used += 1;
+                }
+            }
+        }
+    }
+
+    let renamed: HashMap<usize, (usize, usize)> = dsts
+        .iter()
+        .filter_map(|dst| dst.matched.map(|matched| (dst.pair, matched)))
+        .collect();
+    let dropped: Vec<usize> = deleted
+        .iter()
+        .filter(|(_, &s)| srcs[s].used > 0)
+        .map(|(&i, _)| i)
+        .collect();
+    let mut result: Vec<FilePair> = vec![];
+    for (i, pair) in pairs.into_iter().enumerate() {
+        if dropped.contains(&i) {
+            continue;
+        }
+        match renamed.get(&i) {
+            Some(&(s, score)) => {
+                srcs[s].used -= 1;
+                let rename = Rename::new(srcs[s].path.clone(), score, srcs[s].used > 0);
+                result.push(pair.with_rename(srcs[s].file, rename));
+            }
+            None => result.push(pair),
+        }
+    }
+    Ok(result)
+}
+
+// NOTE:
+// Finds the file that `path` of the new tree was renamed or copied from,
+// like "git log --follow". Any file of the old tree can be the source, and
+// the other added files are ignored.
+pub fn find_origin<P: AsRef<Path>>(
+    root: P,
+    old_tree: &str,
+    new_tree: &str,
+    path: &str,
+) -> Result<Option<String>> {
+    let root = root.as_ref();
+    let old = files::Source::Tree(old_tree.to_string());
+    let new = files::Source::Tree(new_tree.to_string());
+    let mut pairs = files::all_pairs(root, &old, &new)?;
+    pairs.retain(|pair| pair.old_file().is_some() || pair.path() == path);
+    Ok(detect(root, pairs, Detection::copies())?
+        .into_iter()
+        .find(|pair| pair.path() == path)
+        .and_then(|pair| pair.rename().map(|rename| rename.from().to_string())))
+}
+
+fn is_regular(mode: Mode) -> bool {
+    !matches!(mode, Mode::Symlink | Mode::Directory | Mode::Gitlink)
+}
+
+fn basename(path: &str) -> &str {
+    path.rsplit_once('/').map_or(path, |(_, name)| name)
+}
+
+fn basename_same(a: &str, b: &str) -> bool {
+    basename(a) == basename(b)
+}
+
+// NOTE:
+// Estimates how much of the destination was copied from the source, like
+// git. The files are cut into chunks that end at a newline or after 64
+// bytes, and the bytes of the chunks found on both sides are counted.
+struct Matcher<'a> {
+    root: &'a Path,
+    min_score: usize,
+    chunks: HashMap<Sha1Hash, (usize, HashMap<u32, usize>)>,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(root: &'a Path, min_score: usize) -> Self {
+        Self {
+            root,
+            min_score,
+            chunks: HashMap::new(),
+        }
+    }
+
+    fn similarity(&mut self, src: (&str, DiffFile), dst: (&str, DiffFile)) -> Result<usize> {
+        if !is_regular(src.1.mode()) || !is_regular(dst.1.mode()) {
+            return Ok(0);
+        }
+        self.load(src)?;
+        self.load(dst)?;
+        let (src_size, src_chunks) = &self.chunks[&src.1.hash()];
+        let (dst_size, dst_chunks) = &self.chunks[&dst.1.hash()];
+        let max_size = *src_size.max(dst_size);
+        let delta_size = max_size - *src_size.min(dst_size);
+        if max_size * (MAX_SCORE - self.min_score) < delta_size * MAX_SCORE || *dst_size == 0 {
+            return Ok(0);
+        }
+        let copied: usize = src_chunks
+            .iter()
+            .map(|(hash, count)| dst_chunks.get(hash).map_or(0, |other| *count.min(other)))
+            .sum();
+        Ok(copied * MAX_SCORE / max_size)
+    }
+
+    fn load(&mut self, (path, file): (&str, DiffFile)) -> Result<()> {
+        if !self.chunks.contains_key(&file.hash()) {
+            let content = file.content(self.root, path)?;
+            self.chunks
+                .insert(file.hash(), (content.len(), chunks(&content)));
+        }
+        Ok(())
+    }
+}
+
+fn chunks(content: &[u8]) -> HashMap<u32, usize> {
+    let text = !is_binary(content);
+    let mut counts: HashMap<u32, usize> = HashMap::new();
+    let (mut accum1, mut accum2, mut n) = (0u32, 0u32, 0);
+    for (i, &c) in content.iter().enumerate() {
+        // NOTE:
+        // The CR of a CRLF is ignored in text files.
+        if text && c == b'\r' && content.get(i + 1) == Some(&b'\n') {
+            continue;
+        }
+        let old = accum1;
+        accum1 = (accum1 << 7) ^ (accum2 >> 25);
+        accum2 = (accum2 << 7) ^ (old >> 25);
+        accum1 = accum1.wrapping_add(c as u32);
+        n += 1;
+        if n < 64 && c != b'\n' {
+            continue;
+        }
+        let hash = accum1.wrapping_add(accum2.wrapping_mul(0x61)) % HASHBASE;
+        *counts.entry(hash).or_default() += n;
+        (accum1, accum2, n) = (0, 0, 0);
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use files::Source;
+
+    fn write_tree(root: &Path, files: &[(&str, String)]) -> Source {
+        let files: Vec<(&str, &str)> = files.iter().map(|(p, c)| (*p, c.as_str())).collect();
+        Source::Tree(testing::write_tree(root, &files))
+    }
+
+    fn numbers(range: std::ops::Range<usize>) -> String {
+        range.map(|n| format!("{n}\n")).collect()
+    }
+
+    // NOTE:
+    // Like "new R90 old" for renames, as in `git diff --name-status`.
+    fn summary(pairs: &[FilePair]) -> Vec<String> {
+        pairs
+            .iter()
+            .map(|pair| match pair.rename() {
+                Some(r) => {
+                    let code = if r.is_copy() { 'C' } else { 'R' };
+                    format!("{} {code}{} {}", pair.path(), r.similarity(), r.from())
+                }
+                None => pair.path().to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_parses_scores() {
+        assert_eq!(Detection::parse_score("50%").unwrap(), 30000);
+        assert_eq!(Detection::parse_score("5").unwrap(), 30000);
+        assert_eq!(Detection::parse_score("05").unwrap(), 3000);
+        assert_eq!(Detection::parse_score("0.9").unwrap(), 54000);
+        assert_eq!(Detection::parse_score("100%").unwrap(), MAX_SCORE);
+        assert!(Detection::parse_score("5x").is_err());
+    }
+
+    #[test]
+    fn it_detects_exact_and_similar_renames() {
+        let root = testing::repo("rename-renames");
+        let old = write_tree(
+            &root,
+            &[
+                ("a", numbers(0..20)),
+                ("dir/b", numbers(20..40)),
+                ("gone", numbers(40..60)),
+            ],
+        );
+        let mut b = numbers(20..39);
+        b.push_str("changed\n");
+        let new = write_tree(
+            &root,
+            &[
+                ("moved/a", numbers(0..20)),
+                ("c", b),
+                ("other", numbers(60..80)),
+            ],
+        );
+
+        let pairs = files::pairs(&root, &old, &new, &[]).unwrap();
+        let pairs = detect(&root, pairs, Detection::renames()).unwrap();
+        assert_eq!(
+            summary(&pairs),
+            vec!["c R87 dir/b", "gone", "moved/a R100 a", "other",]
+        );
+        assert_eq!(pairs[0].old_path(), "dir/b");
+
+        let pairs = files::pairs(&root, &old, &new, &[]).unwrap();
+        let strict = Detection::renames().min_score(MAX_SCORE);
+        let renamed: Vec<String> = detect(&root, pairs, strict)
+            .unwrap()
+            .into_iter()
+            .filter(|pair| pair.rename().is_some())
+            .map(|pair| pair.path().to_string())
+            .collect();
+        assert_eq!(renamed, vec!["moved/a"]);
+    }
+
+    #[test]
+    fn it_detects_copies() {
+        let root = testing::repo("rename-copies");
+        let old = write_tree(&root, &[("a", numbers(0..20)), ("b", numbers(20..40))]);
+        let mut a = numbers(0..20);
+        a.push_str("more\n");
+        let new = write_tree(
+            &root,
+            &[
+                ("a", a),
+                ("a2", numbers(0..20)),
+                ("b2", numbers(20..40)),
+                ("b3", numbers(20..40)),
+            ],
+        );
+
+        let pairs = files::pairs(&root, &old, &new, &[]).unwrap();
+        let pairs = detect(&root, pairs, Detection::copies()).unwrap();
+        assert_eq!(
+            summary(&pairs),
+            vec!["a", "a2 C100 a", "b2 C100 b", "b3 R100 b"]
+        );
+    }
+}
@@ -0,0 +1,53 @@
+use super::{config::Config, Error, Result};
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+// NOTE:
+// The editor for messages, looked up in GIT_EDITOR, "core.editor", VISUAL
+// and EDITOR. Without a terminal there is no default editor to fall
+// back on, so None means the text is used as it is.
+pub fn editor<P: AsRef<Path>>(root: P) -> Result<Option<String>> {
+    let config = Config::load(root)?;
+    Ok(env::var("GIT_EDITOR")
+        .ok()
+        .or_else(|| config.get("core.editor").map(String::from))
+        .or_else(|| env::var("VISUAL").ok())
+        .or_else(|| env::var("EDITOR").ok())
+        .filter(|editor| !editor.is_empty()))
+}
+
+// NOTE:
+// The editor for the todo list of an interactive rebase, GIT_SEQUENCE_EDITOR
+// or "sequence.editor" before the message editor.
+pub fn sequence_editor<P: AsRef<Path>>(root: P) -> Result<Option<String>> {
+    let config = Config::load(&root)?;
+    match env::var("GIT_SEQUENCE_EDITOR")
+        .ok()
+        .or_else(|| config.get("sequence.editor").map(String::from))
+        .filter(|editor| !editor.is_empty())
+    {
+        Some(editor) => Ok(Some(editor)),
+        None => editor(root),
+    }
+}
+
+// NOTE:
+// The editor is a shell command that gets the file as its argument, so that
+// it can have arguments of its own.
+pub fn edit<P: AsRef<Path>, Q: AsRef<Path>>(root: P, editor: &str, path: Q) -> Result<()> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$@\""))
+        .arg(editor)
+        .arg(path.as_ref())
+        .current_dir(root)
+        .status();
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        _ => {
+            let msg = format!("There was a problem with the editor '{editor}'.");
+            Err(Error::from(msg.as_str()))
+        }
+    }
+}
@@ -1,9 +1,9 @@
+use crate::date;
 use regex::Regex;
 use std::fmt;
-use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Debug, Clone, PartialEq)]
-struct User {
+pub struct User {
     name: String,
     email: String,
     timestamp: u64,
@@ -25,19 +25,37 @@ impl From<&[u8]> for User {
     }
 }
 
-impl Default for User {
-    fn default() -> Self {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+impl User {
+    pub fn new(name: String, email: String, timestamp: u64, timezone: String) -> Self {
         Self {
-            name: "Kanji Tanaka".into(),
-            email: "sumireminami@gmail.com".into(),
+            name,
+            email,
             timestamp,
-            timezone: "+0000".to_string(),
+            timezone,
         }
     }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn email(&self) -> &str {
+        self.email.as_str()
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn timezone(&self) -> &str {
+        self.timezone.as_str()
+    }
+
+    // NOTE:
+    // The timezone in minutes east of UTC.
+    pub fn offset(&self) -> i32 {
+        date::parse_offset(&self.timezone).unwrap_or_default()
+    }
 }
 
 impl fmt::Display for User {
@@ -57,19 +75,51 @@ pub struct Commit {
     comment: String,
     author: User,
     committer: User,
+    headers: Vec<(String, String)>,
 }
 
 impl Commit {
-    pub fn new(tree: String, comment: String, parents: Vec<String>) -> Self {
+    pub fn new(
+        tree: String,
+        comment: String,
+        parents: Vec<String>,
+        author: User,
+        committer: User,
+    ) -> Self {
         Self {
             tree,
-            comment,
+            comment: with_trailing_newline(comment),
             parents,
-            author: User::default(),
-            committer: User::default(),
+            author,
+            committer,
+            headers: vec![],
         }
     }
 
+    pub fn tree(&self) -> &str {
+        self.tree.as_str()
+    }
+
+    pub fn parents(&self) -> &[String] {
+        &self.parents
+    }
+
+    pub fn comment(&self) -> &str {
+        self.comment.as_str()
+    }
+
+    pub fn author(&self) -> &User {
+        &self.author
+    }
+
+    pub fn committer(&self) -> &User {
+        &self.committer
+    }
+
+    pub fn summary(&self) -> &str {
+        self.comment.lines().next().unwrap_or_default()
+    }
+
     pub fn serialize(&self) -> Vec<u8> {
         let parents: String = self
             .parents
@@ -77,12 +127,21 @@ impl Commit {
             .map(|p| format!("parent {p}\n"))
             .collect::<Vec<String>>()
             .join("");
+        // NOTE:
+        // Multi-line header values (like gpgsig) continue with a leading space.
+        let headers: String = self
+            .headers
+            .iter()
+            .map(|(key, value)| format!("{key} {}\n", value.replace('\n', "\n ")))
+            .collect::<Vec<String>>()
+            .join("");
         [
             format!("tree {}\n", self.tree),
             parents,
             format!("author {}\n", self.author),
             format!("committer {}\n", self.committer),
-            format!("\n{}\n", self.comment),
+            headers,
+            format!("\n{}", self.comment),
         ]
         .join("")
         .into_bytes()
@@ -92,39 +151,67 @@ impl Commit {
         self.serialize().len()
     }
 
+    // NOTE:
+    // The commit file is like "tree <hash>\n(parent <hash>\n)*author <user>\ncommitter <user>\n
+    // (<other headers>\n)*\n<message>". The message and any other headers are kept
+    // as they are so that the commit hashes the same.
     pub fn from_bytes(bytes: &[u8]) -> Self {
+        let (header_bytes, message) = match bytes.windows(2).position(|w| w == b"\n\n") {
+            Some(pos) => (&bytes[..pos], &bytes[(pos + 2)..]),
+            None => (bytes, &b""[..]),
+        };
+
+        let mut tree = String::new();
         let mut parents: Vec<String> = vec![];
+        let mut author: Option<User> = None;
+        let mut committer: Option<User> = None;
+        let mut headers: Vec<(String, String)> = vec![];
 
-        let mut lines = bytes.split(|&b| b == b'\n');
-        let mut line = lines.next().unwrap();
-        let tree = stringify(&line[5..]);
+        for line in header_bytes.split(|&b| b == b'\n') {
+            if let Some(continuation) = line.strip_prefix(b" ") {
+                if let Some((_, value)) = headers.last_mut() {
+                    value.push('\n');
+                    value.push_str(&stringify(continuation));
+                }
+                continue;
+            }
 
-        line = lines.next().unwrap();
-        while line.starts_with(b"parent") {
-            parents.push(stringify(&line[7..]));
-            line = lines.next().unwrap();
+            let line = stringify(line);
+            let (key, value) = line.split_once(' ').unwrap_or((line.as_str(), ""));
+            match key {
+                "tree" => tree = value.into(),
+                "parent" => parents.push(value.into()),
+                "author" => author = Some(User::from(value.as_bytes())),
+                "committer" => committer = Some(User::from(value.as_bytes())),
+                _ => headers.push((key.into(), value.into())),
+            }
         }
 
-        let author = User::from(&line[7..]);
-
-        line = lines.next().unwrap();
-        let committer = User::from(&line[10..]);
-
-        lines.next().unwrap();
-        line = lines.next().unwrap();
-        let comment = Some(stringify(line));
-
         Self {
             tree,
             parents,
-            comment: comment.unwrap(),
-            author,
-            committer,
+            comment: stringify(message),
+            author: author.expect("commit must have author"),
+            committer: committer.expect("commit must have committer"),
+            headers,
         }
     }
 }
 
-fn stringify(bytes: &[u8]) -> String {
+impl fmt::Display for Commit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", stringify(&self.serialize()))
+    }
+}
+
+fn with_trailing_newline(mut comment: String) -> String {
+    if !comment.ends_with('\n') {
+        comment.push('\n');
+    }
+    comment
+}
+
+pub(super) fn stringify(bytes: &[u8]) -> String {
     String::from_utf8_lossy(bytes).to_string()
 }
 
@@ -159,10 +246,35 @@ mod tests {
         let commit = Commit {
             tree: "8119b90c6adef211483e6dcf1a3c89e966af9c60".into(),
             parents: vec!["b521b9179412d90a893bc36f33f5dcfd987105ef".into()],
-            comment: "Update content".into(),
+            comment: "Update content\n".into(),
             author: user.clone(),
             committer: user,
+            headers: vec![],
         };
         assert_eq!(Commit::from_bytes(bytes), commit);
     }
+
+    #[test]
+    fn it_keeps_multiline_message_and_extra_headers() {
+        let bytes: &[u8] = b"tree 8119b90c6adef211483e6dcf1a3c89e966af9c60\nparent b521b9179412d90a893bc36f33f5dcfd987105ef\nparent 3b1031798a00fdf9b574b5857b1721bc4b0e6bac\nauthor Paul Kuruvilla <rohitpaulk@gmail.com> 1587032850 +0530\ncommitter Paul Kuruvilla <rohitpaulk@gmail.com> 1587032850 +0530\ngpgsig -----BEGIN PGP SIGNATURE-----\n \n abcdef\n -----END PGP SIGNATURE-----\n\nMerge branch\n\nWith a body.\n";
+        let commit = Commit::from_bytes(bytes);
+        assert_eq!(commit.parents().len(), 2);
+        assert_eq!(commit.comment(), "Merge branch\n\nWith a body.\n");
+        assert_eq!(commit.summary(), "Merge branch");
+        assert_eq!(commit.serialize(), bytes);
+    }
+
+    #[test]
+    fn it_creates_commit_with_trailing_newline() {
+        let user = User::from(&b"Paul Kuruvilla <rohitpaulk@gmail.com> 1587032850 +0530"[..]);
+        let commit = Commit::new(
+            "8119b90c6adef211483e6dcf1a3c89e966af9c60".into(),
+            "msg".into(),
+            vec![],
+            user.clone(),
+            user,
+        );
+        assert_eq!(commit.comment(), "msg\n");
+        assert!(commit.serialize().ends_with(b"\n\nmsg\n"));
+    }
 }
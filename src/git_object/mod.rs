@@ -1,11 +1,14 @@
 pub mod blob;
 pub mod commit;
+pub mod tag;
 pub mod tree;
 
-use super::{git_protocol::Delta, Error, Result, Sha1Hash, GIT_OBJ_DIR, SHA1_HASH_SIZE};
+use super::{
+    git_protocol::Delta, odb::ObjectDatabase, Error, Result, Sha1Hash, GIT_OBJ_DIR, SHA1_HASH_SIZE,
+};
 use blob::Blob;
 use bytes::Bytes;
-use commit::Commit;
+use commit::{Commit, User};
 use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
 use sha1::Digest;
 use std::ffi::OsStr;
@@ -13,18 +16,94 @@ use std::fmt;
 use std::fs::{self, DirEntry, File};
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
-use tree::{TreeNode, TreeRecords};
+use tag::Tag;
+use tree::{Mode, TreeNode, TreeRecords};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum GitObject {
     Blob(Blob),
     Tree(Vec<TreeNode>),
     Commit(Box<Commit>),
+    Tag(Box<Tag>),
 }
 
 impl GitObject {
     pub fn open_from_hash<P: AsRef<Path>>(root: P, hash: &str) -> Result<Self> {
-        Self::path(root, hash).and_then(Self::open)
+        ObjectDatabase::open(root).read(hash)
+    }
+
+    pub fn open_commit<P: AsRef<Path>>(root: P, hash: &str) -> Result<Commit> {
+        match Self::open_from_hash(root, hash)? {
+            Self::Commit(commit) => Ok(*commit),
+            obj => {
+                let msg = format!("{hash} is a {}, not a commit", obj.type_name());
+                Err(Error::from(msg.as_str()))
+            }
+        }
+    }
+
+    pub fn open_tree<P: AsRef<Path>>(root: P, hash: &str) -> Result<Vec<TreeNode>> {
+        match Self::open_from_hash(root, hash)? {
+            Self::Tree(nodes) => Ok(nodes),
+            obj => {
+                let msg = format!("{hash} is a {}, not a tree", obj.type_name());
+                Err(Error::from(msg.as_str()))
+            }
+        }
+    }
+
+    // NOTE:
+    // Finds the entry at the slash separated path under the tree.
+    pub fn find_in_tree<P: AsRef<Path>>(
+        root: P,
+        tree: &str,
+        path: &str,
+    ) -> Result<Option<TreeNode>> {
+        let root = root.as_ref();
+        let mut tree = tree.to_string();
+        let mut found: Option<TreeNode> = None;
+
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            if found
+                .as_ref()
+                .is_some_and(|node| node.mode() != Mode::Directory)
+            {
+                return Ok(None);
+            }
+            let nodes = Self::open_tree(root, &tree)?;
+            match nodes.into_iter().find(|node| node.name() == name) {
+                Some(node) => {
+                    tree = node.hash().hex();
+                    found = Some(node);
+                }
+                None => return Ok(None),
+            }
+        }
+
+        Ok(found)
+    }
+
+    // NOTE:
+    // Lists the files under the tree recursively, named by their slash
+    // separated path and sorted like the index.
+    pub fn flatten_tree<P: AsRef<Path>>(root: P, tree: &str) -> Result<Vec<TreeNode>> {
+        let root = root.as_ref();
+        let mut files: Vec<TreeNode> = vec![];
+        let mut stack: Vec<(String, String)> = vec![(String::new(), tree.to_string())];
+
+        while let Some((prefix, tree)) = stack.pop() {
+            for node in Self::open_tree(root, &tree)? {
+                let path = format!("{prefix}{}", node.name());
+                if node.mode() == Mode::Directory {
+                    stack.push((format!("{path}/"), node.hash().hex()));
+                } else {
+                    files.push(TreeNode::from_parts(node.mode(), path, node.hash()));
+                }
+            }
+        }
+
+        files.sort_by(|a, b| a.name().cmp(b.name()));
+        Ok(files)
     }
 
     pub fn new_blob<R: Read>(mut content: R) -> Result<Self> {
@@ -33,6 +112,17 @@ impl GitObject {
         Ok(Self::Blob(Blob::from(Bytes::from_iter(buf))))
     }
 
+    // NOTE:
+    // The content of a symlink blob is the path it points to.
+    pub fn new_blob_from_path<P: AsRef<Path>>(path: P, mode: Mode) -> Result<Self> {
+        if mode == Mode::Symlink {
+            let target = fs::read_link(path)?;
+            Self::new_blob(target.to_string_lossy().as_bytes())
+        } else {
+            Self::new_blob(File::open(path)?)
+        }
+    }
+
     pub fn new_tree<P: AsRef<Path>>(path: P) -> Result<Self> {
         let mut trees: Vec<TreeNode> = vec![];
 
@@ -50,8 +140,32 @@ impl GitObject {
         Ok(Self::Tree(trees))
     }
 
-    pub fn new_commit(tree: String, comment: String, parents: Vec<String>) -> Result<Self> {
-        Ok(Self::Commit(Box::new(Commit::new(tree, comment, parents))))
+    pub fn new_commit(
+        tree: String,
+        comment: String,
+        parents: Vec<String>,
+        author: User,
+        committer: User,
+    ) -> Result<Self> {
+        Ok(Self::Commit(Box::new(Commit::new(
+            tree, comment, parents, author, committer,
+        ))))
+    }
+
+    pub fn new_tag(
+        object: String,
+        object_type: String,
+        tag: String,
+        tagger: User,
+        message: String,
+    ) -> Result<Self> {
+        Ok(Self::Tag(Box::new(Tag::new(
+            object,
+            object_type,
+            tag,
+            tagger,
+            message,
+        ))))
     }
 
     pub fn hash(&self) -> Sha1Hash {
@@ -70,6 +184,9 @@ impl GitObject {
             Self::Commit(commit) => {
                 hasher = hasher.chain_update(commit.serialize());
             }
+            Self::Tag(tag) => {
+                hasher = hasher.chain_update(tag.serialize());
+            }
         }
 
         Sha1Hash::new(hasher)
@@ -78,8 +195,9 @@ impl GitObject {
     pub fn serialize(&self) -> Vec<u8> {
         match self {
             Self::Blob(blob) => blob.as_ref().to_vec(),
-            Self::Tree(trees) => trees.iter().map(TreeNode::serialize).flatten().collect(),
+            Self::Tree(trees) => trees.iter().flat_map(TreeNode::serialize).collect(),
             Self::Commit(commit) => commit.serialize(),
+            Self::Tag(tag) => tag.serialize(),
         }
     }
 
@@ -120,12 +238,13 @@ impl GitObject {
         }
     }
 
-    pub fn restore(&self, delta: Delta) -> Result<Self> {
-        let mut buf: Vec<u8> = vec![];
-        let mut header = self.header().into_bytes();
-        buf.append(&mut header);
-        buf.append(&mut delta.restore(&self.serialize()));
-        Self::new(buf)
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Blob(_) => "blob",
+            Self::Tree(_) => "tree",
+            Self::Commit(_) => "commit",
+            Self::Tag(_) => "tag",
+        }
     }
 
     fn size(&self) -> usize {
@@ -133,19 +252,15 @@ impl GitObject {
             Self::Blob(blob) => blob.len(),
             Self::Tree(trees) => trees.iter().map(TreeNode::len).sum(),
             Self::Commit(commit) => commit.len(),
+            Self::Tag(tag) => tag.len(),
         }
     }
 
     fn header(&self) -> String {
-        let size = self.size();
-        match self {
-            Self::Blob(_) => format!("blob {size}\0"),
-            Self::Tree(_) => format!("tree {size}\0"),
-            Self::Commit(_) => format!("commit {size}\0"),
-        }
+        format!("{} {}\0", self.type_name(), self.size())
     }
 
-    fn path<P: AsRef<Path>>(root: P, hash: &str) -> Result<PathBuf> {
+    pub(crate) fn path<P: AsRef<Path>>(root: P, hash: &str) -> Result<PathBuf> {
         if hash.len() != 40 {
             return Err(anyhow::anyhow!("SHA-1 hash must be 40-characters long").into());
         }
@@ -158,50 +273,24 @@ impl GitObject {
         Ok(path)
     }
 
-    fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let f = File::open(path)?;
-        let mut decoder = ZlibDecoder::new(f);
-        let mut data = vec![];
-        decoder.read_to_end(&mut data)?;
-
-        Self::new(data)
+    #[cfg(test)]
+    fn new(data: Vec<u8>) -> Result<Self> {
+        RawObject::from_bytes(data)?.parse()
     }
 
-    fn new(data: Vec<u8>) -> Result<Self> {
-        // NOTE:
-        // The blob file is like "blob <size>\0<contents>"
-        if data.starts_with(b"blob") {
-            let zero_pos =
-                zero_position(&data[..]).ok_or(Error::from("Not found 0x00 in git object file"))?;
-            let size = std::str::from_utf8(&data[5..zero_pos])
-                .map_err(|err| {
-                    let msg = format!("Cannot stringify blob size in git object file. {err}");
-                    Error::from(msg.as_str())
-                })?
-                .parse::<usize>()
-                .map_err(|err| {
-                    let msg = format!("Parsing error! blob size in git object file. {err}");
-                    Error::from(msg.as_str())
-                })?;
-            let bytes = Bytes::copy_from_slice(&data[(zero_pos + 1)..(zero_pos + 1 + size)]);
-            Ok(Self::Blob(Blob::from(bytes)))
-        // NOTE:
-        // The tree file is like "tree <size>\0...."
-        } else if data.starts_with(b"tree") {
-            let zero_pos =
-                zero_position(&data[..]).ok_or(Error::from("Not found 0x00 in git object file"))?;
-            let trees = TreeRecords::new(&data[(zero_pos + 1)..]).collect::<Vec<TreeNode>>();
-            Ok(Self::Tree(trees))
-        // NOTE:
-        // The commit file is like "commit <size>\0...."
-        } else if data.starts_with(b"commit") {
-            let zero_pos =
-                zero_position(&data[..]).ok_or(Error::from("Not found 0x00 in git object file"))?;
-            Ok(Self::Commit(Box::new(Commit::from_bytes(
-                &data[(zero_pos + 1)..],
-            ))))
-        } else {
-            unimplemented!()
+    fn from_content(object_type: &[u8], content: &[u8]) -> Result<Self> {
+        match object_type {
+            b"blob" => Ok(Self::Blob(Blob::from(Bytes::copy_from_slice(content)))),
+            b"tree" => Ok(Self::Tree(TreeRecords::new(content).collect())),
+            b"commit" => Ok(Self::Commit(Box::new(Commit::from_bytes(content)))),
+            b"tag" => Ok(Self::Tag(Box::new(Tag::from_bytes(content)?))),
+            _ => {
+                let msg = format!(
+                    "Unknown object type: {}",
+                    String::from_utf8_lossy(object_type)
+                );
+                Err(Error::from(msg.as_str()))
+            }
         }
     }
 }
@@ -216,11 +305,93 @@ impl fmt::Display for GitObject {
                 }
                 Ok(())
             }
-            Self::Commit(_) => unimplemented!(),
+            Self::Commit(commit) => commit.fmt(f),
+            Self::Tag(tag) => tag.fmt(f),
         }
     }
 }
 
+// NOTE:
+// An object as it is stored, its type and its content bytes. The parsed
+// objects can't always give those bytes back, because commits and tree names
+// are read as UTF-8 lossily, so hashes and delta bases are taken from here.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawObject {
+    kind: &'static str,
+    content: Vec<u8>,
+}
+
+impl RawObject {
+    pub fn new(kind: &'static str, content: Vec<u8>) -> Self {
+        Self { kind, content }
+    }
+
+    pub(crate) fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let f = File::open(path)?;
+        let mut decoder = ZlibDecoder::new(f);
+        let mut data = vec![];
+        decoder.read_to_end(&mut data)?;
+
+        Self::from_bytes(data)
+    }
+
+    // NOTE:
+    // Every git object file is like "<type> <size>\0<contents>"
+    fn from_bytes(mut data: Vec<u8>) -> Result<Self> {
+        let zero_pos =
+            zero_position(&data[..]).ok_or(Error::from("Not found 0x00 in git object file"))?;
+        let sp_pos = space_position(&data[..zero_pos])
+            .ok_or(Error::from("Not found object type in git object file"))?;
+        let size = std::str::from_utf8(&data[(sp_pos + 1)..zero_pos])
+            .map_err(|err| {
+                let msg = format!("Cannot stringify object size in git object file. {err}");
+                Error::from(msg.as_str())
+            })?
+            .parse::<usize>()
+            .map_err(|err| {
+                let msg = format!("Parsing error! object size in git object file. {err}");
+                Error::from(msg.as_str())
+            })?;
+        if data.len() < zero_pos + 1 + size {
+            return Err(Error::from("git object file is shorter than its size"));
+        }
+
+        let kind = match &data[..sp_pos] {
+            b"blob" => "blob",
+            b"tree" => "tree",
+            b"commit" => "commit",
+            b"tag" => "tag",
+            object_type => {
+                let msg = format!(
+                    "Unknown object type: {}",
+                    String::from_utf8_lossy(object_type)
+                );
+                return Err(Error::from(msg.as_str()));
+            }
+        };
+        data.truncate(zero_pos + 1 + size);
+        let content = data.split_off(zero_pos + 1);
+
+        Ok(Self { kind, content })
+    }
+
+    pub fn hash(&self) -> Sha1Hash {
+        let mut hasher = Sha1Hash::hasher();
+        hasher.update(format!("{} {}\0", self.kind, self.content.len()));
+        hasher.update(&self.content);
+        Sha1Hash::new(hasher)
+    }
+
+    pub fn restore(&self, delta: Delta) -> Result<Self> {
+        let content = delta.restore(&self.content)?;
+        Ok(Self::new(self.kind, content))
+    }
+
+    pub fn parse(&self) -> Result<GitObject> {
+        GitObject::from_content(self.kind.as_bytes(), &self.content)
+    }
+}
+
 type GetPosition = Box<dyn Fn(&[u8]) -> Option<usize>>;
 fn position(byte: u8) -> GetPosition {
     Box::new(move |bytes: &[u8]| bytes.iter().position(|&b| b == byte))
@@ -234,7 +405,7 @@ fn space_position(bytes: &[u8]) -> Option<usize> {
     position(b' ')(bytes)
 }
 
-fn is_git_file(entry: &DirEntry) -> bool {
+pub(crate) fn is_git_file(entry: &DirEntry) -> bool {
     let path = entry.path();
     path.ancestors().any(is_git_root)
 }
@@ -268,6 +439,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_creates_tag_git_object() {
+        let content =
+            b"object 8119b90c6adef211483e6dcf1a3c89e966af9c60\ntype commit\ntag v1\n\nv1\n";
+        let bytes = [format!("tag {}\0", content.len()).as_bytes(), content].concat();
+        let obj = GitObject::new(bytes.clone()).unwrap();
+
+        let mut hasher = Sha1::new();
+        hasher.update(&bytes);
+        assert_eq!(obj.hash().hex(), hex::encode(hasher.finalize()));
+        assert_eq!(obj.serialize(), content);
+        assert_eq!(obj.to_string().as_bytes(), content);
+    }
+
+    #[test]
+    fn it_hashes_raw_objects_from_their_stored_bytes() {
+        let content = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+            author \xe9 <a@example.com> 0 +0000\n\
+            committer \xe9 <a@example.com> 0 +0000\n\nlatin-1\n";
+        let bytes = [format!("commit {}\0", content.len()).as_bytes(), content].concat();
+        let raw = RawObject::from_bytes(bytes.clone()).unwrap();
+
+        let mut hasher = Sha1::new();
+        hasher.update(&bytes);
+        assert_eq!(raw.hash().hex(), hex::encode(hasher.finalize()));
+        assert_ne!(raw.parse().unwrap().hash(), raw.hash());
+    }
+
+    #[test]
+    fn it_fails_to_create_unknown_git_object() {
+        let bytes = b"unknown 3\0foo";
+        assert!(GitObject::new(bytes.to_vec()).is_err());
+    }
+
     #[test]
     fn it_calculates_sha1_hash() {
         let bytes = b"blob 11\0hello world";
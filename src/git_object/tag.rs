@@ -0,0 +1,214 @@
+use super::{
+    commit::{stringify, User},
+    Error, Result,
+};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tag {
+    object: String,
+    object_type: String,
+    tag: String,
+    tagger: Option<User>,
+    headers: Vec<(String, String)>,
+    message: Option<String>,
+}
+
+impl Tag {
+    pub fn new(
+        object: String,
+        object_type: String,
+        tag: String,
+        tagger: User,
+        message: String,
+    ) -> Self {
+        Self {
+            object,
+            object_type,
+            tag,
+            tagger: Some(tagger),
+            headers: vec![],
+            message: Some(message),
+        }
+    }
+
+    pub fn object(&self) -> &str {
+        self.object.as_str()
+    }
+
+    pub fn message(&self) -> &str {
+        self.message.as_deref().unwrap_or_default()
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let tagger = self
+            .tagger
+            .as_ref()
+            .map(|user| format!("tagger {user}\n"))
+            .unwrap_or_default();
+        // NOTE:
+        // Multi-line header values continue with a leading space, like in commits.
+        let headers: String = self
+            .headers
+            .iter()
+            .map(|(key, value)| format!("{key} {}\n", value.replace('\n', "\n ")))
+            .collect();
+        let message = self
+            .message
+            .as_ref()
+            .map(|message| format!("\n{message}"))
+            .unwrap_or_default();
+        [
+            format!("object {}\n", self.object),
+            format!("type {}\n", self.object_type),
+            format!("tag {}\n", self.tag),
+            tagger,
+            headers,
+            message,
+        ]
+        .join("")
+        .into_bytes()
+    }
+
+    pub fn len(&self) -> usize {
+        self.serialize().len()
+    }
+
+    // NOTE:
+    // The tag file is like "object <hash>\ntype <type>\ntag <name>\ntagger <user>\n
+    // (<other headers>\n)*\n<message>". The tagger line is missing in some very old
+    // tags, and some have no message at all. Any other headers and the message are
+    // kept as they are (including a trailing signature if any) so that the tag
+    // hashes the same.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (header_bytes, message) = match bytes.windows(2).position(|w| w == b"\n\n") {
+            Some(pos) => (&bytes[..pos], Some(&bytes[(pos + 2)..])),
+            None => (bytes.strip_suffix(b"\n").unwrap_or(bytes), None),
+        };
+
+        let mut object: Option<String> = None;
+        let mut object_type: Option<String> = None;
+        let mut tag: Option<String> = None;
+        let mut tagger: Option<User> = None;
+        let mut headers: Vec<(String, String)> = vec![];
+
+        for line in header_bytes.split(|&b| b == b'\n') {
+            if let Some(continuation) = line.strip_prefix(b" ") {
+                if let Some((_, value)) = headers.last_mut() {
+                    value.push('\n');
+                    value.push_str(&stringify(continuation));
+                }
+            } else if let Some(value) = line.strip_prefix(b"object ") {
+                object = Some(stringify(value));
+            } else if let Some(value) = line.strip_prefix(b"type ") {
+                object_type = Some(stringify(value));
+            } else if let Some(value) = line.strip_prefix(b"tag ") {
+                tag = Some(stringify(value));
+            } else if let Some(value) = line.strip_prefix(b"tagger ") {
+                tagger = Some(User::from(value));
+            } else {
+                let line = stringify(line);
+                let (key, value) = line.split_once(' ').unwrap_or((line.as_str(), ""));
+                headers.push((key.into(), value.into()));
+            }
+        }
+
+        Ok(Self {
+            object: object.ok_or(Error::from("Not found object line in tag object"))?,
+            object_type: object_type.ok_or(Error::from("Not found type line in tag object"))?,
+            tag: tag.ok_or(Error::from("Not found tag line in tag object"))?,
+            tagger,
+            headers,
+            message: message.map(stringify),
+        })
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", stringify(&self.serialize()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TAG: &[u8] = b"object 8119b90c6adef211483e6dcf1a3c89e966af9c60\ntype commit\ntag v1.0.0\ntagger Paul Kuruvilla <rohitpaulk@gmail.com> 1587032850 +0530\n\nRelease v1.0.0\n\nFirst stable release.\n";
+
+    #[test]
+    fn it_creates_tag_from_bytes() {
+        let tag = Tag::from_bytes(TAG).unwrap();
+        assert_eq!(
+            tag,
+            Tag {
+                object: "8119b90c6adef211483e6dcf1a3c89e966af9c60".into(),
+                object_type: "commit".into(),
+                tag: "v1.0.0".into(),
+                tagger: Some(User::from(
+                    &b"Paul Kuruvilla <rohitpaulk@gmail.com> 1587032850 +0530"[..]
+                )),
+                headers: vec![],
+                message: Some("Release v1.0.0\n\nFirst stable release.\n".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn it_serializes_tag() {
+        let tag = Tag::from_bytes(TAG).unwrap();
+        assert_eq!(tag.serialize(), TAG);
+        assert_eq!(tag.to_string().as_bytes(), TAG);
+    }
+
+    #[test]
+    fn it_creates_new_tag() {
+        let tagger = User::from(&b"Paul Kuruvilla <rohitpaulk@gmail.com> 1587032850 +0530"[..]);
+        let tag = Tag::new(
+            "8119b90c6adef211483e6dcf1a3c89e966af9c60".into(),
+            "commit".into(),
+            "v1.0.0".into(),
+            tagger,
+            "Release v1.0.0\n\nFirst stable release.\n".into(),
+        );
+        assert_eq!(tag.serialize(), TAG);
+        assert_eq!(tag.message(), "Release v1.0.0\n\nFirst stable release.\n");
+    }
+
+    #[test]
+    fn it_creates_tag_without_tagger() {
+        let bytes =
+            b"object 8119b90c6adef211483e6dcf1a3c89e966af9c60\ntype tree\ntag old\n\nold tag\n";
+        let tag = Tag::from_bytes(bytes).unwrap();
+        assert!(tag.tagger.is_none());
+        assert_eq!(tag.serialize(), bytes);
+    }
+
+    #[test]
+    fn it_keeps_other_headers_and_a_missing_message() {
+        let bytes = b"object 8119b90c6adef211483e6dcf1a3c89e966af9c60\ntype commit\ntag v2\ntagger A <a@x> 1700000000 +0000\nencoding ISO-8859-1\nsig line one\n line two\n";
+        let tag = Tag::from_bytes(bytes).unwrap();
+        assert_eq!(
+            tag.headers,
+            vec![
+                ("encoding".into(), "ISO-8859-1".into()),
+                ("sig".into(), "line one\nline two".into())
+            ]
+        );
+        assert_eq!(tag.message, None);
+        assert_eq!(tag.message(), "");
+        assert_eq!(tag.serialize(), bytes);
+
+        let bytes =
+            b"object 8119b90c6adef211483e6dcf1a3c89e966af9c60\ntype commit\ntag v3\nextra x\n\n";
+        let tag = Tag::from_bytes(bytes).unwrap();
+        assert_eq!(tag.message.as_deref(), Some(""));
+        assert_eq!(tag.serialize(), bytes);
+    }
+
+    #[test]
+    fn it_fails_without_object_line() {
+        let bytes = b"type commit\ntag v1.0.0\n\nmessage\n";
+        assert!(Tag::from_bytes(bytes).is_err());
+    }
+}
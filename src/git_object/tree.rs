@@ -4,13 +4,15 @@ use std::os::unix::fs::PermissionsExt;
 use std::{
     cmp::Ordering,
     fmt,
-    fs::{DirEntry, File},
+    fs::{self, DirEntry, Metadata},
     io::{Cursor, Read},
+    path::Path,
 };
 
 const MODE_DIR: isize = 40000;
 const MODE_FILE: isize = 100644;
 const MODE_SYML: isize = 120000;
+const MODE_GITLINK: isize = 160000;
 #[cfg(unix)]
 const MODE_EXEC: isize = 100755;
 
@@ -31,6 +33,7 @@ impl TreeNode {
             Ok(MODE_FILE) => Mode::File,
             Ok(MODE_DIR) => Mode::Directory,
             Ok(MODE_SYML) => Mode::Symlink,
+            Ok(MODE_GITLINK) => Mode::Gitlink,
             #[cfg(unix)]
             Ok(MODE_EXEC) => Mode::Executable,
             _ => panic!("Unknown mode: {mode_str}"),
@@ -47,6 +50,14 @@ impl TreeNode {
         }
     }
 
+    pub fn from_parts(mode: Mode, name: String, hash: Sha1Hash) -> Self {
+        Self { mode, name, hash }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
     pub fn name(&self) -> &str {
         self.name.as_str()
     }
@@ -71,26 +82,11 @@ impl TryFrom<DirEntry> for TreeNode {
     fn try_from(entry: DirEntry) -> Result<Self, Self::Error> {
         let path = entry.path();
         let name = format!("{}", entry.file_name().to_string_lossy());
-        let (mode, hash) = if path.is_dir() {
-            let obj = GitObject::new_tree(path)?;
-            (Mode::Directory, obj.hash())
-        } else if path.is_file() {
-            let f = File::open(path)?;
-            let mode = if cfg!(unix) {
-                Mode::from_file(&f)
-            } else {
-                Mode::File
-            };
-            let obj = GitObject::new_blob(f)?;
-            (mode, obj.hash())
-        } else if path.is_symlink() {
-            let f = File::open(path)?;
-            let obj = GitObject::new_blob(f)?;
-            (Mode::Symlink, obj.hash())
+        let mode = Mode::from_path(&path)?;
+        let hash = if mode == Mode::Directory {
+            GitObject::new_tree(path)?.hash()
         } else {
-            return Err(Error::from(anyhow::anyhow!(
-                "DirEntry is not directory, file or symlink."
-            )));
+            GitObject::new_blob_from_path(path, mode)?.hash()
         };
 
         Ok(Self { mode, name, hash })
@@ -99,13 +95,27 @@ impl TryFrom<DirEntry> for TreeNode {
 
 impl PartialOrd for TreeNode {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.name().cmp(other.name()))
+        Some(self.cmp(other))
     }
 }
 
+// NOTE:
+// Git sorts tree entries as if directory names had a trailing slash,
+// so "foo.txt" comes before the directory "foo".
 impl Ord for TreeNode {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.name().cmp(other.name())
+        self.sort_key().cmp(other.sort_key())
+    }
+}
+
+impl TreeNode {
+    fn sort_key(&self) -> impl Iterator<Item = u8> + '_ {
+        let slash = if self.mode == Mode::Directory {
+            Some(b'/')
+        } else {
+            None
+        };
+        self.name.bytes().chain(slash)
     }
 }
 
@@ -115,10 +125,10 @@ impl fmt::Display for TreeNode {
             f,
             "{:06} {} {}    {}",
             self.mode as isize,
-            if self.mode == Mode::Directory {
-                "tree"
-            } else {
-                "blob"
+            match self.mode {
+                Mode::Directory => "tree",
+                Mode::Gitlink => "commit",
+                _ => "blob",
             },
             self.hash.hex(),
             self.name,
@@ -127,26 +137,86 @@ impl fmt::Display for TreeNode {
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-enum Mode {
+pub enum Mode {
     File = MODE_FILE,
     Directory = MODE_DIR,
     Symlink = MODE_SYML,
+    // NOTE:
+    // A submodule, recorded as the commit its repository is at. Git doesn't
+    // look into its directory in the working tree, and neither do we.
+    Gitlink = MODE_GITLINK,
     #[cfg(unix)]
     Executable = MODE_EXEC,
 }
 
 impl Mode {
+    // NOTE:
+    // Symlinks are not followed, so that the link itself is recorded
+    // rather than the file it points to.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let meta = fs::symlink_metadata(path)?;
+        let file_type = meta.file_type();
+
+        if file_type.is_symlink() {
+            Ok(Self::Symlink)
+        } else if file_type.is_dir() {
+            Ok(Self::Directory)
+        } else if file_type.is_file() {
+            Ok(Self::from_metadata(&meta))
+        } else {
+            Err(Error::from(anyhow::anyhow!(
+                "DirEntry is not directory, file or symlink."
+            )))
+        }
+    }
+
     #[cfg(unix)]
-    fn from_file(file: &File) -> Self {
-        if file
-            .metadata()
-            .is_ok_and(|meta| meta.permissions().mode() == 0o755)
-        {
+    fn from_metadata(meta: &Metadata) -> Self {
+        // NOTE:
+        // Git only looks at the user executable bit.
+        if meta.permissions().mode() & 0o100 == 0o100 {
             Self::Executable
         } else {
             Self::File
         }
     }
+
+    #[cfg(not(unix))]
+    fn from_metadata(_meta: &Metadata) -> Self {
+        Self::File
+    }
+}
+
+// NOTE:
+// The index keeps modes as 32-bit integers: 4 bits of object type,
+// 3 unused bits and 9 bits of unix permission.
+impl TryFrom<u32> for Mode {
+    type Error = Error;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0o100644 => Ok(Self::File),
+            0o040000 => Ok(Self::Directory),
+            0o120000 => Ok(Self::Symlink),
+            0o160000 => Ok(Self::Gitlink),
+            #[cfg(unix)]
+            0o100755 => Ok(Self::Executable),
+            _ => Err(Error::from(anyhow::anyhow!("Unknown mode: {value:o}"))),
+        }
+    }
+}
+
+impl From<Mode> for u32 {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::File => 0o100644,
+            Mode::Directory => 0o040000,
+            Mode::Symlink => 0o120000,
+            Mode::Gitlink => 0o160000,
+            #[cfg(unix)]
+            Mode::Executable => 0o100755,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -211,6 +281,26 @@ mod tests {
         assert_eq!(tree, expected);
     }
 
+    #[test]
+    fn it_sorts_directories_with_trailing_slash() {
+        let dir = TreeNode::from_parts(Mode::Directory, "foo".into(), [1; 20].into());
+        let file = TreeNode::from_parts(Mode::File, "foo.txt".into(), [2; 20].into());
+        let other = TreeNode::from_parts(Mode::File, "foo0".into(), [3; 20].into());
+        let mut nodes = vec![other.clone(), dir.clone(), file.clone()];
+        nodes.sort();
+        assert_eq!(nodes, vec![file, dir, other]);
+    }
+
+    #[test]
+    fn it_converts_mode_to_and_from_index_mode() {
+        assert_eq!(Mode::try_from(0o100644).unwrap(), Mode::File);
+        assert_eq!(Mode::try_from(0o100755).unwrap(), Mode::Executable);
+        assert_eq!(u32::from(Mode::Symlink), 0o120000);
+        assert_eq!(Mode::try_from(0o160000).unwrap(), Mode::Gitlink);
+        assert_eq!(u32::from(Mode::Gitlink), 0o160000);
+        assert!(Mode::try_from(0o170000).is_err());
+    }
+
     #[test]
     fn it_generates_trees_from_tree_records() {
         let bytes = b"100644 file1\x001111111111111111111140000 dir1\x0099999999999999999999";
@@ -234,4 +324,13 @@ mod tests {
 
         assert!(trees.next().is_none());
     }
+
+    #[test]
+    fn it_reads_and_writes_gitlinks() {
+        let bytes = b"160000 sub\x0011111111111111111111";
+        let node = TreeNode::new(bytes);
+        assert_eq!(node.mode(), Mode::Gitlink);
+        assert_eq!(node.serialize(), bytes);
+        assert!(node.to_string().starts_with("160000 commit "));
+    }
 }
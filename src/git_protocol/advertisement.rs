@@ -0,0 +1,176 @@
+use super::{Error, PktLines, Result};
+
+const SERVICE_LINE: &str = "# service=git-upload-pack";
+const PEELED_SUFFIX: &str = "^{}";
+const SYMREF_PREFIX: &str = "symref=HEAD:";
+const NO_REFS: &str = "capabilities^{}";
+
+// NOTE:
+// A ref the remote advertises, with the object an annotated tag peels to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteRef {
+    name: String,
+    hash: String,
+    peeled: Option<String>,
+}
+
+impl RemoteRef {
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn hash(&self) -> &str {
+        self.hash.as_str()
+    }
+
+    pub fn peeled(&self) -> Option<&str> {
+        self.peeled.as_deref()
+    }
+}
+
+// NOTE:
+// The response to "info/refs?service=git-upload-pack": a "# service" line and
+// a flush, then a "<hash> <refname>" line per ref. The first one carries the
+// capabilities after a NUL byte, and an annotated tag is followed by a
+// "<hash> <refname>^{}" line with the object it peels to. An empty repository
+// advertises a single "capabilities^{}" line instead.
+#[derive(Debug, Default)]
+pub struct Advertisement {
+    refs: Vec<RemoteRef>,
+    capabilities: Vec<String>,
+}
+
+impl Advertisement {
+    // NOTE:
+    // A server that doesn't start with the service line doesn't speak the
+    // smart protocol, or sent something else like an error page.
+    pub fn parse(mut lines: PktLines) -> Result<Self> {
+        let mut advertisement = Self::default();
+        let service = lines.next().map(|line| line.serialize());
+        match service.as_deref().map(String::from_utf8_lossy) {
+            Some(text) if text.trim_end_matches('\n') == SERVICE_LINE => {}
+            Some(text) if !text.is_empty() => {
+                let msg = format!("invalid server response; got '{}'", text.trim_end());
+                return Err(Error::from(msg.as_str()));
+            }
+            _ => {
+                return Err(Error::from(
+                    "invalid server response; expected service, got flush packet",
+                ))
+            }
+        }
+
+        for line in lines {
+            let bytes = line.serialize();
+            if bytes.is_empty() {
+                continue;
+            }
+            let text = String::from_utf8_lossy(&bytes);
+            let text = text.trim_end_matches('\n');
+
+            let text = match text.split_once('\0') {
+                Some((text, capabilities)) => {
+                    advertisement.capabilities =
+                        capabilities.split(' ').map(String::from).collect();
+                    text
+                }
+                None => text,
+            };
+            let Some((hash, name)) = text.split_once(' ') else {
+                let msg = format!("invalid ref advertisement: {text}");
+                return Err(Error::from(msg.as_str()));
+            };
+            if name == NO_REFS {
+                continue;
+            }
+            match name.strip_suffix(PEELED_SUFFIX) {
+                Some(name) => {
+                    let tag = advertisement.refs.last_mut().filter(|r| r.name == name);
+                    if let Some(tag) = tag {
+                        tag.peeled = Some(hash.into());
+                    }
+                }
+                None => advertisement.refs.push(RemoteRef {
+                    name: name.into(),
+                    hash: hash.into(),
+                    peeled: None,
+                }),
+            }
+        }
+        Ok(advertisement)
+    }
+
+    pub fn refs(&self) -> &[RemoteRef] {
+        &self.refs
+    }
+
+    // NOTE:
+    // The branch the remote HEAD points to. Servers that don't advertise the
+    // "symref" capability leave it to be guessed from the branch with the
+    // same commit, preferring "master".
+    pub fn head(&self) -> Option<&str> {
+        let symref = self
+            .capabilities
+            .iter()
+            .find_map(|capability| capability.strip_prefix(SYMREF_PREFIX));
+        if symref.is_some() {
+            return symref;
+        }
+
+        let head = self.refs.iter().find(|r| r.name == "HEAD")?;
+        let mut branches = self
+            .refs
+            .iter()
+            .filter(|r| r.name.starts_with("refs/heads/") && r.hash == head.hash);
+        branches
+            .clone()
+            .find(|r| r.name == "refs/heads/master")
+            .or_else(|| branches.next())
+            .map(RemoteRef::name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_ref_advertisements() {
+        let bytes = b"001e# service=git-upload-pack\n0000\
+            00693b1031798a00fdf9b574b5857b1721bc4b0e6bac HEAD\x00multi_ack symref=HEAD:refs/heads/main agent=git/2.39.5\n\
+            003d3b1031798a00fdf9b574b5857b1721bc4b0e6bac refs/heads/main\n\
+            003a8119b90c6adef211483e6dcf1a3c89e966af9c60 refs/tags/v1\n\
+            003d3b1031798a00fdf9b574b5857b1721bc4b0e6bac refs/tags/v1^{}\n\
+            0000"
+            .to_vec();
+        let advertisement = Advertisement::parse(PktLines::new(bytes)).unwrap();
+
+        let names: Vec<&str> = advertisement.refs().iter().map(RemoteRef::name).collect();
+        assert_eq!(names, vec!["HEAD", "refs/heads/main", "refs/tags/v1"]);
+        let tag = &advertisement.refs()[2];
+        assert_eq!(tag.hash(), "8119b90c6adef211483e6dcf1a3c89e966af9c60");
+        assert_eq!(
+            tag.peeled(),
+            Some("3b1031798a00fdf9b574b5857b1721bc4b0e6bac")
+        );
+        assert_eq!(advertisement.head(), Some("refs/heads/main"));
+    }
+
+    #[test]
+    fn it_parses_empty_advertisements() {
+        let bytes = b"001e# service=git-upload-pack\n0000\
+            00470000000000000000000000000000000000000000 capabilities^{}\x00agent=git\n0000"
+            .to_vec();
+        let advertisement = Advertisement::parse(PktLines::new(bytes)).unwrap();
+        assert!(advertisement.refs().is_empty());
+        assert_eq!(advertisement.head(), None);
+    }
+
+    #[test]
+    fn it_rejects_responses_without_the_service_line() {
+        let bytes = b"003d3b1031798a00fdf9b574b5857b1721bc4b0e6bac refs/heads/main\n0000".to_vec();
+        assert!(Advertisement::parse(PktLines::new(bytes)).is_err());
+        assert!(Advertisement::parse(PktLines::new(b"0000".to_vec())).is_err());
+        assert!(Advertisement::parse(PktLines::new(b"Not Found".to_vec())).is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use super::{Error, Result};
 use std::io::Read;
 
 const MASK_LAST_7: u8 = 0b01111111;
@@ -5,11 +6,13 @@ const MASK_LAST_7: u8 = 0b01111111;
 fn get_length<R: Read>(r: &mut R) -> usize {
     let mut byte = super::read_one(r);
     let mut len: usize = (byte & MASK_LAST_7) as usize;
+    let mut shift = 7;
 
     while super::msb_is_1(byte) {
         byte = super::read_one(r);
         let additional_len: usize = (byte & MASK_LAST_7) as usize;
-        len += additional_len << 7;
+        len += additional_len << shift;
+        shift += 7;
     }
 
     len
@@ -17,9 +20,7 @@ fn get_length<R: Read>(r: &mut R) -> usize {
 
 #[derive(Debug)]
 pub struct Delta {
-    #[allow(unused)]
     base_size: usize,
-    #[allow(unused)]
     target_size: usize,
     instructions: Vec<Instruction>,
 }
@@ -47,14 +48,25 @@ impl Delta {
         }
     }
 
-    pub fn restore(self, buf: &[u8]) -> Vec<u8> {
-        let mut result: Vec<u8> = vec![];
+    pub fn restore(self, buf: &[u8]) -> Result<Vec<u8>> {
+        if buf.len() != self.base_size {
+            let msg = format!(
+                "Delta base must be {} bytes but is {} bytes",
+                self.base_size,
+                buf.len()
+            );
+            return Err(Error::from(msg.as_str()));
+        }
+
+        let mut result: Vec<u8> = Vec::with_capacity(self.target_size);
 
         for inst in self.instructions {
             match inst {
                 Instruction::Copy { offset, size } => {
-                    let mut bytes = buf[offset..(offset + size)].to_vec();
-                    result.append(&mut bytes);
+                    let bytes = buf
+                        .get(offset..(offset + size))
+                        .ok_or(Error::from("Delta copies bytes out of its base"))?;
+                    result.extend_from_slice(bytes);
                 }
                 Instruction::Insert(mut bytes) => {
                     result.append(&mut bytes);
@@ -62,7 +74,16 @@ impl Delta {
             }
         }
 
-        result
+        if result.len() != self.target_size {
+            let msg = format!(
+                "Delta must restore {} bytes but restored {} bytes",
+                self.target_size,
+                result.len()
+            );
+            return Err(Error::from(msg.as_str()));
+        }
+
+        Ok(result)
     }
 }
 
@@ -93,10 +114,17 @@ fn get_delta_offset<R: Read>(byte: u8, r: &mut R) -> usize {
 }
 
 fn get_delta_size<R: Read>(byte: u8, r: &mut R) -> usize {
-    size1(byte, r) + size2(byte, r) + size3(byte, r)
+    // NOTE:
+    // A copy instruction whose size bits are all zero copies 0x10000 bytes.
+    match size1(byte, r) + size2(byte, r) + size3(byte, r) {
+        0 => 0x10000,
+        size => size,
+    }
 }
 
-fn read_size<R: Read>(mask: u8, shift: usize) -> Box<dyn FnMut(u8, &mut R) -> usize> {
+type ReadSize<R> = Box<dyn FnMut(u8, &mut R) -> usize>;
+
+fn read_size<R: Read>(mask: u8, shift: usize) -> ReadSize<R> {
     Box::new(move |byte: u8, r: &mut R| {
         if byte & mask == mask {
             let val: usize = super::read_one(r) as usize;
@@ -149,6 +177,10 @@ mod tests {
         let bytes = [0b10101100, 0b00101110];
         let mut cursor = Cursor::new(&bytes[..]);
         assert_eq!(get_length(&mut cursor), 5932);
+
+        let bytes = [0b10000001, 0b10000000, 0b00000001];
+        let mut cursor = Cursor::new(&bytes[..]);
+        assert_eq!(get_length(&mut cursor), 16385);
     }
 
     #[test]
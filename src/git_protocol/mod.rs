@@ -1,14 +1,20 @@
+mod advertisement;
 mod delta;
 mod pack_file;
+mod pack_index;
 mod pkt_line;
 
 use std::io::Read;
 
+pub use advertisement::{Advertisement, RemoteRef};
 pub use delta::Delta;
-pub use pack_file::PackFile;
+#[cfg(test)]
+pub use pack_file::PackedObject;
+pub use pack_file::{PackEntry, PackFile};
+pub use pack_index::{PackIndex, PackIndexFile};
 pub use pkt_line::{PktLine, PktLines};
 
-use super::{git_object, GitObject, Sha1Hash, SHA1_HASH_SIZE};
+use super::{git_object, Error, Result, Sha1Hash, SHA1_HASH_SIZE};
 
 fn read_one<R: Read>(r: &mut R) -> u8 {
     let mut buf = [0u8; 1];
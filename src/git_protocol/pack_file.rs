@@ -1,19 +1,20 @@
-use super::{
-    git_object::{blob::Blob, commit::Commit, tree::TreeRecords},
-    Delta, GitObject, Sha1Hash, SHA1_HASH_SIZE,
-};
-use bytes::Bytes;
+use super::{git_object::RawObject, Delta, Error, Result, Sha1Hash, SHA1_HASH_SIZE};
 use flate2::read::ZlibDecoder;
+use sha1::Digest;
+use std::collections::HashMap;
 use std::fmt;
-use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
 
 const MASK_LAST_4: u8 = 0b00001111;
 const MASK_LAST_7: u8 = 0b01111111;
+const PACK_HEADER_SIZE: u64 = 12;
 
 #[derive(Debug)]
-pub struct PackFile {
+pub struct PackFile<R = Cursor<Vec<u8>>> {
     num_objects: u32,
-    cursor: Cursor<Vec<u8>>,
+    cursor: R,
 }
 
 impl PackFile {
@@ -23,22 +24,123 @@ impl PackFile {
             .expect("Cannot find bytes for number of objects");
         let num_objects = u32::from_be_bytes(num_bytes);
 
+        // NOTE:
+        // The cursor keeps the whole pack so that its position is always the
+        // byte offset in the pack file, which is what ofs-delta entries refer to.
+        let mut cursor = Cursor::new(bytes);
+        cursor.set_position(PACK_HEADER_SIZE);
+
         Self {
             num_objects,
-            cursor: Cursor::new(bytes[12..].to_vec()),
+            cursor,
         }
     }
 
-    pub fn get_objects(bytes: Vec<u8>) -> Vec<GitObject> {
+    pub fn get_objects(bytes: Vec<u8>) -> Result<Vec<PackedObject>> {
         let objects: Vec<PackFileObject> = Self::new(bytes).collect();
         expand_deltas(objects)
     }
 
-    fn read_object_header(&mut self) -> Option<(usize, ObjectType)> {
-        if self.num_objects == 0 {
-            return None;
+    // NOTE:
+    // The last 20 bytes of a pack file are the SHA-1 checksum of everything before them.
+    pub fn checksum(bytes: &[u8]) -> Result<Sha1Hash> {
+        let trailer_pos = bytes
+            .len()
+            .checked_sub(SHA1_HASH_SIZE)
+            .ok_or(Error::from("Pack file is too short to have a checksum"))?;
+        let expected = Sha1Hash::try_from(&bytes[trailer_pos..])?;
+
+        let mut hasher = Sha1Hash::hasher();
+        hasher.update(&bytes[..trailer_pos]);
+        if Sha1Hash::new(hasher) != expected {
+            return Err(Error::from("Pack file checksum mismatch"));
+        }
+
+        Ok(expected)
+    }
+}
+
+impl PackFile<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut cursor = BufReader::new(File::open(path)?);
+
+        let mut header = [0u8; PACK_HEADER_SIZE as usize];
+        cursor.read_exact(&mut header)?;
+        if &header[..4] != b"PACK" {
+            return Err(Error::from("Not a pack file"));
         }
+        let num_objects = u32::from_be_bytes(header[8..12].try_into()?);
+
+        Ok(Self {
+            num_objects,
+            cursor,
+        })
+    }
+}
+
+impl<R: Read + Seek> PackFile<R> {
+    // NOTE:
+    // Reads the entry that starts at the offset. A delta is returned as is,
+    // because its base may be in the cache of the caller, or for a ref-delta,
+    // anywhere in the object database.
+    pub fn entry_at(&mut self, offset: u64) -> Result<PackEntry> {
+        self.cursor.seek(SeekFrom::Start(offset))?;
+
+        let (_, entry) = self.read_entry().ok_or_else(|| {
+            let msg = format!("Cannot read pack entry at offset {offset}");
+            Error::from(msg.as_str())
+        })?;
+        Ok(entry)
+    }
+
+    fn position(&mut self) -> u64 {
+        self.cursor
+            .stream_position()
+            .expect("Cannot get cursor position")
+    }
+
+    fn read_entry(&mut self) -> Option<(u64, PackEntry)> {
+        let offset = self.position();
+        let (len, obj_type) = self.read_object_header();
+
+        let entry = match obj_type {
+            ObjectType::Commit | ObjectType::Tree | ObjectType::Blob | ObjectType::Tag => {
+                let buf = self.read_zlib(len);
+                PackEntry::Object(RawObject::new(obj_type.name(), buf))
+            }
+            ObjectType::OfsDelta => {
+                let relative = self.read_ofs_delta_offset();
+                let base_offset = offset
+                    .checked_sub(relative)
+                    .expect("ofsdelta's base must precede the delta");
+
+                let buf = self.read_zlib(len);
+                let delta = Delta::new(&mut Cursor::new(buf));
+
+                PackEntry::OfsDelta { base_offset, delta }
+            }
+            ObjectType::RefDelta => {
+                let mut buf = [0u8; SHA1_HASH_SIZE];
+                self.cursor
+                    .read_exact(&mut buf)
+                    .expect("Cannot read refdelta's basename");
+                let basename = Sha1Hash::from(buf);
+
+                let buf = self.read_zlib(len);
+                let delta = Delta::new(&mut Cursor::new(buf));
 
+                PackEntry::RefDelta { basename, delta }
+            }
+            _ => {
+                eprintln!("Unexpected object type: {obj_type:?}");
+                return None;
+            }
+        };
+
+        Some((offset, entry))
+    }
+
+    fn read_object_header(&mut self) -> (usize, ObjectType) {
         let mut byte = super::read_one(&mut self.cursor);
         let obj_type = ObjectType::new(byte);
         let mut len: usize = (byte & MASK_LAST_4) as usize;
@@ -53,17 +155,33 @@ impl PackFile {
             shift += 7;
         }
 
-        Some((len, obj_type))
+        (len, obj_type)
+    }
+
+    // NOTE:
+    // The offset is encoded big-endian in 7-bit groups and every continuation
+    // adds one before shifting, so that there is exactly one encoding per value.
+    fn read_ofs_delta_offset(&mut self) -> u64 {
+        let mut byte = super::read_one(&mut self.cursor);
+        let mut offset = (byte & MASK_LAST_7) as u64;
+
+        while super::msb_is_1(byte) {
+            byte = super::read_one(&mut self.cursor);
+            offset = ((offset + 1) << 7) + (byte & MASK_LAST_7) as u64;
+        }
+
+        offset
     }
 
-    fn read_zlib(&mut self, buf: &mut [u8]) {
-        let current = self.cursor.position();
+    fn read_zlib(&mut self, len: usize) -> Vec<u8> {
+        let current = self.position();
+        let mut buf: Vec<u8> = Vec::with_capacity(len);
 
         let mut decoder = ZlibDecoder::new(&mut self.cursor);
-        match decoder.read(buf) {
+        match decoder.read_to_end(&mut buf) {
             Ok(n) => {
-                if n < buf.len() {
-                    eprintln!("Need {} bytes but read {} byte", buf.len(), n);
+                if n != len {
+                    eprintln!("Need {len} bytes but read {n} byte");
                     panic!();
                 }
             }
@@ -78,6 +196,8 @@ impl PackFile {
         self.cursor
             .seek(SeekFrom::Start(current + consumed))
             .expect("Cannot reset cursor position");
+
+        buf
     }
 }
 
@@ -85,39 +205,22 @@ impl Iterator for PackFile {
     type Item = PackFileObject;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let (len, obj_type) = self.read_object_header()?;
-
-        match obj_type {
-            ObjectType::Commit | ObjectType::Tree | ObjectType::Blob => {
-                let mut buf = vec![0u8; len];
-                self.read_zlib(&mut buf);
-
-                self.num_objects -= 1;
-
-                Some(PackFileObject::undeltified(obj_type, &buf))
-            }
-            ObjectType::RefDelta => {
-                let mut buf = [0u8; SHA1_HASH_SIZE];
-                self.cursor
-                    .read_exact(&mut buf)
-                    .expect("Cannot read refdelta's basename");
-                let basename = Sha1Hash::from(buf);
+        if self.num_objects == 0 {
+            return None;
+        }
 
-                let mut buf = vec![0u8; len];
-                self.read_zlib(&mut buf);
+        let (offset, entry) = self.read_entry()?;
 
-                let mut tmp_cursor = Cursor::new(buf);
-                let delta = Delta::new(&mut tmp_cursor);
+        self.num_objects -= 1;
 
-                self.num_objects -= 1;
+        let end = self.cursor.position() as usize;
+        let crc32 = crc32fast::hash(&self.cursor.get_ref()[(offset as usize)..end]);
 
-                Some(PackFileObject::RefDelta { basename, delta })
-            }
-            _ => {
-                eprintln!("Unexpected object type: {obj_type:?}");
-                None
-            }
-        }
+        Some(PackFileObject {
+            offset,
+            crc32,
+            entry,
+        })
     }
 }
 
@@ -146,11 +249,9 @@ impl ObjectType {
             _ => Self::Unknown,
         }
     }
-}
 
-impl fmt::Display for ObjectType {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let value = match self {
+    fn name(&self) -> &'static str {
+        match self {
             Self::Commit => "commit",
             Self::Tree => "tree",
             Self::Blob => "blob",
@@ -158,96 +259,283 @@ impl fmt::Display for ObjectType {
             Self::OfsDelta => "ofsdelta",
             Self::RefDelta => "refdelta",
             Self::Unknown => "unknown",
-        };
-        write!(f, "{value}")
+        }
+    }
+}
+
+impl fmt::Display for ObjectType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
     }
 }
 
 #[derive(Debug)]
-pub enum PackFileObject {
-    GitObject(GitObject),
-    RefDelta { basename: Sha1Hash, delta: Delta },
+pub struct PackFileObject {
+    offset: u64,
+    crc32: u32,
+    entry: PackEntry,
 }
 
-impl PackFileObject {
-    fn undeltified(obj_type: ObjectType, buf: &[u8]) -> Self {
-        match obj_type {
-            ObjectType::Commit => GitObject::Commit(Box::new(Commit::from_bytes(buf))).into(),
-            ObjectType::Tree => {
-                let trees = TreeRecords::new(buf).collect();
-                GitObject::Tree(trees).into()
-            }
-            ObjectType::Blob => {
-                let bytes = Bytes::copy_from_slice(buf);
-                GitObject::Blob(Blob::from(bytes)).into()
-            }
-            _ => panic!("Cannot create undeltified PackFileObject from object type: {obj_type}"),
-        }
-    }
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackedObject {
+    offset: u64,
+    crc32: u32,
+    hash: Sha1Hash,
+    object: RawObject,
 }
 
-impl From<GitObject> for PackFileObject {
-    fn from(object: GitObject) -> Self {
-        Self::GitObject(object)
+impl PackedObject {
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn crc32(&self) -> u32 {
+        self.crc32
+    }
+
+    // NOTE:
+    // The hash of the bytes stored in the pack, which is the name of the
+    // object even when parsing it isn't lossless.
+    pub fn hash(&self) -> Sha1Hash {
+        self.hash
+    }
+
+    #[cfg(test)]
+    pub fn into_object(self) -> super::git_object::GitObject {
+        self.object.parse().expect("Cannot parse packed object")
     }
 }
 
-type DeltaPair = (Sha1Hash, Delta);
+#[derive(Debug)]
+pub enum PackEntry {
+    Object(RawObject),
+    OfsDelta { base_offset: u64, delta: Delta },
+    RefDelta { basename: Sha1Hash, delta: Delta },
+}
+
+#[derive(Debug)]
+enum DeltaBase {
+    Offset(u64),
+    Hash(Sha1Hash),
+}
 
-fn group_objects(objects: Vec<PackFileObject>) -> (Vec<GitObject>, Vec<DeltaPair>) {
-    let mut git_objects: Vec<GitObject> = vec![];
+type DeltaPair = (u64, u32, DeltaBase, Delta);
+type RawPair = (u64, u32, Sha1Hash, RawObject);
+
+fn group_objects(objects: Vec<PackFileObject>) -> (Vec<RawPair>, Vec<DeltaPair>) {
+    let mut raw_objects: Vec<RawPair> = vec![];
     let mut delta_pairs: Vec<DeltaPair> = vec![];
 
-    for object in objects {
-        match object {
-            PackFileObject::GitObject(o) => {
-                git_objects.push(o);
+    for PackFileObject {
+        offset,
+        crc32,
+        entry,
+    } in objects
+    {
+        match entry {
+            PackEntry::Object(object) => {
+                raw_objects.push((offset, crc32, object.hash(), object));
+            }
+            PackEntry::OfsDelta { base_offset, delta } => {
+                delta_pairs.push((offset, crc32, DeltaBase::Offset(base_offset), delta));
             }
-            PackFileObject::RefDelta { basename, delta } => {
-                delta_pairs.push((basename, delta));
+            PackEntry::RefDelta { basename, delta } => {
+                delta_pairs.push((offset, crc32, DeltaBase::Hash(basename), delta));
             }
         }
     }
 
-    (git_objects, delta_pairs)
+    (raw_objects, delta_pairs)
 }
 
-fn expand_deltas(objects: Vec<PackFileObject>) -> Vec<GitObject> {
-    let (mut git_objects, deltas) = group_objects(objects);
-    let mut deltas = if deltas.is_empty() {
-        None
-    } else {
-        Some(deltas)
-    };
+// NOTE:
+// Returns every object of the pack paired with the offset of its entry.
+// Deltas are resolved repeatedly until no more of them can be restored,
+// because a base can itself be a delta appearing anywhere in the pack.
+// They are applied to the bytes of their base as stored, which the parsed
+// object can't always give back.
+fn expand_deltas(objects: Vec<PackFileObject>) -> Result<Vec<PackedObject>> {
+    let (mut raw_objects, mut deltas) = group_objects(objects);
+
+    let mut by_offset: HashMap<u64, usize> = HashMap::new();
+    let mut by_hash: HashMap<Sha1Hash, usize> = HashMap::new();
+    for (i, (offset, _, hash, _)) in raw_objects.iter().enumerate() {
+        by_offset.insert(*offset, i);
+        by_hash.insert(*hash, i);
+    }
 
-    while deltas.is_some() {
+    while !deltas.is_empty() {
         let mut next_pairs: Vec<DeltaPair> = vec![];
-        let pairs = deltas.take().unwrap();
-        let pairs_len = pairs.len();
-        eprintln!("There are {} deltas left!", pairs_len);
-
-        for (hash, delta) in pairs {
-            if let Some(obj) = git_objects.iter().find(|o| o.hash() == hash) {
-                let restored = obj.restore(delta).expect("Cannot restore GitObject");
-                git_objects.push(restored);
+        let pairs_len = deltas.len();
+
+        for (offset, crc32, base, delta) in deltas {
+            let found = match base {
+                DeltaBase::Offset(ref base_offset) => by_offset.get(base_offset),
+                DeltaBase::Hash(ref hash) => by_hash.get(hash),
+            };
+
+            if let Some(&i) = found {
+                let object = raw_objects[i].3.restore(delta)?;
+                let hash = object.hash();
+                by_offset.insert(offset, raw_objects.len());
+                by_hash.insert(hash, raw_objects.len());
+                raw_objects.push((offset, crc32, hash, object));
             } else {
-                next_pairs.push((hash, delta));
+                next_pairs.push((offset, crc32, base, delta));
             }
         }
 
         if next_pairs.len() == pairs_len {
-            for o in git_objects.iter() {
-                println!("{}", o.hash().hex());
-            }
-            panic!("Not Found base object for delta");
+            let msg = match &next_pairs[0].2 {
+                DeltaBase::Offset(offset) => {
+                    format!("Not found base object of delta at offset {offset}")
+                }
+                DeltaBase::Hash(hash) => format!("Not found base object {}", hash.hex()),
+            };
+            return Err(Error::from(msg.as_str()));
         }
 
-        deltas = if next_pairs.len() <= 1 {
-            None
-        } else {
-            Some(next_pairs)
+        deltas = next_pairs;
+    }
+
+    let objects = raw_objects
+        .into_iter()
+        .map(|(offset, crc32, hash, object)| PackedObject {
+            offset,
+            crc32,
+            hash,
+            object,
+        })
+        .collect();
+    Ok(objects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git_object::{blob::Blob, GitObject};
+    use bytes::Bytes;
+    use flate2::{write::ZlibEncoder, Compression};
+    use std::io::Write;
+
+    fn compress(bytes: &[u8]) -> Vec<u8> {
+        let mut e = ZlibEncoder::new(vec![], Compression::default());
+        e.write_all(bytes).unwrap();
+        e.finish().unwrap()
+    }
+
+    fn pack(entries: &[Vec<u8>]) -> Vec<u8> {
+        let header = [b"PACK".to_vec(), 2u32.to_be_bytes().to_vec()].concat();
+        let num = (entries.len() as u32).to_be_bytes().to_vec();
+        [header, num, entries.concat()].concat()
+    }
+
+    #[test]
+    fn it_reads_ofs_delta_offset() {
+        let mut pack = PackFile {
+            num_objects: 1,
+            cursor: Cursor::new(vec![0x81, 0x00, 0x05]),
         };
+        assert_eq!(pack.read_ofs_delta_offset(), 256);
+        assert_eq!(pack.read_ofs_delta_offset(), 5);
+    }
+
+    #[test]
+    fn it_restores_ofs_delta_objects() {
+        let blob = [vec![0x3b], compress(b"hello world")].concat();
+        // base size 11, target size 12, copy 11 bytes from 0, insert "!"
+        let delta = compress(&[0x0b, 0x0c, 0x91, 0x00, 0x0b, 0x01, b'!']);
+        let ofs_delta = [vec![0x67, blob.len() as u8], delta].concat();
+
+        let objects: Vec<GitObject> = PackFile::get_objects(pack(&[blob, ofs_delta]))
+            .unwrap()
+            .into_iter()
+            .map(PackedObject::into_object)
+            .collect();
+
+        assert_eq!(
+            objects,
+            vec![
+                GitObject::Blob(Blob::from(Bytes::from_static(b"hello world"))),
+                GitObject::Blob(Blob::from(Bytes::from_static(b"hello world!"))),
+            ]
+        );
     }
 
-    git_objects
+    #[test]
+    fn it_restores_deltas_on_the_stored_bytes_of_their_base() {
+        let content = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+            author \xe9 <a@example.com> 0 +0000\n\
+            committer \xe9 <a@example.com> 0 +0000\n\nlatin-1\n";
+        let len = content.len();
+        let commit = [
+            vec![0x90 | (len & 0x0f) as u8, (len >> 4) as u8],
+            compress(content),
+        ]
+        .concat();
+        let mut ref_delta = vec![0x77];
+        let base = RawObject::new("commit", content.to_vec());
+        ref_delta.extend_from_slice(base.hash().as_bytes());
+        ref_delta.extend(compress(&[
+            len as u8,
+            len as u8 + 1,
+            0x91,
+            0x00,
+            len as u8,
+            0x01,
+            b'!',
+        ]));
+
+        let objects = PackFile::get_objects(pack(&[commit, ref_delta])).unwrap();
+        let restored = RawObject::new("commit", [&content[..], b"!"].concat());
+        assert_eq!(objects[0].hash(), base.hash());
+        assert_eq!(objects[1].hash(), restored.hash());
+        assert_eq!(objects[1].object, restored);
+    }
+
+    #[test]
+    fn it_fails_to_restore_deltas_without_a_base() {
+        let mut ref_delta = vec![0x77];
+        ref_delta.extend_from_slice(&[0xab; SHA1_HASH_SIZE]);
+        ref_delta.extend(compress(&[0x03, 0x04, 0x91, 0x00, 0x03, 0x01, b'!']));
+
+        assert!(PackFile::get_objects(pack(&[ref_delta])).is_err());
+    }
+
+    #[test]
+    fn it_tracks_offsets_of_entries() {
+        let first = [vec![0x33], compress(b"foo")].concat();
+        let second = [vec![0x33], compress(b"bar")].concat();
+        let first_len = first.len() as u64;
+
+        let offsets: Vec<u64> = PackFile::new(pack(&[first, second]))
+            .map(|object| object.offset)
+            .collect();
+
+        assert_eq!(offsets, vec![12, 12 + first_len]);
+    }
+
+    #[test]
+    fn it_calculates_crc32_of_entries() {
+        let entry = [vec![0x33], compress(b"foo")].concat();
+        let expected = crc32fast::hash(&entry);
+
+        let objects = PackFile::get_objects(pack(&[entry])).unwrap();
+        assert_eq!(objects[0].crc32(), expected);
+    }
+
+    #[test]
+    fn it_verifies_pack_checksum() {
+        let bytes = pack(&[[vec![0x33], compress(b"foo")].concat()]);
+        let mut hasher = Sha1Hash::hasher();
+        hasher.update(&bytes);
+        let checksum = Sha1Hash::new(hasher);
+        let bytes = [bytes, checksum.as_bytes().to_vec()].concat();
+
+        assert_eq!(PackFile::checksum(&bytes).unwrap(), checksum);
+
+        let mut broken = bytes.clone();
+        broken[12] ^= 0xff;
+        assert!(PackFile::checksum(&broken).is_err());
+    }
 }
@@ -0,0 +1,387 @@
+use super::{pack_file::PackedObject, Error, Result, Sha1Hash, SHA1_HASH_SIZE};
+use sha1::Digest;
+use std::cmp::Ordering;
+
+const IDX_MAGIC: &[u8] = b"\xfftOc";
+const IDX_VERSION: u32 = 2;
+const FANOUT_SIZE: usize = 256;
+// NOTE:
+// Offsets above this limit don't fit in the 31 bits of the offset table, so
+// they go to the 64-bit table and the entry keeps the index with the MSB set.
+const MAX_OFFSET_32: u64 = 0x7fffffff;
+const LARGE_OFFSET_FLAG: u32 = 0x80000000;
+const HEADER_SIZE: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+struct IndexEntry {
+    hash: Sha1Hash,
+    crc32: u32,
+    offset: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackIndex {
+    entries: Vec<IndexEntry>,
+    pack_checksum: Sha1Hash,
+}
+
+impl PackIndex {
+    pub fn new(objects: &[PackedObject], pack_checksum: Sha1Hash) -> Self {
+        let mut entries: Vec<IndexEntry> = objects
+            .iter()
+            .map(|packed| IndexEntry {
+                hash: packed.hash(),
+                crc32: packed.crc32(),
+                offset: packed.offset(),
+            })
+            .collect();
+        entries.sort_by(|a, b| a.hash.as_bytes().cmp(b.hash.as_bytes()));
+
+        Self {
+            entries,
+            pack_checksum,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let num = read_header(bytes)?;
+
+        let hashes_pos = HEADER_SIZE + 4 * FANOUT_SIZE;
+        let crcs_pos = hashes_pos + SHA1_HASH_SIZE * num;
+        let offsets_pos = crcs_pos + 4 * num;
+        let large_offsets_pos = offsets_pos + 4 * num;
+
+        let mut entries: Vec<IndexEntry> = Vec::with_capacity(num);
+        let mut num_large_offsets = 0;
+
+        for i in 0..num {
+            let hash_pos = hashes_pos + SHA1_HASH_SIZE * i;
+            let hash = bytes
+                .get(hash_pos..(hash_pos + SHA1_HASH_SIZE))
+                .ok_or(Error::from("Pack index file is truncated"))?;
+
+            let offset = match read_u32(bytes, offsets_pos + 4 * i)? {
+                v if v & LARGE_OFFSET_FLAG == LARGE_OFFSET_FLAG => {
+                    let pos = large_offsets_pos + 8 * (v & !LARGE_OFFSET_FLAG) as usize;
+                    num_large_offsets += 1;
+                    read_u64(bytes, pos)?
+                }
+                v => v as u64,
+            };
+
+            entries.push(IndexEntry {
+                hash: Sha1Hash::try_from(hash)?,
+                crc32: read_u32(bytes, crcs_pos + 4 * i)?,
+                offset,
+            });
+        }
+
+        let checksum_pos = large_offsets_pos + 8 * num_large_offsets;
+        let pack_checksum = bytes
+            .get(checksum_pos..(checksum_pos + SHA1_HASH_SIZE))
+            .ok_or(Error::from("Pack index file is truncated"))?;
+
+        Ok(Self {
+            entries,
+            pack_checksum: Sha1Hash::try_from(pack_checksum)?,
+        })
+    }
+
+    // NOTE:
+    // Git names the pack and its index after the pack checksum.
+    pub fn pack_name(&self) -> String {
+        format!("pack-{}", self.pack_checksum.hex())
+    }
+
+    // NOTE:
+    // The version 2 index file is laid out as
+    //   magic, version, 256 fanout counts,
+    //   sorted hashes, crc32s, 32-bit offsets, 64-bit offsets,
+    //   pack checksum and the checksum of the index itself.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf: Vec<u8> = vec![];
+        buf.extend_from_slice(IDX_MAGIC);
+        buf.extend_from_slice(&IDX_VERSION.to_be_bytes());
+
+        let mut fanout = [0u32; FANOUT_SIZE];
+        for entry in self.entries.iter() {
+            fanout[entry.hash.as_bytes()[0] as usize] += 1;
+        }
+        let mut count = 0u32;
+        for n in fanout {
+            count += n;
+            buf.extend_from_slice(&count.to_be_bytes());
+        }
+
+        for entry in self.entries.iter() {
+            buf.extend_from_slice(entry.hash.as_bytes());
+        }
+
+        for entry in self.entries.iter() {
+            buf.extend_from_slice(&entry.crc32.to_be_bytes());
+        }
+
+        let mut large_offsets: Vec<u64> = vec![];
+        for entry in self.entries.iter() {
+            let offset = if entry.offset > MAX_OFFSET_32 {
+                large_offsets.push(entry.offset);
+                LARGE_OFFSET_FLAG | (large_offsets.len() - 1) as u32
+            } else {
+                entry.offset as u32
+            };
+            buf.extend_from_slice(&offset.to_be_bytes());
+        }
+
+        for offset in large_offsets {
+            buf.extend_from_slice(&offset.to_be_bytes());
+        }
+
+        buf.extend_from_slice(self.pack_checksum.as_bytes());
+
+        let mut hasher = Sha1Hash::hasher();
+        hasher.update(&buf);
+        buf.extend_from_slice(Sha1Hash::new(hasher).as_bytes());
+
+        buf
+    }
+}
+
+// NOTE:
+// An index file as it is on disk, for looking up objects in its pack. The
+// fanout gives the range of the sorted hash table where the hashes starting
+// with a byte are, so a lookup is a binary search of that range and no entry
+// is parsed until it is found.
+#[derive(Debug)]
+pub struct PackIndexFile {
+    bytes: Vec<u8>,
+    num: usize,
+}
+
+impl PackIndexFile {
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        let num = read_header(&bytes)?;
+        let min_len = HEADER_SIZE + 4 * FANOUT_SIZE + (SHA1_HASH_SIZE + 8) * num;
+        if bytes.len() < min_len + 2 * SHA1_HASH_SIZE {
+            return Err(Error::from("Pack index file is truncated"));
+        }
+        Ok(Self { bytes, num })
+    }
+
+    // NOTE:
+    // Returns the offset in the pack of the entry for the object.
+    pub fn find(&self, hash: &Sha1Hash) -> Option<u64> {
+        let (mut lo, mut hi) = self.bucket(hash.as_bytes()[0]);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.hash(mid).cmp(hash.as_bytes()) {
+                Ordering::Less => lo = mid + 1,
+                Ordering::Greater => hi = mid,
+                Ordering::Equal => return self.offset(mid),
+            }
+        }
+        None
+    }
+
+    pub fn find_prefix(&self, prefix: &str) -> Vec<Sha1Hash> {
+        let (mut lo, hi) = match prefix.get(..2).map(|byte| u8::from_str_radix(byte, 16)) {
+            Some(Ok(byte)) => self.bucket(byte),
+            _ => (0, self.num),
+        };
+        let mut end = hi;
+        while lo < end {
+            let mid = lo + (end - lo) / 2;
+            if hex::encode(self.hash(mid)).as_str() < prefix {
+                lo = mid + 1;
+            } else {
+                end = mid;
+            }
+        }
+        (lo..hi)
+            .map(|i| self.hash(i))
+            .take_while(|hash| hex::encode(hash).starts_with(prefix))
+            .filter_map(|hash| Sha1Hash::try_from(hash).ok())
+            .collect()
+    }
+
+    // NOTE:
+    // The range of the hash table with the hashes whose first byte is the byte.
+    fn bucket(&self, byte: u8) -> (usize, usize) {
+        let count = |byte: usize| {
+            let pos = HEADER_SIZE + 4 * byte;
+            u32::from_be_bytes(self.bytes[pos..(pos + 4)].try_into().unwrap()) as usize
+        };
+        let start = match byte {
+            0 => 0,
+            byte => count(byte as usize - 1),
+        };
+        (start.min(self.num), count(byte as usize).min(self.num))
+    }
+
+    fn hash(&self, i: usize) -> &[u8] {
+        let pos = HEADER_SIZE + 4 * FANOUT_SIZE + SHA1_HASH_SIZE * i;
+        &self.bytes[pos..(pos + SHA1_HASH_SIZE)]
+    }
+
+    fn offset(&self, i: usize) -> Option<u64> {
+        let offsets_pos = HEADER_SIZE + 4 * FANOUT_SIZE + (SHA1_HASH_SIZE + 4) * self.num;
+        match read_u32(&self.bytes, offsets_pos + 4 * i).ok()? {
+            v if v & LARGE_OFFSET_FLAG == LARGE_OFFSET_FLAG => {
+                let large_offsets_pos = offsets_pos + 4 * self.num;
+                let pos = large_offsets_pos + 8 * (v & !LARGE_OFFSET_FLAG) as usize;
+                read_u64(&self.bytes, pos).ok()
+            }
+            v => Some(v as u64),
+        }
+    }
+}
+
+// NOTE:
+// Checks the magic and version of the index file and returns the number of
+// objects, which is the last count of the fanout.
+fn read_header(bytes: &[u8]) -> Result<usize> {
+    if !bytes.starts_with(IDX_MAGIC) {
+        return Err(Error::from("Unsupported pack index file: version 1"));
+    }
+    if read_u32(bytes, 4)? != IDX_VERSION {
+        return Err(Error::from("Unsupported pack index file version"));
+    }
+    Ok(read_u32(bytes, HEADER_SIZE + 4 * FANOUT_SIZE - 4)? as usize)
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> Result<u32> {
+    let buf = bytes
+        .get(pos..(pos + 4))
+        .ok_or(Error::from("Pack index file is truncated"))?;
+    Ok(u32::from_be_bytes(buf.try_into()?))
+}
+
+fn read_u64(bytes: &[u8], pos: usize) -> Result<u64> {
+    let buf = bytes
+        .get(pos..(pos + 8))
+        .ok_or(Error::from("Pack index file is truncated"))?;
+    Ok(u64::from_be_bytes(buf.try_into()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(first: u8, crc32: u32, offset: u64) -> IndexEntry {
+        IndexEntry {
+            hash: [first; 20].into(),
+            crc32,
+            offset,
+        }
+    }
+
+    fn index(entries: Vec<IndexEntry>) -> PackIndex {
+        PackIndex {
+            entries,
+            pack_checksum: [0xaa; 20].into(),
+        }
+    }
+
+    #[test]
+    fn it_serializes_header_and_fanout() {
+        let bytes = index(vec![entry(0x01, 1, 12), entry(0x03, 2, 40)]).serialize();
+
+        assert_eq!(&bytes[..4], IDX_MAGIC);
+        assert_eq!(&bytes[4..8], &[0, 0, 0, 2]);
+
+        let fanout: Vec<u32> = bytes[8..(8 + 4 * FANOUT_SIZE)]
+            .chunks(4)
+            .map(|c| u32::from_be_bytes(c.try_into().unwrap()))
+            .collect();
+        assert_eq!(&fanout[..5], &[0, 1, 1, 2, 2]);
+        assert_eq!(fanout[255], 2);
+    }
+
+    #[test]
+    fn it_serializes_tables_and_checksums() {
+        let bytes = index(vec![entry(0x01, 1, 12), entry(0x03, 2, 40)]).serialize();
+        let tables = &bytes[(8 + 4 * FANOUT_SIZE)..];
+
+        assert_eq!(&tables[..20], &[0x01; 20]);
+        assert_eq!(&tables[20..40], &[0x03; 20]);
+        assert_eq!(&tables[40..48], &[0, 0, 0, 1, 0, 0, 0, 2]);
+        assert_eq!(&tables[48..56], &[0, 0, 0, 12, 0, 0, 0, 40]);
+        assert_eq!(&tables[56..76], &[0xaa; 20]);
+
+        let mut hasher = Sha1Hash::hasher();
+        hasher.update(&bytes[..(bytes.len() - 20)]);
+        assert_eq!(&tables[76..], Sha1Hash::new(hasher).as_bytes());
+    }
+
+    #[test]
+    fn it_serializes_large_offsets() {
+        let large = 0x1_0000_0000u64;
+        let bytes = index(vec![entry(0x01, 1, large), entry(0x02, 2, 12)]).serialize();
+        let offsets = &bytes[(8 + 4 * FANOUT_SIZE + 2 * 24)..];
+
+        assert_eq!(&offsets[..4], &[0x80, 0, 0, 0]);
+        assert_eq!(&offsets[4..8], &[0, 0, 0, 12]);
+        assert_eq!(&offsets[8..16], &large.to_be_bytes());
+    }
+
+    #[test]
+    fn it_parses_serialized_index() {
+        let large = 0x1_0000_0000u64;
+        let idx = index(vec![entry(0x01, 1, large), entry(0x02, 2, 12)]);
+        assert_eq!(PackIndex::from_bytes(&idx.serialize()).unwrap(), idx);
+    }
+
+    #[test]
+    fn it_finds_offset_by_hash() {
+        let idx = index(vec![
+            entry(0x01, 1, 12),
+            entry(0x03, 2, 40),
+            entry(0x07, 3, 99),
+        ]);
+        let idx = PackIndexFile::from_bytes(idx.serialize()).unwrap();
+        assert_eq!(idx.find(&[0x03; 20].into()), Some(40));
+        assert_eq!(idx.find(&[0x07; 20].into()), Some(99));
+        assert_eq!(idx.find(&[0x05; 20].into()), None);
+        assert_eq!(idx.find(&[0x00; 20].into()), None);
+        assert_eq!(idx.find(&[0xff; 20].into()), None);
+    }
+
+    #[test]
+    fn it_finds_hashes_by_prefix() {
+        let idx = index(vec![
+            entry(0x01, 1, 12),
+            entry(0x03, 2, 40),
+            entry(0x07, 3, 99),
+        ]);
+        let idx = PackIndexFile::from_bytes(idx.serialize()).unwrap();
+        assert_eq!(idx.find_prefix("0303"), vec![[0x03; 20].into()]);
+        assert_eq!(idx.find_prefix("0").len(), 3);
+        assert!(idx.find_prefix("05").is_empty());
+        assert!(idx.find_prefix("0304").is_empty());
+    }
+
+    #[test]
+    fn it_finds_large_offsets_on_disk() {
+        let large = 0x1_0000_0000u64;
+        let idx = index(vec![entry(0x01, 1, large), entry(0x02, 2, 12)]);
+        let idx = PackIndexFile::from_bytes(idx.serialize()).unwrap();
+        assert_eq!(idx.find(&[0x01; 20].into()), Some(large));
+        assert_eq!(idx.find(&[0x02; 20].into()), Some(12));
+    }
+
+    #[test]
+    fn it_rejects_unknown_index_file() {
+        assert!(PackIndex::from_bytes(b"\x00\x00\x00\x00").is_err());
+        assert!(PackIndexFile::from_bytes(b"\x00\x00\x00\x00".to_vec()).is_err());
+        let truncated = index(vec![entry(0x01, 1, 12)]).serialize()[..1080].to_vec();
+        assert!(PackIndexFile::from_bytes(truncated).is_err());
+    }
+
+    #[test]
+    fn it_names_pack_after_checksum() {
+        assert_eq!(
+            index(vec![]).pack_name(),
+            format!("pack-{}", "aa".repeat(20))
+        );
+    }
+}
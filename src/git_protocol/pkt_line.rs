@@ -98,12 +98,12 @@ impl Iterator for PktLines {
             .read_exact(&mut buf)
             .expect("Cannot read pkt line size");
 
-        let line_len = line_size(&buf[..]);
+        let line_len = line_size(&buf[..])?;
         if line_len == 0 {
             return Some(PktLine::flush());
         }
 
-        let value_len = line_len - 4;
+        let value_len = line_len.checked_sub(4)?;
         if self.remaining().len() < value_len {
             self.cursor
                 .seek_relative(-4)
@@ -120,9 +120,11 @@ impl Iterator for PktLines {
     }
 }
 
-fn line_size(buf: &[u8]) -> usize {
-    let len_str = unsafe { std::str::from_utf8_unchecked(buf) };
-    usize::from_str_radix(len_str, 16).expect("Cannot parse pkt line size.")
+// NOTE:
+// A response that isn't made of pkt-lines, like an error page, has no size.
+fn line_size(buf: &[u8]) -> Option<usize> {
+    let len_str = std::str::from_utf8(buf).ok()?;
+    usize::from_str_radix(len_str, 16).ok()
 }
 
 #[cfg(test)]
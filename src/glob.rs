@@ -0,0 +1,124 @@
+// NOTE:
+// A small version of git's wildmatch. "*" and "?" don't match "/" when
+// `pathname` is set, and then "**/" matches zero or more directories.
+// "[...]" classes support ranges and negation with "!" or "^".
+pub fn wildmatch(pattern: &str, text: &str, pathname: bool) -> bool {
+    do_match(pattern.as_bytes(), text.as_bytes(), pathname)
+}
+
+fn do_match(pattern: &[u8], text: &[u8], pathname: bool) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pathname && pattern.starts_with(b"**") => {
+            let rest = &pattern[2..];
+            match rest.strip_prefix(b"/") {
+                Some(rest) => {
+                    do_match(rest, text, pathname)
+                        || text
+                            .iter()
+                            .enumerate()
+                            .any(|(i, &c)| c == b'/' && do_match(rest, &text[(i + 1)..], pathname))
+                }
+                None => (0..=text.len()).any(|i| do_match(rest, &text[i..], pathname)),
+            }
+        }
+        Some(b'*') => {
+            let rest = &pattern[1..];
+            for i in 0..=text.len() {
+                if do_match(rest, &text[i..], pathname) {
+                    return true;
+                }
+                if pathname && text.get(i) == Some(&b'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some(b'?') => match text.first() {
+            Some(b'/') if pathname => false,
+            Some(_) => do_match(&pattern[1..], &text[1..], pathname),
+            None => false,
+        },
+        Some(b'[') => match (text.first(), class_end(pattern)) {
+            (Some(&c), Some(end)) => {
+                !(pathname && c == b'/')
+                    && class_matches(&pattern[1..end], c)
+                    && do_match(&pattern[(end + 1)..], &text[1..], pathname)
+            }
+            (Some(&c), None) => c == b'[' && do_match(&pattern[1..], &text[1..], pathname),
+            (None, _) => false,
+        },
+        Some(b'\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && do_match(&pattern[2..], &text[1..], pathname)
+        }
+        Some(&c) => text.first() == Some(&c) && do_match(&pattern[1..], &text[1..], pathname),
+    }
+}
+
+// NOTE:
+// Returns the position of the "]" closing the class that starts the pattern.
+// A "]" right after the opening (or after the negation) is a literal.
+fn class_end(pattern: &[u8]) -> Option<usize> {
+    let mut i = 1;
+    if matches!(pattern.get(i), Some(b'!' | b'^')) {
+        i += 1;
+    }
+    if pattern.get(i) == Some(&b']') {
+        i += 1;
+    }
+    pattern[i..]
+        .iter()
+        .position(|&c| c == b']')
+        .map(|pos| i + pos)
+}
+
+fn class_matches(class: &[u8], c: u8) -> bool {
+    let (negated, class) = match class.first() {
+        Some(b'!' | b'^') => (true, &class[1..]),
+        _ => (false, class),
+    };
+
+    let mut matched = false;
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == b'-' {
+            matched |= class[i] <= c && c <= class[i + 2];
+            i += 3;
+        } else {
+            matched |= class[i] == c;
+            i += 1;
+        }
+    }
+    matched != negated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_matches_wildcards() {
+        assert!(wildmatch("v1.*", "v1.2.3", false));
+        assert!(wildmatch("v?.0", "v2.0", false));
+        assert!(!wildmatch("v?.0", "v10.0", false));
+        assert!(wildmatch("release/*", "release/a/b", false));
+        assert!(!wildmatch("release/*", "release/a/b", true));
+    }
+
+    #[test]
+    fn it_matches_double_stars_in_pathname_mode() {
+        assert!(wildmatch("**/work/**", "/home/me/work/repo/.git", true));
+        assert!(wildmatch("/home/**/.git", "/home/.git", true));
+        assert!(wildmatch("/home/**/.git", "/home/me/repo/.git", true));
+        assert!(!wildmatch("/home/**/.git", "/srv/repo/.git", true));
+    }
+
+    #[test]
+    fn it_matches_classes() {
+        assert!(wildmatch("v[0-9]", "v7", false));
+        assert!(!wildmatch("v[!0-9]", "v7", false));
+        assert!(wildmatch("[]a]", "]", false));
+        assert!(wildmatch("a\\*", "a*", false));
+        assert!(!wildmatch("a\\*", "ab", false));
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::VecDeque;
+
+// NOTE:
+// Draws the ASCII history graph of `log --graph`. Each column is the commit
+// expected next on that line. A commit takes its column (or a new one on the
+// right) and passes it to its first parent, other parents open new columns
+// ("|\") and columns waiting for the same commit are joined ("|/").
+#[derive(Debug, Default)]
+pub struct Graph {
+    columns: Vec<String>,
+    transitions: VecDeque<String>,
+    width: usize,
+}
+
+impl Graph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // NOTE:
+    // Lays out the commit and returns the prefix of its first line.
+    pub fn commit(&mut self, hash: &str, parents: &[String]) -> String {
+        let idx = match self.columns.iter().position(|c| c == hash) {
+            Some(idx) => idx,
+            None => {
+                self.columns.push(hash.into());
+                self.columns.len() - 1
+            }
+        };
+        let before = self.columns.len();
+        let extra = parents.len().saturating_sub(1);
+
+        let mut line = (0..before)
+            .map(|i| if i == idx { "*" } else { "|" })
+            .collect::<Vec<&str>>()
+            .join(" ");
+        line.push_str(&"  ".repeat(extra));
+
+        self.columns.splice(idx..=idx, parents.iter().cloned());
+        if extra > 0 {
+            let after = before - idx - 1;
+            let expand = format!("{}|\\{}", "| ".repeat(idx), " \\".repeat(after));
+            self.transitions.push_back(expand);
+        }
+        let widest = self.columns.len().max(before);
+        self.collapse();
+
+        self.width = (2 * widest).max(line.len() + 1);
+        self.pad(line)
+    }
+
+    // NOTE:
+    // The prefix of the next line of the commit.
+    pub fn next_prefix(&mut self) -> String {
+        let line = self
+            .transitions
+            .pop_front()
+            .unwrap_or_else(|| self.padding());
+        self.pad(line)
+    }
+
+    // NOTE:
+    // The transitions not yet drawn as prefixes, to be drawn on their own lines.
+    pub fn flush(&mut self) -> Vec<String> {
+        let lines: Vec<String> = self.transitions.drain(..).collect();
+        lines.into_iter().map(|line| self.pad(line)).collect()
+    }
+
+    fn collapse(&mut self) {
+        loop {
+            let duplicate =
+                (1..self.columns.len()).find(|&k| self.columns[..k].contains(&self.columns[k]));
+            let Some(k) = duplicate else {
+                break;
+            };
+
+            let len = self.columns.len();
+            let line = format!("{}/{}", vec!["|"; k].join(" "), " /".repeat(len - k - 1));
+            self.columns.remove(k);
+            self.transitions.push_back(line);
+        }
+    }
+
+    fn padding(&self) -> String {
+        vec!["|"; self.columns.len()].join(" ")
+    }
+
+    fn pad(&self, line: String) -> String {
+        format!("{line:width$}", width = self.width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parents(hashes: &[&str]) -> Vec<String> {
+        hashes.iter().map(|h| h.to_string()).collect()
+    }
+
+    #[test]
+    fn it_draws_merge_and_join() {
+        let mut graph = Graph::new();
+        let mut lines: Vec<String> = vec![];
+
+        lines.push(graph.commit("top", &parents(&["merge"])));
+        lines.extend(graph.flush());
+        lines.push(graph.commit("merge", &parents(&["main", "side"])));
+        lines.extend(graph.flush());
+        lines.push(graph.commit("side", &parents(&["base"])));
+        lines.extend(graph.flush());
+        lines.push(graph.commit("main", &parents(&["base"])));
+        lines.extend(graph.flush());
+        lines.push(graph.commit("base", &[]));
+        lines.extend(graph.flush());
+
+        assert_eq!(
+            lines,
+            vec!["* ", "*   ", "|\\  ", "| * ", "* | ", "|/  ", "* "]
+        );
+    }
+
+    #[test]
+    fn it_uses_transitions_as_prefixes() {
+        let mut graph = Graph::new();
+        assert_eq!(graph.commit("merge", &parents(&["a", "b"])), "*   ");
+        assert_eq!(graph.next_prefix(), "|\\  ");
+        assert_eq!(graph.next_prefix(), "| | ");
+        assert!(graph.flush().is_empty());
+    }
+}
@@ -3,7 +3,7 @@ use sha1::{Digest, Sha1};
 
 pub const SHA1_HASH_SIZE: usize = 20;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct Sha1Hash([u8; SHA1_HASH_SIZE]);
 
 impl Sha1Hash {
@@ -22,6 +22,14 @@ impl Sha1Hash {
     pub fn as_bytes(&self) -> &[u8] {
         &self.0
     }
+
+    pub fn from_hex(hex: &str) -> Result<Self, Error> {
+        let bytes = hex::decode(hex).map_err(|err| {
+            let msg = format!("Invalid SHA-1 hash {hex}. {err}");
+            Error::from(msg.as_str())
+        })?;
+        Self::try_from(&bytes[..])
+    }
 }
 
 impl TryFrom<&[u8]> for Sha1Hash {
@@ -37,3 +45,16 @@ impl From<[u8; SHA1_HASH_SIZE]> for Sha1Hash {
         Self(value)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_creates_hash_from_hex() {
+        let hash = Sha1Hash::from_hex("e88f7a929cd70b0274c4ea33b209c97fa845fdbc").unwrap();
+        assert_eq!(hash.hex(), "e88f7a929cd70b0274c4ea33b209c97fa845fdbc");
+        assert!(Sha1Hash::from_hex("e88f7a").is_err());
+        assert!(Sha1Hash::from_hex("not a hash").is_err());
+    }
+}
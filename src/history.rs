@@ -0,0 +1,436 @@
+use super::{diff::rename, git_object::commit::Commit, GitObject, Result};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+// NOTE:
+// Commits with the same date come out in the order they were queued.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Queued {
+    timestamp: u64,
+    seq: Reverse<usize>,
+    hash: String,
+}
+
+// NOTE:
+// Walks the history from the pushed commits, newest committer date first.
+// When limited to paths, a commit is shown only if it changes any of them, and
+// a merge that has a parent with the same content follows only that parent
+// (git's default history simplification). When following renames, the path
+// is replaced by the file it was renamed or copied from as the walk goes past
+// the commit that created it.
+#[derive(Debug)]
+pub struct RevWalk {
+    root: PathBuf,
+    queue: BinaryHeap<Queued>,
+    commits: HashMap<String, Commit>,
+    seen: HashSet<String>,
+    paths: Vec<String>,
+    follow: bool,
+    skipped: HashMap<String, Vec<String>>,
+    seq: usize,
+}
+
+impl RevWalk {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().into(),
+            queue: BinaryHeap::new(),
+            commits: HashMap::new(),
+            seen: HashSet::new(),
+            paths: vec![],
+            follow: false,
+            skipped: HashMap::new(),
+            seq: 0,
+        }
+    }
+
+    pub fn paths(mut self, paths: Vec<String>) -> Self {
+        self.paths = paths;
+        self
+    }
+
+    // NOTE:
+    // Following renames needs a single path.
+    pub fn follow(mut self, follow: bool) -> Self {
+        self.follow = follow;
+        self
+    }
+
+    // NOTE:
+    // Excludes the commit and its ancestors like "^<rev>". It has to be called
+    // before pushing the commits to show.
+    pub fn hide(&mut self, hash: &str) -> Result<()> {
+        let mut stack = vec![hash.to_string()];
+        while let Some(hash) = stack.pop() {
+            if self.seen.insert(hash.clone()) {
+                let commit = GitObject::open_commit(&self.root, &hash)?;
+                stack.extend(commit.parents().iter().cloned());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn push(&mut self, hash: &str) -> Result<()> {
+        if !self.seen.insert(hash.to_string()) {
+            return Ok(());
+        }
+
+        let commit = GitObject::open_commit(&self.root, hash)?;
+        self.queue.push(Queued {
+            timestamp: commit.committer().timestamp(),
+            seq: Reverse(self.seq),
+            hash: hash.to_string(),
+        });
+        self.commits.insert(hash.to_string(), commit);
+        self.seq += 1;
+        Ok(())
+    }
+
+    // NOTE:
+    // Replaces the parents hidden by path limiting with their nearest shown
+    // ancestors so that the graph connects the shown commits. It's only
+    // complete after the walk is done.
+    pub fn rewrite_parents(&self, parents: &[String]) -> Vec<String> {
+        let mut rewritten: Vec<String> = vec![];
+        for parent in parents {
+            let mut parent = Some(parent);
+            while let Some(followed) = parent.and_then(|p| self.skipped.get(p)) {
+                parent = followed.first();
+            }
+            if let Some(parent) = parent {
+                if !rewritten.contains(parent) {
+                    rewritten.push(parent.clone());
+                }
+            }
+        }
+        rewritten
+    }
+
+    fn next_commit(&mut self) -> Result<Option<(String, Commit)>> {
+        while let Some(Queued { hash, .. }) = self.queue.pop() {
+            let commit = self
+                .commits
+                .remove(&hash)
+                .expect("queued commit must be loaded");
+            let (shown, parents) = self.simplify(&commit)?;
+            for parent in parents.iter() {
+                self.push(parent)?;
+            }
+
+            if shown {
+                return Ok(Some((hash, commit)));
+            }
+            self.skipped.insert(hash, parents);
+        }
+        Ok(None)
+    }
+
+    // NOTE:
+    // Returns whether to show the commit and the parents to walk on.
+    fn simplify(&mut self, commit: &Commit) -> Result<(bool, Vec<String>)> {
+        if self.paths.is_empty() {
+            return Ok((true, commit.parents().to_vec()));
+        }
+
+        let entries = self.entries(commit.tree())?;
+        if commit.parents().is_empty() {
+            return Ok((entries.iter().any(Option::is_some), vec![]));
+        }
+
+        for parent in commit.parents() {
+            let parent_commit = GitObject::open_commit(&self.root, parent)?;
+            let parent_entries = self.entries(parent_commit.tree())?;
+            if parent_entries == entries {
+                return Ok((false, vec![parent.clone()]));
+            }
+            if self.follow && parent_entries == [None] {
+                let (old, new) = (parent_commit.tree(), commit.tree());
+                let origin = rename::find_origin(&self.root, old, new, &self.paths[0])?;
+                if let Some(origin) = origin {
+                    self.paths = vec![origin];
+                    break;
+                }
+            }
+        }
+        Ok((true, commit.parents().to_vec()))
+    }
+
+    fn entries(&self, tree: &str) -> Result<Vec<Option<String>>> {
+        self.paths
+            .iter()
+            .map(|path| {
+                if path.is_empty() {
+                    return Ok(Some(tree.to_string()));
+                }
+                let node = GitObject::find_in_tree(&self.root, tree, path)?;
+                Ok(node.map(|node| node.hash().hex()))
+            })
+            .collect()
+    }
+}
+
+impl Iterator for RevWalk {
+    type Item = Result<(String, Commit)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_commit().transpose()
+    }
+}
+
+const PARENT1: u8 = 1;
+const PARENT2: u8 = 2;
+const STALE: u8 = 4;
+const RESULT: u8 = 8;
+
+// NOTE:
+// A commit counts as its own ancestor.
+pub fn is_ancestor<P: AsRef<Path>>(root: P, ancestor: &str, commit: &str) -> Result<bool> {
+    Ok(merge_bases(root, ancestor, commit)?
+        .iter()
+        .any(|base| base == ancestor))
+}
+
+// NOTE:
+// The best common ancestors of the two commits. The commits are painted
+// from both sides in date order and a commit reached from both is a result,
+// while its ancestors are marked stale and can't be results.
+pub fn merge_bases<P: AsRef<Path>>(root: P, one: &str, two: &str) -> Result<Vec<String>> {
+    let root = root.as_ref();
+    if one == two {
+        return Ok(vec![one.to_string()]);
+    }
+
+    let mut flags: HashMap<String, u8> = HashMap::new();
+    let mut queue: BinaryHeap<Queued> = BinaryHeap::new();
+    let mut seq = 0;
+    let mut enqueue = |queue: &mut BinaryHeap<Queued>, hash: &str| -> Result<()> {
+        let commit = GitObject::open_commit(root, hash)?;
+        queue.push(Queued {
+            timestamp: commit.committer().timestamp(),
+            seq: Reverse(seq),
+            hash: hash.to_string(),
+        });
+        seq += 1;
+        Ok(())
+    };
+
+    flags.insert(one.to_string(), PARENT1);
+    flags.insert(two.to_string(), PARENT2);
+    enqueue(&mut queue, one)?;
+    enqueue(&mut queue, two)?;
+
+    let mut results: Vec<String> = vec![];
+    while queue
+        .iter()
+        .any(|q| flags.get(&q.hash).is_some_and(|f| f & STALE == 0))
+    {
+        let Some(Queued { hash, .. }) = queue.pop() else {
+            break;
+        };
+        let current = flags[&hash];
+        let mut paint = current & (PARENT1 | PARENT2 | STALE);
+        if paint == PARENT1 | PARENT2 {
+            if current & RESULT == 0 {
+                flags.insert(hash.clone(), current | RESULT);
+                results.push(hash.clone());
+            }
+            paint |= STALE;
+        }
+
+        let commit = GitObject::open_commit(root, &hash)?;
+        for parent in commit.parents() {
+            let parent_flags = flags.get(parent).copied().unwrap_or_default();
+            if parent_flags & paint == paint {
+                continue;
+            }
+            flags.insert(parent.clone(), parent_flags | paint);
+            enqueue(&mut queue, parent)?;
+        }
+    }
+
+    Ok(results
+        .into_iter()
+        .filter(|hash| flags[hash] & STALE == 0)
+        .collect())
+}
+
+// NOTE:
+// Orders the commits so that children come before their parents, given as
+// pairs of a hash and its parents. Like git's --topo-order, the last parent
+// is shown first so that a side branch is shown together with its merge.
+pub fn topo_order(commits: &[(String, Vec<String>)]) -> Vec<usize> {
+    let positions: HashMap<&str, usize> = commits
+        .iter()
+        .enumerate()
+        .map(|(i, (hash, _))| (hash.as_str(), i))
+        .collect();
+
+    let mut indegrees = vec![0usize; commits.len()];
+    for (_, parents) in commits {
+        for parent in parents {
+            if let Some(&i) = positions.get(parent.as_str()) {
+                indegrees[i] += 1;
+            }
+        }
+    }
+
+    let mut stack: Vec<usize> = (0..commits.len())
+        .filter(|&i| indegrees[i] == 0)
+        .rev()
+        .collect();
+    let mut order: Vec<usize> = Vec::with_capacity(commits.len());
+    while let Some(i) = stack.pop() {
+        order.push(i);
+        for parent in commits[i].1.iter() {
+            if let Some(&p) = positions.get(parent.as_str()) {
+                indegrees[p] -= 1;
+                if indegrees[p] == 0 {
+                    stack.push(p);
+                }
+            }
+        }
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git_object::commit::User;
+    use crate::testing;
+
+    fn commit(root: &Path, tree: &str, parents: &[&str], timestamp: u64, msg: &str) -> String {
+        let user = User::new("A".into(), "a@x".into(), timestamp, "+0000".into());
+        let obj = GitObject::new_commit(
+            tree.into(),
+            msg.into(),
+            parents.iter().map(|p| p.to_string()).collect(),
+            user.clone(),
+            user,
+        )
+        .unwrap();
+        obj.write(root).unwrap();
+        obj.hash().hex()
+    }
+
+    fn summaries(walk: RevWalk) -> Vec<String> {
+        walk.map(|entry| entry.unwrap().1.summary().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn it_walks_merges_by_date() {
+        let root = testing::repo("history-date");
+        let tree = testing::write_tree(&root, &[("a", "a")]);
+        let base = commit(&root, &tree, &[], 100, "base");
+        let side = commit(&root, &tree, &[&base], 200, "side");
+        let main = commit(&root, &tree, &[&base], 300, "main");
+        let merge = commit(&root, &tree, &[&main, &side], 400, "merge");
+
+        let mut walk = RevWalk::new(&root);
+        walk.push(&merge).unwrap();
+        assert_eq!(summaries(walk), vec!["merge", "main", "side", "base"]);
+    }
+
+    #[test]
+    fn it_limits_history_to_paths() {
+        let root = testing::repo("history-paths");
+        let t1 = testing::write_tree(&root, &[("a", "1"), ("dir/f", "1")]);
+        let c1 = commit(&root, &t1, &[], 100, "first");
+        let t2 = testing::write_tree(&root, &[("a", "2"), ("dir/f", "1")]);
+        let c2 = commit(&root, &t2, &[&c1], 200, "change a");
+        let t3 = testing::write_tree(&root, &[("a", "2"), ("dir/f", "2")]);
+        let c3 = commit(&root, &t3, &[&c2], 300, "change dir");
+
+        let mut walk = RevWalk::new(&root).paths(vec!["dir".into()]);
+        walk.push(&c3).unwrap();
+        assert_eq!(summaries(walk), vec!["change dir", "first"]);
+
+        let mut walk = RevWalk::new(&root).paths(vec!["a".into()]);
+        walk.push(&c3).unwrap();
+        let entries: Vec<(String, Commit)> = walk.by_ref().map(|e| e.unwrap()).collect();
+        assert_eq!(entries[0].1.summary(), "change a");
+        assert_eq!(
+            walk.rewrite_parents(std::slice::from_ref(&c3)),
+            vec![c2.clone()]
+        );
+    }
+
+    #[test]
+    fn it_follows_renames() {
+        let root = testing::repo("history-follow");
+        let content = "1\n2\n3\n4\n5\n6\n7\n8\n";
+        let t1 = testing::write_tree(&root, &[("old", content), ("other", "x")]);
+        let c1 = commit(&root, &t1, &[], 100, "add old");
+        let t2 = testing::write_tree(&root, &[("new", content), ("other", "x")]);
+        let c2 = commit(&root, &t2, &[&c1], 200, "rename");
+        let t3 = testing::write_tree(
+            &root,
+            &[("new", "1\n2\n3\n4\n5\n6\n7\n9\n"), ("other", "y")],
+        );
+        let c3 = commit(&root, &t3, &[&c2], 300, "change new");
+
+        let mut walk = RevWalk::new(&root).paths(vec!["new".into()]);
+        walk.push(&c3).unwrap();
+        assert_eq!(summaries(walk), vec!["change new", "rename"]);
+
+        let mut walk = RevWalk::new(&root).paths(vec!["new".into()]).follow(true);
+        walk.push(&c3).unwrap();
+        assert_eq!(summaries(walk), vec!["change new", "rename", "add old"]);
+    }
+
+    #[test]
+    fn it_hides_ancestors() {
+        let root = testing::repo("history-hide");
+        let tree = testing::write_tree(&root, &[("a", "a")]);
+        let base = commit(&root, &tree, &[], 100, "base");
+        let side = commit(&root, &tree, &[&base], 200, "side");
+        let main = commit(&root, &tree, &[&base], 300, "main");
+
+        let mut walk = RevWalk::new(&root);
+        walk.hide(&side).unwrap();
+        walk.push(&main).unwrap();
+        assert_eq!(summaries(walk), vec!["main"]);
+    }
+
+    #[test]
+    fn it_finds_merge_bases() {
+        let root = testing::repo("history-merge-base");
+        let tree = testing::write_tree(&root, &[("a", "a")]);
+        let base = commit(&root, &tree, &[], 100, "base");
+        let side = commit(&root, &tree, &[&base], 200, "side");
+        let main = commit(&root, &tree, &[&base], 300, "main");
+        let merge = commit(&root, &tree, &[&main, &side], 400, "merge");
+        let next = commit(&root, &tree, &[&side], 500, "next");
+
+        assert_eq!(
+            merge_bases(&root, &main, &side).unwrap(),
+            vec![base.clone()]
+        );
+        assert_eq!(
+            merge_bases(&root, &merge, &next).unwrap(),
+            vec![side.clone()]
+        );
+        assert_eq!(
+            merge_bases(&root, &merge, &main).unwrap(),
+            vec![main.clone()]
+        );
+        assert!(is_ancestor(&root, &side, &merge).unwrap());
+        assert!(!is_ancestor(&root, &next, &merge).unwrap());
+    }
+
+    #[test]
+    fn it_orders_topologically() {
+        let commits = vec![
+            (
+                "merge".to_string(),
+                vec!["main".to_string(), "side".to_string()],
+            ),
+            ("main".to_string(), vec!["base".to_string()]),
+            ("side".to_string(), vec!["base".to_string()]),
+            ("base".to_string(), vec![]),
+        ];
+        assert_eq!(topo_order(&commits), vec![0, 2, 1, 3]);
+    }
+}
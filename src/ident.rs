@@ -0,0 +1,285 @@
+use super::{config::Config, date, git_object::commit::User, Error, Result};
+use std::env;
+#[cfg(unix)]
+use std::ffi::CStr;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    Author,
+    Committer,
+}
+
+impl Role {
+    fn env_prefix(&self) -> &'static str {
+        match self {
+            Self::Author => "GIT_AUTHOR",
+            Self::Committer => "GIT_COMMITTER",
+        }
+    }
+
+    fn config_section(&self) -> &'static str {
+        match self {
+            Self::Author => "author",
+            Self::Committer => "committer",
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Author => "Author",
+            Self::Committer => "Committer",
+        }
+    }
+}
+
+pub fn author<P: AsRef<Path>>(root: P) -> Result<User> {
+    ident(root, Role::Author)
+}
+
+pub fn committer<P: AsRef<Path>>(root: P) -> Result<User> {
+    ident(root, Role::Committer)
+}
+
+// NOTE:
+// The committer of an entry in a reflog. Git writes reflogs without a
+// configured identity too, so instead of failing, the parts that are missing
+// are taken from the user running the process and the name of the host.
+pub fn reflog_committer<P: AsRef<Path>>(root: P) -> Result<User> {
+    let config = Config::load(root)?;
+    let system = Some(system_user());
+    resolve(&config, Role::Committer, system.as_ref(), |var| {
+        env::var(var).ok()
+    })
+}
+
+// NOTE:
+// Each part is looked up in this order:
+//   name:  GIT_<ROLE>_NAME, <role>.name, user.name, the login name
+//   email: GIT_<ROLE>_EMAIL, <role>.email, user.email, EMAIL, <login name>@<hostname>
+//   date:  GIT_<ROLE>_DATE, the current time in the local timezone
+pub fn ident<P: AsRef<Path>>(root: P, role: Role) -> Result<User> {
+    let config = Config::load(root)?;
+    resolve(&config, role, None, |var| env::var(var).ok())
+}
+
+// NOTE:
+// With the full name and login of the system user, an identity that isn't
+// configured is made up from them instead of being an error. The email then
+// has "(none)" as the domain of a host name without one.
+fn resolve<F>(
+    config: &Config,
+    role: Role,
+    system: Option<&(String, String)>,
+    env: F,
+) -> Result<User>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let prefix = role.env_prefix();
+    let section = role.config_section();
+
+    let lookup = |var: &str, key: &str| -> Option<String> {
+        env(&format!("{prefix}_{var}"))
+            .or_else(|| config.get(&format!("{section}.{key}")).map(String::from))
+            .or_else(|| config.get(&format!("user.{key}")).map(String::from))
+    };
+    let login_name = || {
+        env("USER")
+            .or_else(|| env("LOGNAME"))
+            .filter(|name| !name.is_empty())
+    };
+
+    let name = match (lookup("NAME", "name"), system) {
+        (Some(name), _) => name,
+        (None, Some((full_name, _))) => full_name.clone(),
+        (None, None) => login_name().ok_or_else(|| unknown_identity(role))?,
+    };
+    if name.trim().is_empty() && system.is_none() {
+        let msg = format!("empty ident name (for <{}>) not allowed", role.label());
+        return Err(Error::from(msg.as_str()));
+    }
+
+    let email = match (lookup("EMAIL", "email").or_else(|| env("EMAIL")), system) {
+        (Some(email), _) => email,
+        (None, Some((_, login))) => match hostname() {
+            host if host.contains('.') => format!("{login}@{host}"),
+            host => format!("{login}@{host}.(none)"),
+        },
+        (None, None) => login_name()
+            .map(|login| format!("{login}@{}", hostname()))
+            .ok_or_else(|| unknown_identity(role))?,
+    };
+
+    let (timestamp, offset) = match env(&format!("{prefix}_DATE")) {
+        Some(value) => date::parse(&value)?,
+        None => {
+            let now = date::now();
+            (now, date::local_offset(now))
+        }
+    };
+
+    Ok(User::new(
+        name.trim().to_string(),
+        email.trim().to_string(),
+        timestamp,
+        date::format_offset(offset),
+    ))
+}
+
+// NOTE:
+// The full name is the GECOS field up to the first comma, where "&" stands
+// for the capitalized login, or the login when it is empty.
+#[cfg(unix)]
+fn system_user() -> (String, String) {
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 16384];
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    let ret = unsafe {
+        libc::getpwuid_r(
+            libc::getuid(),
+            &mut passwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+    if ret != 0 || result.is_null() {
+        return ("unknown".into(), "unknown".into());
+    }
+
+    let read = |ptr: *const libc::c_char| match ptr.is_null() {
+        true => String::new(),
+        false => unsafe { CStr::from_ptr(ptr) }
+            .to_string_lossy()
+            .into_owned(),
+    };
+    let login = read(passwd.pw_name);
+    let gecos = read(passwd.pw_gecos);
+    let full_name = gecos.split(',').next().unwrap_or_default();
+    let full_name = match full_name.is_empty() {
+        true => login.clone(),
+        false => {
+            let mut chars = login.chars();
+            let capitalized: String = chars
+                .next()
+                .map(|c| c.to_uppercase().chain(chars).collect())
+                .unwrap_or_default();
+            full_name.replace('&', &capitalized)
+        }
+    };
+    (full_name, login)
+}
+
+#[cfg(not(unix))]
+fn system_user() -> (String, String) {
+    let login = env::var("USERNAME").unwrap_or_else(|_| "unknown".into());
+    (login.clone(), login)
+}
+
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    let ret = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    match (ret, len) {
+        (0, 1..) => String::from_utf8_lossy(&buf[..len]).into_owned(),
+        _ => "localhost".into(),
+    }
+}
+
+#[cfg(not(unix))]
+fn hostname() -> String {
+    env::var("COMPUTERNAME").unwrap_or_else(|_| "localhost".into())
+}
+
+fn unknown_identity(role: Role) -> Error {
+    let msg = format!(
+        "{} identity unknown\n\n*** Please tell me who you are.\n\nRun\n\n  git config --global user.email \"you@example.com\"\n  git config --global user.name \"Your Name\"\n\nto set your account's default identity.",
+        role.label()
+    );
+    Error::from(msg.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn resolve_with(config: &str, role: Role, vars: &[(&str, &str)]) -> Result<User> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let config = Config::parse(config).unwrap();
+        resolve(&config, role, None, |var| vars.get(var).cloned())
+    }
+
+    fn user(line: &str) -> User {
+        User::from(line.as_bytes())
+    }
+
+    #[test]
+    fn it_resolves_identity_from_config() {
+        let config = "[user]\nname = Paul Kuruvilla\nemail = rohitpaulk@gmail.com\n";
+        let vars = [("GIT_AUTHOR_DATE", "1587032850 +0530")];
+        assert_eq!(
+            resolve_with(config, Role::Author, &vars).unwrap(),
+            user("Paul Kuruvilla <rohitpaulk@gmail.com> 1587032850 +0530")
+        );
+    }
+
+    #[test]
+    fn it_prefers_environment_over_config() {
+        let config = "[user]\nname = Paul Kuruvilla\nemail = rohitpaulk@gmail.com\n";
+        let vars = [
+            ("GIT_COMMITTER_NAME", "Committer"),
+            ("GIT_COMMITTER_EMAIL", "committer@example.com"),
+            ("GIT_COMMITTER_DATE", "2005-04-07T22:13:13+02:00"),
+            ("GIT_AUTHOR_NAME", "Author"),
+        ];
+        assert_eq!(
+            resolve_with(config, Role::Committer, &vars).unwrap(),
+            user("Committer <committer@example.com> 1112904793 +0200")
+        );
+    }
+
+    #[test]
+    fn it_prefers_role_section_over_user_section() {
+        let config = "[user]\nname = User\nemail = user@example.com\n[author]\nname = Author\n";
+        let vars = [("GIT_AUTHOR_DATE", "@0 +0000")];
+        assert_eq!(
+            resolve_with(config, Role::Author, &vars).unwrap(),
+            user("Author <user@example.com> 0 +0000")
+        );
+    }
+
+    #[test]
+    fn it_fails_without_identity() {
+        assert!(resolve_with("", Role::Author, &[]).is_err());
+        let vars = [("GIT_AUTHOR_NAME", " "), ("GIT_AUTHOR_EMAIL", "a@b")];
+        assert!(resolve_with("", Role::Author, &vars).is_err());
+    }
+
+    #[test]
+    fn it_falls_back_to_the_system_user_for_reflogs() {
+        let system = ("Jane Doe".to_string(), "jane".to_string());
+        let vars = [("GIT_COMMITTER_DATE", "@0 +0000")];
+        let env = |var: &str| {
+            vars.iter()
+                .find(|(k, _)| *k == var)
+                .map(|(_, v)| v.to_string())
+        };
+
+        let config = Config::parse("").unwrap();
+        let fallback = resolve(&config, Role::Committer, Some(&system), env).unwrap();
+        assert_eq!(fallback.name(), "Jane Doe");
+        assert!(fallback.email().starts_with("jane@"));
+
+        let config = Config::parse("[user]\nemail = jane@example.com\n").unwrap();
+        assert_eq!(
+            resolve(&config, Role::Committer, Some(&system), env).unwrap(),
+            user("Jane Doe <jane@example.com> 0 +0000")
+        );
+    }
+}
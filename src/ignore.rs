@@ -0,0 +1,237 @@
+use super::{
+    config::{self, Config},
+    glob, Result, GIT_DIR,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::iter;
+use std::path::{Path, PathBuf};
+
+const GITIGNORE: &str = ".gitignore";
+const INFO_EXCLUDE: &str = "info/exclude";
+
+// NOTE:
+// A line of an exclude file. A pattern with a slash other than a trailing one
+// is matched against the path relative to the directory of its file, and one
+// without it against the name only. A trailing slash only matches directories.
+#[derive(Debug, Clone, PartialEq)]
+struct Pattern {
+    base: String,
+    pattern: String,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+impl Pattern {
+    fn parse(line: &str, base: &str) -> Option<Self> {
+        let line = trim_trailing_spaces(line);
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        if line.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            base: base.into(),
+            pattern: line.strip_prefix('/').unwrap_or(line).into(),
+            negated,
+            dir_only,
+            anchored: line.contains('/'),
+        })
+    }
+
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            let rest = match self.base.as_str() {
+                "" => Some(path),
+                base => path.strip_prefix(base).and_then(|r| r.strip_prefix('/')),
+            };
+            rest.is_some_and(|rest| glob::wildmatch(&self.pattern, rest, true))
+        } else {
+            let name = path.rsplit('/').next().unwrap_or(path);
+            glob::wildmatch(&self.pattern, name, true)
+        }
+    }
+}
+
+// NOTE:
+// The patterns deciding which untracked files are ignored. They come from
+// core.excludesFile, .git/info/exclude and the .gitignore files of the
+// working tree, each overriding the ones before, and the ones of a deeper
+// directory overriding those above it. Within a file, the last pattern that
+// matches wins. The .gitignore files are read the first time a path under
+// their directory is looked at.
+#[derive(Debug)]
+pub struct Excludes {
+    root: PathBuf,
+    global: Vec<Pattern>,
+    dirs: HashMap<String, Vec<Pattern>>,
+}
+
+impl Excludes {
+    pub fn open<P: AsRef<Path>>(root: P) -> Result<Self> {
+        let root = root.as_ref();
+        let config = Config::load(root)?;
+        let excludes_file = config
+            .get_path(root, "core.excludesFile")
+            .or_else(|| config::xdg_path("ignore"));
+
+        let mut global: Vec<Pattern> = vec![];
+        for path in excludes_file
+            .into_iter()
+            .chain([root.join(GIT_DIR).join(INFO_EXCLUDE)])
+        {
+            global.extend(read_patterns(&path, "")?);
+        }
+
+        Ok(Self {
+            root: root.into(),
+            global,
+            dirs: HashMap::new(),
+        })
+    }
+
+    // NOTE:
+    // Everything under an ignored directory is ignored, whatever the patterns
+    // say about the files in it.
+    pub fn is_ignored(&mut self, path: &str, is_dir: bool) -> Result<bool> {
+        Ok(self.ignored_path(path, is_dir)?.is_some())
+    }
+
+    // NOTE:
+    // The path itself or the directory above it that is excluded.
+    pub fn ignored_path<'a>(&mut self, path: &'a str, is_dir: bool) -> Result<Option<&'a str>> {
+        for (i, _) in path.match_indices('/') {
+            if self.is_excluded(&path[..i], true)? {
+                return Ok(Some(&path[..i]));
+            }
+        }
+        Ok(self.is_excluded(path, is_dir)?.then_some(path))
+    }
+
+    fn is_excluded(&mut self, path: &str, is_dir: bool) -> Result<bool> {
+        let dirs: Vec<&str> = iter::once("")
+            .chain(path.match_indices('/').map(|(i, _)| &path[..i]))
+            .collect();
+        for dir in dirs.iter() {
+            if !self.dirs.contains_key(*dir) {
+                let patterns = read_patterns(&self.root.join(dir).join(GITIGNORE), dir)?;
+                self.dirs.insert(dir.to_string(), patterns);
+            }
+        }
+
+        let matched = self
+            .global
+            .iter()
+            .chain(dirs.iter().flat_map(|dir| self.dirs[*dir].iter()))
+            .rev()
+            .find(|pattern| pattern.matches(path, is_dir));
+        Ok(matched.is_some_and(|pattern| !pattern.negated))
+    }
+}
+
+// NOTE:
+// A missing file has no patterns.
+fn read_patterns(path: &Path, base: &str) -> Result<Vec<Pattern>> {
+    if !path.is_file() {
+        return Ok(vec![]);
+    }
+    let text = String::from_utf8_lossy(&fs::read(path)?).to_string();
+    Ok(text
+        .lines()
+        .filter_map(|line| Pattern::parse(line, base))
+        .collect())
+}
+
+// NOTE:
+// Trailing spaces are dropped unless the last one is escaped with a backslash.
+fn trim_trailing_spaces(line: &str) -> &str {
+    let trimmed = line.trim_end_matches(' ');
+    if trimmed.len() < line.len() && trimmed.ends_with('\\') {
+        &line[..(trimmed.len() + 1)]
+    } else {
+        trimmed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn it_parses_patterns() {
+        assert_eq!(Pattern::parse("# comment", ""), None);
+        assert_eq!(Pattern::parse("   ", ""), None);
+
+        let pattern = Pattern::parse("!/build/  ", "sub").unwrap();
+        assert_eq!(
+            pattern,
+            Pattern {
+                base: "sub".into(),
+                pattern: "build".into(),
+                negated: true,
+                dir_only: true,
+                anchored: true,
+            }
+        );
+        assert_eq!(Pattern::parse("a\\ ", "").unwrap().pattern, "a\\ ");
+        assert_eq!(Pattern::parse("\\#a", "").unwrap().pattern, "\\#a");
+    }
+
+    #[test]
+    fn it_matches_names_and_anchored_paths() {
+        let name = Pattern::parse("*.log", "").unwrap();
+        assert!(name.matches("x.log", false));
+        assert!(name.matches("a/b/x.log", false));
+
+        let anchored = Pattern::parse("/doc/*.txt", "sub").unwrap();
+        assert!(anchored.matches("sub/doc/a.txt", false));
+        assert!(!anchored.matches("doc/a.txt", false));
+        assert!(!anchored.matches("sub/doc/x/a.txt", false));
+
+        let dir = Pattern::parse("target/", "").unwrap();
+        assert!(dir.matches("target", true));
+        assert!(dir.matches("a/target", true));
+        assert!(!dir.matches("target", false));
+    }
+
+    #[test]
+    fn it_ignores_paths_by_the_last_matching_pattern() {
+        let root = testing::repo("ignore-excludes");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::create_dir_all(root.join(".git/info")).unwrap();
+        fs::write(root.join(".git/info/exclude"), "*.tmp\n").unwrap();
+        fs::write(root.join(".gitignore"), "*.log\ntarget/\n").unwrap();
+        fs::write(root.join("sub/.gitignore"), "!keep.log\n*.tmp\n!keep.tmp\n").unwrap();
+
+        let mut excludes = Excludes::open(&root).unwrap();
+        assert!(excludes.is_ignored("x.log", false).unwrap());
+        assert!(excludes.is_ignored("a.tmp", false).unwrap());
+        assert!(!excludes.is_ignored("x.txt", false).unwrap());
+        assert!(!excludes.is_ignored("sub/keep.log", false).unwrap());
+        assert!(!excludes.is_ignored("sub/keep.tmp", false).unwrap());
+        assert!(excludes.is_ignored("sub/other.log", false).unwrap());
+        assert!(excludes.is_ignored("target", true).unwrap());
+        assert!(!excludes.is_ignored("target", false).unwrap());
+        assert!(excludes.is_ignored("target/keep.log", false).unwrap());
+        assert_eq!(
+            excludes.ignored_path("target/a/b", false).unwrap(),
+            Some("target")
+        );
+        assert_eq!(excludes.ignored_path("x.txt", false).unwrap(), None);
+    }
+}
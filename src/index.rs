@@ -0,0 +1,1021 @@
+use super::{
+    git_object::tree::{Mode, TreeNode},
+    refs::LockFile,
+    Error, GitObject, Result, Sha1Hash, GIT_INDEX, SHA1_HASH_SIZE,
+};
+use sha1::Digest;
+use std::collections::BTreeMap;
+use std::fs::{self, Metadata};
+use std::ops::{Deref, DerefMut};
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+const INDEX_SIGNATURE: &[u8] = b"DIRC";
+const INDEX_HEADER_SIZE: usize = 12;
+const DEFAULT_VERSION: u32 = 2;
+const SUPPORTED_VERSIONS: [u32; 3] = [2, 3, 4];
+
+const EXT_TREE: &[u8] = b"TREE";
+const EXT_REUC: &[u8] = b"REUC";
+
+// NOTE:
+// ctime, mtime, dev, ino, mode, uid, gid and size (4 bytes each) + SHA-1 + flags
+const ENTRY_FIXED_SIZE: usize = 40 + SHA1_HASH_SIZE + 2;
+
+const FLAG_EXTENDED: u16 = 0x4000;
+const MASK_STAGE: u16 = 0x3000;
+const MASK_NAME_LEN: u16 = 0x0fff;
+const EXT_FLAG_INTENT_TO_ADD: u16 = 0x2000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatData {
+    ctime: (u32, u32),
+    mtime: (u32, u32),
+    dev: u32,
+    ino: u32,
+    uid: u32,
+    gid: u32,
+    size: u32,
+}
+
+impl StatData {
+    // NOTE:
+    // The fields are truncated to 32 bits the same way git does.
+    #[cfg(unix)]
+    pub fn new(meta: &Metadata) -> Self {
+        Self {
+            ctime: (meta.ctime() as u32, meta.ctime_nsec() as u32),
+            mtime: (meta.mtime() as u32, meta.mtime_nsec() as u32),
+            dev: meta.dev() as u32,
+            ino: meta.ino() as u32,
+            uid: meta.uid(),
+            gid: meta.gid(),
+            size: meta.size() as u32,
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn new(meta: &Metadata) -> Self {
+        Self {
+            size: meta.len() as u32,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    stat: StatData,
+    mode: Mode,
+    hash: Sha1Hash,
+    flags: u16,
+    extended_flags: u16,
+    path: String,
+}
+
+impl IndexEntry {
+    pub fn new(path: String, mode: Mode, hash: Sha1Hash, meta: &Metadata) -> Self {
+        Self {
+            stat: StatData::new(meta),
+            mode,
+            hash,
+            flags: 0,
+            extended_flags: 0,
+            path,
+        }
+    }
+
+    // NOTE:
+    // An entry that is not backed by a file on disk, like the stages of an
+    // unmerged path, has empty stat data.
+    pub fn with_stage(path: String, mode: Mode, hash: Sha1Hash, stage: u8) -> Self {
+        Self {
+            stat: StatData::default(),
+            mode,
+            hash,
+            flags: (u16::from(stage) << 12) & MASK_STAGE,
+            extended_flags: 0,
+            path,
+        }
+    }
+
+    // NOTE:
+    // Like "git add -N", which records the empty blob with no stat data.
+    #[cfg(test)]
+    pub fn intent_to_add(path: String, mode: Mode) -> Self {
+        Self {
+            stat: StatData::default(),
+            mode,
+            hash: GitObject::new_blob(&b""[..]).unwrap().hash(),
+            flags: 0,
+            extended_flags: EXT_FLAG_INTENT_TO_ADD,
+            path,
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        self.path.as_str()
+    }
+
+    pub fn hash(&self) -> Sha1Hash {
+        self.hash
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn stage(&self) -> u8 {
+        ((self.flags & MASK_STAGE) >> 12) as u8
+    }
+
+    // NOTE:
+    // An entry added with "git add -N". The path is known to the index but
+    // its content isn't staged, so it's left out of the trees written from it.
+    pub fn is_intent_to_add(&self) -> bool {
+        self.extended_flags & EXT_FLAG_INTENT_TO_ADD == EXT_FLAG_INTENT_TO_ADD
+    }
+
+    fn sort_key(&self) -> (&[u8], u8) {
+        (self.path.as_bytes(), self.stage())
+    }
+
+    fn parse(bytes: &[u8], version: u32, prev_path: &str) -> Result<(Self, usize)> {
+        let fixed = bytes
+            .get(..ENTRY_FIXED_SIZE)
+            .ok_or(Error::from("Index entry is truncated"))?;
+        let word = |i: usize| u32::from_be_bytes(fixed[(i * 4)..(i * 4 + 4)].try_into().unwrap());
+
+        let stat = StatData {
+            ctime: (word(0), word(1)),
+            mtime: (word(2), word(3)),
+            dev: word(4),
+            ino: word(5),
+            uid: word(7),
+            gid: word(8),
+            size: word(9),
+        };
+        let mode = Mode::try_from(word(6))?;
+        let hash = Sha1Hash::try_from(&fixed[40..60])?;
+        let flags = u16::from_be_bytes([fixed[60], fixed[61]]);
+        let mut pos = ENTRY_FIXED_SIZE;
+
+        let extended_flags = if flags & FLAG_EXTENDED == FLAG_EXTENDED {
+            if version < 3 {
+                return Err(Error::from("Extended flags in index version 2"));
+            }
+            let buf = bytes
+                .get(pos..(pos + 2))
+                .ok_or(Error::from("Index entry is truncated"))?;
+            pos += 2;
+            u16::from_be_bytes([buf[0], buf[1]])
+        } else {
+            0
+        };
+
+        let path = if version == 4 {
+            // NOTE:
+            // In version 4, the path is stored as the number of bytes to strip
+            // from the previous path and the NUL-terminated suffix to append.
+            let (strip, n) = read_varint(&bytes[pos..])?;
+            pos += n;
+            let suffix_len = nul_position(&bytes[pos..])?;
+            let keep = prev_path
+                .len()
+                .checked_sub(strip)
+                .ok_or(Error::from("Invalid path compression in index entry"))?;
+            let mut path = prev_path.as_bytes()[..keep].to_vec();
+            path.extend_from_slice(&bytes[pos..(pos + suffix_len)]);
+            pos += suffix_len + 1;
+            String::from_utf8_lossy(&path).to_string()
+        } else {
+            // NOTE:
+            // In versions 2 and 3, the path is padded with 1-8 NUL bytes
+            // so that the entry size is a multiple of 8.
+            let path_len = nul_position(&bytes[pos..])?;
+            let path = String::from_utf8_lossy(&bytes[pos..(pos + path_len)]).to_string();
+            pos = padded_entry_size(pos + path_len);
+            path
+        };
+
+        // NOTE:
+        // The name length and the extended bit are derived from the entry when written.
+        let entry = Self {
+            stat,
+            mode,
+            hash,
+            flags: flags & !(MASK_NAME_LEN | FLAG_EXTENDED),
+            extended_flags,
+            path,
+        };
+        Ok((entry, pos))
+    }
+
+    fn serialize(&self, version: u32, prev_path: &str) -> Vec<u8> {
+        let mut buf: Vec<u8> = Vec::with_capacity(ENTRY_FIXED_SIZE + self.path.len() + 8);
+        let StatData {
+            ctime,
+            mtime,
+            dev,
+            ino,
+            uid,
+            gid,
+            size,
+        } = self.stat;
+
+        for word in [
+            ctime.0,
+            ctime.1,
+            mtime.0,
+            mtime.1,
+            dev,
+            ino,
+            u32::from(self.mode),
+            uid,
+            gid,
+            size,
+        ] {
+            buf.extend_from_slice(&word.to_be_bytes());
+        }
+        buf.extend_from_slice(self.hash.as_bytes());
+
+        let name_len = self.path.len().min(MASK_NAME_LEN as usize) as u16;
+        let mut flags = (self.flags & !MASK_NAME_LEN & !FLAG_EXTENDED) | name_len;
+        if self.extended_flags != 0 {
+            flags |= FLAG_EXTENDED;
+        }
+        buf.extend_from_slice(&flags.to_be_bytes());
+        if self.extended_flags != 0 {
+            buf.extend_from_slice(&self.extended_flags.to_be_bytes());
+        }
+
+        if version == 4 {
+            let common = common_prefix_len(prev_path.as_bytes(), self.path.as_bytes());
+            buf.extend(write_varint(prev_path.len() - common));
+            buf.extend_from_slice(&self.path.as_bytes()[common..]);
+            buf.push(0);
+        } else {
+            buf.extend_from_slice(self.path.as_bytes());
+            buf.resize(padded_entry_size(buf.len()), 0);
+        }
+
+        buf
+    }
+}
+
+// NOTE:
+// The TREE extension caches the tree objects of the index. Each node has the
+// number of index entries it covers (-1 when invalidated) and its subtrees,
+// stored in pre-order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheTree {
+    name: String,
+    entry_count: i32,
+    hash: Option<Sha1Hash>,
+    children: Vec<CacheTree>,
+}
+
+impl CacheTree {
+    fn parse(bytes: &[u8]) -> Result<(Self, usize)> {
+        let name_len = nul_position(bytes)?;
+        let name = String::from_utf8_lossy(&bytes[..name_len]).to_string();
+        let mut pos = name_len + 1;
+
+        let line_len = bytes[pos..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or(Error::from("Invalid TREE extension"))?;
+        let line = String::from_utf8_lossy(&bytes[pos..(pos + line_len)]).to_string();
+        pos += line_len + 1;
+
+        let (entry_count, subtrees) = line
+            .split_once(' ')
+            .ok_or(Error::from("Invalid TREE extension"))?;
+        let entry_count = entry_count.parse::<i32>()?;
+        let subtrees = subtrees.parse::<usize>()?;
+
+        let hash = if entry_count >= 0 {
+            let hash = bytes
+                .get(pos..(pos + SHA1_HASH_SIZE))
+                .ok_or(Error::from("Invalid TREE extension"))?;
+            pos += SHA1_HASH_SIZE;
+            Some(Sha1Hash::try_from(hash)?)
+        } else {
+            None
+        };
+
+        let mut children: Vec<CacheTree> = Vec::with_capacity(subtrees);
+        for _ in 0..subtrees {
+            let (child, n) = Self::parse(&bytes[pos..])?;
+            pos += n;
+            children.push(child);
+        }
+
+        let tree = Self {
+            name,
+            entry_count,
+            hash,
+            children,
+        };
+        Ok((tree, pos))
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut buf: Vec<u8> = vec![];
+        buf.extend_from_slice(self.name.as_bytes());
+        buf.push(0);
+        buf.extend(format!("{} {}\n", self.entry_count, self.children.len()).into_bytes());
+        if let Some(hash) = self.hash.filter(|_| self.entry_count >= 0) {
+            buf.extend_from_slice(hash.as_bytes());
+        }
+        for child in self.children.iter() {
+            buf.extend(child.serialize());
+        }
+        buf
+    }
+}
+
+// NOTE:
+// The REUC extension remembers the higher stages of a path that was resolved,
+// so that the conflict can be recreated. A zero mode means the stage was missing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolveUndo {
+    path: String,
+    modes: [u32; 3],
+    hashes: [Option<Sha1Hash>; 3],
+}
+
+impl ResolveUndo {
+    fn parse(bytes: &[u8]) -> Result<(Self, usize)> {
+        let path_len = nul_position(bytes)?;
+        let path = String::from_utf8_lossy(&bytes[..path_len]).to_string();
+        let mut pos = path_len + 1;
+
+        let mut modes = [0u32; 3];
+        for mode in modes.iter_mut() {
+            let len = nul_position(&bytes[pos..])?;
+            let value = String::from_utf8_lossy(&bytes[pos..(pos + len)]).to_string();
+            *mode = u32::from_str_radix(&value, 8)?;
+            pos += len + 1;
+        }
+
+        let mut hashes: [Option<Sha1Hash>; 3] = [None; 3];
+        for (i, mode) in modes.iter().enumerate() {
+            if *mode != 0 {
+                let hash = bytes
+                    .get(pos..(pos + SHA1_HASH_SIZE))
+                    .ok_or(Error::from("Invalid REUC extension"))?;
+                hashes[i] = Some(Sha1Hash::try_from(hash)?);
+                pos += SHA1_HASH_SIZE;
+            }
+        }
+
+        let undo = Self {
+            path,
+            modes,
+            hashes,
+        };
+        Ok((undo, pos))
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut buf: Vec<u8> = vec![];
+        buf.extend_from_slice(self.path.as_bytes());
+        buf.push(0);
+        for mode in self.modes {
+            buf.extend(format!("{mode:o}").into_bytes());
+            buf.push(0);
+        }
+        for hash in self.hashes.iter().flatten() {
+            buf.extend_from_slice(hash.as_bytes());
+        }
+        buf
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index {
+    version: u32,
+    entries: Vec<IndexEntry>,
+    tree: Option<CacheTree>,
+    resolve_undo: Vec<ResolveUndo>,
+    mtime: Option<(u32, u32)>,
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Self {
+            version: DEFAULT_VERSION,
+            entries: vec![],
+            tree: None,
+            resolve_undo: vec![],
+            mtime: None,
+        }
+    }
+}
+
+impl Index {
+    pub fn exists<P: AsRef<Path>>(root: P) -> bool {
+        root.as_ref().join(GIT_INDEX).is_file()
+    }
+
+    // NOTE:
+    // A missing index file is the same as an empty index.
+    pub fn open<P: AsRef<Path>>(root: P) -> Result<Self> {
+        let path = root.as_ref().join(GIT_INDEX);
+        if !path.try_exists()? {
+            return Ok(Self::default());
+        }
+        let mut index = Self::from_bytes(&fs::read(&path)?)?;
+        index.mtime = Some(StatData::new(&fs::metadata(path)?).mtime);
+        Ok(index)
+    }
+
+    // NOTE:
+    // Reads the index holding "index.lock", for the commands that update it.
+    // Another process can't write the index until the lock is committed or
+    // dropped, so no change made in between is lost.
+    pub fn lock<P: AsRef<Path>>(root: P) -> Result<LockedIndex> {
+        let root = root.as_ref();
+        let lock = LockFile::acquire(root.join(GIT_INDEX))?;
+        let index = Self::open(root)?;
+        Ok(LockedIndex { index, lock })
+    }
+
+    // NOTE:
+    // The index is written to "index.lock" first and renamed over the index,
+    // so that readers never see a partially written file.
+    pub fn write<P: AsRef<Path>>(&self, root: P) -> Result<()> {
+        let mut lock = LockFile::acquire(root.as_ref().join(GIT_INDEX))?;
+        lock.write(&self.serialize())?;
+        lock.commit()
+    }
+
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    // NOTE:
+    // The file is unchanged if its stat data is the same as when it was staged.
+    // A file modified in the same second the index was written can still have
+    // the same stat data ("racy git"), so it's never trusted and has to be
+    // compared by content.
+    pub fn is_up_to_date(&self, entry: &IndexEntry, meta: &Metadata) -> bool {
+        let racy = !self.mtime.is_some_and(|mtime| entry.stat.mtime < mtime);
+        !racy && entry.stat == StatData::new(meta)
+    }
+
+    // NOTE:
+    // Records the stat data of a file whose content was found unchanged, so
+    // that it doesn't have to be hashed again.
+    pub fn refresh(&mut self, path: &str, meta: &Metadata) {
+        if let Some(entry) = self
+            .entries
+            .iter_mut()
+            .find(|e| e.path == path && e.stage() == 0)
+        {
+            entry.stat = StatData::new(meta);
+        }
+    }
+
+    pub fn is_unmerged(&self) -> bool {
+        self.entries.iter().any(|e| e.stage() != 0)
+    }
+
+    // NOTE:
+    // Adding an entry at stage 0 resolves any conflict on the path.
+    // A file replaces a directory of the same name and vice versa.
+    pub fn add(&mut self, entry: IndexEntry) {
+        self.invalidate(&entry.path);
+        if entry.stage() == 0 {
+            let dir = format!("{}/", entry.path);
+            self.entries.retain(|e| {
+                e.path != entry.path
+                    && !e.path.starts_with(&dir)
+                    && !entry.path.starts_with(&format!("{}/", e.path))
+            });
+        }
+        match self
+            .entries
+            .binary_search_by(|e| e.sort_key().cmp(&entry.sort_key()))
+        {
+            Ok(i) => self.entries[i] = entry,
+            Err(i) => self.entries.insert(i, entry),
+        }
+    }
+
+    pub fn remove(&mut self, path: &str) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|e| e.path != path);
+        if self.entries.len() != len {
+            self.invalidate(path);
+            true
+        } else {
+            false
+        }
+    }
+
+    // NOTE:
+    // Writes the tree objects for the stage 0 entries and returns the root tree.
+    // The TREE extension is refreshed with the trees written here.
+    pub fn write_tree<P: AsRef<Path>>(&mut self, root: P) -> Result<GitObject> {
+        if self.is_unmerged() {
+            return Err(Error::from("Cannot write a tree from an unmerged index"));
+        }
+
+        let entries: Vec<&IndexEntry> = self.entries.iter().collect();
+        let (object, tree) = build_tree(root.as_ref(), "", &entries)?;
+        self.tree = Some(tree);
+        Ok(object)
+    }
+
+    fn invalidate(&mut self, path: &str) {
+        if let Some(tree) = self.tree.as_mut() {
+            let mut node = tree;
+            node.entry_count = -1;
+            for component in path.split('/') {
+                match node.children.iter_mut().position(|c| c.name == component) {
+                    Some(i) => {
+                        node = &mut node.children[i];
+                        node.entry_count = -1;
+                    }
+                    None => break,
+                }
+            }
+        }
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < INDEX_HEADER_SIZE + SHA1_HASH_SIZE || !bytes.starts_with(INDEX_SIGNATURE) {
+            return Err(Error::from("Invalid index file signature"));
+        }
+
+        let content_len = bytes.len() - SHA1_HASH_SIZE;
+        let content = &bytes[..content_len];
+        let mut hasher = Sha1Hash::hasher();
+        hasher.update(content);
+        if Sha1Hash::new(hasher).as_bytes() != &bytes[content_len..] {
+            return Err(Error::from("Index file checksum mismatch"));
+        }
+
+        let version = u32::from_be_bytes(bytes[4..8].try_into()?);
+        if !SUPPORTED_VERSIONS.contains(&version) {
+            let msg = format!("Unsupported index file version {version}");
+            return Err(Error::from(msg.as_str()));
+        }
+        let num_entries = u32::from_be_bytes(bytes[8..12].try_into()?) as usize;
+
+        let mut index = Self {
+            version,
+            ..Self::default()
+        };
+        let mut pos = INDEX_HEADER_SIZE;
+        let mut prev_path = String::new();
+
+        for _ in 0..num_entries {
+            let (entry, n) = IndexEntry::parse(&content[pos..], version, &prev_path)?;
+            pos += n;
+            prev_path = entry.path.clone();
+            index.entries.push(entry);
+        }
+
+        while pos + 8 <= content_len {
+            let signature = &content[pos..(pos + 4)];
+            let size = u32::from_be_bytes(content[(pos + 4)..(pos + 8)].try_into()?) as usize;
+            let data = content
+                .get((pos + 8)..(pos + 8 + size))
+                .ok_or(Error::from("Index extension is truncated"))?;
+
+            match signature {
+                EXT_TREE => {
+                    let (tree, _) = CacheTree::parse(data)?;
+                    index.tree = Some(tree);
+                }
+                EXT_REUC => {
+                    let mut i = 0;
+                    while i < data.len() {
+                        let (undo, n) = ResolveUndo::parse(&data[i..])?;
+                        i += n;
+                        index.resolve_undo.push(undo);
+                    }
+                }
+                // NOTE:
+                // Extensions starting with an uppercase letter are optional.
+                [b'A'..=b'Z', ..] => {}
+                _ => {
+                    let msg = format!(
+                        "Unsupported index extension {}",
+                        String::from_utf8_lossy(signature)
+                    );
+                    return Err(Error::from(msg.as_str()));
+                }
+            }
+
+            pos += 8 + size;
+        }
+
+        Ok(index)
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let needs_extended = self.entries.iter().any(|e| e.extended_flags != 0);
+        let version = if needs_extended && self.version < 3 {
+            3
+        } else {
+            self.version
+        };
+
+        let mut buf: Vec<u8> = vec![];
+        buf.extend_from_slice(INDEX_SIGNATURE);
+        buf.extend_from_slice(&version.to_be_bytes());
+        buf.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+
+        let mut prev_path = "";
+        for entry in self.entries.iter() {
+            buf.extend(entry.serialize(version, prev_path));
+            prev_path = entry.path.as_str();
+        }
+
+        if let Some(tree) = self.tree.as_ref() {
+            write_extension(&mut buf, EXT_TREE, &tree.serialize());
+        }
+
+        if !self.resolve_undo.is_empty() {
+            let data: Vec<u8> = self
+                .resolve_undo
+                .iter()
+                .flat_map(ResolveUndo::serialize)
+                .collect();
+            write_extension(&mut buf, EXT_REUC, &data);
+        }
+
+        let mut hasher = Sha1Hash::hasher();
+        hasher.update(&buf);
+        buf.extend_from_slice(Sha1Hash::new(hasher).as_bytes());
+        buf
+    }
+}
+
+// NOTE:
+// A tree covering intent-to-add entries isn't what the index would give once
+// they are staged, so its TREE extension node is left invalid. A directory
+// with nothing but intent-to-add entries is left out altogether.
+fn build_tree(root: &Path, name: &str, entries: &[&IndexEntry]) -> Result<(GitObject, CacheTree)> {
+    let prefix = if name.is_empty() {
+        String::new()
+    } else {
+        format!("{name}/")
+    };
+
+    let mut nodes: Vec<TreeNode> = vec![];
+    let mut children: Vec<CacheTree> = vec![];
+    let mut subdirs: BTreeMap<&str, Vec<&IndexEntry>> = BTreeMap::new();
+
+    for entry in entries {
+        let rest = &entry.path[prefix.len()..];
+        match rest.split_once('/') {
+            Some((dir, _)) => subdirs.entry(dir).or_default().push(entry),
+            None if entry.is_intent_to_add() => {}
+            None => nodes.push(TreeNode::from_parts(entry.mode, rest.into(), entry.hash)),
+        }
+    }
+
+    for (dir, sub_entries) in subdirs {
+        let path = format!("{prefix}{dir}");
+        let (object, mut cache) = build_tree(root, &path, &sub_entries)?;
+        cache.name = dir.into();
+        if !matches!(&object, GitObject::Tree(nodes) if nodes.is_empty()) {
+            nodes.push(TreeNode::from_parts(
+                Mode::Directory,
+                dir.into(),
+                object.hash(),
+            ));
+        }
+        children.push(cache);
+    }
+
+    nodes.sort();
+    let object = GitObject::Tree(nodes);
+    object.write(root)?;
+
+    let entry_count = match entries.iter().any(|e| e.is_intent_to_add()) {
+        true => -1,
+        false => entries.len() as i32,
+    };
+    let cache = CacheTree {
+        name: String::new(),
+        entry_count,
+        hash: Some(object.hash()),
+        children,
+    };
+    Ok((object, cache))
+}
+
+#[derive(Debug)]
+pub struct LockedIndex {
+    index: Index,
+    lock: LockFile,
+}
+
+impl LockedIndex {
+    // NOTE:
+    // Writes the index and releases the lock. The index is given back for
+    // the commands that go on reading it.
+    pub fn commit(mut self) -> Result<Index> {
+        self.lock.write(&self.index.serialize())?;
+        self.lock.commit()?;
+        Ok(self.index)
+    }
+}
+
+impl Deref for LockedIndex {
+    type Target = Index;
+
+    fn deref(&self) -> &Index {
+        &self.index
+    }
+}
+
+impl DerefMut for LockedIndex {
+    fn deref_mut(&mut self) -> &mut Index {
+        &mut self.index
+    }
+}
+
+fn write_extension(buf: &mut Vec<u8>, signature: &[u8], data: &[u8]) {
+    buf.extend_from_slice(signature);
+    buf.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    buf.extend_from_slice(data);
+}
+
+fn padded_entry_size(len: usize) -> usize {
+    (len + 8) & !7
+}
+
+fn nul_position(bytes: &[u8]) -> Result<usize> {
+    bytes
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or(Error::from("Not found NUL in index file"))
+}
+
+fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+// NOTE:
+// The same variable length integer as the ofs-delta offset in pack files.
+fn read_varint(bytes: &[u8]) -> Result<(usize, usize)> {
+    let mut iter = bytes.iter();
+    let mut byte = *iter.next().ok_or(Error::from("Index entry is truncated"))?;
+    let mut value = (byte & 0x7f) as usize;
+    let mut n = 1;
+
+    while byte & 0x80 == 0x80 {
+        byte = *iter.next().ok_or(Error::from("Index entry is truncated"))?;
+        value = ((value + 1) << 7) | (byte & 0x7f) as usize;
+        n += 1;
+    }
+
+    Ok((value, n))
+}
+
+fn write_varint(mut value: usize) -> Vec<u8> {
+    let mut buf = vec![(value & 0x7f) as u8];
+    value >>= 7;
+    while value > 0 {
+        value -= 1;
+        buf.push(0x80 | (value & 0x7f) as u8);
+        value >>= 7;
+    }
+    buf.reverse();
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn entry(path: &str, hash: u8) -> IndexEntry {
+        IndexEntry {
+            stat: StatData {
+                ctime: (1, 2),
+                mtime: (3, 4),
+                dev: 5,
+                ino: 6,
+                uid: 7,
+                gid: 8,
+                size: 9,
+            },
+            mode: Mode::File,
+            hash: [hash; 20].into(),
+            flags: 0,
+            extended_flags: 0,
+            path: path.into(),
+        }
+    }
+
+    fn index(version: u32, entries: Vec<IndexEntry>) -> Index {
+        Index {
+            version,
+            entries,
+            ..Index::default()
+        }
+    }
+
+    #[test]
+    fn it_encodes_and_decodes_varint() {
+        for value in [0, 1, 127, 128, 255, 16511, 16512, 1 << 20] {
+            let bytes = write_varint(value);
+            assert_eq!(read_varint(&bytes).unwrap(), (value, bytes.len()));
+        }
+        assert_eq!(write_varint(128), vec![0x80, 0x00]);
+    }
+
+    #[test]
+    fn it_serializes_entry_with_padding() {
+        let bytes = entry("abc", 1).serialize(2, "");
+        assert_eq!(bytes.len(), 72);
+        assert_eq!(&bytes[60..62], &[0x00, 0x03]);
+        assert_eq!(&bytes[62..65], b"abc");
+        assert!(bytes[65..].iter().all(|&b| b == 0));
+
+        // NOTE:
+        // An entry is always padded with at least one NUL byte.
+        let bytes = entry("abcdefghij", 1).serialize(2, "");
+        assert_eq!(bytes.len(), 80);
+    }
+
+    #[test]
+    fn it_roundtrips_index_versions() {
+        for version in [2, 3, 4] {
+            let mut entries = vec![entry("dir/a.txt", 1), entry("dir/b.txt", 2), entry("z", 3)];
+            if version == 3 {
+                entries[1].extended_flags = 0x2000;
+            }
+            let idx = index(version, entries);
+            let parsed = Index::from_bytes(&idx.serialize()).unwrap();
+            assert_eq!(parsed, idx);
+        }
+    }
+
+    #[test]
+    fn it_compresses_paths_in_version_4() {
+        let idx = index(4, vec![entry("dir/a.txt", 1), entry("dir/b.txt", 2)]);
+        let bytes = idx.serialize();
+        let second = &bytes[(INDEX_HEADER_SIZE + ENTRY_FIXED_SIZE + 1 + 10)..];
+        assert_eq!(
+            &second[ENTRY_FIXED_SIZE..(ENTRY_FIXED_SIZE + 7)],
+            b"\x05b.txt\0"
+        );
+    }
+
+    #[test]
+    fn it_roundtrips_extensions() {
+        let mut idx = index(2, vec![entry("a", 1)]);
+        idx.tree = Some(CacheTree {
+            name: String::new(),
+            entry_count: 2,
+            hash: Some([9; 20].into()),
+            children: vec![CacheTree {
+                name: "dir".into(),
+                entry_count: -1,
+                hash: None,
+                children: vec![],
+            }],
+        });
+        idx.resolve_undo = vec![ResolveUndo {
+            path: "conflict.txt".into(),
+            modes: [0o100644, 0, 0o100755],
+            hashes: [Some([1; 20].into()), None, Some([3; 20].into())],
+        }];
+        let parsed = Index::from_bytes(&idx.serialize()).unwrap();
+        assert_eq!(parsed, idx);
+    }
+
+    #[test]
+    fn it_rejects_broken_checksum() {
+        let mut bytes = index(2, vec![entry("a", 1)]).serialize();
+        let len = bytes.len();
+        bytes[len - 1] ^= 0xff;
+        assert!(Index::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn it_distrusts_racily_clean_entries() {
+        let root = testing::TempDir::new("index-racy");
+        let path = root.join("a");
+        fs::write(&path, "a").unwrap();
+        let meta = fs::symlink_metadata(&path).unwrap();
+
+        let mut idx = index(2, vec![]);
+        idx.add(IndexEntry::new(
+            "a".into(),
+            Mode::File,
+            [1; 20].into(),
+            &meta,
+        ));
+        assert!(!idx.is_up_to_date(&idx.entries()[0], &meta));
+
+        idx.mtime = Some((u32::MAX, 0));
+        assert!(idx.is_up_to_date(&idx.entries()[0], &meta));
+        idx.entries[0].stat.size += 1;
+        assert!(!idx.is_up_to_date(&idx.entries()[0], &meta));
+        idx.refresh("a", &meta);
+        assert!(idx.is_up_to_date(&idx.entries()[0], &meta));
+    }
+
+    #[test]
+    fn it_keeps_entries_sorted() {
+        let mut idx = Index::default();
+        idx.add(entry("b", 1));
+        idx.add(entry("a/c", 2));
+        idx.add(entry("a-b", 3));
+        idx.add(entry("b", 4));
+        let paths: Vec<&str> = idx.entries().iter().map(IndexEntry::path).collect();
+        assert_eq!(paths, vec!["a-b", "a/c", "b"]);
+        assert_eq!(idx.entries()[2].hash, [4; 20].into());
+
+        assert!(idx.remove("a/c"));
+        assert!(!idx.remove("a/c"));
+        assert_eq!(idx.entries().len(), 2);
+    }
+
+    #[test]
+    fn it_replaces_directory_and_file_entries() {
+        let mut idx = Index::default();
+        idx.add(entry("a/b", 1));
+        idx.add(entry("a/c", 2));
+        idx.add(entry("a", 3));
+        let paths: Vec<&str> = idx.entries().iter().map(IndexEntry::path).collect();
+        assert_eq!(paths, vec!["a"]);
+
+        idx.add(entry("a/d", 4));
+        let paths: Vec<&str> = idx.entries().iter().map(IndexEntry::path).collect();
+        assert_eq!(paths, vec!["a/d"]);
+    }
+
+    #[test]
+    fn it_leaves_intent_to_add_entries_out_of_trees() {
+        let root = testing::repo("index-intent-to-add");
+        let mut idx = index(2, vec![entry("a", 1)]);
+        let expected = idx.write_tree(&root).unwrap().hash();
+
+        idx.add(IndexEntry::intent_to_add("d/f".into(), Mode::File));
+        idx.add(IndexEntry::intent_to_add("e".into(), Mode::File));
+        assert_eq!(idx.write_tree(&root).unwrap().hash(), expected);
+        assert_eq!(idx.tree.as_ref().unwrap().entry_count, -1);
+
+        let idx = Index::from_bytes(&idx.serialize()).unwrap();
+        assert!(idx.entries()[1].is_intent_to_add());
+        assert_eq!(idx.version, 3);
+    }
+
+    #[test]
+    fn it_holds_the_lock_while_the_index_is_updated() {
+        let root = testing::repo("index-lock");
+        index(2, vec![entry("a", 1)]).write(&root).unwrap();
+
+        let mut locked = Index::lock(&root).unwrap();
+        assert!(Index::lock(&root).is_err());
+        assert!(index(2, vec![]).write(&root).is_err());
+        locked.add(entry("b", 2));
+        locked.commit().unwrap();
+
+        let paths: Vec<String> = Index::open(&root)
+            .unwrap()
+            .entries()
+            .iter()
+            .map(|e| e.path().to_string())
+            .collect();
+        assert_eq!(paths, vec!["a", "b"]);
+
+        let lock = root.join(".git/index.lock");
+        fs::write(&lock, "").unwrap();
+        assert!(Index::lock(&root).is_err());
+        assert!(index(2, vec![]).write(&root).is_err());
+        assert!(lock.exists());
+    }
+
+    #[test]
+    fn it_invalidates_cache_tree() {
+        let mut idx = index(2, vec![entry("dir/a", 1)]);
+        idx.tree = Some(CacheTree {
+            name: String::new(),
+            entry_count: 1,
+            hash: Some([9; 20].into()),
+            children: vec![CacheTree {
+                name: "dir".into(),
+                entry_count: 1,
+                hash: Some([8; 20].into()),
+                children: vec![],
+            }],
+        });
+        idx.add(entry("dir/b", 2));
+        let tree = idx.tree.as_ref().unwrap();
+        assert_eq!(tree.entry_count, -1);
+        assert_eq!(tree.children[0].entry_count, -1);
+    }
+}
@@ -1,13 +1,36 @@
 mod args;
+mod checkout;
 mod cmd;
+mod config;
+mod date;
+mod diff;
+mod editor;
 mod error;
 mod git_object;
 mod git_protocol;
+mod glob;
+mod graph;
 mod hash;
-mod tree;
+mod history;
+mod ident;
+mod ignore;
+mod index;
+mod merge;
+mod odb;
+mod pretty;
+mod reflog;
+mod refs;
+mod revision;
+mod sequencer;
+mod status;
+#[cfg(test)]
+mod testing;
+mod worktree;
 
 const GIT_DIR: &str = ".git";
+const GIT_INDEX: &str = ".git/index";
 const GIT_OBJ_DIR: &str = ".git/objects";
+const GIT_PACK_DIR: &str = ".git/objects/pack";
 const GIT_REF_DIR: &str = ".git/refs";
 
 use args::Args;
@@ -0,0 +1,714 @@
+use super::{
+    checkout::{self, Entry, Operation},
+    diff::{
+        self,
+        files::{self, Source},
+        rename::{self, Detection},
+        Algorithm, Edit,
+    },
+    git_object::tree::Mode,
+    index::{Index, IndexEntry},
+    Error, GitObject, Result, GIT_DIR,
+};
+use std::collections::BTreeMap;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+pub const MERGE_HEAD: &str = "MERGE_HEAD";
+pub const MERGE_MSG: &str = "MERGE_MSG";
+pub const MERGE_MODE: &str = "MERGE_MODE";
+pub const ORIG_HEAD: &str = "ORIG_HEAD";
+
+const MARKER_SIZE: usize = 7;
+
+// NOTE:
+// Conflicts at most this many lines apart are shown as one.
+const CONFLICT_GAP: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Ours,
+    Theirs,
+    Conflict,
+}
+
+// NOTE:
+// A change from the common ancestor, as the lines it spans in ours and in
+// theirs, and the side it is taken from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Chunk {
+    side: Side,
+    ours: Range<usize>,
+    theirs: Range<usize>,
+}
+
+// NOTE:
+// How a path ends up after merging the trees. A conflict keeps the common
+// ancestor, ours and theirs as stages 1 to 3, and the version left in the
+// working tree, which has the conflict markers for a content conflict.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolution {
+    Clean(Option<Entry>),
+    Conflict {
+        stages: [Option<Entry>; 3],
+        worktree: Option<Entry>,
+    },
+}
+
+impl Resolution {
+    pub fn worktree(&self) -> Option<Entry> {
+        match self {
+            Self::Clean(entry) => *entry,
+            Self::Conflict { worktree, .. } => *worktree,
+        }
+    }
+}
+
+// NOTE:
+// The result of a three-way merge of trees, with the "Auto-merging" and
+// "CONFLICT" messages for the paths in order.
+#[derive(Debug, Default)]
+pub struct TreeMerge {
+    paths: BTreeMap<String, Resolution>,
+    messages: Vec<String>,
+}
+
+impl TreeMerge {
+    #[cfg(test)]
+    pub fn paths(&self) -> &BTreeMap<String, Resolution> {
+        &self.paths
+    }
+
+    pub fn messages(&self) -> &[String] {
+        &self.messages
+    }
+
+    pub fn conflicts(&self) -> Vec<&str> {
+        self.paths
+            .iter()
+            .filter(|(_, resolution)| matches!(resolution, Resolution::Conflict { .. }))
+            .map(|(path, _)| path.as_str())
+            .collect()
+    }
+
+    // NOTE:
+    // Writes the tree of the result, with the working tree version of the
+    // conflicts, like git does for the virtual ancestor of a criss-cross merge.
+    pub fn write_tree<P: AsRef<Path>>(&self, root: P) -> Result<String> {
+        let mut index = Index::default();
+        for (path, resolution) in self.paths.iter() {
+            if let Some((mode, hash)) = resolution.worktree() {
+                index.add(IndexEntry::with_stage(path.clone(), mode, hash, 0));
+            }
+        }
+        Ok(index.write_tree(root)?.hash().hex())
+    }
+}
+
+// NOTE:
+// Merges the changes from `base` to `ours` and from `base` to `theirs` line by
+// line with histogram diffs. Changes that overlap or touch conflict unless
+// they are the same, and are written between conflict markers named after
+// `labels`, leaving out the lines at their ends that both sides agree on.
+// Returns the merged content and whether it has conflicts.
+pub fn merge_content(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    labels: (&str, &str),
+) -> (Vec<u8>, bool) {
+    let (base, ours, theirs) = (diff::lines(base), diff::lines(ours), diff::lines(theirs));
+    let mut content: Vec<u8> = vec![];
+    let mut conflicted = false;
+    let mut pos = 0;
+    for chunk in refine(&ours, &theirs, chunks(&base, &ours, &theirs)) {
+        content.extend(ours[pos..chunk.ours.start].concat());
+        match chunk.side {
+            Side::Ours => content.extend(ours[chunk.ours.clone()].concat()),
+            Side::Theirs => content.extend(theirs[chunk.theirs.clone()].concat()),
+            Side::Conflict => {
+                conflicted = true;
+                push_marker(&mut content, b'<', labels.0);
+                push_conflict_side(&mut content, &ours[chunk.ours.clone()]);
+                push_marker(&mut content, b'=', "");
+                push_conflict_side(&mut content, &theirs[chunk.theirs.clone()]);
+                push_marker(&mut content, b'>', labels.1);
+            }
+        }
+        pos = chunk.ours.end;
+    }
+    content.extend(ours[pos..].concat());
+    (content, conflicted)
+}
+
+// NOTE:
+// Groups the edits of both sides that overlap or touch in the common
+// ancestor, and finds the lines each group spans on both sides.
+fn chunks(base: &[&[u8]], ours: &[&[u8]], theirs: &[&[u8]]) -> Vec<Chunk> {
+    let ours_edits = diff::diff(base, ours, Algorithm::Histogram);
+    let theirs_edits = diff::diff(base, theirs, Algorithm::Histogram);
+    let (mut i, mut j) = (0, 0);
+    let (mut ours_delta, mut theirs_delta) = (0, 0);
+    let mut chunks: Vec<Chunk> = vec![];
+    loop {
+        let start = match (ours_edits.get(i), theirs_edits.get(j)) {
+            (Some(a), Some(b)) => a.old.start.min(b.old.start),
+            (Some(a), None) => a.old.start,
+            (None, Some(b)) => b.old.start,
+            (None, None) => break,
+        };
+        let (first_i, first_j) = (i, j);
+        let mut end = start;
+        loop {
+            if let Some(edit) = ours_edits.get(i).filter(|e| e.old.start <= end) {
+                end = end.max(edit.old.end);
+                i += 1;
+            } else if let Some(edit) = theirs_edits.get(j).filter(|e| e.old.start <= end) {
+                end = end.max(edit.old.end);
+                j += 1;
+            } else {
+                break;
+            }
+        }
+
+        let ours_range = side_range(&ours_edits[first_i..i], start..end, ours_delta);
+        let theirs_range = side_range(&theirs_edits[first_j..j], start..end, theirs_delta);
+        ours_delta = ours_range.end as isize - end as isize;
+        theirs_delta = theirs_range.end as isize - end as isize;
+        let side = if first_j == j || ours[ours_range.clone()] == theirs[theirs_range.clone()] {
+            Side::Ours
+        } else if first_i == i {
+            Side::Theirs
+        } else {
+            Side::Conflict
+        };
+        chunks.push(Chunk {
+            side,
+            ours: ours_range,
+            theirs: theirs_range,
+        });
+    }
+    chunks
+}
+
+// NOTE:
+// The lines of one side for the `base` lines, given the edits of that side
+// within them and how far the side was shifted by its earlier edits.
+fn side_range(edits: &[Edit], base: Range<usize>, delta: isize) -> Range<usize> {
+    match (edits.first(), edits.last()) {
+        (Some(first), Some(last)) => {
+            (first.new.start - (first.old.start - base.start))
+                ..(last.new.end + (base.end - last.old.end))
+        }
+        _ => base.start.saturating_add_signed(delta)..base.end.saturating_add_signed(delta),
+    }
+}
+
+// NOTE:
+// Narrows the conflicts down to the lines where the two sides differ, and
+// joins the ones that end up only a few lines apart again, like git's
+// "zealous" merge level.
+fn refine(ours: &[&[u8]], theirs: &[&[u8]], chunks: Vec<Chunk>) -> Vec<Chunk> {
+    let mut refined: Vec<Chunk> = vec![];
+    for chunk in chunks {
+        if chunk.side != Side::Conflict {
+            refined.push(chunk);
+            continue;
+        }
+        let edits = diff::diff(
+            &ours[chunk.ours.clone()],
+            &theirs[chunk.theirs.clone()],
+            Algorithm::Histogram,
+        );
+        for edit in edits {
+            let conflict = Chunk {
+                side: Side::Conflict,
+                ours: (chunk.ours.start + edit.old.start)..(chunk.ours.start + edit.old.end),
+                theirs: (chunk.theirs.start + edit.new.start)..(chunk.theirs.start + edit.new.end),
+            };
+            match refined.last_mut() {
+                Some(last)
+                    if last.side == Side::Conflict
+                        && conflict.ours.start - last.ours.end <= CONFLICT_GAP =>
+                {
+                    last.ours.end = conflict.ours.end;
+                    last.theirs.end = conflict.theirs.end;
+                }
+                _ => refined.push(conflict),
+            }
+        }
+    }
+    refined
+}
+
+fn push_marker(content: &mut Vec<u8>, marker: u8, label: &str) {
+    content.extend(std::iter::repeat(marker).take(MARKER_SIZE));
+    if !label.is_empty() {
+        content.push(b' ');
+        content.extend(label.as_bytes());
+    }
+    content.push(b'\n');
+}
+
+// NOTE:
+// A last line without a newline gets one, so that the marker after it is on
+// a line of its own.
+fn push_conflict_side(content: &mut Vec<u8>, lines: &[&[u8]]) {
+    content.extend(lines.concat());
+    if lines.last().is_some_and(|line| !line.ends_with(b"\n")) {
+        content.push(b'\n');
+    }
+}
+
+// NOTE:
+// Merges the changes from the `base` tree to `ours` and to `theirs` path by
+// path. A path changed on one side only takes that change, and one changed
+// on both is merged by content. Files renamed on one side are merged with
+// the other side's version under their new name. `labels` name ours and
+// theirs in the conflict markers and messages. Merged blobs are written to the
+// object database.
+pub fn merge_trees<P: AsRef<Path>>(
+    root: P,
+    base: Option<&str>,
+    ours: &str,
+    theirs: &str,
+    labels: (&str, &str),
+) -> Result<TreeMerge> {
+    let root = root.as_ref();
+    let mut base_files = checkout::tree_files(root, base)?;
+    let mut ours_files = checkout::tree_files(root, Some(ours))?;
+    let mut theirs_files = checkout::tree_files(root, Some(theirs))?;
+
+    let ours_renames = renames(root, base, ours)?;
+    let theirs_renames = renames(root, base, theirs)?;
+    for (from, to) in ours_renames.iter() {
+        follow_rename(
+            from,
+            to,
+            &mut base_files,
+            &mut theirs_files,
+            &theirs_renames,
+        );
+    }
+    for (from, to) in theirs_renames.iter() {
+        follow_rename(from, to, &mut base_files, &mut ours_files, &ours_renames);
+    }
+
+    let mut paths: Vec<&String> = base_files
+        .keys()
+        .chain(ours_files.keys())
+        .chain(theirs_files.keys())
+        .collect();
+    paths.sort();
+    paths.dedup();
+
+    let mut merge = TreeMerge::default();
+    for path in paths {
+        let base = base_files.get(path).copied();
+        let ours = ours_files.get(path).copied();
+        let theirs = theirs_files.get(path).copied();
+        let resolution = if ours == theirs || base == theirs {
+            Resolution::Clean(ours)
+        } else if base == ours {
+            Resolution::Clean(theirs)
+        } else {
+            match (ours, theirs) {
+                (Some(ours), Some(theirs)) => {
+                    merge_file(root, path, base, ours, theirs, labels, &mut merge.messages)?
+                }
+                (modified, _) => {
+                    let (deleted_in, modified_in) = match modified {
+                        Some(_) => (labels.1, labels.0),
+                        None => (labels.0, labels.1),
+                    };
+                    merge.messages.push(format!(
+                        "CONFLICT (modify/delete): {path} deleted in {deleted_in} and modified in \
+                         {modified_in}.  Version {modified_in} of {path} left in tree."
+                    ));
+                    Resolution::Conflict {
+                        stages: [base, ours, theirs],
+                        worktree: ours.or(theirs),
+                    }
+                }
+            }
+        };
+        merge.paths.insert(path.clone(), resolution);
+    }
+    Ok(merge)
+}
+
+// NOTE:
+// The files renamed from `base` in `tree`, as their old and new paths.
+fn renames(root: &Path, base: Option<&str>, tree: &str) -> Result<Vec<(String, String)>> {
+    let Some(base) = base else {
+        return Ok(vec![]);
+    };
+    let pairs = files::pairs(
+        root,
+        &Source::Tree(base.to_string()),
+        &Source::Tree(tree.to_string()),
+        &[],
+    )?;
+    Ok(rename::detect(root, pairs, Detection::renames())?
+        .iter()
+        .filter_map(|pair| {
+            let rename = pair.rename().filter(|rename| !rename.is_copy())?;
+            Some((rename.from().to_string(), pair.path().to_string()))
+        })
+        .collect())
+}
+
+// NOTE:
+// Moves the other side's version of a file renamed on one side to the new
+// name, along with the common ancestor, unless the other side renamed or
+// removed it too, or has another file there.
+fn follow_rename(
+    from: &str,
+    to: &str,
+    base: &mut BTreeMap<String, Entry>,
+    other: &mut BTreeMap<String, Entry>,
+    other_renames: &[(String, String)],
+) {
+    let Some(&original) = base.get(from) else {
+        return;
+    };
+    if other_renames.iter().any(|(f, t)| f == from && t == to) {
+        base.insert(to.to_string(), original);
+        return;
+    }
+    if other.contains_key(to) || other_renames.iter().any(|(f, _)| f == from) {
+        return;
+    }
+    if let Some(entry) = other.remove(from) {
+        base.insert(to.to_string(), original);
+        other.insert(to.to_string(), entry);
+    }
+}
+
+// NOTE:
+// Merges a file changed on both sides. Regular files are merged by content,
+// and anything else conflicts with our version left in the working tree.
+fn merge_file(
+    root: &Path,
+    path: &str,
+    base: Option<Entry>,
+    ours: Entry,
+    theirs: Entry,
+    (ours_label, theirs_label): (&str, &str),
+    messages: &mut Vec<String>,
+) -> Result<Resolution> {
+    let stages = [base, Some(ours), Some(theirs)];
+    let conflict = Resolution::Conflict {
+        stages,
+        worktree: Some(ours),
+    };
+    let mode = match base.map(|(mode, _)| mode) {
+        _ if ours.0 == theirs.0 => ours.0,
+        Some(mode) if mode == ours.0 => theirs.0,
+        Some(mode) if mode == theirs.0 => ours.0,
+        _ => ours.0,
+    };
+    let mode_conflict = base.map_or(ours.0 != theirs.0, |(base, _)| {
+        ours.0 != theirs.0 && base != ours.0 && base != theirs.0
+    });
+    let regular = |mode: Mode| !matches!(mode, Mode::Symlink | Mode::Directory | Mode::Gitlink);
+
+    if ours.1 == theirs.1 {
+        if mode_conflict {
+            messages.push(format!("CONFLICT (content): Merge conflict in {path}"));
+            return Ok(conflict);
+        }
+        return Ok(Resolution::Clean(Some((mode, ours.1))));
+    }
+    if !regular(ours.0) || !regular(theirs.0) {
+        messages.push(format!("CONFLICT (content): Merge conflict in {path}"));
+        return Ok(conflict);
+    }
+
+    let base_content = match base {
+        Some((mode, hash)) if regular(mode) => blob(root, &hash.hex())?,
+        _ => vec![],
+    };
+    let ours_content = blob(root, &ours.1.hex())?;
+    let theirs_content = blob(root, &theirs.1.hex())?;
+    if [&base_content, &ours_content, &theirs_content]
+        .iter()
+        .any(|content| diff::is_binary(content))
+    {
+        messages.push(format!(
+            "warning: Cannot merge binary files: {path} ({ours_label} vs. {theirs_label})"
+        ));
+        messages.push(format!("Auto-merging {path}"));
+        messages.push(format!("CONFLICT (content): Merge conflict in {path}"));
+        return Ok(conflict);
+    }
+
+    messages.push(format!("Auto-merging {path}"));
+    let (content, conflicted) = merge_content(
+        &base_content,
+        &ours_content,
+        &theirs_content,
+        (ours_label, theirs_label),
+    );
+    let obj = GitObject::new_blob(content.as_slice())?;
+    obj.write(root)?;
+    let merged = (mode, obj.hash());
+    if !conflicted && !mode_conflict {
+        return Ok(Resolution::Clean(Some(merged)));
+    }
+    let kind = if base.is_some() { "content" } else { "add/add" };
+    messages.push(format!("CONFLICT ({kind}): Merge conflict in {path}"));
+    Ok(Resolution::Conflict {
+        stages,
+        worktree: Some(merged),
+    })
+}
+
+fn blob(root: &Path, hash: &str) -> Result<Vec<u8>> {
+    match GitObject::open_from_hash(root, hash)? {
+        GitObject::Blob(blob) => Ok(blob.as_ref().to_vec()),
+        obj => {
+            let msg = format!("{hash} is a {}, not a blob", obj.type_name());
+            Err(Error::from(msg.as_str()))
+        }
+    }
+}
+
+// NOTE:
+// Updates the index and the working tree from the `ours` tree to the result
+// of the merge, with the stages of the conflicts in the index. Nothing is
+// touched if a file the merge changes has local changes or an untracked file
+// is in its way.
+pub fn checkout<P: AsRef<Path>>(
+    root: P,
+    index: &mut Index,
+    ours: &str,
+    merge: &TreeMerge,
+    operation: Operation,
+) -> Result<()> {
+    let root = root.as_ref();
+    let ours_files = checkout::tree_files(root, Some(ours))?;
+    let staged: BTreeMap<String, Entry> = index
+        .entries()
+        .iter()
+        .filter(|e| e.stage() == 0)
+        .map(|e| (e.path().to_string(), (e.mode(), e.hash())))
+        .collect();
+
+    let mut updates: Vec<(&str, &Resolution)> = vec![];
+    let (mut changed, mut untracked, mut dirs): (Vec<&str>, Vec<&str>, Vec<&str>) =
+        (vec![], vec![], vec![]);
+    for (path, resolution) in merge.paths.iter() {
+        let current = ours_files.get(path).copied();
+        let conflicted = matches!(resolution, Resolution::Conflict { .. });
+        if resolution.worktree() == current && !conflicted {
+            continue;
+        }
+        updates.push((path, resolution));
+        if resolution.worktree() == current {
+            continue;
+        }
+        if current.is_some() && checkout::is_modified(root, index, path)? {
+            changed.push(path);
+        } else if current.is_none() && checkout::is_in_the_way(root, path, &staged)? {
+            if root.join(path).is_dir() {
+                dirs.push(path);
+            } else {
+                untracked.push(path);
+            }
+        }
+    }
+    checkout::refuse(operation, &changed, &untracked, &dirs)?;
+
+    for (path, resolution) in updates.iter() {
+        let current = ours_files.get(*path).copied();
+        if resolution.worktree().is_none() && current.is_some() {
+            crate::worktree::remove_file(root, path)?;
+        }
+        index.remove(path);
+    }
+    for (path, resolution) in updates {
+        let current = ours_files.get(path).copied();
+        if let Some((mode, hash)) = resolution.worktree() {
+            if Some((mode, hash)) != current {
+                let entry = checkout::write_file(root, path, mode, hash)?;
+                if let Resolution::Clean(_) = resolution {
+                    index.add(entry);
+                }
+            } else if let Resolution::Clean(_) = resolution {
+                index.add(IndexEntry::with_stage(path.to_string(), mode, hash, 0));
+            }
+        }
+        if let Resolution::Conflict { stages, .. } = resolution {
+            for (stage, entry) in (1..).zip(stages) {
+                if let Some((mode, hash)) = entry {
+                    let entry = IndexEntry::with_stage(path.to_string(), *mode, *hash, stage);
+                    index.add(entry);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn merge_head<P: AsRef<Path>>(root: P) -> Result<Option<String>> {
+    let path = root.as_ref().join(GIT_DIR).join(MERGE_HEAD);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    Ok(fs::read_to_string(path)?.lines().next().map(String::from))
+}
+
+pub fn merge_message<P: AsRef<Path>>(root: P) -> Result<Option<String>> {
+    let path = root.as_ref().join(GIT_DIR).join(MERGE_MSG);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    Ok(Some(fs::read_to_string(path)?))
+}
+
+// NOTE:
+// Records a merge that stopped on conflicts, for the commit that concludes it.
+pub fn write_state<P: AsRef<Path>>(root: P, theirs: &str, message: &str) -> Result<()> {
+    let git_dir = root.as_ref().join(GIT_DIR);
+    fs::write(git_dir.join(MERGE_HEAD), format!("{theirs}\n"))?;
+    fs::write(git_dir.join(MERGE_MSG), message)?;
+    fs::write(git_dir.join(MERGE_MODE), "")?;
+    Ok(())
+}
+
+pub fn clear_state<P: AsRef<Path>>(root: P) -> Result<()> {
+    let git_dir = root.as_ref().join(GIT_DIR);
+    for name in [MERGE_HEAD, MERGE_MSG, MERGE_MODE] {
+        let path = git_dir.join(name);
+        if path.is_file() {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const LABELS: (&str, &str) = ("HEAD", "side");
+
+    fn merged(base: &str, ours: &str, theirs: &str) -> (String, bool) {
+        let (content, conflicted) =
+            merge_content(base.as_bytes(), ours.as_bytes(), theirs.as_bytes(), LABELS);
+        (String::from_utf8(content).unwrap(), conflicted)
+    }
+
+    #[test]
+    fn it_merges_changes_on_both_sides() {
+        let base = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let ours = "1\ntwo\n3\n4\n5\n6\n7\n8\n9\n";
+        let theirs = "1\n2\n3\n4\n5\n6\n7\neight\n9\nten\n";
+        assert_eq!(
+            merged(base, ours, theirs),
+            ("1\ntwo\n3\n4\n5\n6\n7\neight\n9\nten\n".into(), false)
+        );
+        assert_eq!(merged(base, ours, ours), (ours.into(), false));
+        assert_eq!(merged(base, base, theirs), (theirs.into(), false));
+    }
+
+    #[test]
+    fn it_writes_conflict_markers() {
+        let base = "1\n2\n3\n4\n5\n";
+        let (content, conflicted) = merged(base, "1\n2\nours\n4\n5\n", "1\n2\ntheirs\n4\n5\n");
+        assert!(conflicted);
+        assert_eq!(
+            content,
+            "1\n2\n<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> side\n4\n5\n"
+        );
+
+        // NOTE:
+        // The lines both sides added the same way are left out.
+        let (content, _) = merged(base, "1\n2\nx\ny\n3\n4\n5\n", "1\n2\nx\nz\n3\n4\n5\n");
+        assert_eq!(
+            content,
+            "1\n2\nx\n<<<<<<< HEAD\ny\n=======\nz\n>>>>>>> side\n3\n4\n5\n"
+        );
+
+        let (content, _) = merged("", "a", "b");
+        assert_eq!(content, "<<<<<<< HEAD\na\n=======\nb\n>>>>>>> side\n");
+    }
+
+    #[test]
+    fn it_merges_trees() {
+        let root = testing::repo("merge-trees");
+        let numbers = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let base = testing::write_tree(
+            &root,
+            &[("a", numbers), ("b", "b\n"), ("c", "c\n"), ("d", "d\n")],
+        );
+        let ours = testing::write_tree(
+            &root,
+            &[
+                ("a", "one\n2\n3\n4\n5\n6\n7\n8\n9\n"),
+                ("b", "ours\n"),
+                ("d", "d\n"),
+            ],
+        );
+        let theirs = testing::write_tree(
+            &root,
+            &[
+                ("moved", "1\n2\n3\n4\n5\n6\n7\n8\nnine\n"),
+                ("b", "theirs\n"),
+                ("c", "changed\n"),
+                ("d", "d\n"),
+                ("new", "new\n"),
+            ],
+        );
+
+        let merge = merge_trees(&root, Some(&base), &ours, &theirs, LABELS).unwrap();
+        let paths: Vec<&String> = merge.paths().keys().collect();
+        assert_eq!(paths, vec!["a", "b", "c", "d", "moved", "new"]);
+        assert_eq!(merge.paths()["a"], Resolution::Clean(None));
+        assert_eq!(merge.conflicts(), vec!["b", "c"]);
+        assert_eq!(
+            merge.messages(),
+            &[
+                "Auto-merging b",
+                "CONFLICT (content): Merge conflict in b",
+                "CONFLICT (modify/delete): c deleted in HEAD and modified in side.  \
+                 Version side of c left in tree.",
+                "Auto-merging moved",
+            ]
+        );
+
+        let mut index = Index::default();
+        checkout::switch(&root, &mut index, None, &ours, false).unwrap();
+        checkout(&root, &mut index, &ours, &merge, Operation::Merge).unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("moved")).unwrap(),
+            "one\n2\n3\n4\n5\n6\n7\n8\nnine\n"
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("b")).unwrap(),
+            "<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> side\n"
+        );
+        assert!(!root.join("a").exists());
+        let entries: Vec<(&str, u8)> = index
+            .entries()
+            .iter()
+            .map(|e| (e.path(), e.stage()))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("b", 1),
+                ("b", 2),
+                ("b", 3),
+                ("c", 1),
+                ("c", 3),
+                ("d", 0),
+                ("moved", 0),
+                ("new", 0)
+            ]
+        );
+    }
+}
@@ -0,0 +1,362 @@
+use super::{
+    git_object::RawObject,
+    git_protocol::{PackEntry, PackFile, PackIndexFile},
+    Error, GitObject, Result, Sha1Hash, GIT_OBJ_DIR, GIT_PACK_DIR,
+};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+
+// NOTE:
+// The number of delta bases kept in memory. Trees and blobs are mostly stored
+// as chains of deltas, and without the bases every object of a chain would be
+// resolved again from the start of the chain.
+const DELTA_BASE_CACHE_SIZE: usize = 256;
+
+#[derive(Debug)]
+struct Pack {
+    path: PathBuf,
+    index: PackIndexFile,
+    file: Mutex<PackFile<BufReader<File>>>,
+}
+
+#[derive(Debug, Default)]
+struct DeltaBaseCache {
+    objects: HashMap<(usize, u64), Arc<RawObject>>,
+    order: VecDeque<(usize, u64)>,
+}
+
+impl DeltaBaseCache {
+    fn get(&self, key: (usize, u64)) -> Option<Arc<RawObject>> {
+        self.objects.get(&key).cloned()
+    }
+
+    fn insert(&mut self, key: (usize, u64), object: Arc<RawObject>) {
+        if self.objects.insert(key, object).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > DELTA_BASE_CACHE_SIZE {
+            if let Some(oldest) = self.order.pop_front() {
+                self.objects.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ObjectDatabase {
+    root: PathBuf,
+    packs: Mutex<Option<Vec<Arc<Pack>>>>,
+    bases: Mutex<DeltaBaseCache>,
+}
+
+impl ObjectDatabase {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().into(),
+            packs: Mutex::new(None),
+            bases: Mutex::new(DeltaBaseCache::default()),
+        }
+    }
+
+    // NOTE:
+    // The database of the repository shared by the whole process, so that the
+    // pack indexes are read and the packs opened only once however many objects
+    // are read. Repositories are told apart by their canonical path, because
+    // the same root can be given as "." or as the full path.
+    pub fn open<P: AsRef<Path>>(root: P) -> Arc<Self> {
+        static DATABASES: OnceLock<Mutex<HashMap<PathBuf, Arc<ObjectDatabase>>>> = OnceLock::new();
+
+        let root = root.as_ref();
+        let root = fs::canonicalize(root).unwrap_or_else(|_| root.into());
+        let mut databases = lock(DATABASES.get_or_init(Default::default));
+        databases
+            .entry(root.clone())
+            .or_insert_with(|| Arc::new(Self::new(root)))
+            .clone()
+    }
+
+    // NOTE:
+    // Loose objects are searched first, then every pack under objects/pack.
+    pub fn read(&self, hash: &str) -> Result<GitObject> {
+        self.read_raw(hash)?.parse()
+    }
+
+    // NOTE:
+    // Reads the object as stored, without parsing its content.
+    pub fn read_raw(&self, hash: &str) -> Result<RawObject> {
+        let path = GitObject::path(&self.root, hash)?;
+        if path.try_exists()? {
+            return RawObject::open(path);
+        }
+
+        let hash = Sha1Hash::from_hex(hash)?;
+        self.read_packed(&hash)?.ok_or_else(|| {
+            let msg = format!("Not found object {}", hash.hex());
+            Error::from(msg.as_str())
+        })
+    }
+
+    // NOTE:
+    // Returns every object, loose or packed, whose hash starts with the hex prefix.
+    pub fn find_prefix(&self, prefix: &str) -> Result<Vec<Sha1Hash>> {
+        let prefix = prefix.to_lowercase();
+        let mut hashes: Vec<Sha1Hash> = vec![];
+
+        if prefix.len() >= 2 {
+            let dir = self.root.join(GIT_OBJ_DIR).join(&prefix[..2]);
+            if dir.is_dir() {
+                for entry in fs::read_dir(dir)? {
+                    let name = entry?.file_name().to_string_lossy().to_string();
+                    let hex = format!("{}{name}", &prefix[..2]);
+                    if hex.starts_with(&prefix) {
+                        if let Ok(hash) = Sha1Hash::from_hex(&hex) {
+                            hashes.push(hash);
+                        }
+                    }
+                }
+            }
+        }
+
+        for pack in self.packs(true)? {
+            hashes.extend(pack.index.find_prefix(&prefix));
+        }
+
+        hashes.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+        hashes.dedup();
+        Ok(hashes)
+    }
+
+    // NOTE:
+    // A pack written since the packs were listed is only looked for when the
+    // object isn't in the ones already open.
+    fn read_packed(&self, hash: &Sha1Hash) -> Result<Option<RawObject>> {
+        let mut packs = self.packs(false)?;
+        if !packs.iter().any(|pack| pack.index.find(hash).is_some()) {
+            packs = self.packs(true)?;
+        }
+
+        for (n, pack) in packs.iter().enumerate() {
+            if let Some(offset) = pack.index.find(hash) {
+                return self.read_at(n, pack, offset).map(Some);
+            }
+        }
+
+        Ok(None)
+    }
+
+    // NOTE:
+    // Ofs-delta bases are in the same pack, while ref-delta bases can be
+    // anywhere in the database. Deltas are applied to the bytes of their base
+    // as stored, which the parsed object can't always give back.
+    fn read_at(&self, n: usize, pack: &Pack, offset: u64) -> Result<RawObject> {
+        let entry = lock(&pack.file).entry_at(offset)?;
+        match entry {
+            PackEntry::Object(object) => Ok(object),
+            PackEntry::OfsDelta { base_offset, delta } => {
+                self.delta_base(n, pack, base_offset)?.restore(delta)
+            }
+            PackEntry::RefDelta { basename, delta } => {
+                self.read_raw(&basename.hex())?.restore(delta)
+            }
+        }
+    }
+
+    fn delta_base(&self, n: usize, pack: &Pack, offset: u64) -> Result<Arc<RawObject>> {
+        if let Some(object) = lock(&self.bases).get((n, offset)) {
+            return Ok(object);
+        }
+        let object = Arc::new(self.read_at(n, pack, offset)?);
+        lock(&self.bases).insert((n, offset), object.clone());
+        Ok(object)
+    }
+
+    // NOTE:
+    // The packs are listed the first time they are needed, and listed again
+    // on a rescan to open the ones written since. Packs already open keep their
+    // place, because the delta base cache knows them by it.
+    fn packs(&self, rescan: bool) -> Result<Vec<Arc<Pack>>> {
+        let mut packs = lock(&self.packs);
+        if let (Some(packs), false) = (packs.as_ref(), rescan) {
+            return Ok(packs.clone());
+        }
+
+        let mut open = packs.take().unwrap_or_default();
+        for idx_path in self.pack_indexes()? {
+            if open.iter().any(|pack| pack.path == idx_path) {
+                continue;
+            }
+            let index = PackIndexFile::from_bytes(fs::read(&idx_path)?)?;
+            let file = PackFile::open(idx_path.with_extension("pack"))?;
+            open.push(Arc::new(Pack {
+                path: idx_path,
+                index,
+                file: Mutex::new(file),
+            }));
+        }
+        *packs = Some(open.clone());
+
+        Ok(open)
+    }
+
+    fn pack_indexes(&self) -> Result<Vec<PathBuf>> {
+        let pack_dir = self.root.join(GIT_PACK_DIR);
+        if !pack_dir.is_dir() {
+            return Ok(vec![]);
+        }
+
+        let mut paths: Vec<PathBuf> = vec![];
+        for entry in fs::read_dir(pack_dir)? {
+            let path = entry?.path();
+            let is_pack_index = path.extension().is_some_and(|ext| ext == "idx")
+                && path
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with("pack-"));
+
+            if is_pack_index {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        Ok(paths)
+    }
+}
+
+// NOTE:
+// A panic while a lock was held can't leave the caches half written, so a
+// poisoned lock is used as is.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git_protocol::{PackIndex, PackedObject};
+    use crate::testing;
+    use flate2::{write::ZlibEncoder, Compression};
+    use sha1::Digest;
+    use std::io::Write;
+
+    fn compress(bytes: &[u8]) -> Vec<u8> {
+        let mut e = ZlibEncoder::new(vec![], Compression::default());
+        e.write_all(bytes).unwrap();
+        e.finish().unwrap()
+    }
+
+    fn write_pack(root: &Path, entries: &[Vec<u8>]) {
+        let num = (entries.len() as u32).to_be_bytes();
+        let bytes = [b"PACK", &[0, 0, 0, 2][..], &num[..], &entries.concat()].concat();
+        let mut hasher = Sha1Hash::hasher();
+        hasher.update(&bytes);
+        let bytes = [bytes, Sha1Hash::new(hasher).as_bytes().to_vec()].concat();
+
+        let checksum = PackFile::checksum(&bytes).unwrap();
+        let objects: Vec<PackedObject> = PackFile::get_objects(bytes.clone()).unwrap();
+        let index = PackIndex::new(&objects, checksum);
+
+        let pack_dir = root.join(GIT_PACK_DIR);
+        let name = index.pack_name();
+        fs::write(pack_dir.join(format!("{name}.pack")), &bytes).unwrap();
+        fs::write(pack_dir.join(format!("{name}.idx")), index.serialize()).unwrap();
+    }
+
+    fn blob(content: &[u8]) -> GitObject {
+        GitObject::new_blob(content).unwrap()
+    }
+
+    #[test]
+    fn it_reads_loose_objects() {
+        let root = testing::repo("odb-loose");
+        let object = blob(b"loose object");
+        object.write(&root).unwrap();
+
+        let odb = ObjectDatabase::new(&root);
+        assert_eq!(odb.read(&object.hash().hex()).unwrap(), object);
+    }
+
+    #[test]
+    fn it_reads_packed_objects_resolving_deltas() {
+        let root = testing::repo("odb-packed");
+        let base = [vec![0x3b], compress(b"hello world")].concat();
+        let ofs_delta = [
+            vec![0x67, base.len() as u8],
+            compress(&[0x0b, 0x0c, 0x91, 0x00, 0x0b, 0x01, b'!']),
+        ]
+        .concat();
+        let mut ref_delta = vec![0x77];
+        ref_delta.extend_from_slice(blob(b"hello world!").hash().as_bytes());
+        ref_delta.extend(compress(&[0x0c, 0x0d, 0x91, 0x00, 0x0c, 0x01, b'?']));
+        write_pack(&root, &[base, ofs_delta, ref_delta]);
+
+        let odb = ObjectDatabase::new(&root);
+        for content in [&b"hello world"[..], b"hello world!", b"hello world!?"] {
+            let object = blob(content);
+            assert_eq!(odb.read(&object.hash().hex()).unwrap(), object);
+        }
+    }
+
+    #[test]
+    fn it_indexes_packed_objects_by_their_stored_bytes() {
+        let root = testing::repo("odb-raw");
+        let content = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+            author \xe9 <a@example.com> 0 +0000\n\
+            committer \xe9 <a@example.com> 0 +0000\n\nlatin-1\n";
+        let len = content.len();
+        write_pack(
+            &root,
+            &[[
+                vec![0x90 | (len & 0x0f) as u8, (len >> 4) as u8],
+                compress(content),
+            ]
+            .concat()],
+        );
+
+        let odb = ObjectDatabase::new(&root);
+        let object = RawObject::new("commit", content.to_vec());
+        assert_eq!(odb.read_raw(&object.hash().hex()).unwrap(), object);
+    }
+
+    #[test]
+    fn it_finds_loose_and_packed_objects_by_prefix() {
+        let root = testing::repo("odb-prefix");
+        let loose = blob(b"loose object");
+        loose.write(&root).unwrap();
+        write_pack(&root, &[[vec![0x3b], compress(b"hello world")].concat()]);
+        let packed = blob(b"hello world");
+
+        let odb = ObjectDatabase::new(&root);
+        let loose_hex = loose.hash().hex();
+        let packed_hex = packed.hash().hex();
+        assert_eq!(
+            odb.find_prefix(&loose_hex[..6]).unwrap(),
+            vec![loose.hash()]
+        );
+        assert_eq!(
+            odb.find_prefix(&packed_hex[..6]).unwrap(),
+            vec![packed.hash()]
+        );
+    }
+
+    #[test]
+    fn it_shares_the_database_and_opens_packs_written_later() {
+        let root = testing::repo("odb-shared");
+        let odb = ObjectDatabase::open(&root);
+        assert!(Arc::ptr_eq(&odb, &ObjectDatabase::open(root.join("."))));
+
+        let object = blob(b"hello world");
+        assert!(odb.read(&object.hash().hex()).is_err());
+        write_pack(&root, &[[vec![0x3b], compress(b"hello world")].concat()]);
+        assert_eq!(odb.read(&object.hash().hex()).unwrap(), object);
+    }
+
+    #[test]
+    fn it_fails_to_read_missing_objects() {
+        let root = testing::repo("odb-missing");
+        let odb = ObjectDatabase::new(&root);
+        assert!(odb.read(&blob(b"missing").hash().hex()).is_err());
+    }
+}
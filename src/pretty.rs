@@ -0,0 +1,302 @@
+use super::{
+    date,
+    git_object::commit::{Commit, User},
+    Error, Result,
+};
+
+const ABBREV_LEN: usize = 7;
+
+// NOTE:
+// The built-in formats print a blank line between commits. "tformat:" ends
+// each commit with a newline and "format:" puts one between commits.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Format {
+    Short,
+    Medium,
+    Full,
+    Fuller,
+    Custom { template: String, terminator: bool },
+}
+
+impl Format {
+    pub fn parse(value: &str) -> Result<Self> {
+        let format = match value {
+            "short" => Self::Short,
+            "medium" => Self::Medium,
+            "full" => Self::Full,
+            "fuller" => Self::Fuller,
+            "oneline" => Self::tformat("%H %s"),
+            _ => {
+                if let Some(template) = value.strip_prefix("format:") {
+                    Self::Custom {
+                        template: template.into(),
+                        terminator: false,
+                    }
+                } else if let Some(template) = value.strip_prefix("tformat:") {
+                    Self::tformat(template)
+                } else if value.contains('%') {
+                    Self::tformat(value)
+                } else {
+                    let msg = format!("invalid --pretty format: {value}");
+                    return Err(Error::from(msg.as_str()));
+                }
+            }
+        };
+        Ok(format)
+    }
+
+    pub fn tformat(template: &str) -> Self {
+        Self::Custom {
+            template: template.into(),
+            terminator: true,
+        }
+    }
+
+    pub fn separator(&self) -> &str {
+        match self {
+            Self::Custom {
+                terminator: true, ..
+            } => "",
+            _ => "\n",
+        }
+    }
+
+    pub fn terminator(&self) -> &str {
+        match self {
+            Self::Custom {
+                terminator: false, ..
+            } => "",
+            _ => "\n",
+        }
+    }
+
+    // NOTE:
+    // Renders the commit without the separator and the terminator.
+    pub fn render(&self, hash: &str, commit: &Commit) -> String {
+        let mut lines: Vec<String> = vec![];
+        match self {
+            Self::Custom { template, .. } => return expand(template, hash, commit),
+            _ => lines.push(format!("commit {hash}")),
+        }
+
+        if commit.parents().len() > 1 {
+            let parents: Vec<&str> = commit.parents().iter().map(|p| abbrev(p)).collect();
+            lines.push(format!("Merge: {}", parents.join(" ")));
+        }
+
+        let (author, committer) = (commit.author(), commit.committer());
+        match self {
+            Self::Short => lines.push(format!("Author: {}", ident(author))),
+            Self::Medium => {
+                lines.push(format!("Author: {}", ident(author)));
+                lines.push(format!("Date:   {}", user_date(author)));
+            }
+            Self::Full => {
+                lines.push(format!("Author: {}", ident(author)));
+                lines.push(format!("Commit: {}", ident(committer)));
+            }
+            Self::Fuller => {
+                lines.push(format!("Author:     {}", ident(author)));
+                lines.push(format!("AuthorDate: {}", user_date(author)));
+                lines.push(format!("Commit:     {}", ident(committer)));
+                lines.push(format!("CommitDate: {}", user_date(committer)));
+            }
+            Self::Custom { .. } => unreachable!(),
+        }
+        lines.push(String::new());
+
+        let message = if *self == Self::Short {
+            subject(commit.comment())
+        } else {
+            commit.comment().trim_end_matches('\n').to_string()
+        };
+        for line in message.lines() {
+            lines.push(format!("    {line}"));
+        }
+        lines.join("\n")
+    }
+}
+
+pub fn abbrev(hash: &str) -> &str {
+    &hash[..hash.len().min(ABBREV_LEN)]
+}
+
+// NOTE:
+// The subject is the first paragraph of the message joined into one line, and
+// the body is the rest.
+pub fn subject(message: &str) -> String {
+    message
+        .trim_start_matches('\n')
+        .lines()
+        .take_while(|line| !line.trim().is_empty())
+        .map(str::trim)
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+// NOTE:
+// Like the cleanup git does on messages: trailing whitespace is removed from
+// each line, runs of blank lines are collapsed into one, and blank lines at
+// the start and the end are dropped.
+pub fn cleanup(message: &str) -> String {
+    let mut cleaned = String::new();
+    let mut blank = false;
+    for line in message.lines().map(str::trim_end) {
+        if line.is_empty() {
+            blank = !cleaned.is_empty();
+            continue;
+        }
+        if blank {
+            cleaned.push('\n');
+            blank = false;
+        }
+        cleaned.push_str(line);
+        cleaned.push('\n');
+    }
+    cleaned
+}
+
+pub fn body(message: &str) -> String {
+    let mut lines = message
+        .trim_start_matches('\n')
+        .lines()
+        .skip_while(|line| !line.trim().is_empty())
+        .skip_while(|line| line.trim().is_empty())
+        .peekable();
+    if lines.peek().is_none() {
+        return String::new();
+    }
+    let mut body = lines.collect::<Vec<&str>>().join("\n");
+    body.push('\n');
+    body
+}
+
+fn ident(user: &User) -> String {
+    format!("{} <{}>", user.name(), user.email())
+}
+
+fn user_date(user: &User) -> String {
+    date::format(user.timestamp(), user.offset())
+}
+
+// NOTE:
+// Supports the placeholders %H %h %T %t %P %p %s %b %B %n %% %xNN and the
+// author (%a) and committer (%c) ones followed by n, e, d, t, i or I.
+// Unknown placeholders are kept as they are.
+fn expand(template: &str, hash: &str, commit: &Commit) -> String {
+    let mut out = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+
+        let Some(key) = chars.next() else {
+            out.push('%');
+            break;
+        };
+        match key {
+            '%' => out.push('%'),
+            'n' => out.push('\n'),
+            'H' => out.push_str(hash),
+            'h' => out.push_str(abbrev(hash)),
+            'T' => out.push_str(commit.tree()),
+            't' => out.push_str(abbrev(commit.tree())),
+            'P' => out.push_str(&commit.parents().join(" ")),
+            'p' => {
+                let parents: Vec<&str> = commit.parents().iter().map(|p| abbrev(p)).collect();
+                out.push_str(&parents.join(" "));
+            }
+            's' => out.push_str(&subject(commit.comment())),
+            'b' => out.push_str(&body(commit.comment())),
+            'B' => out.push_str(commit.comment()),
+            'x' => {
+                let digits: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(byte) => out.push(byte as char),
+                    Err(_) => out.push_str(&format!("%x{digits}")),
+                }
+            }
+            'a' | 'c' => {
+                let user = if key == 'a' {
+                    commit.author()
+                } else {
+                    commit.committer()
+                };
+                let field = chars.peek().copied();
+                let value = match field {
+                    Some('n') => user.name().to_string(),
+                    Some('e') => user.email().to_string(),
+                    Some('d') => user_date(user),
+                    Some('t') => user.timestamp().to_string(),
+                    Some('i') => date::format_iso(user.timestamp(), user.offset(), false),
+                    Some('I') => date::format_iso(user.timestamp(), user.offset(), true),
+                    _ => {
+                        out.push('%');
+                        out.push(key);
+                        continue;
+                    }
+                };
+                chars.next();
+                out.push_str(&value);
+            }
+            _ => {
+                out.push('%');
+                out.push(key);
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "313d985f6d5649a1467e86464f57d40b39e9c360";
+
+    fn commit() -> Commit {
+        Commit::from_bytes(b"tree 8119b90c6adef211483e6dcf1a3c89e966af9c60\nparent b521b9179412d90a893bc36f33f5dcfd987105ef\nparent 3b1031798a00fdf9b574b5857b1721bc4b0e6bac\nauthor A <a@x> 1112900000 -0700\ncommitter C <c@x> 1112900400 +0000\n\nMerge side\ninto main\n\nbody line\n")
+    }
+
+    #[test]
+    fn it_renders_medium_format() {
+        assert_eq!(
+            Format::Medium.render(HASH, &commit()),
+            format!("commit {HASH}\nMerge: b521b91 3b10317\nAuthor: A <a@x>\nDate:   Thu Apr 7 11:53:20 2005 -0700\n\n    Merge side\n    into main\n    \n    body line")
+        );
+    }
+
+    #[test]
+    fn it_renders_fuller_format() {
+        let rendered = Format::Fuller.render(HASH, &commit());
+        assert!(
+            rendered.contains("\nCommit:     C <c@x>\nCommitDate: Thu Apr 7 19:00:00 2005 +0000\n")
+        );
+    }
+
+    #[test]
+    fn it_expands_placeholders() {
+        let format = Format::parse("format:%h %s%n%an <%ae> %at%x09%ci|%b|%%|%q").unwrap();
+        assert_eq!(
+            format.render(HASH, &commit()),
+            "313d985 Merge side into main\nA <a@x> 1112900000\t2005-04-07 19:00:00 +0000|body line\n|%|%q"
+        );
+        assert_eq!(format.terminator(), "");
+    }
+
+    #[test]
+    fn it_parses_formats() {
+        assert_eq!(Format::parse("oneline").unwrap(), Format::tformat("%H %s"));
+        assert_eq!(Format::parse("%H").unwrap(), Format::tformat("%H"));
+        assert!(Format::parse("unknown").is_err());
+    }
+
+    #[test]
+    fn it_cleans_up_messages() {
+        assert_eq!(cleanup("\n\nrel  \n\n\n\nbody\t\n\n"), "rel\n\nbody\n");
+        assert_eq!(cleanup("  \n"), "");
+    }
+}
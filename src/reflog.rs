@@ -0,0 +1,307 @@
+use super::{git_object::commit::User, refs::HEAD, Result, GIT_DIR};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub const NULL_HASH: &str = "0000000000000000000000000000000000000000";
+const GIT_LOGS_DIR: &str = "logs";
+
+// NOTE:
+// Each line is "<old> <new> <name> <<email>> <timestamp> <timezone>\t<message>".
+// A ref that didn't exist before has the all-zero hash as the old value.
+pub fn append<P: AsRef<Path>>(
+    root: P,
+    refname: &str,
+    old: Option<&str>,
+    new: &str,
+    user: &User,
+    message: &str,
+) -> Result<()> {
+    let path = log_path(root.as_ref(), refname);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut f = OpenOptions::new().create(true).append(true).open(path)?;
+    let message = message.lines().next().unwrap_or_default();
+    writeln!(f, "{} {new} {user}\t{message}", old.unwrap_or(NULL_HASH))?;
+    Ok(())
+}
+
+// NOTE:
+// One line of the log: the value a ref was set to, the value it had before,
+// who set it and when, and why.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    old: String,
+    hash: String,
+    committer: User,
+    message: String,
+}
+
+impl Entry {
+    pub fn old(&self) -> &str {
+        self.old.as_str()
+    }
+
+    pub fn hash(&self) -> &str {
+        self.hash.as_str()
+    }
+
+    pub fn committer(&self) -> &User {
+        &self.committer
+    }
+
+    pub fn message(&self) -> &str {
+        self.message.as_str()
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}\t{}",
+            self.old, self.hash, self.committer, self.message
+        )
+    }
+}
+
+// NOTE:
+// The entries oldest first. Lines that can't be parsed are skipped, and a ref
+// without a log has no entries.
+pub fn read<P: AsRef<Path>>(root: P, refname: &str) -> Result<Vec<Entry>> {
+    let path = log_path(root.as_ref(), refname);
+    if !path.is_file() {
+        return Ok(vec![]);
+    }
+
+    let mut entries: Vec<Entry> = vec![];
+    for line in fs::read_to_string(path)?.lines() {
+        let (info, message) = line.split_once('\t').unwrap_or((line, ""));
+        let mut parts = info.splitn(3, ' ');
+        let (Some(old), Some(new), Some(committer)) = (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        if old.len() != NULL_HASH.len() || new.len() != NULL_HASH.len() {
+            continue;
+        }
+        if let Some(committer) = parse_user(committer) {
+            entries.push(Entry {
+                old: old.into(),
+                hash: new.into(),
+                committer,
+                message: message.into(),
+            });
+        }
+    }
+    Ok(entries)
+}
+
+// NOTE:
+// Replaces the log of the ref with the entries, like after pruning some.
+pub fn write<P: AsRef<Path>>(root: P, refname: &str, entries: &[Entry]) -> Result<()> {
+    let content: String = entries.iter().map(|entry| format!("{entry}\n")).collect();
+    fs::write(log_path(root.as_ref(), refname), content)?;
+    Ok(())
+}
+
+// NOTE:
+// The refs that have a log, HEAD first and then the others sorted by name.
+pub fn list<P: AsRef<Path>>(root: P) -> Result<Vec<String>> {
+    let logs = root.as_ref().join(GIT_DIR).join(GIT_LOGS_DIR);
+    let mut refnames: Vec<String> = vec![];
+    let mut stack: Vec<String> = vec!["refs".into()];
+    while let Some(dir) = stack.pop() {
+        let Ok(entries) = fs::read_dir(logs.join(&dir)) else {
+            continue;
+        };
+        for entry in entries {
+            let entry = entry?;
+            let refname = format!("{dir}/{}", entry.file_name().to_string_lossy());
+            if entry.file_type()?.is_dir() {
+                stack.push(refname);
+            } else {
+                refnames.push(refname);
+            }
+        }
+    }
+    refnames.sort();
+    if logs.join(HEAD).is_file() {
+        refnames.insert(0, HEAD.into());
+    }
+    Ok(refnames)
+}
+
+// NOTE:
+// Removes the entry of N updates ago and returns the ones left. Like
+// `git reflog delete --rewrite`, each entry then has the value of the one
+// before it as its old value, so that the log stays a chain.
+pub fn remove<P: AsRef<Path>>(root: P, refname: &str, n: usize) -> Result<Vec<Entry>> {
+    let root = root.as_ref();
+    let mut entries = read(root, refname)?;
+    if n >= entries.len() {
+        return Ok(entries);
+    }
+    entries.remove(entries.len() - 1 - n);
+    let mut old = NULL_HASH.to_string();
+    for entry in entries.iter_mut() {
+        entry.old = std::mem::replace(&mut old, entry.hash.clone());
+    }
+    write(root, refname, &entries)?;
+    Ok(entries)
+}
+
+pub fn delete<P: AsRef<Path>>(root: P, refname: &str) -> Result<()> {
+    let path = log_path(root.as_ref(), refname);
+    if path.is_file() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+// NOTE:
+// Moves the log along with a renamed ref, or copies it for a copied one.
+pub fn rename<P: AsRef<Path>>(root: P, from: &str, to: &str, copy: bool) -> Result<()> {
+    let root = root.as_ref();
+    let (from, to) = (log_path(root, from), log_path(root, to));
+    if !from.is_file() {
+        return Ok(());
+    }
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    if copy {
+        fs::copy(from, to)?;
+    } else {
+        fs::rename(from, to)?;
+    }
+    Ok(())
+}
+
+// NOTE:
+// "<name> <<email>> <timestamp> <timezone>", where a bad line gives None
+// instead of panicking.
+fn parse_user(value: &str) -> Option<User> {
+    let (name, rest) = value.split_once(" <")?;
+    let (email, rest) = rest.split_once("> ")?;
+    let (timestamp, timezone) = rest.split_once(' ')?;
+    let timestamp = timestamp.parse().ok()?;
+    Some(User::new(
+        name.into(),
+        email.into(),
+        timestamp,
+        timezone.into(),
+    ))
+}
+
+fn log_path(root: &Path, refname: &str) -> PathBuf {
+    root.join(GIT_DIR).join(GIT_LOGS_DIR).join(refname)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn it_appends_reflog_entries() {
+        let root = testing::TempDir::new("reflog");
+        let user = User::from(&b"Paul Kuruvilla <rohitpaulk@gmail.com> 1587032850 +0530"[..]);
+        let first = "8119b90c6adef211483e6dcf1a3c89e966af9c60";
+        let second = "b521b9179412d90a893bc36f33f5dcfd987105ef";
+
+        append(
+            &root,
+            "refs/heads/main",
+            None,
+            first,
+            &user,
+            "commit (initial): a",
+        )
+        .unwrap();
+        append(
+            &root,
+            "refs/heads/main",
+            Some(first),
+            second,
+            &user,
+            "commit: b\n\nbody",
+        )
+        .unwrap();
+
+        let log = fs::read_to_string(root.join(".git/logs/refs/heads/main")).unwrap();
+        assert_eq!(
+            log,
+            format!(
+                "{NULL_HASH} {first} Paul Kuruvilla <rohitpaulk@gmail.com> 1587032850 +0530\tcommit (initial): a\n\
+                 {first} {second} Paul Kuruvilla <rohitpaulk@gmail.com> 1587032850 +0530\tcommit: b\n"
+            )
+        );
+    }
+
+    #[test]
+    fn it_reads_and_moves_reflogs() {
+        let root = testing::TempDir::new("reflog-read");
+        let user = User::from(&b"Paul Kuruvilla <rohitpaulk@gmail.com> 1587032850 +0530"[..]);
+        let hash = "8119b90c6adef211483e6dcf1a3c89e966af9c60";
+        append(
+            &root,
+            "refs/heads/a",
+            None,
+            hash,
+            &user,
+            "branch: Created from HEAD",
+        )
+        .unwrap();
+
+        rename(&root, "refs/heads/a", "refs/heads/x/b", true).unwrap();
+        rename(&root, "refs/heads/a", "refs/heads/c", false).unwrap();
+        assert!(read(&root, "refs/heads/a").unwrap().is_empty());
+        delete(&root, "refs/heads/c").unwrap();
+        assert!(read(&root, "refs/heads/c").unwrap().is_empty());
+
+        let entries = read(&root, "refs/heads/x/b").unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].hash(), hash);
+        assert_eq!(entries[0].old(), NULL_HASH);
+        assert_eq!(entries[0].committer(), &user);
+        assert_eq!(entries[0].message(), "branch: Created from HEAD");
+        assert_eq!(list(&root).unwrap(), vec!["refs/heads/x/b"]);
+
+        append(&root, HEAD, None, hash, &user, "checkout: moving").unwrap();
+        write(&root, "refs/heads/x/b", &[]).unwrap();
+        assert!(read(&root, "refs/heads/x/b").unwrap().is_empty());
+        assert_eq!(list(&root).unwrap(), vec![HEAD, "refs/heads/x/b"]);
+    }
+
+    #[test]
+    fn it_removes_an_entry_and_rewrites_the_chain() {
+        let root = testing::TempDir::new("reflog-remove");
+        let user = User::from(&b"Paul Kuruvilla <rohitpaulk@gmail.com> 1587032850 +0530"[..]);
+        let hashes = [
+            "8119b90c6adef211483e6dcf1a3c89e966af9c60",
+            "b521b9179412d90a893bc36f33f5dcfd987105ef",
+            "3b18e512dba79e4c8300dd08aeb37f8e728b8dad",
+        ];
+        let mut old: Option<&str> = None;
+        for (i, hash) in hashes.iter().enumerate() {
+            append(&root, "refs/stash", old, hash, &user, &format!("WIP {i}")).unwrap();
+            old = Some(hash);
+        }
+
+        let entries = remove(&root, "refs/stash", 1).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].old(), hashes[0]);
+        assert_eq!(entries[1].hash(), hashes[2]);
+        assert_eq!(read(&root, "refs/stash").unwrap(), entries);
+
+        let entries = remove(&root, "refs/stash", 1).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].old(), NULL_HASH);
+        assert_eq!(entries[0].message(), "WIP 2");
+        assert_eq!(remove(&root, "refs/stash", 1).unwrap(), entries);
+    }
+}
@@ -0,0 +1,97 @@
+use crate::{Error, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const LOCK_SUFFIX: &str = ".lock";
+
+// NOTE:
+// Holds "<file>.lock", created exclusively so that only one writer at a time
+// can update the file. The new content goes into the lock, which is renamed
+// over the file on commit, or removed when dropped without committing.
+#[derive(Debug)]
+pub struct LockFile {
+    path: PathBuf,
+    lock: PathBuf,
+    file: Option<File>,
+}
+
+impl LockFile {
+    pub fn acquire<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut lock = path.clone().into_os_string();
+        lock.push(LOCK_SUFFIX);
+        let lock = PathBuf::from(lock);
+        if let Some(dir) = lock.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let file = match OpenOptions::new().write(true).create_new(true).open(&lock) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                let msg = format!(
+                    "Unable to create '{}': File exists.\n\n\
+                     Another git process seems to be running in this repository, e.g.\n\
+                     an editor opened by 'git commit'. Please make sure all processes\n\
+                     are terminated then try again. If it still fails, a git process\n\
+                     may have crashed in this repository earlier:\n\
+                     remove the file manually to continue.",
+                    lock.display()
+                );
+                return Err(Error::from(msg.as_str()));
+            }
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Self {
+            path,
+            lock,
+            file: Some(file),
+        })
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        let file = self.file.as_mut().expect("lock file must be open");
+        file.write_all(bytes)?;
+        Ok(())
+    }
+
+    pub fn commit(mut self) -> Result<()> {
+        if let Some(file) = self.file.take() {
+            file.sync_all()?;
+        }
+        fs::rename(&self.lock, &self.path)?;
+        Ok(())
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.lock);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn it_locks_files_exclusively() {
+        let dir = testing::TempDir::new("lock");
+        let path = dir.join("ref");
+
+        let mut lock = LockFile::acquire(&path).unwrap();
+        assert!(LockFile::acquire(&path).is_err());
+        lock.write(b"one\n").unwrap();
+        lock.commit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\n");
+
+        let mut lock = LockFile::acquire(&path).unwrap();
+        lock.write(b"two\n").unwrap();
+        drop(lock);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\n");
+        assert!(!dir.join("ref.lock").exists());
+    }
+}
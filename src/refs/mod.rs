@@ -0,0 +1,444 @@
+mod lock;
+mod packed;
+
+use super::{Error, Result, GIT_DIR, GIT_REF_DIR};
+pub use lock::LockFile;
+pub use packed::{PackedRef, PackedRefs};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+pub const HEAD: &str = "HEAD";
+const SYMREF_PREFIX: &str = "ref: ";
+const BRANCH_PREFIX: &str = "refs/heads/";
+const LOCK_SUFFIX: &str = ".lock";
+const MAX_SYMREF_DEPTH: usize = 5;
+
+// NOTE:
+// What a ref has to hold for an update to go through.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Expected<'a> {
+    Any,
+    Missing,
+    Hash(&'a str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Hash(String),
+    Symbolic(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Head {
+    Branch(String),
+    Detached(String),
+}
+
+impl Head {
+    // NOTE:
+    // The ref to move when committing on top of HEAD.
+    pub fn target(&self) -> &str {
+        match self {
+            Self::Branch(refname) => refname.as_str(),
+            Self::Detached(_) => HEAD,
+        }
+    }
+
+    pub fn branch_name(&self) -> Option<&str> {
+        match self {
+            Self::Branch(refname) => Some(short_name(refname)),
+            Self::Detached(_) => None,
+        }
+    }
+}
+
+pub fn head<P: AsRef<Path>>(root: P) -> Result<Head> {
+    let content = fs::read_to_string(root.as_ref().join(GIT_DIR).join(HEAD))?;
+    let content = content.trim_end();
+    match content.strip_prefix(SYMREF_PREFIX) {
+        Some(refname) => Ok(Head::Branch(refname.into())),
+        None => Ok(Head::Detached(content.into())),
+    }
+}
+
+// NOTE:
+// Returns None for a branch that has no commit yet (like the one HEAD points to
+// right after init). Symbolic refs are followed, and a loose ref takes
+// precedence over the same ref in "packed-refs".
+pub fn resolve<P: AsRef<Path>>(root: P, refname: &str) -> Result<Option<String>> {
+    let root = root.as_ref();
+    let mut refname = refname.to_string();
+    for _ in 0..MAX_SYMREF_DEPTH {
+        match read_loose(root, &refname)? {
+            Some(Value::Symbolic(target)) => refname = target,
+            Some(Value::Hash(hash)) => return Ok(Some(hash)),
+            None => {
+                let packed = PackedRefs::read(root)?;
+                return Ok(packed.find(&refname).map(|r| r.hash().to_string()));
+            }
+        }
+    }
+    let msg = format!("symbolic ref nested too deeply: {refname}");
+    Err(Error::from(msg.as_str()))
+}
+
+pub fn write<P: AsRef<Path>>(root: P, refname: &str, hash: &str) -> Result<()> {
+    update(root, refname, hash, Expected::Any)
+}
+
+// NOTE:
+// Points the ref itself at the hash, even when it's a symbolic ref, if it
+// still has the expected value once locked. The lock keeps other writers out
+// until the new value is in place.
+pub fn update<P: AsRef<Path>>(
+    root: P,
+    refname: &str,
+    hash: &str,
+    expected: Expected,
+) -> Result<()> {
+    let root = root.as_ref();
+    if hash.len() != 40 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        let msg = format!("Cannot update {refname} to invalid object name {hash}");
+        return Err(Error::from(msg.as_str()));
+    }
+
+    let mut lock = lock(root, refname)?;
+    let current = match read_loose(root, refname)? {
+        Some(Value::Symbolic(_)) => resolve(root, refname)?,
+        Some(Value::Hash(hash)) => Some(hash),
+        None => PackedRefs::read(root)?
+            .find(refname)
+            .map(|r| r.hash().to_string()),
+    };
+    let conflict = match (expected, current.as_deref()) {
+        (Expected::Any, _) | (Expected::Missing, None) => None,
+        (Expected::Missing, Some(_)) => Some("reference already exists".to_string()),
+        (Expected::Hash(old), Some(current)) if old == current => None,
+        (Expected::Hash(old), Some(current)) => Some(format!("is at {current} but expected {old}")),
+        (Expected::Hash(_), None) => Some(format!("unable to resolve reference '{refname}'")),
+    };
+    if let Some(conflict) = conflict {
+        let msg = format!("cannot lock ref '{refname}': {conflict}");
+        return Err(Error::from(msg.as_str()));
+    }
+
+    lock.write(format!("{hash}\n").as_bytes())?;
+    lock.commit()
+}
+
+pub fn write_symbolic<P: AsRef<Path>>(root: P, name: &str, target: &str) -> Result<()> {
+    let mut lock = lock(root.as_ref(), name)?;
+    lock.write(format!("{SYMREF_PREFIX}{target}\n").as_bytes())?;
+    lock.commit()
+}
+
+pub fn write_head<P: AsRef<Path>>(root: P, head: &Head) -> Result<()> {
+    match head {
+        Head::Branch(refname) => write_symbolic(root, HEAD, refname),
+        Head::Detached(hash) => write(root, HEAD, hash),
+    }
+}
+
+// NOTE:
+// Removes the ref from the loose refs and from "packed-refs", along with the
+// directories it leaves empty, except for the top ones like "refs/heads".
+pub fn delete<P: AsRef<Path>>(root: P, refname: &str) -> Result<()> {
+    let root = root.as_ref();
+    let lock = lock(root, refname)?;
+    let path = root.join(GIT_DIR).join(refname);
+    if path.is_file() {
+        fs::remove_file(&path)?;
+    }
+    let mut packed = PackedRefs::read(root)?;
+    if packed.remove(refname) {
+        packed.write(root)?;
+    }
+    drop(lock);
+
+    let top = root.join(GIT_REF_DIR);
+    let mut dir = path.parent();
+    while let Some(path) = dir.filter(|dir| dir.parent() != Some(top.as_path())) {
+        if !path.starts_with(&top) || fs::remove_dir(path).is_err() {
+            break;
+        }
+        dir = path.parent();
+    }
+    Ok(())
+}
+
+// NOTE:
+// Lists the refs under the prefix (like "refs/heads/") with their hashes,
+// sorted by name. Symbolic refs are followed.
+pub fn list<P: AsRef<Path>>(root: P, prefix: &str) -> Result<Vec<(String, String)>> {
+    let root = root.as_ref();
+    let mut refs: BTreeMap<String, String> = PackedRefs::read(root)?
+        .refs()
+        .iter()
+        .filter(|r| r.name().starts_with(prefix))
+        .map(|r| (r.name().to_string(), r.hash().to_string()))
+        .collect();
+
+    let mut stack: Vec<String> = vec![prefix.trim_end_matches('/').to_string()];
+    while let Some(dir) = stack.pop() {
+        let Ok(entries) = fs::read_dir(root.join(GIT_DIR).join(&dir)) else {
+            continue;
+        };
+        for entry in entries {
+            let entry = entry?;
+            let refname = format!("{dir}/{}", entry.file_name().to_string_lossy());
+            if entry.file_type()?.is_dir() {
+                stack.push(refname);
+            } else if refname.ends_with(LOCK_SUFFIX) {
+                continue;
+            } else if let Some(hash) = resolve(root, &refname)? {
+                refs.insert(refname, hash);
+            }
+        }
+    }
+    Ok(refs.into_iter().collect())
+}
+
+// NOTE:
+// Locks the loose ref, refusing names that clash with existing refs as a file
+// and a directory, like "refs/heads/a" and "refs/heads/a/b".
+fn lock(root: &Path, refname: &str) -> Result<LockFile> {
+    let packed = PackedRefs::read(root)?;
+    let exists =
+        |name: &str| root.join(GIT_DIR).join(name).is_file() || packed.find(name).is_some();
+    let parent = refname
+        .match_indices('/')
+        .map(|(i, _)| &refname[..i])
+        .find(|name| exists(name));
+    let child = match root.join(GIT_DIR).join(refname).is_dir() {
+        true => list(root, &format!("{refname}/"))?.into_iter().next(),
+        false => packed
+            .refs()
+            .iter()
+            .find(|r| r.name().starts_with(&format!("{refname}/")))
+            .map(|r| (r.name().to_string(), r.hash().to_string())),
+    };
+    let clash = match (parent, child) {
+        (Some(parent), _) => Some(format!("'{parent}' exists; cannot create '{refname}'")),
+        (None, Some((child, _))) => Some(format!("'{child}' exists; cannot create '{refname}'")),
+        (None, None) => None,
+    };
+    if let Some(clash) = clash {
+        let msg = format!("cannot lock ref '{refname}': {clash}");
+        return Err(Error::from(msg.as_str()));
+    }
+
+    LockFile::acquire(root.join(GIT_DIR).join(refname)).map_err(|err| {
+        let msg = format!("cannot lock ref '{refname}': {err}");
+        Error::from(msg.as_str())
+    })
+}
+
+fn read_loose(root: &Path, refname: &str) -> Result<Option<Value>> {
+    let path = root.join(GIT_DIR).join(refname);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path)?;
+    let content = content.trim_end();
+    Ok(Some(match content.strip_prefix(SYMREF_PREFIX) {
+        Some(target) => Value::Symbolic(target.into()),
+        None => Value::Hash(content.into()),
+    }))
+}
+
+pub fn branch_ref(name: &str) -> String {
+    format!("{BRANCH_PREFIX}{name}")
+}
+
+pub fn short_name(refname: &str) -> &str {
+    refname.strip_prefix(BRANCH_PREFIX).unwrap_or(refname)
+}
+
+pub fn check_branch_name(name: &str) -> Result<()> {
+    if is_valid_name(name) && name != HEAD {
+        Ok(())
+    } else {
+        let msg = format!("'{name}' is not a valid branch name");
+        Err(Error::from(msg.as_str()))
+    }
+}
+
+pub fn check_tag_name(name: &str) -> Result<()> {
+    if is_valid_name(name) {
+        Ok(())
+    } else {
+        let msg = format!("'{name}' is not a valid tag name.");
+        Err(Error::from(msg.as_str()))
+    }
+}
+
+// NOTE:
+// The rules of "git check-ref-format" that matter in practice.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('-')
+        && !name.starts_with('/')
+        && !name.ends_with('/')
+        && !name.ends_with('.')
+        && !name.ends_with(".lock")
+        && !name.contains("..")
+        && !name.contains("//")
+        && !name.contains("@{")
+        && !name.contains("/.")
+        && !name.starts_with('.')
+        && name != "@"
+        && !name
+            .chars()
+            .any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const HASH: &str = "8119b90c6adef211483e6dcf1a3c89e966af9c60";
+    const OTHER: &str = "b521b9179412d90a893bc36f33f5dcfd987105ef";
+
+    #[test]
+    fn it_reads_symbolic_head() {
+        let root = testing::git_dir("refs-symbolic");
+        fs::write(root.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+
+        let head = head(&root).unwrap();
+        assert_eq!(head, Head::Branch("refs/heads/main".into()));
+        assert_eq!(head.branch_name(), Some("main"));
+        assert_eq!(resolve(&root, HEAD).unwrap(), None);
+
+        write(&root, "refs/heads/main", HASH).unwrap();
+        assert_eq!(resolve(&root, HEAD).unwrap(), Some(HASH.into()));
+    }
+
+    #[test]
+    fn it_reads_detached_head() {
+        let root = testing::git_dir("refs-detached");
+        fs::write(root.join(".git/HEAD"), format!("{HASH}\n")).unwrap();
+
+        let head = head(&root).unwrap();
+        assert_eq!(head, Head::Detached(HASH.into()));
+        assert_eq!(head.target(), HEAD);
+        assert_eq!(resolve(&root, HEAD).unwrap(), Some(HASH.into()));
+    }
+
+    #[test]
+    fn it_writes_head_and_lists_refs() {
+        let root = testing::git_dir("refs-list");
+        write(&root, "refs/heads/main", HASH).unwrap();
+        write(&root, "refs/heads/feature/x", HASH).unwrap();
+        write(&root, "refs/tags/v1", HASH).unwrap();
+        write_head(&root, &Head::Branch(branch_ref("feature/x"))).unwrap();
+        assert_eq!(
+            head(&root).unwrap(),
+            Head::Branch("refs/heads/feature/x".into())
+        );
+
+        let names: Vec<String> = list(&root, "refs/heads/")
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, vec!["refs/heads/feature/x", "refs/heads/main"]);
+
+        delete(&root, "refs/heads/feature/x").unwrap();
+        assert!(!root.join(".git/refs/heads/feature").exists());
+        delete(&root, "refs/heads/main").unwrap();
+        assert!(root.join(".git/refs/heads").is_dir());
+        assert!(list(&root, "refs/heads/").unwrap().is_empty());
+    }
+
+    #[test]
+    fn it_checks_branch_names() {
+        assert!(check_branch_name("feature/x-1").is_ok());
+        for name in [
+            "", "-b", "a..b", "a b", "a.lock", "a/", "HEAD", "a@{1}", "x~1",
+        ] {
+            assert!(check_branch_name(name).is_err(), "{name}");
+        }
+        assert!(check_tag_name("v1.0").is_ok());
+        assert!(check_tag_name("bad..name").is_err());
+    }
+
+    #[test]
+    fn it_rejects_invalid_hash() {
+        let root = testing::git_dir("refs-invalid");
+        assert!(write(&root, "refs/heads/main", "abc").is_err());
+    }
+
+    #[test]
+    fn it_falls_back_to_packed_refs() {
+        let root = testing::git_dir("refs-packed");
+        let mut packed = PackedRefs::read(&root).unwrap();
+        packed.insert(PackedRef::new("refs/heads/main".into(), HASH.into(), None));
+        packed.insert(PackedRef::new("refs/tags/v1".into(), HASH.into(), None));
+        packed.write(&root).unwrap();
+        write(&root, "refs/heads/main", OTHER).unwrap();
+
+        assert_eq!(
+            resolve(&root, "refs/heads/main").unwrap(),
+            Some(OTHER.into())
+        );
+        assert_eq!(resolve(&root, "refs/tags/v1").unwrap(), Some(HASH.into()));
+        assert_eq!(
+            list(&root, "refs/").unwrap(),
+            vec![
+                ("refs/heads/main".into(), OTHER.into()),
+                ("refs/tags/v1".into(), HASH.into())
+            ]
+        );
+
+        delete(&root, "refs/tags/v1").unwrap();
+        assert_eq!(resolve(&root, "refs/tags/v1").unwrap(), None);
+        assert!(PackedRefs::read(&root)
+            .unwrap()
+            .find("refs/tags/v1")
+            .is_none());
+    }
+
+    #[test]
+    fn it_updates_refs_only_from_the_expected_value() {
+        let root = testing::git_dir("refs-expected");
+        update(&root, "refs/heads/main", HASH, Expected::Missing).unwrap();
+        assert!(update(&root, "refs/heads/main", OTHER, Expected::Missing).is_err());
+        assert!(update(&root, "refs/heads/main", OTHER, Expected::Hash(OTHER)).is_err());
+        update(&root, "refs/heads/main", OTHER, Expected::Hash(HASH)).unwrap();
+        assert_eq!(
+            resolve(&root, "refs/heads/main").unwrap(),
+            Some(OTHER.into())
+        );
+
+        assert!(write(&root, "refs/heads/main/x", HASH).is_err());
+        assert!(write(&root, "refs/heads", HASH).is_err());
+
+        fs::write(root.join(".git/refs/heads/main.lock"), "").unwrap();
+        assert!(write(&root, "refs/heads/main", HASH).is_err());
+        assert_eq!(
+            resolve(&root, "refs/heads/main").unwrap(),
+            Some(OTHER.into())
+        );
+        assert_eq!(list(&root, "refs/heads/").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn it_follows_symbolic_refs() {
+        let root = testing::git_dir("refs-symref");
+        write(&root, "refs/remotes/origin/main", HASH).unwrap();
+        write_symbolic(
+            &root,
+            "refs/remotes/origin/HEAD",
+            "refs/remotes/origin/main",
+        )
+        .unwrap();
+        write_symbolic(&root, HEAD, "refs/remotes/origin/HEAD").unwrap();
+        assert_eq!(resolve(&root, HEAD).unwrap(), Some(HASH.into()));
+
+        write_symbolic(&root, "refs/heads/a", "refs/heads/b").unwrap();
+        write_symbolic(&root, "refs/heads/b", "refs/heads/a").unwrap();
+        assert!(resolve(&root, "refs/heads/a").is_err());
+    }
+}
@@ -0,0 +1,155 @@
+use super::lock::LockFile;
+use crate::{Error, Result, GIT_DIR};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const PACKED_REFS: &str = "packed-refs";
+const HEADER: &str = "# pack-refs with: peeled fully-peeled sorted \n";
+
+// NOTE:
+// A ref in "packed-refs", with the object an annotated tag peels to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackedRef {
+    name: String,
+    hash: String,
+    peeled: Option<String>,
+}
+
+impl PackedRef {
+    pub fn new(name: String, hash: String, peeled: Option<String>) -> Self {
+        Self { name, hash, peeled }
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn hash(&self) -> &str {
+        self.hash.as_str()
+    }
+
+    #[cfg(test)]
+    pub fn peeled(&self) -> Option<&str> {
+        self.peeled.as_deref()
+    }
+}
+
+// NOTE:
+// The file has a "<hash> <refname>" line for each ref sorted by name, followed
+// by a "^<hash>" line when the ref is an annotated tag. Loose refs take
+// precedence over the ones in it.
+#[derive(Debug, Default)]
+pub struct PackedRefs {
+    refs: Vec<PackedRef>,
+}
+
+impl PackedRefs {
+    pub fn read<P: AsRef<Path>>(root: P) -> Result<Self> {
+        let path = path(root.as_ref());
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+
+        let mut refs: Vec<PackedRef> = vec![];
+        for (i, line) in fs::read_to_string(&path)?.lines().enumerate() {
+            if line.starts_with('#') || line.is_empty() {
+                continue;
+            }
+            let parsed = match line.strip_prefix('^') {
+                Some(peeled) => refs.last_mut().map(|r| r.peeled = Some(peeled.into())),
+                None => line.split_once(' ').map(|(hash, name)| {
+                    refs.push(PackedRef::new(name.into(), hash.into(), None));
+                }),
+            };
+            if parsed.is_none() {
+                let msg = format!("unexpected line in {}: {}", path.display(), i + 1);
+                return Err(Error::from(msg.as_str()));
+            }
+        }
+        refs.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Self { refs })
+    }
+
+    pub fn refs(&self) -> &[PackedRef] {
+        &self.refs
+    }
+
+    pub fn find(&self, name: &str) -> Option<&PackedRef> {
+        self.refs
+            .binary_search_by(|r| r.name.as_str().cmp(name))
+            .ok()
+            .map(|i| &self.refs[i])
+    }
+
+    // NOTE:
+    // A ref of the same name is replaced.
+    pub fn insert(&mut self, packed: PackedRef) {
+        match self.refs.binary_search_by(|r| r.name.cmp(&packed.name)) {
+            Ok(i) => self.refs[i] = packed,
+            Err(i) => self.refs.insert(i, packed),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.refs.len();
+        self.refs.retain(|r| r.name != name);
+        self.refs.len() != len
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, root: P) -> Result<()> {
+        let mut lock = LockFile::acquire(path(root.as_ref()))?;
+        lock.write(self.serialize().as_bytes())?;
+        lock.commit()
+    }
+
+    fn serialize(&self) -> String {
+        let mut text = HEADER.to_string();
+        for packed in self.refs.iter() {
+            text.push_str(&format!("{} {}\n", packed.hash, packed.name));
+            if let Some(peeled) = packed.peeled.as_ref() {
+                text.push_str(&format!("^{peeled}\n"));
+            }
+        }
+        text
+    }
+}
+
+fn path(root: &Path) -> PathBuf {
+    root.join(GIT_DIR).join(PACKED_REFS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const TAG: &str = "8119b90c6adef211483e6dcf1a3c89e966af9c60";
+    const COMMIT: &str = "b521b9179412d90a893bc36f33f5dcfd987105ef";
+
+    #[test]
+    fn it_reads_and_writes_packed_refs() {
+        let root = testing::git_dir("packed");
+        fs::write(
+            path(&root),
+            format!("# pack-refs with: peeled\n{TAG} refs/tags/v1\n^{COMMIT}\n{COMMIT} refs/heads/main\n"),
+        )
+        .unwrap();
+
+        let mut packed = PackedRefs::read(&root).unwrap();
+        let names: Vec<&str> = packed.refs().iter().map(PackedRef::name).collect();
+        assert_eq!(names, vec!["refs/heads/main", "refs/tags/v1"]);
+        assert_eq!(packed.find("refs/tags/v1").unwrap().peeled(), Some(COMMIT));
+        assert_eq!(packed.find("refs/heads/main").unwrap().peeled(), None);
+
+        assert!(packed.remove("refs/heads/main"));
+        packed.insert(PackedRef::new("refs/heads/a".into(), COMMIT.into(), None));
+        packed.write(&root).unwrap();
+        assert_eq!(
+            fs::read_to_string(path(&root)).unwrap(),
+            format!("{HEADER}{COMMIT} refs/heads/a\n{TAG} refs/tags/v1\n^{COMMIT}\n")
+        );
+
+        fs::write(path(&root), "^dangling\n").unwrap();
+        assert!(PackedRefs::read(&root).is_err());
+    }
+}
@@ -0,0 +1,626 @@
+use super::{
+    config::Config,
+    date, history,
+    index::Index,
+    odb::ObjectDatabase,
+    reflog,
+    refs::{self, Head, HEAD},
+    Error, GitObject, Result,
+};
+use std::path::Path;
+
+const MIN_ABBREV: usize = 4;
+const DEFAULT_ABBREV: usize = 7;
+const BRANCH_PREFIX: &str = "refs/heads/";
+const MIN_TIMESTAMP: usize = 100000000;
+
+// NOTE:
+// The order git tries a name in, like "main" as "refs/heads/main".
+const REF_RULES: [&str; 6] = [
+    "{}",
+    "refs/{}",
+    "refs/tags/{}",
+    "refs/heads/{}",
+    "refs/remotes/{}",
+    "refs/remotes/{}/HEAD",
+];
+
+// NOTE:
+// A commit given to a command walking the history. "^A" and the left side of
+// "A..B" are excluded with their ancestors.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rev {
+    Include(String),
+    Exclude(String),
+}
+
+// NOTE:
+// Resolves a revision like "HEAD~2", "v1.0^{tree}", "a1b2c3" or "main:src/lib.rs"
+// to the full hash of the object it names.
+pub fn resolve<P: AsRef<Path>>(root: P, rev: &str) -> Result<String> {
+    lookup(root.as_ref(), rev)?.ok_or_else(|| unknown(rev))
+}
+
+pub fn resolve_commit<P: AsRef<Path>>(root: P, rev: &str) -> Result<String> {
+    let root = root.as_ref();
+    let hash = resolve(root, rev)?;
+    peel(root, &hash, Some("commit"))?.ok_or_else(|| {
+        let msg = format!("{rev}: expected commit type");
+        Error::from(msg.as_str())
+    })
+}
+
+pub fn resolve_tree<P: AsRef<Path>>(root: P, rev: &str) -> Result<String> {
+    let root = root.as_ref();
+    let hash = resolve(root, rev)?;
+    peel(root, &hash, Some("tree"))?.ok_or_else(|| {
+        let msg = format!("not a tree object: {rev}");
+        Error::from(msg.as_str())
+    })
+}
+
+// NOTE:
+// "-" and "@{-N}" name the branch or commit checked out N switches ago, read
+// from the "checkout: moving from <old> to <new>" entries of the log of HEAD.
+// Any other name is given back as it is.
+pub fn expand_previous<P: AsRef<Path>>(root: P, name: &str) -> Result<String> {
+    let spec = match name {
+        "-" => "1",
+        name => match name.strip_prefix("@{-").and_then(|s| s.strip_suffix('}')) {
+            Some(spec) => spec,
+            None => return Ok(name.to_string()),
+        },
+    };
+    let previous = match spec.parse::<usize>() {
+        Ok(n) if n > 0 => reflog::read(root, HEAD)?
+            .iter()
+            .rev()
+            .filter_map(|entry| {
+                let message = entry.message().strip_prefix("checkout: moving from ")?;
+                let (from, _) = message.split_once(" to ")?;
+                Some(from.to_string())
+            })
+            .nth(n - 1),
+        _ => None,
+    };
+    previous.ok_or_else(|| {
+        let msg = format!("invalid reference: @{{-{spec}}}");
+        Error::from(msg.as_str())
+    })
+}
+
+// NOTE:
+// Expands "^A", "A..B" and "A...B" (the commits reachable from either side but
+// not from both). A missing side of a range is HEAD.
+pub fn parse_revs<P: AsRef<Path>>(root: P, arg: &str) -> Result<Vec<Rev>> {
+    let root = root.as_ref();
+    let side = |rev: &str| resolve_commit(root, if rev.is_empty() { HEAD } else { rev });
+
+    if let Some(rev) = arg.strip_prefix('^') {
+        return Ok(vec![Rev::Exclude(resolve_commit(root, rev)?)]);
+    }
+    if let Some((left, right)) = arg.split_once("...") {
+        let (left, right) = (side(left)?, side(right)?);
+        let mut revs = vec![Rev::Include(right.clone()), Rev::Include(left.clone())];
+        for base in history::merge_bases(root, &left, &right)? {
+            revs.push(Rev::Exclude(base));
+        }
+        return Ok(revs);
+    }
+    if let Some((left, right)) = arg.split_once("..") {
+        return Ok(vec![Rev::Include(side(right)?), Rev::Exclude(side(left)?)]);
+    }
+    Ok(vec![Rev::Include(resolve(root, arg)?)])
+}
+
+// NOTE:
+// The full name of the ref a plain name resolves to, like "refs/heads/main"
+// for "main". HEAD is returned as it is.
+pub fn dwim_ref<P: AsRef<Path>>(root: P, name: &str) -> Result<Option<String>> {
+    let root = root.as_ref();
+    let name = if name == "@" { HEAD } else { name };
+    for rule in REF_RULES {
+        let refname = rule.replace("{}", name);
+        if rule == "{}" && !is_plain_ref(&refname) {
+            continue;
+        }
+        if refs::resolve(root, &refname)?.is_some() {
+            return Ok(Some(refname));
+        }
+    }
+    Ok(None)
+}
+
+// NOTE:
+// The shortest prefix of the hash that is at least `min` long and names only
+// one object.
+pub fn abbrev<P: AsRef<Path>>(root: P, hash: &str, min: Option<usize>) -> Result<String> {
+    let odb = ObjectDatabase::open(root);
+    let min = min.unwrap_or(DEFAULT_ABBREV).clamp(MIN_ABBREV, hash.len());
+    for len in min..hash.len() {
+        if odb.find_prefix(&hash[..len])?.len() <= 1 {
+            return Ok(hash[..len].into());
+        }
+    }
+    Ok(hash.into())
+}
+
+fn lookup(root: &Path, rev: &str) -> Result<Option<String>> {
+    if let Some(pos) = colon(rev) {
+        let (rev, path) = (&rev[..pos], &rev[(pos + 1)..]);
+        return if rev.is_empty() {
+            lookup_index(root, path)
+        } else {
+            lookup_path(root, rev, path)
+        };
+    }
+
+    // NOTE:
+    // Ref names can't contain "~" or "^", so the suffixes start at the first one.
+    let end = rev.find(['~', '^']).unwrap_or(rev.len());
+    let (name, mut suffixes) = rev.split_at(end);
+    let Some(mut hash) = lookup_name(root, name)? else {
+        return Ok(None);
+    };
+
+    while let Some(op) = suffixes.chars().next() {
+        suffixes = &suffixes[1..];
+        if op == '^' && suffixes.starts_with('{') {
+            let Some(close) = suffixes.find('}') else {
+                return Ok(None);
+            };
+            let kind = &suffixes[1..close];
+            suffixes = &suffixes[(close + 1)..];
+            let peeled = match kind {
+                "" => peel(root, &hash, None)?,
+                "object" => Some(hash),
+                "commit" | "tree" | "blob" | "tag" => peel(root, &hash, Some(kind))?,
+                _ => None,
+            };
+            let Some(peeled) = peeled else {
+                return Ok(None);
+            };
+            hash = peeled;
+            continue;
+        }
+
+        let digits = suffixes.len()
+            - suffixes
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .len();
+        let n: usize = match digits {
+            0 => 1,
+            _ => suffixes[..digits].parse()?,
+        };
+        suffixes = &suffixes[digits..];
+
+        let Some(commit) = peel(root, &hash, Some("commit"))? else {
+            return Ok(None);
+        };
+        hash = commit;
+        if op == '~' {
+            for _ in 0..n {
+                let commit = GitObject::open_commit(root, &hash)?;
+                match commit.parents().first() {
+                    Some(parent) => hash = parent.clone(),
+                    None => return Ok(None),
+                }
+            }
+        } else if n > 0 {
+            let commit = GitObject::open_commit(root, &hash)?;
+            match commit.parents().get(n - 1) {
+                Some(parent) => hash = parent.clone(),
+                None => return Ok(None),
+            }
+        }
+    }
+    Ok(Some(hash))
+}
+
+// NOTE:
+// A full hash, a ref name, "<branch>@{upstream}" or an abbreviated hash, in
+// the order git tries them.
+fn lookup_name(root: &Path, name: &str) -> Result<Option<String>> {
+    if name.is_empty() {
+        return Ok(None);
+    }
+    if name.len() == 40 && is_hex(name) {
+        return Ok(Some(name.to_lowercase()));
+    }
+    if let Some(branch) = strip_upstream(name) {
+        let refname = upstream(root, branch)?;
+        return refs::resolve(root, &refname);
+    }
+    if let Some((name, spec)) = strip_reflog(name) {
+        return lookup_reflog(root, name, spec);
+    }
+    if let Some(refname) = dwim_ref(root, name)? {
+        return refs::resolve(root, &refname);
+    }
+
+    if name.len() >= MIN_ABBREV && is_hex(name) {
+        let hashes = ObjectDatabase::open(root).find_prefix(name)?;
+        if hashes.len() > 1 {
+            let msg = format!("short object ID {name} is ambiguous");
+            return Err(Error::from(msg.as_str()));
+        }
+        return Ok(hashes.first().map(|hash| hash.hex()));
+    }
+    Ok(None)
+}
+
+// NOTE:
+// "<ref>@{N}" is the value the ref had N updates ago and "<ref>@{<date>}" the
+// value it had at the date, both read from its log. Without a ref, it's the
+// log of the current branch.
+fn lookup_reflog(root: &Path, name: &str, spec: &str) -> Result<Option<String>> {
+    let refname = match name {
+        "" => refs::head(root)?.target().to_string(),
+        name => match dwim_ref(root, name)? {
+            Some(refname) => refname,
+            None => return Ok(None),
+        },
+    };
+    let shown = if name.is_empty() {
+        refs::short_name(&refname)
+    } else {
+        name
+    };
+
+    let entries = reflog::read(root, &refname)?;
+    // NOTE:
+    // A number too large to be a count is a timestamp.
+    let count = spec.parse::<usize>().ok().filter(|n| *n < MIN_TIMESTAMP);
+    let Some(oldest) = entries.first() else {
+        if count == Some(0) {
+            return refs::resolve(root, &refname);
+        }
+        let msg = format!("log for {refname} is empty");
+        return Err(Error::from(msg.as_str()));
+    };
+    // NOTE:
+    // The oldest entry also tells the value the ref had before it.
+    let before = match oldest.old() {
+        reflog::NULL_HASH => oldest.hash(),
+        old => old,
+    };
+
+    if let Some(count) = count {
+        if let Some(entry) = entries.iter().rev().nth(count) {
+            return Ok(Some(entry.hash().into()));
+        }
+        if count == entries.len() && oldest.old() != reflog::NULL_HASH {
+            return Ok(Some(before.into()));
+        }
+        let msg = format!("log for '{shown}' only has {} entries", entries.len());
+        return Err(Error::from(msg.as_str()));
+    }
+
+    let time = date::approxidate(spec, date::now())?;
+    let entry = entries
+        .iter()
+        .rev()
+        .find(|entry| entry.committer().timestamp() <= time);
+    if let Some(entry) = entry {
+        return Ok(Some(entry.hash().into()));
+    }
+    let committer = oldest.committer();
+    eprintln!(
+        "warning: log for '{shown}' only goes back to {}",
+        date::format_rfc2822(committer.timestamp(), committer.offset())
+    );
+    Ok(Some(before.into()))
+}
+
+fn lookup_path(root: &Path, rev: &str, path: &str) -> Result<Option<String>> {
+    let Some(tree) = lookup(root, rev)? else {
+        return Ok(None);
+    };
+    let Some(tree) = peel(root, &tree, Some("tree"))? else {
+        return Ok(None);
+    };
+
+    let path = path.trim_matches('/');
+    if path.is_empty() {
+        return Ok(Some(tree));
+    }
+    match GitObject::find_in_tree(root, &tree, path)? {
+        Some(node) => Ok(Some(node.hash().hex())),
+        None => {
+            let msg = format!("path '{path}' does not exist in '{rev}'");
+            Err(Error::from(msg.as_str()))
+        }
+    }
+}
+
+// NOTE:
+// ":path" is the staged blob and ":N:path" the blob at stage N of a conflict.
+fn lookup_index(root: &Path, path: &str) -> Result<Option<String>> {
+    let (stage, path) = match path.split_once(':') {
+        Some((stage, path)) if stage.len() == 1 => match stage.parse::<u8>() {
+            Ok(stage) if stage <= 3 => (stage, path),
+            _ => (0, path),
+        },
+        _ => (0, path),
+    };
+
+    let index = Index::open(root)?;
+    let entry = index
+        .entries()
+        .iter()
+        .find(|entry| entry.path() == path && entry.stage() == stage);
+    match entry {
+        Some(entry) => Ok(Some(entry.hash().hex())),
+        None if stage == 0 => {
+            let msg = format!("path '{path}' does not exist in the index");
+            Err(Error::from(msg.as_str()))
+        }
+        None => {
+            let msg = format!("path '{path}' is not at stage {stage} in the index");
+            Err(Error::from(msg.as_str()))
+        }
+    }
+}
+
+// NOTE:
+// Dereferences tags (and commits to their tree) until the object is of the
+// kind. Without a kind, only tags are dereferenced.
+fn peel(root: &Path, hash: &str, kind: Option<&str>) -> Result<Option<String>> {
+    let mut hash = hash.to_string();
+    loop {
+        let obj = GitObject::open_from_hash(root, &hash)?;
+        if kind.map_or(!matches!(obj, GitObject::Tag(_)), |kind| {
+            obj.type_name() == kind
+        }) {
+            return Ok(Some(hash));
+        }
+        hash = match obj {
+            GitObject::Tag(tag) => tag.object().to_string(),
+            GitObject::Commit(commit) if kind == Some("tree") => commit.tree().to_string(),
+            _ => return Ok(None),
+        };
+    }
+}
+
+// NOTE:
+// The remote tracking ref of the branch from "branch.<name>.remote" and
+// "branch.<name>.merge". An empty name is the current branch.
+pub fn upstream<P: AsRef<Path>>(root: P, branch: &str) -> Result<String> {
+    let root = root.as_ref();
+    let branch = match branch {
+        "" | HEAD => match refs::head(root)? {
+            Head::Branch(refname) => refs::short_name(&refname).to_string(),
+            Head::Detached(_) => return Err(Error::from("HEAD does not point to a branch")),
+        },
+        branch => branch.to_string(),
+    };
+
+    let config = Config::load(root)?;
+    let remote = config.get(&format!("branch.{branch}.remote"));
+    let merge = config.get(&format!("branch.{branch}.merge"));
+    let (Some(remote), Some(merge)) = (remote, merge) else {
+        let msg = format!("no upstream configured for branch '{branch}'");
+        return Err(Error::from(msg.as_str()));
+    };
+
+    if remote == "." {
+        return Ok(merge.to_string());
+    }
+    let name = merge.strip_prefix(BRANCH_PREFIX).unwrap_or(merge);
+    Ok(format!("refs/remotes/{remote}/{name}"))
+}
+
+fn strip_upstream(name: &str) -> Option<&str> {
+    let (branch, suffix) = name.split_once("@{")?;
+    let suffix = suffix.strip_suffix('}')?.to_lowercase();
+    matches!(suffix.as_str(), "u" | "upstream").then_some(branch)
+}
+
+fn strip_reflog(name: &str) -> Option<(&str, &str)> {
+    let (name, suffix) = name.split_once("@{")?;
+    let spec = suffix.strip_suffix('}')?;
+    (!spec.is_empty() && !spec.starts_with('-')).then_some((name, spec))
+}
+
+// NOTE:
+// The position of the ":" separating a path, skipping the ones in "@{...}" and
+// "^{...}".
+fn colon(rev: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in rev.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ':' if depth == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+// NOTE:
+// Only names under refs/ and all caps names like HEAD or ORIG_HEAD are looked
+// up as they are, so that "config" doesn't read .git/config.
+fn is_plain_ref(name: &str) -> bool {
+    name.starts_with("refs/")
+        || (!name.is_empty() && name.bytes().all(|b| b.is_ascii_uppercase() || b == b'_'))
+}
+
+fn is_hex(name: &str) -> bool {
+    name.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn unknown(rev: &str) -> Error {
+    let msg =
+        format!("ambiguous argument '{rev}': unknown revision or path not in the working tree.");
+    Error::from(msg.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::{git_object::commit::User, GIT_DIR};
+    use std::fs;
+
+    fn commit(root: &Path, tree: &str, parents: &[&str], timestamp: u64) -> String {
+        let user = User::new("A".into(), "a@x".into(), timestamp, "+0000".into());
+        let obj = GitObject::new_commit(
+            tree.into(),
+            format!("commit {timestamp}"),
+            parents.iter().map(|p| p.to_string()).collect(),
+            user.clone(),
+            user,
+        )
+        .unwrap();
+        obj.write(root).unwrap();
+        obj.hash().hex()
+    }
+
+    #[test]
+    fn it_resolves_names_and_ancestry() {
+        let root = testing::repo("revision-ancestry");
+        let tree = testing::write_tree(&root, &[("a.txt", "a")]);
+        let base = commit(&root, &tree, &[], 100);
+        let side = commit(&root, &tree, &[&base], 200);
+        let main = commit(&root, &tree, &[&base], 300);
+        let merge = commit(&root, &tree, &[&main, &side], 400);
+        refs::write(&root, "refs/heads/main", &merge).unwrap();
+        refs::write(&root, "refs/tags/v1", &side).unwrap();
+
+        assert_eq!(resolve(&root, "HEAD").unwrap(), merge);
+        assert_eq!(resolve(&root, "@").unwrap(), merge);
+        assert_eq!(resolve(&root, "main^2").unwrap(), side);
+        assert_eq!(resolve(&root, "main^").unwrap(), main);
+        assert_eq!(resolve(&root, "main~2").unwrap(), base);
+        assert_eq!(resolve(&root, "main^2~").unwrap(), base);
+        assert_eq!(resolve(&root, "v1^0").unwrap(), side);
+        assert_eq!(resolve(&root, "v1^{tree}").unwrap(), tree);
+        assert_eq!(resolve(&root, &merge[..8]).unwrap(), merge);
+        assert!(resolve(&root, "main~3").is_err());
+        assert!(resolve(&root, "main^3").is_err());
+        assert!(resolve(&root, "config").is_err());
+    }
+
+    #[test]
+    fn it_resolves_paths_in_trees() {
+        let root = testing::repo("revision-path");
+        let tree = testing::write_tree(&root, &[("a.txt", "a")]);
+        let head = commit(&root, &tree, &[], 100);
+        refs::write(&root, "refs/heads/main", &head).unwrap();
+
+        let blob = GitObject::new_blob("a".as_bytes()).unwrap().hash().hex();
+        assert_eq!(resolve(&root, "HEAD:a.txt").unwrap(), blob);
+        assert_eq!(resolve(&root, "HEAD:").unwrap(), tree);
+        assert!(resolve(&root, "HEAD:nope").is_err());
+    }
+
+    #[test]
+    fn it_resolves_upstream() {
+        let root = testing::repo("revision-upstream");
+        let tree = testing::write_tree(&root, &[("a.txt", "a")]);
+        let head = commit(&root, &tree, &[], 100);
+        refs::write(&root, "refs/heads/main", &head).unwrap();
+        refs::write(&root, "refs/remotes/origin/main", &tree).unwrap();
+        assert!(resolve(&root, "@{u}").is_err());
+
+        fs::write(
+            root.join(GIT_DIR).join("config"),
+            "[branch \"main\"]\n\tremote = origin\n\tmerge = refs/heads/main\n",
+        )
+        .unwrap();
+        assert_eq!(resolve(&root, "@{u}").unwrap(), tree);
+        assert_eq!(resolve(&root, "main@{upstream}").unwrap(), tree);
+        assert_eq!(
+            resolve(&root, "origin").unwrap_err().to_string(),
+            unknown("origin").to_string()
+        );
+    }
+
+    #[test]
+    fn it_resolves_reflog_entries() {
+        let root = testing::repo("revision-reflog");
+        let tree = testing::write_tree(&root, &[("a.txt", "a")]);
+        let first = commit(&root, &tree, &[], 100);
+        let second = commit(&root, &tree, &[&first], 200);
+        refs::write(&root, "refs/heads/main", &second).unwrap();
+        for (old, new, timestamp) in [
+            (None, &first, 1700000000),
+            (Some(&first), &second, 1700100000),
+        ] {
+            let user = User::new("A".into(), "a@x".into(), timestamp, "+0000".into());
+            let old = old.map(String::as_str);
+            reflog::append(&root, "refs/heads/main", old, new, &user, "commit").unwrap();
+        }
+
+        assert_eq!(resolve(&root, "main@{0}").unwrap(), second);
+        assert_eq!(resolve(&root, "@{1}").unwrap(), first);
+        assert_eq!(resolve(&root, "main@{1}~0").unwrap(), first);
+        assert!(resolve(&root, "main@{2}").is_err());
+        assert_eq!(resolve(&root, "main@{1700050000}").unwrap(), first);
+        assert_eq!(resolve(&root, "main@{now}").unwrap(), second);
+        assert_eq!(resolve(&root, "main@{2000-01-01}").unwrap(), first);
+        assert!(resolve(&root, "HEAD@{1}").is_err());
+    }
+
+    #[test]
+    fn it_expands_previous_checkouts() {
+        let root = testing::repo("revision-previous");
+        let user = User::new("A".into(), "a@x".into(), 1700000000, "+0000".into());
+        let hash = "a".repeat(40);
+        for message in [
+            "commit (initial): first",
+            "checkout: moving from main to topic",
+            "commit: second",
+            "checkout: moving from topic to v1.0",
+        ] {
+            reflog::append(&root, HEAD, None, &hash, &user, message).unwrap();
+        }
+
+        assert_eq!(expand_previous(&root, "-").unwrap(), "topic");
+        assert_eq!(expand_previous(&root, "@{-1}").unwrap(), "topic");
+        assert_eq!(expand_previous(&root, "@{-2}").unwrap(), "main");
+        assert_eq!(expand_previous(&root, "main").unwrap(), "main");
+        assert_eq!(
+            expand_previous(&root, "@{-3}").unwrap_err().to_string(),
+            Error::from("invalid reference: @{-3}").to_string()
+        );
+        assert!(expand_previous(&root, "@{-0}").is_err());
+    }
+
+    #[test]
+    fn it_parses_ranges() {
+        let root = testing::repo("revision-range");
+        let tree = testing::write_tree(&root, &[("a.txt", "a")]);
+        let base = commit(&root, &tree, &[], 100);
+        let side = commit(&root, &tree, &[&base], 200);
+        let main = commit(&root, &tree, &[&base], 300);
+        refs::write(&root, "refs/heads/main", &main).unwrap();
+        refs::write(&root, "refs/heads/side", &side).unwrap();
+
+        assert_eq!(
+            parse_revs(&root, "side..").unwrap(),
+            vec![Rev::Include(main.clone()), Rev::Exclude(side.clone())]
+        );
+        assert_eq!(
+            parse_revs(&root, "main...side").unwrap(),
+            vec![
+                Rev::Include(side.clone()),
+                Rev::Include(main.clone()),
+                Rev::Exclude(base.clone())
+            ]
+        );
+        assert_eq!(
+            parse_revs(&root, "^main").unwrap(),
+            vec![Rev::Exclude(main)]
+        );
+    }
+
+    #[test]
+    fn it_abbreviates_hashes() {
+        let root = testing::repo("revision-abbrev");
+        let tree = testing::write_tree(&root, &[("a.txt", "a")]);
+        assert_eq!(abbrev(&root, &tree, None).unwrap(), tree[..7]);
+        assert_eq!(abbrev(&root, &tree, Some(1)).unwrap(), tree[..4]);
+    }
+}
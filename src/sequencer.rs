@@ -0,0 +1,725 @@
+use super::{
+    checkout::Operation,
+    diff::{
+        files::{self, Source},
+        patch::{self, Format},
+        Algorithm, DEFAULT_CONTEXT,
+    },
+    git_object::commit::{Commit, User},
+    index::Index,
+    merge::{self, TreeMerge},
+    pretty, revision, Error, Result, Sha1Hash, GIT_DIR,
+};
+use sha1::Digest;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const REBASE_HEAD: &str = "REBASE_HEAD";
+pub const CHERRY_PICK_HEAD: &str = "CHERRY_PICK_HEAD";
+pub const REVERT_HEAD: &str = "REVERT_HEAD";
+const REBASE_DIR: &str = "rebase-merge";
+const SEQUENCER_DIR: &str = "sequencer";
+const TODO: &str = "git-rebase-todo";
+const DONE: &str = "done";
+const TODO_SEQUENCE: &str = "todo";
+const OPTIONS: &str = "opts";
+const COMMENT: char = '#';
+
+// NOTE:
+// The commands of a todo list, which can be spelled out or abbreviated to
+// their first letter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Pick,
+    Reword,
+    Edit,
+    Squash,
+    Fixup,
+    Exec,
+    Break,
+    Drop,
+    Revert,
+}
+
+impl Command {
+    fn parse(word: &str) -> Option<Self> {
+        match word {
+            "p" | "pick" => Some(Self::Pick),
+            "r" | "reword" => Some(Self::Reword),
+            "e" | "edit" => Some(Self::Edit),
+            "s" | "squash" => Some(Self::Squash),
+            "f" | "fixup" => Some(Self::Fixup),
+            "x" | "exec" => Some(Self::Exec),
+            "b" | "break" => Some(Self::Break),
+            "d" | "drop" => Some(Self::Drop),
+            "revert" => Some(Self::Revert),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Pick => "pick",
+            Self::Reword => "reword",
+            Self::Edit => "edit",
+            Self::Squash => "squash",
+            Self::Fixup => "fixup",
+            Self::Exec => "exec",
+            Self::Break => "break",
+            Self::Drop => "drop",
+            Self::Revert => "revert",
+        }
+    }
+}
+
+// NOTE:
+// A line of the todo list. The commands working on a commit have its full
+// hash, and the text is the rest of the line: the subject of the commit or
+// the shell command to run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    command: Command,
+    commit: Option<String>,
+    text: String,
+}
+
+impl Instruction {
+    pub fn pick(commit: String, subject: String) -> Self {
+        Self {
+            command: Command::Pick,
+            commit: Some(commit),
+            text: subject,
+        }
+    }
+
+    pub fn revert(commit: String, subject: String) -> Self {
+        Self {
+            command: Command::Revert,
+            commit: Some(commit),
+            text: subject,
+        }
+    }
+
+    pub fn command(&self) -> Command {
+        self.command
+    }
+
+    pub fn commit(&self) -> Option<&str> {
+        self.commit.as_deref()
+    }
+
+    pub fn text(&self) -> &str {
+        self.text.as_str()
+    }
+
+    pub fn abbreviated<P: AsRef<Path>>(&self, root: P) -> Result<String> {
+        Ok(match self.commit.as_deref() {
+            Some(commit) => format!(
+                "{} {} {}",
+                self.command.name(),
+                revision::abbrev(root, commit, None)?,
+                self.text
+            ),
+            None => self.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.commit.as_deref(), self.text.is_empty()) {
+            (Some(commit), true) => write!(f, "{} {commit}", self.command.name()),
+            (Some(commit), false) => write!(f, "{} {commit} {}", self.command.name(), self.text),
+            (None, true) => write!(f, "{}", self.command.name()),
+            (None, false) => write!(f, "{} {}", self.command.name(), self.text),
+        }
+    }
+}
+
+// NOTE:
+// Reads a todo list, skipping blank lines and comments. The commits can be
+// given by any revision and are resolved to their full hash.
+pub fn parse_todo<P: AsRef<Path>>(root: P, todo: &str) -> Result<Vec<Instruction>> {
+    let root = root.as_ref();
+    let mut instructions: Vec<Instruction> = vec![];
+    for (number, line) in (1..).zip(todo.lines()) {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(COMMENT) {
+            continue;
+        }
+        let invalid = || {
+            let msg = format!("invalid line {number}: {line}");
+            Error::from(msg.as_str())
+        };
+        let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let command = Command::parse(word).ok_or_else(invalid)?;
+        let rest = rest.trim();
+        let instruction = match command {
+            Command::Exec if rest.is_empty() => return Err(invalid()),
+            Command::Exec | Command::Break => Instruction {
+                command,
+                commit: None,
+                text: rest.to_string(),
+            },
+            _ => {
+                let (rev, text) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                if rev.is_empty() {
+                    return Err(invalid());
+                }
+                let commit = revision::resolve_commit(root, rev).map_err(|_| invalid())?;
+                Instruction {
+                    command,
+                    commit: Some(commit),
+                    text: text.trim().to_string(),
+                }
+            }
+        };
+        instructions.push(instruction);
+    }
+    Ok(instructions)
+}
+
+// NOTE:
+// A new todo list can't start by melding a commit into the previous one,
+// since there is none yet.
+pub fn check_todo(instructions: &[Instruction]) -> Result<()> {
+    if let Some(first) = instructions
+        .iter()
+        .find(|i| !matches!(i.command, Command::Exec | Command::Break | Command::Drop))
+        .filter(|i| matches!(i.command, Command::Squash | Command::Fixup))
+    {
+        let msg = format!(
+            "cannot '{}' without a previous commit",
+            first.command.name()
+        );
+        return Err(Error::from(msg.as_str()));
+    }
+    Ok(())
+}
+
+pub fn format_todo(instructions: &[Instruction]) -> String {
+    instructions.iter().map(|i| format!("{i}\n")).collect()
+}
+
+// NOTE:
+// The state of a rebase in ".git/rebase-merge", in the same files git uses so
+// that it's kept across invocations: "head-name", "onto" and "orig-head" for
+// where it started, the instructions left in "git-rebase-todo" and those done
+// in "done", with the commit it stopped at and its message and author.
+#[derive(Debug)]
+pub struct Rebase {
+    root: PathBuf,
+    dir: PathBuf,
+}
+
+impl Rebase {
+    pub fn open<P: AsRef<Path>>(root: P) -> Option<Self> {
+        let root = root.as_ref().to_path_buf();
+        let dir = root.join(GIT_DIR).join(REBASE_DIR);
+        dir.is_dir().then_some(Self { root, dir })
+    }
+
+    pub fn create<P: AsRef<Path>>(root: P) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        let dir = root.join(GIT_DIR).join(REBASE_DIR);
+        fs::create_dir_all(&dir)?;
+        Ok(Self { root, dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.dir.join(name).is_file()
+    }
+
+    // NOTE:
+    // Values are read without their trailing newline.
+    pub fn read(&self, name: &str) -> Result<Option<String>> {
+        let path = self.dir.join(name);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        Ok(Some(fs::read_to_string(path)?.trim_end().to_string()))
+    }
+
+    pub fn write(&self, name: &str, content: &str) -> Result<()> {
+        fs::write(self.dir.join(name), content)?;
+        Ok(())
+    }
+
+    pub fn remove(&self, name: &str) -> Result<()> {
+        let path = self.dir.join(name);
+        if path.is_file() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    pub fn todo(&self) -> Result<Vec<Instruction>> {
+        self.instructions(TODO)
+    }
+
+    pub fn done(&self) -> Result<Vec<Instruction>> {
+        self.instructions(DONE)
+    }
+
+    pub fn write_todo(&self, instructions: &[Instruction]) -> Result<()> {
+        self.write(TODO, &format_todo(instructions))
+    }
+
+    pub fn write_done(&self, instructions: &[Instruction]) -> Result<()> {
+        self.write(DONE, &format_todo(instructions))
+    }
+
+    fn instructions(&self, name: &str) -> Result<Vec<Instruction>> {
+        let todo = self.read(name)?.unwrap_or_default();
+        parse_todo(&self.root, &todo)
+    }
+
+    // NOTE:
+    // The author of the commit being replayed, written as shell variables
+    // like git's "author-script".
+    pub fn write_author(&self, author: &User) -> Result<()> {
+        let quote = |value: &str| format!("'{}'", value.replace('\'', "'\\''"));
+        let script = format!(
+            "GIT_AUTHOR_NAME={}\nGIT_AUTHOR_EMAIL={}\nGIT_AUTHOR_DATE={}\n",
+            quote(author.name()),
+            quote(author.email()),
+            quote(&format!("@{} {}", author.timestamp(), author.timezone())),
+        );
+        self.write("author-script", &script)
+    }
+
+    pub fn read_author(&self) -> Result<Option<User>> {
+        let Some(script) = self.read("author-script")? else {
+            return Ok(None);
+        };
+        let mut values: Vec<String> = vec![];
+        for line in script.lines() {
+            let value = line.split_once('=').map_or("", |(_, value)| value);
+            let value = value.replace("'\\''", "'");
+            values.push(value.trim_matches('\'').to_string());
+        }
+        let [name, email, date] = &values[..] else {
+            return Err(Error::from("unable to parse 'author-script'"));
+        };
+        let (timestamp, timezone) = date
+            .trim_start_matches('@')
+            .split_once(' ')
+            .ok_or(Error::from("unable to parse 'author-script'"))?;
+        Ok(Some(User::new(
+            name.clone(),
+            email.clone(),
+            timestamp.parse()?,
+            timezone.to_string(),
+        )))
+    }
+
+    pub fn clear(self) -> Result<()> {
+        fs::remove_dir_all(&self.dir)?;
+        let path = self.root.join(GIT_DIR).join(REBASE_HEAD);
+        if path.is_file() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+// NOTE:
+// The options of a cherry-pick or revert of several commits, kept with its
+// state for when it goes on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Options {
+    pub no_commit: bool,
+    pub mainline: Option<usize>,
+    pub record_origin: bool,
+}
+
+// NOTE:
+// The state of a cherry-pick or revert of several commits in ".git/sequencer":
+// the commits left to replay in "todo" with the one it stopped at first,
+// where HEAD was in "head" to go back there on abort, and where it was after
+// the last commit in "abort-safety".
+#[derive(Debug)]
+pub struct Sequence {
+    root: PathBuf,
+    dir: PathBuf,
+}
+
+impl Sequence {
+    pub fn open<P: AsRef<Path>>(root: P) -> Option<Self> {
+        let root = root.as_ref().to_path_buf();
+        let dir = root.join(GIT_DIR).join(SEQUENCER_DIR);
+        dir.is_dir().then_some(Self { root, dir })
+    }
+
+    pub fn create<P: AsRef<Path>>(root: P) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        let dir = root.join(GIT_DIR).join(SEQUENCER_DIR);
+        fs::create_dir_all(&dir)?;
+        Ok(Self { root, dir })
+    }
+
+    pub fn read(&self, name: &str) -> Result<Option<String>> {
+        let path = self.dir.join(name);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        Ok(Some(fs::read_to_string(path)?.trim_end().to_string()))
+    }
+
+    pub fn write(&self, name: &str, content: &str) -> Result<()> {
+        fs::write(self.dir.join(name), content)?;
+        Ok(())
+    }
+
+    pub fn todo(&self) -> Result<Vec<Instruction>> {
+        let todo = self.read(TODO_SEQUENCE)?.unwrap_or_default();
+        parse_todo(&self.root, &todo)
+    }
+
+    pub fn write_todo(&self, instructions: &[Instruction]) -> Result<()> {
+        let mut todo = String::new();
+        for instruction in instructions {
+            todo.push_str(&instruction.abbreviated(&self.root)?);
+            todo.push('\n');
+        }
+        self.write(TODO_SEQUENCE, &todo)
+    }
+
+    // NOTE:
+    // Written in the config format, like the "opts" file of git.
+    pub fn write_options(&self, options: &Options) -> Result<()> {
+        let mut opts = String::from("[options]\n");
+        if options.no_commit {
+            opts.push_str("\tno-commit = true\n");
+        }
+        if options.record_origin {
+            opts.push_str("\trecord-origin = true\n");
+        }
+        if let Some(mainline) = options.mainline {
+            opts.push_str(&format!("\tmainline = {mainline}\n"));
+        }
+        if opts.ends_with("[options]\n") {
+            return Ok(());
+        }
+        self.write(OPTIONS, &opts)
+    }
+
+    pub fn read_options(&self) -> Result<Options> {
+        let mut options = Options::default();
+        for line in self.read(OPTIONS)?.unwrap_or_default().lines() {
+            match line.split_once('=').map(|(k, v)| (k.trim(), v.trim())) {
+                Some(("no-commit", value)) => options.no_commit = value == "true",
+                Some(("record-origin", value)) => options.record_origin = value == "true",
+                Some(("mainline", value)) => options.mainline = Some(value.parse()?),
+                _ => {}
+            }
+        }
+        Ok(options)
+    }
+
+    pub fn clear(self) -> Result<()> {
+        fs::remove_dir_all(&self.dir)?;
+        Ok(())
+    }
+}
+
+// NOTE:
+// The commit a cherry-pick or a revert stopped at, with which of them it is.
+pub type PickHead = (Command, String);
+
+pub fn pick_head<P: AsRef<Path>>(root: P) -> Result<Option<PickHead>> {
+    let git_dir = root.as_ref().join(GIT_DIR);
+    for (command, name) in [
+        (Command::Pick, CHERRY_PICK_HEAD),
+        (Command::Revert, REVERT_HEAD),
+    ] {
+        let path = git_dir.join(name);
+        if path.is_file() {
+            let hash = fs::read_to_string(path)?.trim_end().to_string();
+            return Ok(Some((command, hash)));
+        }
+    }
+    Ok(None)
+}
+
+pub fn clear_pick_head<P: AsRef<Path>>(root: P) -> Result<()> {
+    let git_dir = root.as_ref().join(GIT_DIR);
+    for name in [CHERRY_PICK_HEAD, REVERT_HEAD] {
+        let path = git_dir.join(name);
+        if path.is_file() {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+// NOTE:
+// The parent a commit is picked or reverted against. Merges have to be told
+// which one with `mainline`, counted from 1.
+pub fn parent(hash: &str, commit: &Commit, mainline: Option<usize>) -> Result<Option<String>> {
+    let parents = commit.parents();
+    let missing = |mainline: usize| {
+        let msg = format!("commit {hash} does not have parent {mainline}");
+        Error::from(msg.as_str())
+    };
+    match (parents, mainline) {
+        ([], _) => Ok(None),
+        ([_, _, ..], None) => {
+            let msg = format!("commit {hash} is a merge but no -m option was given.");
+            Err(Error::from(msg.as_str()))
+        }
+        (_, Some(0)) => Err(missing(0)),
+        ([parent], Some(1) | None) => Ok(Some(parent.clone())),
+        (_, Some(mainline)) => parents
+            .get(mainline - 1)
+            .filter(|_| parents.len() > 1)
+            .cloned()
+            .map(Some)
+            .ok_or_else(|| missing(mainline)),
+    }
+}
+
+// NOTE:
+// The trees to merge on top of HEAD to replay a commit: from its parent to it
+// when picking it, and the other way around when reverting it. Reverting a
+// root commit goes back to an empty tree.
+pub fn replay_trees<P: AsRef<Path>>(
+    root: P,
+    command: Command,
+    commit: &Commit,
+    parent: Option<&str>,
+) -> Result<(Option<String>, String)> {
+    let root = root.as_ref();
+    let parent = parent
+        .map(|parent| revision::resolve_tree(root, parent))
+        .transpose()?;
+    match command {
+        Command::Revert => {
+            let theirs = match parent {
+                Some(tree) => tree,
+                None => Index::default().write_tree(root)?.hash().hex(),
+            };
+            Ok((Some(commit.tree().to_string()), theirs))
+        }
+        _ => Ok((parent, commit.tree().to_string())),
+    }
+}
+
+// NOTE:
+// The message of a revert names the reverted commit, and the parent it goes
+// back to for a merge.
+pub fn revert_message(hash: &str, commit: &Commit, parent: Option<&str>) -> String {
+    let subject = pretty::subject(commit.comment());
+    match parent.filter(|_| commit.parents().len() > 1) {
+        Some(parent) => format!(
+            "Revert \"{subject}\"\n\nThis reverts commit {hash}, reversing\n\
+             changes made to {parent}.\n"
+        ),
+        None => format!("Revert \"{subject}\"\n\nThis reverts commit {hash}.\n"),
+    }
+}
+
+// NOTE:
+// Applies the changes from `base` to `theirs` on top of the `ours` tree with
+// a three-way merge, and updates the index and the working tree with the
+// result. Picking a commit merges from its parent to it, and reverting one
+// the other way around.
+pub fn apply<P: AsRef<Path>>(
+    root: P,
+    index: &mut Index,
+    base: Option<&str>,
+    ours: &str,
+    theirs: &str,
+    labels: (&str, &str),
+) -> Result<TreeMerge> {
+    let root = root.as_ref();
+    let result = merge::merge_trees(root, base, ours, theirs, labels)?;
+    merge::checkout(root, index, ours, &result, Operation::Merge)?;
+    Ok(result)
+}
+
+// NOTE:
+// Like git's patch ids, the commit's patch against its first parent without
+// the hashes, the line numbers and any whitespace, so that a commit picked on
+// another branch has the same id. Merges have none.
+pub fn patch_id<P: AsRef<Path>>(root: P, commit: &Commit) -> Result<Option<Sha1Hash>> {
+    let root = root.as_ref();
+    let parent = match commit.parents() {
+        [] => Source::Empty,
+        [parent] => Source::Tree(revision::resolve_tree(root, parent)?),
+        _ => return Ok(None),
+    };
+    let pairs = files::pairs(root, &parent, &Source::Tree(commit.tree().to_string()), &[])?;
+    let mut out: Vec<u8> = vec![];
+    for pair in pairs.iter() {
+        patch::write_patch(
+            &mut out,
+            root,
+            pair,
+            Format::Unified,
+            DEFAULT_CONTEXT,
+            Algorithm::Myers,
+        )?;
+    }
+
+    let mut hasher = Sha1Hash::hasher();
+    for line in out.split(|&b| b == b'\n') {
+        if line.starts_with(b"index ") || line.starts_with(b"@@") {
+            continue;
+        }
+        let line: Vec<u8> = line
+            .iter()
+            .copied()
+            .filter(|b| !b.is_ascii_whitespace())
+            .collect();
+        hasher.update(&line);
+    }
+    Ok(Some(Sha1Hash::new(hasher)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::GitObject;
+
+    #[test]
+    fn it_parses_todo_lists() {
+        let root = testing::git_dir("sequencer-todo");
+        let todo = "# comment\n\nx make test\nbreak\nexec  echo 'a b'\n";
+        let instructions = parse_todo(&root, todo).unwrap();
+        assert_eq!(instructions.len(), 3);
+        assert_eq!(instructions[0].command(), Command::Exec);
+        assert_eq!(instructions[0].text(), "make test");
+        assert_eq!(instructions[1].command(), Command::Break);
+        assert_eq!(instructions[2].to_string(), "exec echo 'a b'");
+
+        let err = parse_todo(&root, "pick\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            Error::from("invalid line 1: pick").to_string()
+        );
+        let err = parse_todo(&root, "exec true\nfrob x\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            Error::from("invalid line 2: frob x").to_string()
+        );
+    }
+
+    #[test]
+    fn it_keeps_the_author_script() {
+        let root = testing::git_dir("sequencer-author");
+        let rebase = Rebase::create(&root).unwrap();
+        let author = User::new("O'Neil".into(), "o@x".into(), 1600000000, "+0100".into());
+        rebase.write_author(&author).unwrap();
+        assert_eq!(
+            rebase.read("author-script").unwrap().unwrap(),
+            "GIT_AUTHOR_NAME='O'\\''Neil'\nGIT_AUTHOR_EMAIL='o@x'\nGIT_AUTHOR_DATE='@1600000000 +0100'"
+        );
+        assert_eq!(rebase.read_author().unwrap(), Some(author));
+
+        rebase.write_todo(&[]).unwrap();
+        assert!(rebase.todo().unwrap().is_empty());
+        rebase.clear().unwrap();
+        assert!(Rebase::open(&root).is_none());
+    }
+
+    #[test]
+    fn it_gives_picked_commits_the_same_patch_id() {
+        let root = testing::git_dir("sequencer-patch-id");
+        fs::create_dir_all(root.join(crate::GIT_OBJ_DIR)).unwrap();
+        let user = User::new("A".into(), "a@x".into(), 1700000000, "+0000".into());
+        let commit = |files: &[(&str, &str)], parents: Vec<String>| {
+            let mut index = Index::default();
+            for (path, content) in files {
+                fs::write(root.join(path), content).unwrap();
+                index.add(crate::worktree::stage(&root, path.to_string()).unwrap());
+            }
+            let tree = index.write_tree(&root).unwrap().hash().hex();
+            let obj = GitObject::new_commit(tree, "c".into(), parents, user.clone(), user.clone())
+                .unwrap();
+            obj.write(&root).unwrap();
+            obj.hash().hex()
+        };
+        let base = commit(&[("a", "1\n2\n3\n4\n5\n6\n7\n8\n")], vec![]);
+        let other = commit(&[("a", "1\n2\n3\n4\n5\n6\n7\n9\n")], vec![base.clone()]);
+        let ours = commit(&[("a", "1\n2 \n3\n4\n5\n6\n7\n8\n")], vec![base.clone()]);
+        let picked = commit(&[("a", "1\n2 \n3\n4\n5\n6\n7\n9\n")], vec![other.clone()]);
+
+        let id =
+            |hash: &str| patch_id(&root, &GitObject::open_commit(&root, hash).unwrap()).unwrap();
+        assert_eq!(id(&ours), id(&picked));
+        assert_ne!(id(&ours), id(&other));
+    }
+
+    #[test]
+    fn it_keeps_the_options_of_a_sequence() {
+        let root = testing::git_dir("sequencer-options");
+        let sequence = Sequence::create(&root).unwrap();
+        sequence.write_options(&Options::default()).unwrap();
+        assert!(sequence.read(OPTIONS).unwrap().is_none());
+
+        let options = Options {
+            no_commit: true,
+            mainline: Some(2),
+            record_origin: false,
+        };
+        sequence.write_options(&options).unwrap();
+        assert_eq!(
+            sequence.read(OPTIONS).unwrap().unwrap(),
+            "[options]\n\tno-commit = true\n\tmainline = 2"
+        );
+        let read = sequence.read_options().unwrap();
+        assert_eq!((read.no_commit, read.mainline), (true, Some(2)));
+        sequence.clear().unwrap();
+        assert!(Sequence::open(&root).is_none());
+    }
+
+    #[test]
+    fn it_reverts_from_the_mainline_parent() {
+        let user = User::new("A".into(), "a@x".into(), 1700000000, "+0000".into());
+        let (a, b) = ("a".repeat(40), "b".repeat(40));
+        let commit = |parents: Vec<String>| {
+            let obj = GitObject::new_commit(
+                "t".repeat(40),
+                "merged\n".into(),
+                parents,
+                user.clone(),
+                user.clone(),
+            )
+            .unwrap();
+            let GitObject::Commit(commit) = obj else {
+                unreachable!()
+            };
+            commit
+        };
+        let merge = commit(vec![a.clone(), b.clone()]);
+        let err = parent("m", &merge, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            Error::from("commit m is a merge but no -m option was given.").to_string()
+        );
+        assert_eq!(parent("m", &merge, Some(2)).unwrap(), Some(b.clone()));
+        assert!(parent("m", &merge, Some(3)).is_err());
+        assert_eq!(
+            revert_message("m", &merge, Some(&a)),
+            format!(
+                "Revert \"merged\"\n\nThis reverts commit m, reversing\nchanges made to {a}.\n"
+            )
+        );
+
+        let single = commit(vec![a.clone()]);
+        assert_eq!(parent("s", &single, None).unwrap(), Some(a.clone()));
+        assert!(parent("s", &single, Some(2)).is_err());
+        assert_eq!(
+            revert_message("s", &single, Some(&a)),
+            "Revert \"merged\"\n\nThis reverts commit s.\n"
+        );
+        assert_eq!(parent("r", &commit(vec![]), None).unwrap(), None);
+    }
+}
@@ -0,0 +1,571 @@
+use super::{
+    diff::{
+        files::{self, FilePair, Rename, Source},
+        rename::{self, Detection},
+    },
+    git_object::tree::Mode,
+    index::{Index, IndexEntry},
+    reflog,
+    refs::{self, HEAD},
+    revision, worktree, GitObject, Result, Sha1Hash,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Modified,
+    Deleted,
+    TypeChanged,
+    Renamed,
+    Copied,
+}
+
+impl Change {
+    // NOTE:
+    // The letter used by the short formats and `diff --name-status`.
+    pub fn code(&self) -> char {
+        match self {
+            Self::Added => 'A',
+            Self::Modified => 'M',
+            Self::Deleted => 'D',
+            Self::TypeChanged => 'T',
+            Self::Renamed => 'R',
+            Self::Copied => 'C',
+        }
+    }
+
+    fn between(old: Option<(Mode, Sha1Hash)>, new: Option<(Mode, Sha1Hash)>) -> Option<Self> {
+        match (old, new) {
+            (None, None) => None,
+            (None, Some(_)) => Some(Self::Added),
+            (Some(_), None) => Some(Self::Deleted),
+            (Some((old_mode, _)), Some((new_mode, _)))
+                if (old_mode == Mode::Symlink) != (new_mode == Mode::Symlink) =>
+            {
+                Some(Self::TypeChanged)
+            }
+            (Some(old), Some(new)) => (old != new).then_some(Self::Modified),
+        }
+    }
+}
+
+// NOTE:
+// A tracked path that differs between HEAD and the index (staged) or between
+// the index and the working tree (unstaged). The HEAD side of a staged rename
+// or copy is the file it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileStatus {
+    path: String,
+    head: Option<(Mode, Sha1Hash)>,
+    index: Option<(Mode, Sha1Hash)>,
+    worktree: Option<Mode>,
+    staged: Option<Change>,
+    unstaged: Option<Change>,
+    rename: Option<Rename>,
+}
+
+impl FileStatus {
+    pub fn path(&self) -> &str {
+        self.path.as_str()
+    }
+
+    pub fn rename(&self) -> Option<&Rename> {
+        self.rename.as_ref()
+    }
+
+    pub fn head(&self) -> Option<(Mode, Sha1Hash)> {
+        self.head
+    }
+
+    pub fn index(&self) -> Option<(Mode, Sha1Hash)> {
+        self.index
+    }
+
+    pub fn worktree(&self) -> Option<Mode> {
+        self.worktree
+    }
+
+    pub fn staged(&self) -> Option<Change> {
+        self.staged
+    }
+
+    pub fn unstaged(&self) -> Option<Change> {
+        self.unstaged
+    }
+}
+
+// NOTE:
+// A path with unmerged entries. The stages are the common ancestor (1), ours
+// (2) and theirs (3).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    path: String,
+    stages: [Option<(Mode, Sha1Hash)>; 3],
+    worktree: Option<Mode>,
+}
+
+impl Conflict {
+    pub fn path(&self) -> &str {
+        self.path.as_str()
+    }
+
+    pub fn stages(&self) -> &[Option<(Mode, Sha1Hash)>; 3] {
+        &self.stages
+    }
+
+    pub fn worktree(&self) -> Option<Mode> {
+        self.worktree
+    }
+
+    // NOTE:
+    // The two letter code of the short format, like "UU" for "both modified".
+    pub fn code(&self) -> &'static str {
+        match self.stages.each_ref().map(Option::is_some) {
+            [true, false, false] => "DD",
+            [false, true, false] => "AU",
+            [true, true, false] => "UD",
+            [false, false, true] => "UA",
+            [true, false, true] => "DU",
+            [false, true, true] => "AA",
+            _ => "UU",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Status {
+    files: Vec<FileStatus>,
+    conflicts: Vec<Conflict>,
+    untracked: Vec<String>,
+}
+
+impl Status {
+    // NOTE:
+    // Files whose stat data is the same as in the index are not read. When a
+    // file has to be hashed but turns out unchanged, its stat data is written
+    // back to the index so that the next run doesn't hash it again. That is
+    // skipped when the index is locked by another process or was changed
+    // since it was read.
+    pub fn collect<P: AsRef<Path>>(root: P) -> Result<Self> {
+        let root = root.as_ref();
+        let index = Index::open(root)?;
+
+        let mut head: BTreeMap<String, (Mode, Sha1Hash)> = BTreeMap::new();
+        let mut head_tree = Source::Empty;
+        if let Some(hash) = refs::resolve(root, HEAD)? {
+            let commit = GitObject::open_commit(root, &hash)?;
+            for node in GitObject::flatten_tree(root, commit.tree())? {
+                head.insert(node.name().to_string(), (node.mode(), node.hash()));
+            }
+            head_tree = Source::Tree(commit.tree().to_string());
+        }
+
+        let mut conflicts: BTreeMap<String, Conflict> = BTreeMap::new();
+        let mut staged: BTreeMap<String, &IndexEntry> = BTreeMap::new();
+        for entry in index.entries() {
+            match entry.stage() {
+                0 => {
+                    staged.insert(entry.path().to_string(), entry);
+                }
+                stage => {
+                    let conflict = conflicts
+                        .entry(entry.path().to_string())
+                        .or_insert_with(|| Conflict {
+                            path: entry.path().to_string(),
+                            stages: [None; 3],
+                            worktree: worktree_mode(root, entry.path()),
+                        });
+                    conflict.stages[stage as usize - 1] = Some((entry.mode(), entry.hash()));
+                }
+            }
+        }
+
+        let mut files: Vec<FileStatus> = vec![];
+        let mut refreshed: Vec<(String, fs::Metadata)> = vec![];
+        let mut paths: Vec<&String> = head.keys().chain(staged.keys()).collect();
+        paths.sort();
+        paths.dedup();
+
+        for path in paths {
+            if conflicts.contains_key(path) {
+                continue;
+            }
+            let head = head.get(path).copied();
+            let entry = staged.get(path).copied();
+            let index_file = entry
+                .filter(|e| !e.is_intent_to_add())
+                .map(|e| (e.mode(), e.hash()));
+
+            let (worktree, unstaged) = match entry {
+                Some(entry) => {
+                    let (mode, change, meta) = compare_worktree(root, &index, entry)?;
+                    refreshed.extend(meta.map(|meta| (path.clone(), meta)));
+                    (mode, change)
+                }
+                None => (None, None),
+            };
+
+            let staged = Change::between(head, index_file);
+            if staged.is_some() || unstaged.is_some() {
+                files.push(FileStatus {
+                    path: path.clone(),
+                    head,
+                    index: index_file,
+                    worktree,
+                    staged,
+                    unstaged,
+                    rename: None,
+                });
+            }
+        }
+
+        let detection = Detection::configured(root, &["status.renames", "diff.renames"])?;
+        let added = files.iter().any(|f| f.staged == Some(Change::Added));
+        if let Some(detection) = detection.filter(|_| added) {
+            let conflicted: Vec<&str> = conflicts.keys().map(String::as_str).collect();
+            files = find_renames(root, &head_tree, &conflicted, files, detection)?;
+        }
+
+        let tracked: Vec<&str> = index.entries().iter().map(IndexEntry::path).collect();
+        let untracked = worktree::untracked(root, &tracked)?;
+
+        let locked = match refreshed.is_empty() {
+            true => None,
+            false => Index::lock(root).ok(),
+        };
+        if let Some(mut locked) = locked.filter(|l| l.entries() == index.entries()) {
+            for (path, meta) in refreshed.iter() {
+                locked.refresh(path, meta);
+            }
+            locked.commit()?;
+        }
+
+        Ok(Self {
+            files,
+            conflicts: conflicts.into_values().collect(),
+            untracked,
+        })
+    }
+
+    pub fn files(&self) -> &[FileStatus] {
+        &self.files
+    }
+
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    pub fn untracked(&self) -> &[String] {
+        &self.untracked
+    }
+}
+
+// NOTE:
+// Where a detached HEAD was checked out from, and whether it's still there,
+// from the last "checkout: moving from <old> to <new>" in the log of HEAD.
+// The name is kept when it's a ref to the commit that was checked out, without
+// "refs/tags/" or "refs/remotes/", and is the abbreviated commit otherwise.
+pub fn detached_from<P: AsRef<Path>>(root: P) -> Result<Option<(String, bool)>> {
+    let root = root.as_ref();
+    let checkout = reflog::read(root, HEAD)?
+        .into_iter()
+        .rev()
+        .find_map(|entry| {
+            let message = entry.message().strip_prefix("checkout: moving from ")?;
+            let (_, target) = message.split_once(" to ")?;
+            Some((target.to_string(), entry))
+        });
+    let Some((target, entry)) = checkout else {
+        return Ok(None);
+    };
+
+    let refname = match target.as_str() {
+        HEAD => None,
+        name => revision::dwim_ref(root, name)?,
+    };
+    let name = match refname {
+        Some(refname) if revision::resolve_commit(root, &refname)? == entry.hash() => {
+            let name = refname.strip_prefix("refs/tags/");
+            name.or(refname.strip_prefix("refs/remotes/"))
+                .unwrap_or(&refname)
+                .to_string()
+        }
+        _ => revision::abbrev(root, entry.hash(), None)?,
+    };
+    let at = refs::resolve(root, HEAD)?.as_deref() == Some(entry.hash());
+    Ok(Some((name, at)))
+}
+
+// NOTE:
+// Pairs the staged additions with the files they came from like "git diff
+// --cached", dropping the deletions of the renamed files.
+fn find_renames(
+    root: &Path,
+    head_tree: &Source,
+    conflicted: &[&str],
+    files: Vec<FileStatus>,
+    detection: Detection,
+) -> Result<Vec<FileStatus>> {
+    let mut pairs = files::pairs(root, head_tree, &Source::Index, &[])?;
+    pairs.retain(|pair| !conflicted.contains(&pair.path()));
+    let pairs = rename::detect(root, pairs, detection)?;
+    let kept: HashSet<&str> = pairs.iter().map(FilePair::path).collect();
+    let renamed: HashMap<&str, &FilePair> = pairs
+        .iter()
+        .filter(|pair| pair.rename().is_some())
+        .map(|pair| (pair.path(), pair))
+        .collect();
+
+    Ok(files
+        .into_iter()
+        .filter(|file| file.staged != Some(Change::Deleted) || kept.contains(file.path()))
+        .map(|mut file| {
+            if let Some((pair, rename)) = renamed
+                .get(file.path())
+                .and_then(|pair| pair.rename().map(|rename| (pair, rename)))
+            {
+                file.head = pair.old_file().map(|old| (old.mode(), old.hash()));
+                file.staged = Some(if rename.is_copy() {
+                    Change::Copied
+                } else {
+                    Change::Renamed
+                });
+                file.rename = Some(rename.clone());
+            }
+            file
+        })
+        .collect())
+}
+
+// NOTE:
+// A directory where the index has a file counts as the file being deleted.
+fn worktree_mode(root: &Path, path: &str) -> Option<Mode> {
+    Mode::from_path(root.join(path))
+        .ok()
+        .filter(|mode| *mode != Mode::Directory)
+}
+
+// NOTE:
+// Returns the mode of the file in the working tree, how it differs from the
+// index entry and the metadata to refresh the entry with if it was hashed
+// but is unchanged.
+fn compare_worktree(
+    root: &Path,
+    index: &Index,
+    entry: &IndexEntry,
+) -> Result<(Option<Mode>, Option<Change>, Option<fs::Metadata>)> {
+    let full_path = root.join(entry.path());
+    if entry.mode() == Mode::Gitlink {
+        return Ok(match full_path.is_dir() {
+            true => (Some(Mode::Gitlink), None, None),
+            false => (None, Some(Change::Deleted), None),
+        });
+    }
+    let Some(mode) = worktree_mode(root, entry.path()) else {
+        return Ok((None, Some(Change::Deleted), None));
+    };
+    if entry.is_intent_to_add() {
+        return Ok((Some(mode), Some(Change::Added), None));
+    }
+    if (mode == Mode::Symlink) != (entry.mode() == Mode::Symlink) {
+        return Ok((Some(mode), Some(Change::TypeChanged), None));
+    }
+
+    let meta = fs::symlink_metadata(&full_path)?;
+    if mode == entry.mode() && index.is_up_to_date(entry, &meta) {
+        return Ok((Some(mode), None, None));
+    }
+
+    let hash = GitObject::new_blob_from_path(&full_path, mode)?.hash();
+    if hash != entry.hash() || mode != entry.mode() {
+        Ok((Some(mode), Some(Change::Modified), None))
+    } else {
+        Ok((Some(mode), None, Some(meta)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git_object::commit::User;
+    use crate::testing;
+
+    fn stage(root: &Path, paths: &[&str]) {
+        let mut index = Index::open(root).unwrap();
+        for path in paths {
+            index.add(worktree::stage(root, path.to_string()).unwrap());
+        }
+        index.write(root).unwrap();
+    }
+
+    fn commit(root: &Path) {
+        let mut index = Index::open(root).unwrap();
+        let tree = index.write_tree(root).unwrap();
+        let user = User::new("A".into(), "a@x".into(), 100, "+0000".into());
+        let obj =
+            GitObject::new_commit(tree.hash().hex(), "init".into(), vec![], user.clone(), user)
+                .unwrap();
+        obj.write(root).unwrap();
+        refs::write(root, "refs/heads/main", &obj.hash().hex()).unwrap();
+    }
+
+    fn changes(status: &Status) -> Vec<(&str, Option<Change>, Option<Change>)> {
+        status
+            .files()
+            .iter()
+            .map(|f| (f.path(), f.staged(), f.unstaged()))
+            .collect()
+    }
+
+    #[test]
+    fn it_reports_staged_unstaged_and_untracked() {
+        let root = testing::repo("status-changes");
+        fs::create_dir_all(root.join("dir")).unwrap();
+        for path in ["a", "b", "c", "dir/d"] {
+            fs::write(root.join(path), path).unwrap();
+        }
+        stage(&root, &["a", "b", "c", "dir/d"]);
+        commit(&root);
+
+        fs::write(root.join("a"), "changed").unwrap();
+        fs::write(root.join("b"), "staged").unwrap();
+        stage(&root, &["b"]);
+        fs::remove_file(root.join("c")).unwrap();
+        fs::write(root.join("e"), "new").unwrap();
+        stage(&root, &["e"]);
+        fs::write(root.join("e"), "newer").unwrap();
+        fs::create_dir_all(root.join("u/v")).unwrap();
+        fs::write(root.join("u/v/w"), "w").unwrap();
+        fs::write(root.join("dir/x"), "x").unwrap();
+
+        let status = Status::collect(&root).unwrap();
+        assert_eq!(
+            changes(&status),
+            vec![
+                ("a", None, Some(Change::Modified)),
+                ("b", Some(Change::Modified), None),
+                ("c", None, Some(Change::Deleted)),
+                ("e", Some(Change::Added), Some(Change::Modified)),
+            ]
+        );
+        assert_eq!(status.untracked(), &["dir/x", "u/"]);
+        assert!(status.conflicts().is_empty());
+    }
+
+    #[test]
+    fn it_reports_intent_to_add_files_as_added_in_the_worktree() {
+        let root = testing::repo("status-intent-to-add");
+        fs::write(root.join("a"), "a").unwrap();
+        stage(&root, &["a"]);
+        commit(&root);
+
+        fs::write(root.join("e"), "e").unwrap();
+        let mut index = Index::open(&root).unwrap();
+        for path in ["e", "f"] {
+            index.add(IndexEntry::intent_to_add(path.into(), Mode::File));
+        }
+        index.write(&root).unwrap();
+
+        let status = Status::collect(&root).unwrap();
+        assert_eq!(
+            changes(&status),
+            vec![
+                ("e", None, Some(Change::Added)),
+                ("f", None, Some(Change::Deleted)),
+            ]
+        );
+        assert_eq!(status.files()[0].index(), None);
+        assert!(status.untracked().is_empty());
+    }
+
+    #[test]
+    fn it_refreshes_stat_data_of_unchanged_files() {
+        let root = testing::repo("status-refresh");
+        fs::write(root.join("a"), "a").unwrap();
+        stage(&root, &["a"]);
+        commit(&root);
+
+        // NOTE:
+        // The same content with new stat data.
+        fs::remove_file(root.join("a")).unwrap();
+        fs::write(root.join("a"), "a").unwrap();
+        let before = fs::read(root.join(crate::GIT_INDEX)).unwrap();
+        let status = Status::collect(&root).unwrap();
+        assert!(status.files().is_empty());
+        assert_ne!(fs::read(root.join(crate::GIT_INDEX)).unwrap(), before);
+    }
+
+    #[test]
+    fn it_detects_staged_renames() {
+        let root = testing::repo("status-rename");
+        let content: String = (0..20).map(|n| format!("{n}\n")).collect();
+        fs::write(root.join("a"), &content).unwrap();
+        stage(&root, &["a"]);
+        commit(&root);
+
+        fs::remove_file(root.join("a")).unwrap();
+        let mut index = Index::open(&root).unwrap();
+        index.remove("a");
+        index.write(&root).unwrap();
+        fs::write(root.join("b"), &content).unwrap();
+        stage(&root, &["b"]);
+
+        let status = Status::collect(&root).unwrap();
+        assert_eq!(changes(&status), vec![("b", Some(Change::Renamed), None)]);
+        let rename = status.files()[0].rename().unwrap();
+        assert_eq!((rename.from(), rename.similarity()), ("a", 100));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_detects_type_and_mode_changes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = testing::repo("status-type");
+        fs::write(root.join("a"), "a").unwrap();
+        fs::write(root.join("b"), "b").unwrap();
+        stage(&root, &["a", "b"]);
+        commit(&root);
+
+        fs::remove_file(root.join("a")).unwrap();
+        std::os::unix::fs::symlink("b", root.join("a")).unwrap();
+        fs::set_permissions(root.join("b"), fs::Permissions::from_mode(0o755)).unwrap();
+
+        let status = Status::collect(&root).unwrap();
+        assert_eq!(
+            changes(&status),
+            vec![
+                ("a", None, Some(Change::TypeChanged)),
+                ("b", None, Some(Change::Modified)),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_describes_detached_heads() {
+        let root = testing::repo("status-detached");
+        fs::write(root.join("a"), "a").unwrap();
+        stage(&root, &["a"]);
+        commit(&root);
+        let hash = refs::resolve(&root, "refs/heads/main").unwrap().unwrap();
+        refs::write(&root, "refs/tags/v1", &hash).unwrap();
+        assert_eq!(detached_from(&root).unwrap(), None);
+
+        let user = User::new("A".into(), "a@x".into(), 100, "+0000".into());
+        let message = "checkout: moving from main to v1";
+        reflog::append(&root, HEAD, Some(&hash), &hash, &user, message).unwrap();
+        refs::write_head(&root, &refs::Head::Detached(hash.clone())).unwrap();
+        assert_eq!(detached_from(&root).unwrap(), Some(("v1".into(), true)));
+
+        let message = "checkout: moving from v1 to HEAD";
+        reflog::append(&root, HEAD, Some(&hash), &hash, &user, message).unwrap();
+        refs::write_head(&root, &refs::Head::Detached("0".repeat(40))).unwrap();
+        assert_eq!(
+            detached_from(&root).unwrap(),
+            Some((hash[..7].to_string(), false))
+        );
+    }
+}
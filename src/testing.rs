@@ -0,0 +1,180 @@
+use super::{
+    index::Index,
+    reflog,
+    refs::{self, HEAD},
+    worktree, Command, GitObject, Result, GIT_DIR, GIT_PACK_DIR,
+};
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+// NOTE:
+// A directory of its own for a test under the temporary directory, which is
+// removed when the test is done with it. Names are prefixed with the module
+// so that tests running at the same time don't share one.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("git-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+// NOTE:
+// A directory with only an empty .git directory in it.
+pub fn git_dir(name: &str) -> TempDir {
+    let root = TempDir::new(name);
+    fs::create_dir_all(root.join(GIT_DIR)).unwrap();
+    root
+}
+
+// NOTE:
+// An empty repository, with HEAD on the unborn main branch.
+pub fn repo(name: &str) -> TempDir {
+    let root = TempDir::new(name);
+    fs::create_dir_all(root.join(GIT_PACK_DIR)).unwrap();
+    fs::write(root.join(GIT_DIR).join("HEAD"), "ref: refs/heads/main\n").unwrap();
+    root
+}
+
+// NOTE:
+// Writes the tree of exactly the given files and returns its hash. The files
+// are only in the working tree while their blobs are written.
+pub fn write_tree(root: &Path, files: &[(&str, &str)]) -> String {
+    let mut index = Index::default();
+    for (path, content) in files {
+        let full_path = root.join(path);
+        fs::create_dir_all(full_path.parent().unwrap()).unwrap();
+        fs::write(full_path, content).unwrap();
+        index.add(worktree::stage(root, path.to_string()).unwrap());
+    }
+    let tree = index.write_tree(root).unwrap().hash().hex();
+    for (path, _) in files {
+        worktree::remove_file(root, path).unwrap();
+    }
+    tree
+}
+
+// NOTE:
+// Commands work on the repository in the current directory, which all the
+// tests of the process share. A test that runs commands holds this guard,
+// which keeps the other tests doing the same waiting, and goes back to the
+// directory it came from when dropped, even if the test fails.
+pub struct CurrentDir {
+    previous: PathBuf,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl Drop for CurrentDir {
+    fn drop(&mut self) {
+        let _ = std::env::set_current_dir(&self.previous);
+    }
+}
+
+pub fn current_dir(root: &Path) -> CurrentDir {
+    static LOCK: Mutex<()> = Mutex::new(());
+    let lock = LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    let previous = std::env::current_dir().unwrap();
+    std::env::set_current_dir(root).unwrap();
+    CurrentDir {
+        previous,
+        _lock: lock,
+    }
+}
+
+// NOTE:
+// A repository to run commands in, with an identity to commit with.
+pub fn command_repo(name: &str) -> (TempDir, CurrentDir) {
+    let root = repo(name);
+    fs::write(
+        root.join(GIT_DIR).join("config"),
+        "[user]\n\tname = A U Thor\n\temail = author@example.com\n",
+    )
+    .unwrap();
+    let cwd = current_dir(&root);
+    (root, cwd)
+}
+
+// NOTE:
+// Runs the command the way the binary does, on the current directory.
+pub fn git(args: &[&str]) -> Result<()> {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    let command = Command::new(&args)?;
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(command.run())
+}
+
+// NOTE:
+// Writes the files, commits them with whatever else is staged and returns
+// the hash of the commit.
+pub fn commit(files: &[(&str, &str)], message: &str) -> String {
+    for (path, content) in files {
+        if let Some(dir) = Path::new(path).parent() {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(path, content).unwrap();
+    }
+    if !files.is_empty() {
+        let paths: Vec<&str> = files.iter().map(|(path, _)| *path).collect();
+        git(&[&["add"][..], &paths].concat()).unwrap();
+    }
+    git(&["commit", "--allow-empty", "-m", message]).unwrap();
+    refs::resolve(".", HEAD).unwrap().unwrap()
+}
+
+// NOTE:
+// The hash of the blob staged at the path in the index of the current
+// directory, if any.
+pub fn staged(path: &str) -> Option<String> {
+    let index = Index::open(".").unwrap();
+    let entry = index.entries().iter().find(|e| e.path() == path)?;
+    Some(entry.hash().hex())
+}
+
+// NOTE:
+// The hash of a blob with the content, wrapped to compare with `staged`.
+pub fn blob(content: &str) -> Option<String> {
+    Some(
+        GitObject::new_blob(content.as_bytes())
+            .unwrap()
+            .hash()
+            .hex(),
+    )
+}
+
+// NOTE:
+// The messages of the log of the ref, oldest first.
+pub fn messages(refname: &str) -> Vec<String> {
+    reflog::read(".", refname)
+        .unwrap()
+        .iter()
+        .map(|entry| entry.message().to_string())
+        .collect()
+}
@@ -0,0 +1,292 @@
+use super::{
+    git_object::{is_git_file, tree::Mode},
+    ignore::Excludes,
+    index::IndexEntry,
+    GitObject, Result,
+};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+// NOTE:
+// Pathspecs are relative to the repository root, so "./foo/" and "foo" are
+// the same path and "." is the whole working tree (an empty path).
+pub fn normalize(pathspec: &str) -> String {
+    let path = pathspec.trim_start_matches("./").trim_end_matches('/');
+    if path == "." {
+        String::new()
+    } else {
+        path.into()
+    }
+}
+
+pub fn matches(pathspec: &str, path: &str) -> bool {
+    pathspec.is_empty()
+        || path == pathspec
+        || path
+            .strip_prefix(pathspec)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+// NOTE:
+// Lists the files and symlinks under the path recursively, relative to the root.
+// Symlinks to directories are not followed, and the .git directory is skipped.
+pub fn files<P: AsRef<Path>>(root: P, path: &str) -> Result<Vec<String>> {
+    list(root.as_ref(), path, None)
+}
+
+// NOTE:
+// Lists the files like `files`, leaving out the ones that are ignored.
+pub fn unignored_files<P: AsRef<Path>>(
+    root: P,
+    path: &str,
+    excludes: &mut Excludes,
+) -> Result<Vec<String>> {
+    list(root.as_ref(), path, Some(excludes))
+}
+
+fn list(root: &Path, path: &str, mut excludes: Option<&mut Excludes>) -> Result<Vec<String>> {
+    let full_path = root.join(path);
+    if fs::symlink_metadata(&full_path).is_err() {
+        return Ok(vec![]);
+    }
+
+    let mut files: Vec<String> = vec![];
+    let is_dir = Mode::from_path(&full_path)? == Mode::Directory;
+    if let Some(excludes) = excludes.as_deref_mut() {
+        if !path.is_empty() && excludes.is_ignored(path, is_dir)? {
+            return Ok(files);
+        }
+    }
+    if is_dir {
+        walk(&full_path, path, &mut files, excludes)?;
+    } else {
+        files.push(path.into());
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn walk(
+    dir: &Path,
+    prefix: &str,
+    files: &mut Vec<String>,
+    mut excludes: Option<&mut Excludes>,
+) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if is_git_file(&entry) {
+            continue;
+        }
+
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = if prefix.is_empty() {
+            name
+        } else {
+            format!("{prefix}/{name}")
+        };
+
+        let is_dir = Mode::from_path(entry.path())? == Mode::Directory;
+        if let Some(excludes) = excludes.as_deref_mut() {
+            if excludes.is_ignored(&path, is_dir)? {
+                continue;
+            }
+        }
+        if is_dir {
+            walk(&entry.path(), &path, files, excludes.as_deref_mut())?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// NOTE:
+// Lists the files that are neither tracked nor ignored. A directory without
+// any tracked file is listed once as "dir/", and directories without any
+// file that isn't ignored are skipped. A tracked directory is a gitlink,
+// whose files belong to the submodule.
+pub fn untracked<P: AsRef<Path>>(root: P, tracked: &[&str]) -> Result<Vec<String>> {
+    let root = root.as_ref();
+    let mut excludes = Excludes::open(root)?;
+    let tracked_files: HashSet<&str> = tracked.iter().copied().collect();
+    let tracked_dirs: HashSet<&str> = tracked
+        .iter()
+        .flat_map(|path| path.match_indices('/').map(|(i, _)| &path[..i]))
+        .collect();
+
+    let mut untracked: Vec<String> = vec![];
+    let mut stack: Vec<String> = vec![String::new()];
+    while let Some(dir) = stack.pop() {
+        for entry in fs::read_dir(root.join(&dir))? {
+            let entry = entry?;
+            if is_git_file(&entry) {
+                continue;
+            }
+
+            let name = entry.file_name().to_string_lossy().to_string();
+            let path = if dir.is_empty() {
+                name
+            } else {
+                format!("{dir}/{name}")
+            };
+
+            let is_dir = Mode::from_path(entry.path())? == Mode::Directory;
+            if tracked_files.contains(path.as_str()) || excludes.is_ignored(&path, is_dir)? {
+                continue;
+            }
+            if !is_dir {
+                untracked.push(path);
+            } else if tracked_dirs.contains(path.as_str()) {
+                stack.push(path);
+            } else if !unignored_files(root, &path, &mut excludes)?.is_empty() {
+                untracked.push(format!("{path}/"));
+            }
+        }
+    }
+    untracked.sort();
+    Ok(untracked)
+}
+
+// NOTE:
+// Writes the blob of the file and returns the index entry for it.
+pub fn stage<P: AsRef<Path>>(root: P, path: String) -> Result<IndexEntry> {
+    let root = root.as_ref();
+    let full_path = root.join(&path);
+    let mode = Mode::from_path(&full_path)?;
+    let obj = GitObject::new_blob_from_path(&full_path, mode)?;
+    obj.write(root)?;
+    let meta = fs::symlink_metadata(&full_path)?;
+    Ok(IndexEntry::new(path, mode, obj.hash(), &meta))
+}
+
+// NOTE:
+// Directories left empty by removing the file are removed as well. The
+// directory of a gitlink is only removed when it is empty, as for a
+// submodule that was never cloned.
+pub fn remove_file<P: AsRef<Path>>(root: P, path: &str) -> Result<()> {
+    let root = root.as_ref();
+    let full_path = root.join(path);
+    match fs::symlink_metadata(&full_path) {
+        Ok(meta) if meta.is_dir() => {
+            if fs::remove_dir(&full_path).is_err() {
+                return Ok(());
+            }
+        }
+        Ok(_) => fs::remove_file(&full_path)?,
+        Err(_) => {}
+    }
+
+    let mut dir = full_path.parent();
+    while let Some(d) = dir.filter(|d| *d != root) {
+        if fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn it_normalizes_pathspec() {
+        assert_eq!(normalize("."), "");
+        assert_eq!(normalize("./"), "");
+        assert_eq!(normalize("./foo/bar/"), "foo/bar");
+        assert_eq!(normalize("foo"), "foo");
+    }
+
+    #[test]
+    fn it_matches_pathspec() {
+        assert!(matches("", "foo/bar"));
+        assert!(matches("foo", "foo"));
+        assert!(matches("foo", "foo/bar"));
+        assert!(!matches("foo", "foobar"));
+        assert!(!matches("foo/bar", "foo"));
+    }
+
+    #[test]
+    fn it_lists_files_recursively() {
+        let root = testing::git_dir("worktree-files");
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/b/c.txt"), "c").unwrap();
+        fs::write(root.join("a/d.txt"), "d").unwrap();
+        fs::write(root.join("e.txt"), "e").unwrap();
+        fs::write(root.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+
+        assert_eq!(
+            files(&root, "").unwrap(),
+            vec!["a/b/c.txt", "a/d.txt", "e.txt"]
+        );
+        assert_eq!(files(&root, "a").unwrap(), vec!["a/b/c.txt", "a/d.txt"]);
+        assert_eq!(files(&root, "e.txt").unwrap(), vec!["e.txt"]);
+        assert!(files(&root, "missing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn it_lists_untracked_files() {
+        let root = testing::git_dir("worktree-untracked");
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::create_dir_all(root.join("c/d")).unwrap();
+        fs::create_dir_all(root.join("empty")).unwrap();
+        fs::write(root.join("a/b/c.txt"), "c").unwrap();
+        fs::write(root.join("a/d.txt"), "d").unwrap();
+        fs::write(root.join("c/d/e.txt"), "e").unwrap();
+        fs::write(root.join("f.txt"), "f").unwrap();
+
+        assert_eq!(
+            untracked(&root, &["a/d.txt"]).unwrap(),
+            vec!["a/b/", "c/", "f.txt"]
+        );
+        assert_eq!(
+            untracked(&root, &["a/b/c.txt", "f.txt"]).unwrap(),
+            vec!["a/d.txt", "c/"]
+        );
+    }
+
+    #[test]
+    fn it_leaves_ignored_files_out_of_untracked_ones() {
+        let root = testing::git_dir("worktree-untracked-ignored");
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::create_dir_all(root.join("logs")).unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(root.join("target/debug/app"), "app").unwrap();
+        fs::write(root.join("logs/a.log"), "a").unwrap();
+        fs::write(root.join("src/b.log"), "b").unwrap();
+        fs::write(root.join("src/main.rs"), "main").unwrap();
+        fs::write(root.join("x.log"), "x").unwrap();
+
+        assert_eq!(untracked(&root, &[]).unwrap(), vec![".gitignore", "src/"]);
+        assert_eq!(
+            untracked(&root, &[".gitignore", "src/main.rs"]).unwrap(),
+            Vec::<String>::new()
+        );
+
+        let mut excludes = Excludes::open(&root).unwrap();
+        assert_eq!(
+            unignored_files(&root, "", &mut excludes).unwrap(),
+            vec![".gitignore", "src/main.rs"]
+        );
+    }
+
+    #[test]
+    fn it_removes_file_and_empty_directories() {
+        let root = testing::git_dir("worktree-remove");
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/b/c.txt"), "c").unwrap();
+        fs::write(root.join("a/d.txt"), "d").unwrap();
+
+        remove_file(&root, "a/b/c.txt").unwrap();
+        assert!(!root.join("a/b").exists());
+        assert!(root.join("a/d.txt").exists());
+
+        remove_file(&root, "a/d.txt").unwrap();
+        assert!(!root.join("a").exists());
+        assert!(root.exists());
+    }
+}